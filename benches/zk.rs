@@ -0,0 +1,81 @@
+// Benchmarks of proof generation and verification. Compare against an
+// earlier commit with criterion baselines:
+//
+//     cargo bench --bench zk -- --save-baseline before
+//     cargo bench --bench zk -- --baseline before
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use legalage_logic::api::{CardKey, PhotosDigest, Private, PrivateKey, Public, QrRequest};
+use legalage_logic::zk::{self, Prover, Verifier};
+
+fn request() -> QrRequest {
+    request_older_than(18)
+}
+
+/// Request of a relation which does not hold, proved with a decoy.
+fn decoy_request() -> QrRequest {
+    request_older_than(60000)
+}
+
+fn request_older_than(delta: i32) -> QrRequest {
+    QrRequest::build(
+        Public::older_than(delta, 2459000, 1200).unwrap(),
+        Private::with(2415021, PrivateKey::default(), digest()).unwrap(),
+    )
+    .unwrap()
+}
+
+fn digest() -> PhotosDigest {
+    PhotosDigest::new(vec![2, 7]).unwrap()
+}
+
+fn prove(c: &mut Criterion) {
+    let prover = Prover::prepare().unwrap();
+    let mut group = c.benchmark_group("prove");
+    group.sample_size(10);
+    group.bench_function("single", |b| b.iter(|| prover.prove(request()).unwrap()));
+    // Must not differ from "single".
+    group.bench_function("decoy", |b| {
+        b.iter(|| prover.prove(decoy_request()).unwrap())
+    });
+    group.finish();
+}
+
+fn verify(c: &mut Criterion) {
+    let qr = Prover::prepare().unwrap().prove(request()).unwrap();
+    let verifier = Verifier::new().unwrap();
+    let digest = digest();
+    c.bench_function("verify", |b| {
+        b.iter(|| verifier.verify(&qr, &digest).unwrap())
+    });
+}
+
+/// The shared contexts used by the app, prepared before measuring.
+fn shared(c: &mut Criterion) {
+    zk::generate_proof(request()).unwrap();
+    let qr = zk::generate_proof(request()).unwrap();
+    let digest = digest();
+    let mut group = c.benchmark_group("shared");
+    group.sample_size(10);
+    group.bench_function("generate_proof", |b| {
+        b.iter(|| zk::generate_proof(request()).unwrap())
+    });
+    group.bench_function("verify_proof", |b| {
+        b.iter(|| zk::verify_proof(&qr, &digest).unwrap())
+    });
+    group.finish();
+}
+
+fn keys(c: &mut Criterion) {
+    let private = Private::with(2415021, PrivateKey::new(vec![10]).unwrap(), digest()).unwrap();
+    c.bench_function("generate_card_key", |b| {
+        b.iter(|| zk::generate_card_key(&private).unwrap())
+    });
+    let card_key = CardKey::new(vec![27]).unwrap();
+    c.bench_function("compute_challenge", |b| {
+        b.iter(|| zk::compute_challenge(&card_key, 2459000))
+    });
+}
+
+criterion_group!(benches, prove, verify, shared, keys);
+criterion_main!(benches);
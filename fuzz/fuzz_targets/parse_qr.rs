@@ -0,0 +1,22 @@
+// Parsing of scanned QR codes. Run with `cargo fuzz run parse_qr`;
+// inputs which used to fail are kept in the `qr_string_corpus` test of
+// `src/api.rs`.
+
+#![no_main]
+use legalage_logic::api::{ProofQrCode, COMPACT_PREFIX};
+use legalage_logic::attestation::SignedQr;
+use legalage_logic::zk::unhide_bellman_proof;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ProofQrCode::from_bytes(data);
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(qr) = s.parse::<ProofQrCode>() {
+            let _ = unhide_bellman_proof(&qr.proof, qr.version, &[2, 7], 0);
+        }
+        let compact = format!("{}{}", COMPACT_PREFIX, s);
+        let _ = compact.parse::<ProofQrCode>();
+        let _ = s.parse::<SignedQr>();
+        let _ = ProofQrCode::from_chunks(s.split('\n'));
+    }
+});
@@ -0,0 +1,96 @@
+/* C interface of legalage_logic, built with the `ffi` feature.
+ * legalage_generate_qr needs the `prover` feature and
+ * legalage_verify_qr the `verifier` feature. */
+
+#ifndef LEGALAGE_H
+#define LEGALAGE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Error codes. Positive codes other than these mirror ZkError::code. */
+#define LEGALAGE_OK 0
+#define LEGALAGE_VERIFICATION_FAILED 1
+#define LEGALAGE_INVALID_INPUT 2
+#define LEGALAGE_QR_CODE 3
+#define LEGALAGE_PROOF_DESERIALIZATION 4
+#define LEGALAGE_KEY_DESERIALIZATION 5
+#define LEGALAGE_CIRCUIT_EXECUTION 6
+#define LEGALAGE_PHOTO_MISMATCH 7
+#define LEGALAGE_STALE 8
+#define LEGALAGE_INVALID_ARTIFACT 9
+#define LEGALAGE_UNSUPPORTED_CIRCUIT 10
+#define LEGALAGE_KEY_EXPIRED 11
+#define LEGALAGE_UNKNOWN_KEY 12
+#define LEGALAGE_CORRUPT_INSTALLATION 13
+#define LEGALAGE_NONCE_MISMATCH 14
+#define LEGALAGE_TAG_MISMATCH 15
+#define LEGALAGE_CANCELLED 16
+#define LEGALAGE_UNBOUND_PROOF 17
+#define LEGALAGE_INVALID_ARGUMENT -1
+#define LEGALAGE_PANIC -2
+
+/* Relation */
+#define LEGALAGE_YOUNGER 0
+#define LEGALAGE_OLDER 1
+
+/* Heap buffer owned by the library, release with legalage_free_buffer.
+   Empty buffers have a null data pointer. */
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} LegalageBuffer;
+
+/* Public part of the request, the relation is LEGALAGE_YOUNGER or
+   LEGALAGE_OLDER. */
+typedef struct {
+    int32_t today;
+    int32_t now;
+    uint8_t relation;
+    int32_t delta;
+} LegalagePublic;
+
+/* Returns the UTF-8 encoded QR code string, not terminated by zero. */
+LegalageBuffer legalage_generate_qr(int32_t today, int32_t now, uint8_t relation,
+                                    int32_t delta, int32_t birthday,
+                                    const uint8_t *private_key, size_t private_key_len,
+                                    const uint8_t *photos_digest, size_t photos_digest_len,
+                                    int32_t *error);
+
+/* Returns true if the QR code verifies, otherwise the reason is stored in error. */
+bool legalage_verify_qr(const uint8_t *qr, size_t qr_len,
+                        const uint8_t *photo_digest, size_t photo_digest_len,
+                        int32_t *error);
+
+/* Stores the zero terminated QR code string, release it with
+   legalage_string_free. Returns the error code, out is null on error. */
+int32_t legalage_generate_qr_string(const LegalagePublic *public, int32_t birthday,
+                                    const uint8_t *private_key, size_t private_key_len,
+                                    const uint8_t *photos_digest, size_t photos_digest_len,
+                                    char **out);
+
+/* Returns LEGALAGE_OK if the zero terminated QR code verifies. */
+int32_t legalage_verify_qr_string(const char *qr,
+                                  const uint8_t *photo_digest, size_t photo_digest_len);
+
+LegalageBuffer legalage_generate_private_key(int32_t *error);
+
+LegalageBuffer legalage_compute_card_key(int32_t birthday,
+                                         const uint8_t *private_key, size_t private_key_len,
+                                         const uint8_t *photos_digest, size_t photos_digest_len,
+                                         int32_t *error);
+
+void legalage_string_free(char *s);
+
+void legalage_free_buffer(LegalageBuffer buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
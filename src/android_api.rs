@@ -1,15 +1,407 @@
+// Error mapping for the android interface. The helpers are
+// independent of JNI so that they can be tested on the host.
+
+use crate::phone_api::{
+    PhotosDigest, Private, PrivateKey, ProofQrCode, Public, QrError, Relation, RelationOutcome,
+    ZkError,
+};
+use crate::zk::VerificationFailure;
+use std::any::Any;
+use std::convert::TryFrom;
+
+/// Java class of exceptions thrown for invalid arguments.
+pub const ILLEGAL_ARGUMENT_EXCEPTION: &str = "java/lang/IllegalArgumentException";
+
+/// Java class of exceptions thrown when the proof cannot be generated
+/// or verified.
+pub const ZK_EXCEPTION: &str = "eu/legalage/app/logic/ZkException";
+
+/// Java exception to be thrown instead of returning a value.
+#[derive(PartialEq, Debug, Clone)]
+pub struct JavaException {
+    pub class: &'static str,
+    pub message: String,
+}
+
+impl JavaException {
+    /// Exception for a missing or malformed argument passed from Java.
+    pub fn illegal_argument(message: String) -> Self {
+        JavaException {
+            class: ILLEGAL_ARGUMENT_EXCEPTION,
+            message: message,
+        }
+    }
+}
+
+/// Maps a library error to the Java exception. Invalid requests and
+/// QR codes are the caller's fault, everything else is reported as
+/// `ZkException`.
+pub fn exception_from_error(e: &ZkError) -> JavaException {
+    match e {
+        ZkError::InvalidInput(_) | ZkError::QrCode(_) => {
+            JavaException::illegal_argument(e.to_string())
+        }
+        _ => JavaException {
+            class: ZK_EXCEPTION,
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Maps the payload of a caught panic to the Java exception.
+pub fn exception_from_panic(payload: &(dyn Any + Send)) -> JavaException {
+    let detail = if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown error".to_string()
+    };
+    JavaException {
+        class: ZK_EXCEPTION,
+        message: format!("internal error: {}", detail),
+    }
+}
+
+/// Converts the level given by Java, 1 (errors) to 5 (trace), to the
+/// most verbose level logged.
+pub fn log_level(level: i32) -> Option<log::Level> {
+    match level {
+        1 => Some(log::Level::Error),
+        2 => Some(log::Level::Warn),
+        3 => Some(log::Level::Info),
+        4 => Some(log::Level::Debug),
+        5 => Some(log::Level::Trace),
+        _ => None,
+    }
+}
+
+/// Result code of `validateQrCode` for a verified QR code.
+pub const VERIFIED: i32 = 0;
+
+/// Result code of `validateQrCode` for a well formed proof of a
+/// relation which does not hold.
+pub const NOT_SATISFIED: i32 = 1;
+
+/// Result code of `validateQrCode` for a proof which does not unhide
+/// to curve points or whose seal does not open, usually because the
+/// photo digest belongs to another card.
+pub const PROOF_UNPARSEABLE: i32 = 2;
+
+/// Result code of `validateQrCode` for a corrupted or tampered QR
+/// code.
+pub const MALFORMED: i32 = 3;
+
+/// Result code of `validateQrCode`. The failures of the QR code are
+/// reported by `VerificationFailure`, other errors by `ZkError::code`.
+pub fn result_code(result: &Result<Public, ZkError>) -> i32 {
+    match result {
+        Ok(_) => VERIFIED,
+        Err(e) => match VerificationFailure::from_error(e) {
+            Some(VerificationFailure::PairingCheckFailed) => NOT_SATISFIED,
+            Some(VerificationFailure::ProofUnparseable)
+            | Some(VerificationFailure::TagMismatch) => PROOF_UNPARSEABLE,
+            Some(VerificationFailure::BadPublicEncoding) => MALFORMED,
+            Some(VerificationFailure::UnboundDigest) | None => e.code(),
+        },
+    }
+}
+
+/// Result code of `qrErrorCode`: zero for a QR code which decodes,
+/// `QrError::code` of why it does not otherwise. Tells apart the codes
+/// reported as `MALFORMED` by `validateQrCode`.
+pub fn qr_error_code(qr: &str) -> i32 {
+    match qr.parse::<ProofQrCode>() {
+        Ok(_) => 0,
+        Err(e) => QrError::code(&e),
+    }
+}
+
+/// Result code of `evaluateRelation` for a request which would give a
+/// valid proof.
+pub const RELATION_SATISFIED: i32 = 0;
+
+/// Result code of `evaluateRelation` for a request which would give a
+/// decoy proof.
+pub const RELATION_NOT_SATISFIED: i32 = 1;
+
+/// Result code of `evaluateRelation`. Invalid requests are reported by
+/// `ZkError::code`.
+pub fn relation_code(outcome: &RelationOutcome) -> i32 {
+    match outcome {
+        RelationOutcome::Satisfied => RELATION_SATISFIED,
+        RelationOutcome::NotSatisfied => RELATION_NOT_SATISFIED,
+        RelationOutcome::InvalidInput(e) => ZkError::InvalidInput(e.clone()).code(),
+    }
+}
+
+/// Public part of the integer fields of the Java `PublicInfo` object.
+/// The values are kept as they are, including negative ones, and are
+/// range checked by the library like any other request, so that
+/// `evaluateRelation` reports them by its result code.
+pub fn public_from_fields(
+    today: i32,
+    now: i32,
+    relation: i32,
+    delta: i32,
+) -> Result<Public, JavaException> {
+    let relation = u8::try_from(relation)
+        .ok()
+        .and_then(|value| Relation::try_from(value).ok())
+        .ok_or_else(|| {
+            JavaException::illegal_argument("Cannot unwrap 'PublicInfo::relation'.".to_string())
+        })?;
+    Ok(Public {
+        today: today,
+        now: now,
+        relation: relation,
+        delta: delta,
+        second: None,
+        nonce: None,
+    })
+}
+
+/// Photo digest of the bytes of a Java array.
+pub fn photos_digest_from_bytes(bytes: Vec<u8>) -> Result<PhotosDigest, JavaException> {
+    PhotosDigest::new(bytes).map_err(|e| JavaException::illegal_argument(e.to_string()))
+}
+
+/// Private part of the arguments of the Java calls. The birthday is
+/// range checked by the library.
+pub fn private_from_parts(
+    birthday: i32,
+    private_key: Vec<u8>,
+    photo_digest: Vec<u8>,
+) -> Result<Private, JavaException> {
+    Ok(Private {
+        birthday: birthday,
+        private_key: PrivateKey::new(private_key)
+            .map_err(|e| JavaException::illegal_argument(e.to_string()))?,
+        photos_digest: photos_digest_from_bytes(photo_digest)?,
+    })
+}
+
+/// Circuit id passed by Java, the ids of the QR codes are bytes.
+pub fn circuit_from_int(circuit: i32) -> Result<u8, JavaException> {
+    u8::try_from(circuit)
+        .map_err(|_| JavaException::illegal_argument(format!("Invalid circuit id {}.", circuit)))
+}
+
+/// Integer fields of the Java `PublicInfo` object as (name, JNI
+/// signature, value) tuples.
+pub fn public_fields(public: &Public) -> Vec<(&'static str, &'static str, i32)> {
+    vec![
+        ("today", "I", public.today),
+        ("now", "I", public.now),
+        ("delta", "I", public.delta),
+        ("relation", "I", public.relation as i32),
+    ]
+}
+
 /// Android interface. Compiles on android only.
 #[cfg(target_os = "android")]
 #[allow(non_snake_case)]
 pub mod android {
 
-    use crate::phone_api::{ProofQrCode, Public, QrRequest, Relation};
-    use crate::zk::{generate_proof, verify_proof};
-    use jni::objects::{JClass, JString};
+    use super::{
+        circuit_from_int, exception_from_error, exception_from_panic, log_level,
+        photos_digest_from_bytes, private_from_parts, public_fields, public_from_fields,
+        qr_error_code, relation_code, result_code, JavaException, ZK_EXCEPTION,
+    };
+    #[cfg(feature = "prover")]
+    use crate::phone_api::CancellationToken;
+    use crate::phone_api::{self, PhotosDigest, Private, Public};
+    use jni::objects::{JClass, JString, JValue};
     use jni::sys::{jbyteArray, jint, jobject, jstring};
     use jni::JNIEnv;
-    use std::str::FromStr;
+    use std::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    use std::path::PathBuf;
+    use std::ptr;
+
+    /// Runs `body` converting both errors and panics to a Java
+    /// exception. Returns `on_error` if an exception has been thrown.
+    fn guard<T, F>(env: &JNIEnv, on_error: T, body: F) -> T
+    where
+        F: FnOnce() -> Result<T, JavaException>,
+    {
+        let exception = match catch_unwind(AssertUnwindSafe(body)) {
+            Ok(Ok(value)) => return value,
+            Ok(Err(e)) => e,
+            Err(payload) => exception_from_panic(&*payload),
+        };
+        // If even throwing fails there is nothing more we can do.
+        let _ = env.throw_new(exception.class, exception.message);
+        on_error
+    }
+
+    fn get_int_field(env: &JNIEnv, obj: jobject, name: &str) -> Result<i32, JavaException> {
+        env.get_field(obj, name, "I")
+            .and_then(|v| v.i())
+            .map_err(|_| {
+                JavaException::illegal_argument(format!("Cannot extract 'PublicInfo::{}'.", name))
+            })
+    }
+
+    /// Copies the Java array. Keys and digests made of the copy are
+    /// wiped when dropped, also when they are rejected by validation.
+    fn get_byte_array(
+        env: &JNIEnv,
+        array: jbyteArray,
+        name: &str,
+    ) -> Result<Vec<u8>, JavaException> {
+        env.convert_byte_array(array)
+            .map_err(|_| JavaException::illegal_argument(format!("Cannot unwrap '{}'.", name)))
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    fn get_path(env: &JNIEnv, path: JString, name: &str) -> Result<PathBuf, JavaException> {
+        let path: String = env
+            .get_string(path)
+            .map_err(|_| {
+                JavaException::illegal_argument(format!("Cannot extract '{}' string.", name))
+            })?
+            .into();
+        Ok(PathBuf::from(path))
+    }
+
+    fn get_photos_digest(env: &JNIEnv, array: jbyteArray) -> Result<PhotosDigest, JavaException> {
+        photos_digest_from_bytes(get_byte_array(env, array, "photo_digest")?)
+    }
+
+    fn get_public(env: &JNIEnv, public_info: jobject) -> Result<Public, JavaException> {
+        public_from_fields(
+            get_int_field(env, public_info, "today")?,
+            get_int_field(env, public_info, "now")?,
+            get_int_field(env, public_info, "relation")?,
+            get_int_field(env, public_info, "delta")?,
+        )
+    }
+
+    fn get_private(
+        env: &JNIEnv,
+        birthday: jint,
+        private_key: jbyteArray,
+        photo_digest: jbyteArray,
+    ) -> Result<Private, JavaException> {
+        // The key is copied last so that it is wrapped, and wiped,
+        // right away.
+        let photo_digest = get_byte_array(env, photo_digest, "photo_digest")?;
+        private_from_parts(
+            birthday,
+            get_byte_array(env, private_key, "private_key")?,
+            photo_digest,
+        )
+    }
+
+    fn set_public(
+        env: &JNIEnv,
+        public_info: jobject,
+        public: &Public,
+    ) -> Result<(), JavaException> {
+        for (name, sig, value) in public_fields(public) {
+            env.set_field(public_info, name, sig, JValue::Int(value))
+                .map_err(|_| {
+                    JavaException::illegal_argument(format!("Cannot set 'PublicInfo::{}'.", name))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Sends the log of the library to logcat with the tag `legalage`.
+    /// Only the first call has an effect.
+    pub fn init_logging(level: log::Level) {
+        android_logger::init_once(
+            android_logger::Config::default()
+                .with_min_level(level)
+                .with_tag("legalage"),
+        );
+    }
+
+    /// Enables logging to logcat at the `log_level` of `level`. Throws
+    /// `IllegalArgumentException` for an unknown level. Private inputs
+    /// are not logged at any level.
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_initLogging(
+        env: JNIEnv,
+        _: JClass,
+        level: jint,
+    ) {
+        guard(&env, (), || {
+            let level = log_level(level).ok_or_else(|| {
+                JavaException::illegal_argument(format!("Unknown log level {}.", level))
+            })?;
+            init_logging(level);
+            Ok(())
+        })
+    }
+
+    /// Prepares the prover so that the first `generateQrCode` does not
+    /// wait for it, call it from a background thread when the app
+    /// starts. Throws `ZkException` if the installation is corrupt.
+    #[cfg(feature = "prover")]
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_prepare(
+        env: JNIEnv,
+        _: JClass,
+    ) {
+        guard(&env, (), || {
+            phone_api::prepare().map_err(|e| exception_from_error(&e))
+        })
+    }
+
+    /// Prepares the verifier so that the first `validateQrCode` does
+    /// not wait for it, call it from a background thread when the
+    /// scanner starts. Throws `ZkException` if the installation is
+    /// corrupt.
+    #[cfg(feature = "verifier")]
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_prewarmVerifier(
+        env: JNIEnv,
+        _: JClass,
+    ) {
+        guard(&env, (), || {
+            phone_api::prewarm_verifier().map_err(|e| exception_from_error(&e))
+        })
+    }
+
+    /// Loads the artifacts of a downloaded circuit from the files and
+    /// registers them for the requests and QR codes of `circuit`.
+    /// Throws `IllegalArgumentException` for an id out of range or the
+    /// embedded circuit and `ZkException` if a file cannot be read or
+    /// is not a valid artifact.
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_loadCircuit(
+        env: JNIEnv,
+        _: JClass,
+        circuit: jint,
+        program: JString,
+        abi: JString,
+        proving_key: JString,
+        verification_key: JString,
+    ) {
+        guard(&env, (), || {
+            let circuit = circuit_from_int(circuit)?;
+            if circuit == phone_api::EMBEDDED_CIRCUIT {
+                return Err(JavaException::illegal_argument(
+                    "The embedded circuit cannot be replaced.".to_string(),
+                ));
+            }
+            phone_api::load_circuit(
+                circuit,
+                &get_path(&env, program, "program")?,
+                &get_path(&env, abi, "abi")?,
+                &get_path(&env, proving_key, "proving_key")?,
+                &get_path(&env, verification_key, "verification_key")?,
+            )
+            .map_err(|e| exception_from_error(&e))
+        })
+    }
 
+    /// Returns the QR code string or null if an exception has been thrown.
+    #[cfg(feature = "prover")]
     #[no_mangle]
     pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_generateQrCode(
         env: JNIEnv,
@@ -19,93 +411,426 @@ pub mod android {
         private_key: jbyteArray,
         photo_digest: jbyteArray,
     ) -> jstring {
-        let today_rs = env
-            .get_field(public_info, "today", "I")
-            .expect("Cannot extract 'PublicInfo::today'.")
-            .i()
-            .expect("Cannot unwrap 'PublicInfo::today'.") as u32;
-
-        let now_rs = env
-            .get_field(public_info, "now", "I")
-            .expect("Cannot extract 'PublicInfo::now'.")
-            .i()
-            .expect("Cannot unwrap 'PublicInfo::now'.") as u32;
-
-        let relation_rs = match env
-            .get_field(public_info, "relation", "I")
-            .expect("Cannot extract 'PublicInfo::relation'.")
-            .i()
-        {
-            Ok(0) => Relation::Younger,
-            Ok(1) => Relation::Older,
-            _ => panic!("Cannot unwrap 'PublicInfo::relation'."),
-        };
+        guard(&env, ptr::null_mut(), || {
+            let public = get_public(&env, public_info)?;
+            let private = get_private(&env, birthday, private_key, photo_digest)?;
 
-        let delta_rs = env
-            .get_field(public_info, "delta", "I")
-            .expect("Cannot extract 'PublicInfo::delta'.")
-            .i()
-            .expect("Cannot unwrap 'PublicInfo::delta'.") as u32;
-
-        let birthday_rs = birthday as u32;
-
-        let private_key_rs = env
-            .convert_byte_array(private_key)
-            .expect("Cannot unwrap 'private_key'.");
-
-        let photo_digest_rs = env
-            .convert_byte_array(photo_digest)
-            .expect("Cannot unwrap 'photo_digest'.");
-
-        let rq = QrRequest {
-            public: Public {
-                today: today_rs,
-                now: now_rs,
-                relation: relation_rs,
-                delta: delta_rs,
-            },
-            birthday: birthday_rs,
-            private_key: private_key_rs,
-            photos_digest: photo_digest_rs,
-        };
+            let p =
+                phone_api::generate_qr(public, private).map_err(|e| exception_from_error(&e))?;
+
+            let output = env.new_string(p).map_err(|_| JavaException {
+                class: ZK_EXCEPTION,
+                message: "Couldn't create result string!".to_string(),
+            })?;
+
+            Ok(output.into_inner())
+        })
+    }
+
+    /// Same as `generateQrCode`, calls `progress.onProgress(int stage)`
+    /// with the `ProofStage` discriminant when a stage is entered. If
+    /// the callback throws, it is not called again and null is
+    /// returned with the exception of the callback.
+    #[cfg(feature = "prover")]
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_generateQrCodeWithProgress(
+        env: JNIEnv,
+        _: JClass,
+        public_info: jobject,
+        birthday: jint,
+        private_key: jbyteArray,
+        photo_digest: jbyteArray,
+        progress: jobject,
+    ) -> jstring {
+        guard(&env, ptr::null_mut(), || {
+            let public = get_public(&env, public_info)?;
+            let private = get_private(&env, birthday, private_key, photo_digest)?;
+
+            let failed = Cell::new(false);
+            let result = phone_api::generate_qr_with_progress(public, private, |stage| {
+                if !failed.get() {
+                    let args = [JValue::Int(stage as i32)];
+                    failed.set(
+                        env.call_method(progress, "onProgress", "(I)V", &args)
+                            .is_err(),
+                    );
+                }
+            });
+            // The exception of the callback is pending.
+            if failed.get() {
+                return Ok(ptr::null_mut());
+            }
+            let p = result.map_err(|e| exception_from_error(&e))?;
 
-        let p = generate_proof(rq)
-            .expect("Cannot generate proof")
-            .to_string();
+            let output = env.new_string(p).map_err(|_| JavaException {
+                class: ZK_EXCEPTION,
+                message: "Couldn't create result string!".to_string(),
+            })?;
 
-        let output = env.new_string(p).expect("Couldn't create result string!");
+            Ok(output.into_inner())
+        })
+    }
+
+    /// Same as `generateQrCodeWithProgress`, the callback is
+    /// `boolean onProgress(int stage)` and returning false cancels the
+    /// generation before the stage runs. A cancelled generation throws
+    /// `ZkException`.
+    #[cfg(feature = "prover")]
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_generateQrCodeCancellable(
+        env: JNIEnv,
+        _: JClass,
+        public_info: jobject,
+        birthday: jint,
+        private_key: jbyteArray,
+        photo_digest: jbyteArray,
+        progress: jobject,
+    ) -> jstring {
+        guard(&env, ptr::null_mut(), || {
+            let public = get_public(&env, public_info)?;
+            let private = get_private(&env, birthday, private_key, photo_digest)?;
+
+            let failed = Cell::new(false);
+            let cancellation = CancellationToken::new();
+            let result = phone_api::generate_qr_cancellable(
+                public,
+                private,
+                |stage| {
+                    if failed.get() {
+                        return;
+                    }
+                    let args = [JValue::Int(stage as i32)];
+                    match env
+                        .call_method(progress, "onProgress", "(I)Z", &args)
+                        .and_then(|v| v.z())
+                    {
+                        Ok(true) => {}
+                        Ok(false) => cancellation.cancel(),
+                        Err(_) => {
+                            failed.set(true);
+                            cancellation.cancel();
+                        }
+                    }
+                },
+                &cancellation,
+            );
+            // The exception of the callback is pending.
+            if failed.get() {
+                return Ok(ptr::null_mut());
+            }
+            let p = result.map_err(|e| exception_from_error(&e))?;
+
+            let output = env.new_string(p).map_err(|_| JavaException {
+                class: ZK_EXCEPTION,
+                message: "Couldn't create result string!".to_string(),
+            })?;
+
+            Ok(output.into_inner())
+        })
+    }
+
+    /// Returns the result code of `relation_code` or -1 if an exception
+    /// has been thrown. Does not generate the proof.
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_evaluateRelation(
+        env: JNIEnv,
+        _: JClass,
+        public_info: jobject,
+        birthday: jint,
+        private_key: jbyteArray,
+        photo_digest: jbyteArray,
+    ) -> jint {
+        guard(&env, -1, || {
+            let public = get_public(&env, public_info)?;
+            let private = get_private(&env, birthday, private_key, photo_digest)?;
+            Ok(relation_code(&phone_api::evaluate_relation(
+                public, private,
+            )))
+        })
+    }
+
+    /// Returns the result code of `qr_error_code`. Does not verify the
+    /// proof.
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_qrErrorCode(
+        env: JNIEnv,
+        _: JClass,
+        qr_code: JString,
+    ) -> jint {
+        guard(&env, -1, || {
+            let qr_code_rs: String = env
+                .get_string(qr_code)
+                .map_err(|_| {
+                    JavaException::illegal_argument("Cannot extract 'qr_code' string.".to_string())
+                })?
+                .into();
+            Ok(qr_error_code(&qr_code_rs))
+        })
+    }
 
-        output.into_inner()
+    /// Returns the JSON report of `zk::self_test`. Throws `ZkException`
+    /// if the installation is corrupt.
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_selfTest(
+        env: JNIEnv,
+        _: JClass,
+    ) -> jstring {
+        guard(&env, ptr::null_mut(), || {
+            let report = phone_api::self_test().map_err(|e| exception_from_error(&e))?;
+            let output = env.new_string(report).map_err(|_| JavaException {
+                class: ZK_EXCEPTION,
+                message: "Couldn't create result string!".to_string(),
+            })?;
+            Ok(output.into_inner())
+        })
     }
 
+    /// Returns the result code or -1 if an exception has been thrown:
+    /// `VERIFIED`, `NOT_SATISFIED` for a proof which does not verify,
+    /// `PROOF_UNPARSEABLE` for a proof which does not unhide with the
+    /// photo digest, `MALFORMED` for a QR code which cannot be decoded and
+    /// `ZkError::code` of any other error. The decoded public values of
+    /// a verified QR code are written to `public_info`; if its class
+    /// lacks a field, `IllegalArgumentException` is thrown instead of
+    /// returning `VERIFIED`.
+    #[cfg(feature = "verifier")]
     #[no_mangle]
     pub unsafe extern "C" fn Java_eu_legalage_app_logic_RustInterface_validateQrCode(
         env: JNIEnv,
         _: JClass,
         qr_code: JString,
         photo_digest: jbyteArray,
-        _public_info: jobject,
+        public_info: jobject,
     ) -> jint {
-        let qr_code_rs: String = env
-            .get_string(qr_code)
-            .expect("Cannot extract 'qr_code' string.")
-            .into();
+        guard(&env, -1, || {
+            let qr_code_rs: String = env
+                .get_string(qr_code)
+                .map_err(|_| {
+                    JavaException::illegal_argument("Cannot extract 'qr_code' string.".to_string())
+                })?
+                .into();
 
-        let _photo_digest_rs = env
-            .convert_byte_array(photo_digest)
-            .expect("Cannot unwrap 'photo_digest'.");
+            let photo_digest_rs = get_photos_digest(&env, photo_digest)?;
 
-        let result = match &ProofQrCode::from_str(&qr_code_rs) {
-            Ok(qr_parsed) => match verify_proof(qr_parsed, photo_digest_rs) {
-                Ok(()) => {
-                    //		    let proof = ProofQrCode::public_from_str(&qr_code_rs);
-                    // TODO copy public objects
-                    0
-                }
-                _ => 1,
-            },
-            _ => 1,
+            let result = phone_api::verify_qr(&qr_code_rs, &photo_digest_rs);
+            if let Ok(public) = &result {
+                set_public(&env, public_info, public)?;
+            }
+            Ok(result_code(&result))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{QrError, QrSection, Relation, ValidationError};
+    use std::panic::catch_unwind;
+
+    #[test]
+    fn invalid_input_is_illegal_argument() {
+        let e = exception_from_error(&ZkError::InvalidInput(ValidationError::Birthday));
+        assert_eq!(e.class, ILLEGAL_ARGUMENT_EXCEPTION);
+        assert_eq!(e.message, "invalid input: invalid birthday");
+        let e = exception_from_error(&ZkError::QrCode(QrError::Truncated(QrSection::Payload)));
+        assert_eq!(e.class, ILLEGAL_ARGUMENT_EXCEPTION);
+    }
+
+    #[test]
+    fn proving_failure_is_zk_exception() {
+        let e = exception_from_error(&ZkError::KeyDeserialization);
+        assert_eq!(e.class, ZK_EXCEPTION);
+        let e = exception_from_error(&ZkError::CircuitExecution("x".to_string()));
+        assert_eq!(e.class, ZK_EXCEPTION);
+    }
+
+    #[test]
+    fn result_codes_are_distinct() {
+        let public = Public::older_than(6574, 2459000, 1200).unwrap();
+        let verified = result_code(&Ok(public));
+        let parse_error = result_code(&Err(ZkError::QrCode(QrError::Truncated(
+            QrSection::Payload,
+        ))));
+        let failed = result_code(&Err(ZkError::VerificationFailed));
+        assert_eq!(verified, VERIFIED);
+        assert_ne!(parse_error, verified);
+        assert_ne!(failed, verified);
+        assert_ne!(failed, parse_error);
+    }
+
+    #[test]
+    fn qr_error_codes() {
+        assert_eq!(
+            qr_error_code("0OIl"),
+            QrError::Base58(QrSection::Payload).code()
+        );
+        assert_eq!(qr_error_code("a;b"), QrError::PartCount(2).code());
+        assert_eq!(
+            qr_error_code(""),
+            QrError::Truncated(QrSection::Payload).code()
+        );
+        // Malformed for `validateQrCode` either way.
+        assert_ne!(qr_error_code("0OIl"), qr_error_code(""));
+    }
+
+    #[test]
+    fn outcomes_have_own_codes() {
+        assert_eq!(
+            result_code(&Err(ZkError::VerificationFailed)),
+            NOT_SATISFIED
+        );
+        for e in &[
+            ZkError::PhotoMismatch,
+            ZkError::TagMismatch,
+            ZkError::ProofDeserialization,
+        ] {
+            assert_eq!(result_code(&Err(e.clone())), PROOF_UNPARSEABLE);
+        }
+        for e in &[
+            ZkError::QrCode(QrError::InvalidLength),
+            ZkError::InvalidInput(ValidationError::Today),
+        ] {
+            assert_eq!(result_code(&Err(e.clone())), MALFORMED);
+        }
+        assert_eq!(
+            result_code(&Err(ZkError::KeyDeserialization)),
+            ZkError::KeyDeserialization.code()
+        );
+    }
+
+    #[test]
+    fn validation_codes_do_not_collide() {
+        // The codes of the outcomes are not reused by other errors
+        // which `validateQrCode` can return.
+        for e in &[
+            ZkError::KeyDeserialization,
+            ZkError::UnsupportedCircuit(9),
+            ZkError::UnknownKey(9),
+            ZkError::KeyExpired,
+            ZkError::UnboundProof,
+        ] {
+            let code = result_code(&Err(e.clone()));
+            assert!(![VERIFIED, NOT_SATISFIED, PROOF_UNPARSEABLE, MALFORMED].contains(&code));
+        }
+    }
+
+    #[test]
+    fn public_fields_round_trip() {
+        let public = Public::older_than(6574, 2459000, 1200).unwrap();
+        let fields: Vec<i32> = public_fields(&public).iter().map(|f| f.2).collect();
+        assert_eq!(
+            public_from_fields(fields[0], fields[1], fields[3], fields[2]),
+            Ok(public)
+        );
+        for relation in &[-1, 2, 256] {
+            assert_eq!(
+                public_from_fields(2459000, 1200, *relation, 6574)
+                    .unwrap_err()
+                    .class,
+                ILLEGAL_ARGUMENT_EXCEPTION
+            );
+        }
+    }
+
+    #[test]
+    fn negative_values_are_refused_not_wrapped() {
+        for (today, delta) in &[(-1, 6574), (2459000, -1), (i32::MIN, i32::MIN)] {
+            let public = public_from_fields(*today, 1200, 1, *delta).unwrap();
+            assert_eq!(public.today, *today);
+            assert_eq!(public.delta, *delta);
+            let private = private_from_parts(2451000, vec![10], vec![2, 7]).unwrap();
+            let outcome = crate::phone_api::evaluate_relation(public, private);
+            match &outcome {
+                RelationOutcome::InvalidInput(_) => {}
+                _ => panic!("{:?} accepted", (today, delta)),
+            }
+            assert_ne!(relation_code(&outcome), RELATION_SATISFIED);
+            assert_ne!(relation_code(&outcome), RELATION_NOT_SATISFIED);
+        }
+    }
+
+    #[test]
+    fn private_parts_are_checked() {
+        assert!(private_from_parts(2451000, vec![10], vec![2, 7]).is_ok());
+        let e = private_from_parts(2451000, vec![0xff; 32], vec![2, 7]).unwrap_err();
+        assert_eq!(e.class, ILLEGAL_ARGUMENT_EXCEPTION);
+        assert_eq!(e.message, "invalid private_key");
+        let e = private_from_parts(2451000, vec![10], vec![0; 33]).unwrap_err();
+        assert_eq!(e.message, "invalid photos_digest");
+    }
+
+    #[test]
+    fn log_levels() {
+        assert_eq!(log_level(1), Some(log::Level::Error));
+        assert_eq!(log_level(5), Some(log::Level::Trace));
+        assert_eq!(log_level(0), None);
+        assert_eq!(log_level(6), None);
+    }
+
+    #[test]
+    fn relation_codes() {
+        assert_eq!(
+            relation_code(&RelationOutcome::Satisfied),
+            RELATION_SATISFIED
+        );
+        assert_eq!(
+            relation_code(&RelationOutcome::NotSatisfied),
+            RELATION_NOT_SATISFIED
+        );
+        let invalid = relation_code(&RelationOutcome::InvalidInput(ValidationError::Delta));
+        assert_eq!(
+            invalid,
+            ZkError::InvalidInput(ValidationError::Delta).code()
+        );
+        assert_ne!(invalid, RELATION_SATISFIED);
+        assert_ne!(invalid, RELATION_NOT_SATISFIED);
+    }
+
+    #[test]
+    fn public_fields_are_copied() {
+        let public = Public {
+            today: 2459000,
+            now: 1200,
+            relation: Relation::Older,
+            delta: 6574,
+            second: None,
+            nonce: None,
         };
-        result
+        assert_eq!(
+            public_fields(&public),
+            vec![
+                ("today", "I", 2459000),
+                ("now", "I", 1200),
+                ("delta", "I", 6574),
+                ("relation", "I", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn circuit_ids() {
+        assert_eq!(circuit_from_int(2), Ok(2));
+        assert_eq!(circuit_from_int(255), Ok(255));
+        for circuit in &[-1, 256] {
+            assert_eq!(
+                circuit_from_int(*circuit).unwrap_err().class,
+                ILLEGAL_ARGUMENT_EXCEPTION
+            );
+        }
+    }
+
+    #[test]
+    fn panic_is_zk_exception() {
+        let payload = catch_unwind(|| panic!("boom")).unwrap_err();
+        let e = exception_from_panic(&*payload);
+        assert_eq!(e.class, ZK_EXCEPTION);
+        assert_eq!(e.message, "internal error: boom");
+
+        let payload = catch_unwind(|| panic!("code {}", 7)).unwrap_err();
+        assert_eq!(
+            exception_from_panic(&*payload).message,
+            "internal error: code 7"
+        );
     }
 }
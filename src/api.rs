@@ -1,27 +1,372 @@
 use bs58;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use std::io::Cursor;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Cursor, Read};
 
 use std::str::FromStr;
 
 use chrono::{Datelike, NaiveDate};
 
+use crate::base45;
+use crate::date;
+use crate::encoding;
+use crate::manual_entry;
+use crate::qr_chunking::{self, ChunkError};
+use zeroize::Zeroize;
+use zokrates_field::Bn128Field;
+
+/// Length of the serialized Groth16 proof (compressed a, b and c
+/// points on Bn256, 256 bytes uncompressed). All format versions use
+/// the compressed points. Masking does not change the length, sealing
+/// adds the nonce and the tag, see `SEALED_PROOF_LENGTH`.
+pub const PROOF_LENGTH: usize = 128;
+
+/// Version of the binary QR code format written by this library.
+/// Version 2 added the circuit id, version 3 the key id, version 4
+/// the keystream hiding, version 5 the sealing of the proofs and
+/// version 6 the checksum. Older versions are still parsed.
+pub const QR_FORMAT_VERSION: u8 = 6;
+
+/// First format version whose proofs are hidden by a keystream, see
+/// `zk::hiding_mask`. Older proofs are hidden by the repeated photo
+/// digest.
+pub const STREAM_MASK_VERSION: u8 = 4;
+
+/// First format version whose proofs are sealed by ChaCha20-Poly1305
+/// instead of masked, see `zk::hiding_mask`. A sealed proof is the
+/// nonce, the encrypted proof and the tag.
+pub const SEALED_VERSION: u8 = 5;
+
+/// First format version whose binary payload ends with a checksum,
+/// see `ProofQrCode::to_bytes`.
+pub const CHECKSUM_VERSION: u8 = 6;
+
+/// Length of the nonce of a sealed proof.
+pub const SEAL_NONCE_LENGTH: usize = 12;
+
+/// Length of the Poly1305 tag of a sealed proof.
+pub const SEAL_TAG_LENGTH: usize = 16;
+
+/// Length of a sealed proof in the QR code.
+pub const SEALED_PROOF_LENGTH: usize = SEAL_NONCE_LENGTH + PROOF_LENGTH + SEAL_TAG_LENGTH;
+
+/// Length of a hidden proof of the format `version` in the QR code.
+pub const fn hidden_proof_length(version: u8) -> usize {
+    if version >= SEALED_VERSION {
+        SEALED_PROOF_LENGTH
+    } else {
+        PROOF_LENGTH
+    }
+}
+
+/// Length of the checksum at the end of the binary QR code payload of
+/// the format `version`, zero before `CHECKSUM_VERSION`.
+pub const fn checksum_length(version: u8) -> usize {
+    if version >= CHECKSUM_VERSION {
+        CHECKSUM_LENGTH
+    } else {
+        0
+    }
+}
+
+/// Length of the binary QR code payload of the format `version`, 3 or
+/// later, with a single proof, see `ProofQrCode::to_bytes`.
+pub const fn payload_length(version: u8) -> usize {
+    16 + hidden_proof_length(version) + FIELD_LENGTH + checksum_length(version)
+}
+
+/// Length of the binary QR code payload of the format `version`, 3 or
+/// later, of a band with two proofs.
+pub const fn band_payload_length(version: u8) -> usize {
+    payload_length(version) + 5 + hidden_proof_length(version)
+}
+
+/// Length of the header of the version 3 and later layouts.
+const HEADER_LENGTH: usize = 3;
+
+/// Id of the circuit embedded in the library, see
+/// `zk::CircuitRegistry`.
+pub const EMBEDDED_CIRCUIT: u8 = 1;
+
+/// Id of the proving and verification key embedded in the library.
+pub const EMBEDDED_KEY: u8 = 1;
+
+/// Length of the binary QR code payload with a single proof written
+/// by this library, see `ProofQrCode::to_bytes`.
+pub const PAYLOAD_LENGTH: usize = payload_length(QR_FORMAT_VERSION);
+
+/// Length of the binary QR code payload of a band with two proofs.
+pub const BAND_PAYLOAD_LENGTH: usize = band_payload_length(QR_FORMAT_VERSION);
+
+/// Maximal length of the binary QR code payload, a band with a nonce.
+pub const MAX_PAYLOAD_LENGTH: usize = BAND_PAYLOAD_LENGTH + FIELD_LENGTH;
+
+/// Length of the public part of the legacy format with a band.
+const MAX_PUBLIC_LENGTH: usize = 18;
+
+/// Length of the checksum of the binary layout, see
+/// `ProofQrCode::to_bytes`, and of the optional checksum of the legacy
+/// format, see `ProofQrCode::to_checked_legacy_string`.
+pub const CHECKSUM_LENGTH: usize = 4;
+
+/// Length of the `QrRequest` string payload with full length fields
+/// and a band.
+const MAX_REQUEST_LENGTH: usize = 1 + 17 + 2 * (1 + FIELD_LENGTH) + 5;
+
+/// Upper bound of the length of `bytes` bytes encoded in base58.
+pub(crate) const fn base58_length(bytes: usize) -> usize {
+    bytes * 138 / 100 + 1
+}
+
+/// Maximal length of a QR code string accepted by
+/// `ProofQrCode::from_str`, in either format. The legacy format is
+/// shorter by the header bytes.
+pub const MAX_QR_STRING_LENGTH: usize = base58_length(MAX_PAYLOAD_LENGTH);
+
+/// Prefix of the compact QR code string, see
+/// `ProofQrCode::to_string_compact`. The `:` is not a base58
+/// character.
+pub const COMPACT_PREFIX: &str = "LA:";
+
+/// Decodes base58 of at most `max_bytes` bytes. Longer strings are
+/// refused before decoding, so that a malicious QR code cannot make
+/// the decoder allocate much memory.
+pub(crate) fn decode_base58(s: &str, max_bytes: usize) -> Result<Vec<u8>, QrError> {
+    if s.len() > base58_length(max_bytes) {
+        return Err(QrError::InvalidLength);
+    }
+    let bytes = bs58::decode(s).into_vec()?;
+    if bytes.len() > max_bytes {
+        return Err(QrError::InvalidLength);
+    }
+    Ok(bytes)
+}
+
+/// Maximal julian day accepted by the library. Dates after
+/// 22666-12-19 are not supported.
+pub const MAX_JULIAN_DAY: i32 = 9_999_999;
+
+/// Maximal length of a byte-encoded Bn128 field element.
+pub const FIELD_LENGTH: usize = 32;
+
+/// Modulus of the Bn128 scalar field, little-endian as produced by
+/// `Bn128Field::into_byte_vector`.
+const FIELD_MODULUS: [u8; FIELD_LENGTH] = [
+    0x01, 0x00, 0x00, 0xf0, 0x93, 0xf5, 0xe1, 0x43, 0x91, 0x70, 0xb9, 0x79, 0x48, 0xe8, 0x33, 0x28,
+    0x5d, 0x58, 0x81, 0x81, 0xb6, 0x45, 0x50, 0xb8, 0x29, 0xa0, 0x31, 0xe1, 0x72, 0x4e, 0x64, 0x30,
+];
+
+/// Returns true if the bytes encode a Bn128 field element, that is
+/// a little-endian number of at most 32 bytes below the modulus.
+pub fn is_field_element(bytes: &[u8]) -> bool {
+    if bytes.len() > FIELD_LENGTH {
+        return false;
+    }
+    let mut padded = [0u8; FIELD_LENGTH];
+    padded[..bytes.len()].copy_from_slice(bytes);
+    padded.iter().rev().lt(FIELD_MODULUS.iter().rev())
+}
+
+/// Defines a byte vector newtype holding a Bn128 field element. The
+/// types keep the private key, photo digest, card key and challenge
+/// apart so that they cannot be swapped by accident. The bytes are
+/// wiped when the value is dropped.
+macro_rules! field_bytes {
+    ($(#[$doc:meta])* $name:ident, $error:expr) => {
+        $(#[$doc])*
+        #[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub struct $name(Vec<u8>);
+
+        impl $name {
+            /// Checks that the bytes are a little-endian field element.
+            pub fn new(bytes: Vec<u8>) -> Result<Self, ValidationError> {
+                if is_field_element(&bytes) {
+                    Ok($name(bytes))
+                } else {
+                    let mut bytes = bytes;
+                    bytes.zeroize();
+                    Err($error)
+                }
+            }
+
+            pub fn as_bytes(&self) -> &[u8] {
+                &self.0
+            }
+
+            /// Reads the big-endian form of `FIELD_LENGTH` bytes, see
+            /// `encoding::field_from_be_bytes`.
+            pub fn from_be_bytes(bytes: &[u8]) -> Result<Self, ValidationError> {
+                let value = encoding::field_from_be_bytes(bytes).map_err(|_| $error)?;
+                Ok($name::from_field(&value))
+            }
+
+            /// Big-endian form of `FIELD_LENGTH` bytes.
+            pub fn to_be_bytes(&self) -> [u8; FIELD_LENGTH] {
+                encoding::le_to_be(&self.0)
+            }
+
+            pub fn from_field(value: &Bn128Field) -> Self {
+                $name(encoding::field_to_le_bytes(value))
+            }
+
+            pub fn to_field(&self) -> Bn128Field {
+                encoding::field_from_le_bytes(&self.0).expect("checked by new")
+            }
+        }
+
+        impl TryFrom<Vec<u8>> for $name {
+            type Error = ValidationError;
+
+            fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+                $name::new(bytes)
+            }
+        }
+
+        impl From<$name> for Vec<u8> {
+            fn from(mut value: $name) -> Self {
+                std::mem::take(&mut value.0)
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.0.zeroize();
+            }
+        }
+
+        /// Base58 encoding, the same as used in the QR code.
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", bs58::encode(&self.0).into_string())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = QrError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $name::new(decode_base58(s, FIELD_LENGTH)?).map_err(|_| QrError::FieldRange)
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = QrError;
+
+            fn try_from(s: String) -> Result<Self, Self::Error> {
+                s.parse()
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.to_string()
+            }
+        }
+    };
+}
+
+field_bytes!(
+    /// Private key known only to the prover and to the certifier.
+    PrivateKey,
+    ValidationError::PrivateKey
+);
+
+field_bytes!(
+    /// Digest of the photoset printed on the card.
+    PhotosDigest,
+    ValidationError::PhotosDigest
+);
+
+impl PhotosDigest {
+    /// A digest of zero, e.g. an empty one, gives a mask which
+    /// everybody knows, so the proof is not bound to the photo.
+    pub fn is_unbound(&self) -> bool {
+        self.to_field() == Bn128Field::from(0)
+    }
+}
+
+field_bytes!(
+    /// Card key derived from the private values by the certifier.
+    CardKey,
+    ValidationError::CardKey
+);
+
+field_bytes!(
+    /// Public output of the proof computation binding the proof to the
+    /// card and the day.
+    Challenge,
+    ValidationError::Challenge
+);
+
+field_bytes!(
+    /// Random value chosen by the verifier and shown to the prover, so
+    /// that a QR code cannot be generated before the verifier asks for
+    /// it, see `Public::nonce`.
+    Nonce,
+    ValidationError::Nonce
+);
+
 /// Trust level of the verifier.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum VerifierLevel {
     SelfSignedTest,
     HasPublicCertificate,
     Professional,
 }
 
-/// The relation to be proved.
-#[derive(PartialEq, Debug, Clone)]
+/// The relation to be proved. The discriminants are part of the QR
+/// code format. JSON uses the lowercase names of `Display`, the
+/// capitalized names written by older versions are still accepted.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
 pub enum Relation {
-    Younger,
-    Older,
+    #[serde(alias = "Younger")]
+    Younger = 0,
+    #[serde(alias = "Older")]
+    Older = 1,
+}
+
+impl TryFrom<u8> for Relation {
+    type Error = QrError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Relation::Younger),
+            1 => Ok(Relation::Older),
+            _ => Err(QrError::RelationByte(value)),
+        }
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Relation::Younger => write!(f, "younger"),
+            Relation::Older => write!(f, "older"),
+        }
+    }
+}
+
+impl FromStr for Relation {
+    type Err = QrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "younger" => Ok(Relation::Younger),
+            "older" => Ok(Relation::Older),
+            _ => Err(QrError::InvalidRelation),
+        }
+    }
 }
 
 /// Public part of the proof.
-#[derive(Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct Public {
     /// Today julian date.
     pub today: i32,
@@ -35,47 +380,344 @@ pub struct Public {
 
     /// Minimal (maximal) difference between 'today' and 'birthday' in days.
     pub delta: i32,
+
+    /// Optional second relation proved in the same QR code, used to
+    /// check an age band such as "older than 18 and younger than 26".
+    /// The code verifies only if both bounds hold; there are no results
+    /// per bound, as those would tell which bound the holder missed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub second: Option<Bound>,
+
+    /// Optional nonce of the verifier. The circuit has no nonce input,
+    /// the nonce is hashed into the mask hiding the proof instead, see
+    /// `zk::hiding_mask`. A code captured or shared as a picture does
+    /// not verify against a fresh nonce, but the binding is not proved:
+    /// anybody who knows the photo digest can unhide the proof and hide
+    /// it again with another nonce, so the nonce does not stop replay by
+    /// the holder. The nonce is not part of the legacy format, the text
+    /// form and the `QrRequest` string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<Nonce>,
+}
+
+/// Relation and delta of an additional bound.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Bound {
+    pub relation: Relation,
+    pub delta: i32,
+}
+
+/// Minutes in a day, `Public::now` is less than that.
+pub const MINUTES_PER_DAY: i32 = 24 * 60;
+
+fn check_today(today: i32) -> Result<(), ValidationError> {
+    if today <= 0 || today > MAX_JULIAN_DAY {
+        return Err(ValidationError::Today);
+    }
+    Ok(())
+}
+
+fn check_now(now: i32) -> Result<(), ValidationError> {
+    if now < 0 || now >= MINUTES_PER_DAY {
+        return Err(ValidationError::Now);
+    }
+    Ok(())
+}
+
+fn check_delta(delta: i32) -> Result<(), ValidationError> {
+    if delta < 0 || delta > MAX_JULIAN_DAY {
+        return Err(ValidationError::Delta);
+    }
+    Ok(())
 }
 
 impl Public {
+    #[deprecated(note = "use `Public::older_than` or `Public::younger_than`")]
     pub fn new() -> Self {
         Public {
             today: 0,
             now: 0,
             relation: Relation::Younger,
             delta: 0,
+            second: None,
+            nonce: None,
+        }
+    }
+
+    /// Public part of a proof that the holder is at least `delta`
+    /// days old on the julian date `today`. `now` is the UTC time in
+    /// minutes since midnight.
+    pub fn older_than(delta: i32, today: i32, now: i32) -> Result<Self, ValidationError> {
+        Self::with_relation(Relation::Older, delta, today, now)
+    }
+
+    /// Public part of a proof that the holder is less than `delta`
+    /// days old on the julian date `today`.
+    pub fn younger_than(delta: i32, today: i32, now: i32) -> Result<Self, ValidationError> {
+        Self::with_relation(Relation::Younger, delta, today, now)
+    }
+
+    /// Checks the ranges of the day, the time and the deltas. The
+    /// verifier checks the public part of a QR code before using it as
+    /// input of the circuit.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check_today(self.today)?;
+        check_now(self.now)?;
+        check_delta(self.delta)?;
+        if let Some(bound) = &self.second {
+            check_delta(bound.delta)?;
+            // A band is bounded from both sides.
+            if bound.relation == self.relation {
+                return Err(ValidationError::SecondBound);
+            }
+        }
+        Ok(())
+    }
+
+    fn with_relation(
+        relation: Relation,
+        delta: i32,
+        today: i32,
+        now: i32,
+    ) -> Result<Self, ValidationError> {
+        check_delta(delta)?;
+        check_today(today)?;
+        check_now(now)?;
+        Ok(Public {
+            today: today,
+            now: now,
+            relation: relation,
+            delta: delta,
+            second: None,
+            nonce: None,
+        })
+    }
+}
+
+/// Text form of a relation, e.g. `older than 6575 days (18 years)`.
+/// The years are left out if the delta cannot be converted.
+fn write_relation(
+    f: &mut fmt::Formatter,
+    relation: &Relation,
+    delta: i32,
+    today: i32,
+) -> fmt::Result {
+    write!(f, "{} than {} {}", relation, delta, plural(delta, "day"))?;
+    if let Ok((years, days)) = date::years_and_days(delta, today) {
+        write!(f, " ({} {}", years, plural(years, "year"))?;
+        if days != 0 {
+            write!(f, " {} {}", days, plural(days, "day"))?;
+        }
+        write!(f, ")")?;
+    }
+    Ok(())
+}
+
+fn plural(n: i32, unit: &str) -> String {
+    if n == 1 {
+        unit.to_string()
+    } else {
+        format!("{}s", unit)
+    }
+}
+
+/// Parses `write_relation`. The years in parentheses are only
+/// informative and are not parsed.
+fn parse_relation(s: &str) -> Result<(Relation, i32), QrError> {
+    let s = match s.find(" (") {
+        Some(i) if s.ends_with(')') => &s[..i],
+        _ => s,
+    };
+    let words: Vec<&str> = s.split(' ').collect();
+    match words[..] {
+        [relation, "than", delta, unit] => {
+            let delta = delta.parse().map_err(|_| QrError::InvalidText)?;
+            if unit != plural(delta, "day") {
+                return Err(QrError::InvalidText);
+            }
+            Ok((relation.parse()?, delta))
+        }
+        _ => Err(QrError::InvalidText),
+    }
+}
+
+impl fmt::Display for Public {
+    /// English text form for logs and support staff, for example
+    /// `older than 6575 days (18 years) as of 2024-05-17 12:30 UTC`.
+    /// The date is ISO 8601 and does not depend on the locale. Values
+    /// which are not valid dates or times are written as `JD<julian
+    /// day>` and `<minutes>min`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_relation(f, &self.relation, self.delta, self.today)?;
+        if let Some(bound) = &self.second {
+            write!(f, " and ")?;
+            write_relation(f, &bound.relation, bound.delta, self.today)?;
+        }
+        write!(f, " as of ")?;
+        match date::ymd_from_julian(self.today) {
+            Ok((y, m, d)) => write!(f, "{:04}-{:02}-{:02}", y, m, d)?,
+            Err(_) => write!(f, "JD{}", self.today)?,
+        }
+        if check_now(self.now).is_ok() {
+            write!(f, " {:02}:{:02} UTC", self.now / 60, self.now % 60)
+        } else {
+            write!(f, " {}min UTC", self.now)
+        }
+    }
+}
+
+impl FromStr for Public {
+    type Err = QrError;
+
+    /// Parses the text form written by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let at = s.rfind(" as of ").ok_or(QrError::InvalidText)?;
+        let (relations, time) = (&s[..at], &s[at + " as of ".len()..]);
+
+        let mut bounds = relations.split(" and ");
+        let (relation, delta) = parse_relation(bounds.next().ok_or(QrError::InvalidText)?)?;
+        let second = match bounds.next() {
+            Some(bound) => {
+                let (relation, delta) = parse_relation(bound)?;
+                Some(Bound {
+                    relation: relation,
+                    delta: delta,
+                })
+            }
+            None => None,
+        };
+        if bounds.next().is_some() {
+            return Err(QrError::InvalidText);
         }
+
+        let words: Vec<&str> = time.split(' ').collect();
+        let (day, minute) = match words[..] {
+            [day, minute, "UTC"] => (day, minute),
+            _ => return Err(QrError::InvalidText),
+        };
+        let today = match day.strip_prefix("JD") {
+            Some(julian) => julian.parse().map_err(|_| QrError::InvalidText)?,
+            None => {
+                let ymd: Vec<&str> = day.rsplitn(3, '-').collect();
+                match ymd[..] {
+                    [d, m, y] if d.len() == 2 && m.len() == 2 => date::julian_from_ymd(
+                        y.parse().map_err(|_| QrError::InvalidText)?,
+                        m.parse().map_err(|_| QrError::InvalidText)?,
+                        d.parse().map_err(|_| QrError::InvalidText)?,
+                    )
+                    .map_err(|_| QrError::InvalidText)?,
+                    _ => return Err(QrError::InvalidText),
+                }
+            }
+        };
+        let now = match minute.strip_suffix("min") {
+            Some(minutes) => minutes.parse().map_err(|_| QrError::InvalidText)?,
+            None => {
+                let hm: Vec<&str> = minute.split(':').collect();
+                match hm[..] {
+                    [h, m] if h.len() == 2 && m.len() == 2 => {
+                        let h: i32 = h.parse().map_err(|_| QrError::InvalidText)?;
+                        let m: i32 = m.parse().map_err(|_| QrError::InvalidText)?;
+                        if h >= 24 || m >= 60 {
+                            return Err(QrError::InvalidText);
+                        }
+                        h * 60 + m
+                    }
+                    _ => return Err(QrError::InvalidText),
+                }
+            }
+        };
+        Ok(Public {
+            today: today,
+            now: now,
+            relation: relation,
+            delta: delta,
+            second: second,
+            nonce: None,
+        })
+    }
+}
+
+fn read_relation(rdr: &mut Cursor<Vec<u8>>) -> Result<Relation, QrError> {
+    Relation::try_from(rdr.read_u8()?)
+}
+
+fn write_bound(wtr: &mut Vec<u8>, bound: &Option<Bound>) {
+    if let Some(bound) = bound {
+        wtr.push(bound.relation as u8);
+        wtr.write_i32::<BigEndian>(bound.delta).unwrap();
+    }
+}
+
+/// Reads the optional second bound, which is the last item of the
+/// encoded data.
+fn read_bound(rdr: &mut Cursor<Vec<u8>>) -> Result<Option<Bound>, QrError> {
+    if rdr.position() as usize == rdr.get_ref().len() {
+        return Ok(None);
     }
+    let relation = read_relation(rdr)?;
+    let delta = rdr.read_i32::<BigEndian>()?;
+    if rdr.position() as usize != rdr.get_ref().len() {
+        return Err(QrError::InvalidLength);
+    }
+    Ok(Some(Bound {
+        relation: relation,
+        delta: delta,
+    }))
 }
 
-/// Private part of the proof
-#[derive(Debug)]
+/// Private part of the proof. The birthday is wiped when dropped,
+/// the key and the digest wipe themselves. A dropped `QrRequest` is
+/// wiped by its parts.
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Private {
     /// Birthday - julian date. Private part of the proof.
     pub birthday: i32,
 
     /// Private key known only to the prover and to the
-    /// certifier. Big-endian encoded number in Field range. Private
-    /// part of the proof.
-    pub private_key: Vec<u8>,
+    /// certifier. Private part of the proof.
+    pub private_key: PrivateKey,
 
-    /// Digest of the photoset. Big-endian encoded number in Field
-    /// range. Private part of the proof.
-    pub photos_digest: Vec<u8>,
+    /// Digest of the photoset. Private part of the proof.
+    pub photos_digest: PhotosDigest,
 }
 
 impl Private {
+    #[deprecated(note = "use `Private::with`")]
     pub fn new() -> Self {
         Private {
             birthday: 0,
-            private_key: Vec::new(),
-            photos_digest: Vec::new(),
+            private_key: PrivateKey::default(),
+            photos_digest: PhotosDigest::default(),
+        }
+    }
+
+    /// Private part of the proof. The birthday is a julian date, the
+    /// key and the digest are checked by their types.
+    pub fn with(
+        birthday: i32,
+        private_key: PrivateKey,
+        photos_digest: PhotosDigest,
+    ) -> Result<Self, ValidationError> {
+        if birthday <= 0 || birthday > MAX_JULIAN_DAY {
+            return Err(ValidationError::Birthday);
         }
+        Ok(Private {
+            birthday: birthday,
+            private_key: private_key,
+            photos_digest: photos_digest,
+        })
+    }
+}
+
+impl Drop for Private {
+    fn drop(&mut self) {
+        self.birthday.zeroize();
     }
 }
 
 pub fn age_to_delta(birthday: i32, age: i32, relation: Relation) -> i32 {
-    println!("age_to_delta birthday={} age={} relation={:?}", birthday, age, relation);
+    trace!("age_to_delta age={} relation={:?}", age, relation);
     let dbirth = NaiveDate::from_num_days_from_ce(birthday - 1721425);
     let dtest =
         NaiveDate::from_ymd_opt(dbirth.year() + age, dbirth.month(), dbirth.day()).unwrap_or(
@@ -89,54 +731,397 @@ pub fn age_to_delta(birthday: i32, age: i32, relation: Relation) -> i32 {
     }
 }
 
+fn embedded_circuit() -> u8 {
+    EMBEDDED_CIRCUIT
+}
+
 /// Request for QR code generation from phone app.
-#[derive(Debug)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct QrRequest {
+    /// Id of the circuit to prove with.
+    #[serde(default = "embedded_circuit")]
+    pub circuit: u8,
     pub public: Public,
     pub private: Private,
+    /// Proves also with a photo digest of zero, e.g. an empty one.
+    /// Such a proof is not bound to a photo: anybody unhides it with
+    /// the zero digest. Only for tests and the test values of the
+    /// command line tool, the flag is not kept by `to_qr_code_string`.
+    #[serde(default)]
+    pub allow_unbound_proof: bool,
 }
 
 impl QrRequest {
+    #[deprecated(note = "use `QrRequest::build`")]
+    #[allow(deprecated)]
     pub fn new() -> Self {
         QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
             public: Public::new(),
             private: Private::new(),
+            allow_unbound_proof: false,
+        }
+    }
+
+    /// Request of the public and private parts which passes
+    /// `validate`.
+    pub fn build(public: Public, private: Private) -> Result<Self, ValidationError> {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: public,
+            private: private,
+            allow_unbound_proof: false,
+        };
+        rq.validate()?;
+        Ok(rq)
+    }
+
+    /// Encodes the whole request, including the private part, into
+    /// a base58 string. Used by the phone app to persist a pending
+    /// request between enrollment and proving. The first byte is the
+    /// circuit id.
+    pub fn to_qr_code_string(&self) -> String {
+        let mut wtr = vec![self.circuit];
+        wtr.write_i32::<BigEndian>(self.public.today).unwrap();
+        wtr.write_i32::<BigEndian>(self.public.now).unwrap();
+        wtr.write_i32::<BigEndian>(self.public.delta).unwrap();
+        wtr.push(self.public.relation as u8);
+        wtr.write_i32::<BigEndian>(self.private.birthday).unwrap();
+        for field in &[
+            self.private.private_key.as_bytes(),
+            self.private.photos_digest.as_bytes(),
+        ] {
+            wtr.push(field.len() as u8);
+            wtr.extend_from_slice(field);
         }
+        write_bound(&mut wtr, &self.public.second);
+        bs58::encode(wtr).into_string()
     }
 
-    pub fn to_qr_code_string() -> String {
-        String::from("")
+    pub fn from_qr_code_string(qr_str: &str) -> Result<Self, QrError> {
+        let mut rdr = Cursor::new(decode_base58(qr_str, MAX_REQUEST_LENGTH)?);
+
+        let circuit = rdr.read_u8()?;
+        let today = rdr.read_i32::<BigEndian>()?;
+        let now = rdr.read_i32::<BigEndian>()?;
+        let delta = rdr.read_i32::<BigEndian>()?;
+        let relation = read_relation(&mut rdr)?;
+        let birthday = rdr.read_i32::<BigEndian>()?;
+        let private_key =
+            PrivateKey::new(Self::read_field_bytes(&mut rdr)?).map_err(|_| QrError::FieldRange)?;
+        let photos_digest = PhotosDigest::new(Self::read_field_bytes(&mut rdr)?)
+            .map_err(|_| QrError::FieldRange)?;
+        let second = read_bound(&mut rdr)?;
+
+        Ok(QrRequest {
+            circuit: circuit,
+            public: Public {
+                today: today,
+                now: now,
+                relation: relation,
+                delta: delta,
+                second: second,
+                nonce: None,
+            },
+            private: Private {
+                birthday: birthday,
+                private_key: private_key,
+                photos_digest: photos_digest,
+            },
+            allow_unbound_proof: false,
+        })
+    }
+
+    fn read_field_bytes(rdr: &mut Cursor<Vec<u8>>) -> Result<Vec<u8>, QrError> {
+        let len = rdr.read_u8()? as usize;
+        if len > FIELD_LENGTH {
+            return Err(QrError::InvalidLength);
+        }
+        let mut field = vec![0u8; len];
+        rdr.read_exact(&mut field)?;
+        Ok(field)
     }
 
-    pub fn from_qr_code_string(_qr_str: &str) -> Self {
-        QrRequest::new()
+    /// Checks ranges of all request fields and that the birthday is
+    /// not after today. Requests which pass the validation can be
+    /// safely converted to field elements. The private key and the
+    /// photo digest are checked by their types.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let birthday = self.private.birthday;
+        let today = self.public.today;
+        if birthday <= 0 || birthday > today {
+            return Err(ValidationError::Birthday);
+        }
+        self.public.validate()
     }
 
-    pub fn is_relation_valid(&self) -> bool {
-        match self.public.relation {
-            Relation::Younger => self.private.birthday + self.public.delta > self.public.today,
-            Relation::Older => self.private.birthday + self.public.delta < self.public.today,
+    /// Refuses a photo digest of zero unless `allow_unbound_proof` is
+    /// set, see `PhotosDigest::is_unbound`.
+    pub fn check_photo_binding(&self) -> Result<(), ValidationError> {
+        if self.private.photos_digest.is_unbound() && !self.allow_unbound_proof {
+            return Err(ValidationError::PhotosDigest);
         }
+        Ok(())
+    }
+
+    /// Tells whether the relation, and the second bound if there is
+    /// one, holds for the birthday. The request is validated first, so
+    /// that `false` means a sane request which is not satisfied and
+    /// never a wrapped sum of values out of range.
+    pub fn is_relation_valid(&self) -> Result<bool, ValidationError> {
+        self.validate()?;
+        let (birthday, today) = (self.private.birthday, self.public.today);
+        // Both bounds are evaluated, a decoy does not take a shorter
+        // path.
+        Ok(
+            relation_holds(birthday, today, self.public.delta, self.public.relation)
+                & match &self.public.second {
+                    Some(bound) => relation_holds(birthday, today, bound.delta, bound.relation),
+                    None => true,
+                },
+        )
+    }
+}
+
+/// The relation proved by the circuit: "younger than" is `birthday +
+/// delta > today`, "older than" is `birthday + delta < today`. Both
+/// are strict, on the day `birthday + delta == today` neither holds,
+/// so a holder who turns 18 today proves "older than" the delta of 18
+/// years minus one day, see `policy::AgePolicy::at_least_years`.
+pub fn relation_holds(birthday: i32, today: i32, delta: i32, relation: Relation) -> bool {
+    let limit = i64::from(birthday) + i64::from(delta);
+    match relation {
+        Relation::Younger => limit > i64::from(today),
+        Relation::Older => limit < i64::from(today),
     }
 }
 
 /// QR code containing the proof. Is generated by the prover and
 /// verified by the verifier
-#[derive(Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct ProofQrCode {
+    /// Format version of the QR code, `QR_FORMAT_VERSION` for newly
+    /// generated codes, 0 for codes parsed from the legacy format.
+    /// The version selects how the proofs are hidden.
+    #[serde(default)]
+    pub version: u8,
+
+    /// Id of the circuit the proof was generated with.
+    #[serde(default = "embedded_circuit")]
+    pub circuit: u8,
+
+    /// Id of the proving key. Codes before version 3 do not have it,
+    /// the verifier tries all its keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<u8>,
+
     /// Public part of the proof.
     pub public: Public,
 
-    // Proof a,b,c curve points hidden by photo digest.
+    // Proof a,b,c curve points hidden by the mask of the version or
+    // sealed, see `hidden_proof_length`.
+    #[serde(with = "base58_proof")]
     pub proof: Vec<u8>,
 
-    /// Challenge. Big-endian encoded number in Field
-    /// range. Public output of the proof computation.
-    pub challenge: Vec<u8>,
+    /// Challenge. Public output of the proof computation.
+    pub challenge: Challenge,
+
+    /// Hidden proof of the second bound if `public.second` is set.
+    /// The challenge does not depend on the relation, so it is shared
+    /// by both proofs.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "base58_second_proof"
+    )]
+    pub second_proof: Option<Vec<u8>>,
+}
+
+/// Invalid field of a `QrRequest`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ValidationError {
+    Birthday,
+    Today,
+    Delta,
+    Now,
+    SecondBound,
+    PrivateKey,
+    PhotosDigest,
+    CardKey,
+    Challenge,
+    Nonce,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let field = match self {
+            ValidationError::Birthday => "birthday",
+            ValidationError::Today => "today",
+            ValidationError::Delta => "delta",
+            ValidationError::Now => "now",
+            ValidationError::SecondBound => "second bound",
+            ValidationError::PrivateKey => "private_key",
+            ValidationError::PhotosDigest => "photos_digest",
+            ValidationError::CardKey => "card_key",
+            ValidationError::Challenge => "challenge",
+            ValidationError::Nonce => "nonce",
+        };
+        write!(f, "invalid {}", field)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Part of a QR code string in which decoding failed.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrSection {
+    /// The whole binary layout of `ProofQrCode::to_bytes`, a request
+    /// or a single key, digest or challenge.
+    Payload,
+    /// The public part of the legacy format.
+    Public,
+    /// The proof part of the legacy format.
+    Proof,
+    /// The challenge part of the legacy format.
+    Challenge,
+    /// The checksum part of the checked legacy format.
+    Checksum,
+}
+
+impl fmt::Display for QrSection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QrSection::Payload => write!(f, "payload"),
+            QrSection::Public => write!(f, "public part"),
+            QrSection::Proof => write!(f, "proof"),
+            QrSection::Challenge => write!(f, "challenge"),
+            QrSection::Checksum => write!(f, "checksum"),
+        }
+    }
+}
+
+/// Error decoding a QR code string.
+#[derive(PartialEq, Debug, Clone)]
+pub enum QrError {
+    /// A section is not valid base58.
+    Base58(QrSection),
+    /// The data of a section ended before all fields were read.
+    Truncated(QrSection),
+    /// A field or the whole payload has unexpected length.
+    InvalidLength,
+    /// Unknown relation of the text form.
+    InvalidRelation,
+    /// Unknown relation byte of the binary forms.
+    RelationByte(u8),
+    /// Wrong number of `;` separated parts.
+    PartCount(usize),
+    /// The checksum of the binary layout or of the legacy format does
+    /// not match, the code has been scanned wrongly and should be
+    /// scanned again.
+    ChecksumMismatch,
+    /// The unhidden proof bytes are not valid curve points.
+    ProofPoints,
+    /// The binary format version is not known.
+    UnsupportedVersion(u8),
+    /// A key, digest or challenge is not a field element.
+    FieldRange,
+    /// The frames of a chunked QR code cannot be assembled.
+    Chunk(ChunkError),
+    /// The text form of `Public` cannot be parsed.
+    InvalidText,
+    /// The compact string is not valid base45.
+    Base45,
+    /// The group with this 1-based number of a chunked string does not
+    /// match its check character and should be typed again.
+    ChunkCheck(usize),
+    /// The tag of a sealed proof does not match its key.
+    ProofTag,
+}
+
+impl fmt::Display for QrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QrError::Base58(section) => write!(f, "invalid base58 encoding of the {}", section),
+            QrError::Truncated(section) => write!(f, "truncated {}", section),
+            QrError::InvalidLength => write!(f, "invalid field length"),
+            QrError::InvalidRelation => write!(f, "unknown relation"),
+            QrError::RelationByte(b) => write!(f, "unknown relation byte {}", b),
+            QrError::PartCount(n) => write!(f, "expected 3 or 4 parts, found {}", n),
+            QrError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            QrError::ProofPoints => write!(f, "proof is not a valid set of curve points"),
+            QrError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            QrError::FieldRange => write!(f, "value out of field range"),
+            QrError::Chunk(e) => write!(f, "{}", e),
+            QrError::InvalidText => write!(f, "invalid text form"),
+            QrError::Base45 => write!(f, "invalid base45 encoding"),
+            QrError::ChunkCheck(n) => write!(f, "group {} does not match its check character", n),
+            QrError::ProofTag => write!(f, "proof tag does not match"),
+        }
+    }
+}
+
+impl std::error::Error for QrError {}
+
+impl QrError {
+    /// Integer code of the error, stable across versions, for the
+    /// verifiers which log why a code was refused, see
+    /// `web_api::ErrorReport`. Zero is reserved for success.
+    pub fn code(&self) -> i32 {
+        match self {
+            QrError::Base58(_) => 1,
+            QrError::Truncated(_) => 2,
+            QrError::InvalidLength => 3,
+            QrError::InvalidRelation => 4,
+            QrError::RelationByte(_) => 5,
+            QrError::PartCount(_) => 6,
+            QrError::ChecksumMismatch => 7,
+            QrError::ProofPoints => 8,
+            QrError::UnsupportedVersion(_) => 9,
+            QrError::FieldRange => 10,
+            QrError::Chunk(_) => 11,
+            QrError::InvalidText => 12,
+            QrError::Base45 => 13,
+            QrError::ChunkCheck(_) => 14,
+            QrError::ProofTag => 15,
+        }
+    }
+
+    /// Section of the errors which have one.
+    pub fn section(&self) -> Option<QrSection> {
+        match self {
+            QrError::Base58(section) | QrError::Truncated(section) => Some(*section),
+            _ => None,
+        }
+    }
+
+    /// The same error located in `section` instead of the payload.
+    fn in_section(self, section: QrSection) -> Self {
+        match self {
+            QrError::Base58(_) => QrError::Base58(section),
+            QrError::Truncated(_) => QrError::Truncated(section),
+            e => e,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct QrError {}
+impl From<bs58::decode::Error> for QrError {
+    fn from(_: bs58::decode::Error) -> Self {
+        QrError::Base58(QrSection::Payload)
+    }
+}
+
+impl From<ChunkError> for QrError {
+    fn from(e: ChunkError) -> Self {
+        QrError::Chunk(e)
+    }
+}
+
+impl From<std::io::Error> for QrError {
+    fn from(_: std::io::Error) -> Self {
+        QrError::Truncated(QrSection::Payload)
+    }
+}
 
 impl ProofQrCode {
     pub fn public_to_string(&self) -> String {
@@ -144,70 +1129,1724 @@ impl ProofQrCode {
         wtr.write_i32::<BigEndian>(self.public.today).unwrap();
         wtr.write_i32::<BigEndian>(self.public.now).unwrap();
         wtr.write_i32::<BigEndian>(self.public.delta).unwrap();
-        wtr.push(self.public.relation.clone() as u8);
+        wtr.push(self.public.relation as u8);
+        write_bound(&mut wtr, &self.public.second);
         bs58::encode(wtr).into_string()
     }
 
     pub fn public_from_str(s: &str) -> Result<Public, QrError> {
-        let mut rdr = Cursor::new(bs58::decode(s).into_vec().map_err(|_| QrError {})?);
+        Self::read_public(s).map_err(|e| e.in_section(QrSection::Public))
+    }
 
-        let today = rdr.read_i32::<BigEndian>().map_err(|_| QrError {})?;
-        let now = rdr.read_i32::<BigEndian>().map_err(|_| QrError {})?;
-        let delta = rdr.read_i32::<BigEndian>().map_err(|_| QrError {})?;
-        const YOUNGER: u8 = Relation::Younger as u8;
-        let relation = match rdr.read_u8().map_err(|_| QrError {})? {
-            YOUNGER => Relation::Younger,
-            _ => Relation::Older,
-        };
+    fn read_public(s: &str) -> Result<Public, QrError> {
+        let mut rdr = Cursor::new(decode_base58(s, MAX_PUBLIC_LENGTH)?);
+
+        let today = rdr.read_i32::<BigEndian>()?;
+        let now = rdr.read_i32::<BigEndian>()?;
+        let delta = rdr.read_i32::<BigEndian>()?;
+        let relation = read_relation(&mut rdr)?;
+        let second = read_bound(&mut rdr)?;
         Ok(Public {
             today: today,
             now: now,
             delta: delta,
             relation: relation,
+            second: second,
+            nonce: None,
         })
     }
 
+    /// Encodes the proof, followed by the second proof if there is one.
     pub fn proof_to_string(&self) -> String {
-        bs58::encode(&self.proof).into_string()
+        let mut proofs = self.proof.clone();
+        if let Some(second) = &self.second_proof {
+            proofs.extend_from_slice(second);
+        }
+        bs58::encode(&proofs).into_string()
     }
 
     pub fn proof_from_str(s: &str) -> Result<Vec<u8>, QrError> {
-        bs58::decode(s).into_vec().map_err(|_| QrError {})
+        decode_base58(s, 2 * PROOF_LENGTH).map_err(|e| e.in_section(QrSection::Proof))
     }
 
     pub fn challenge_to_string(&self) -> String {
-        bs58::encode(&self.challenge).into_string()
+        self.challenge.to_string()
     }
 
-    pub fn challenge_from_str(s: &str) -> Result<Vec<u8>, QrError> {
-        bs58::decode(s).into_vec().map_err(|_| QrError {})
+    pub fn challenge_from_str(s: &str) -> Result<Challenge, QrError> {
+        s.parse()
+            .map_err(|e: QrError| e.in_section(QrSection::Challenge))
     }
 }
 
-impl ToString for ProofQrCode {
-    fn to_string(&self) -> String {
-        let parts = vec![
-            self.public_to_string(),
+impl ProofQrCode {
+    /// Encodes the QR code into the fixed binary layout suitable for
+    /// QR binary mode:
+    ///
+    /// | bytes | field                          |
+    /// |-------|--------------------------------|
+    /// | 1     | format version                 |
+    /// | 1     | circuit id                     |
+    /// | 1     | key id                         |
+    /// | 4     | today, big-endian              |
+    /// | 4     | now, big-endian                |
+    /// | 4     | delta, big-endian              |
+    /// | 1     | relation                       |
+    /// | 156   | sealed proof                   |
+    /// | 32    | challenge, zero padded         |
+    ///
+    /// A band appends the second relation (1 byte), delta (4 bytes)
+    /// and sealed proof (156 bytes), then a nonce is appended, zero
+    /// padded to 32 bytes. The payload ends with the checksum, the
+    /// first `CHECKSUM_LENGTH` bytes of SHA-256 of all the bytes before
+    /// it, so that a corrupted scan is refused as
+    /// `QrError::ChecksumMismatch`. This gives `PAYLOAD_LENGTH` bytes
+    /// for a single proof and `BAND_PAYLOAD_LENGTH` for a band. Before
+    /// version 6 there is no checksum, before version 5 the proofs are
+    /// masked, 128 bytes each, see `hidden_proof_length`.
+    ///
+    /// Codes of version 4 and later keep their version, the key id 0
+    /// stands for no key id. Older codes keep their repeated mask: a
+    /// code with a key id is written as version 3, a code without it in
+    /// the version 2 layout, which has no key id byte.
+    ///
+    /// A proof whose length does not match the version, or a band
+    /// without its second proof, is refused as `QrError::InvalidLength`
+    /// as it could not be read back.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, QrError> {
+        let proof_length = hidden_proof_length(self.version);
+        let second_length = self.second_proof.as_ref().map(Vec::len);
+        if self.proof.len() != proof_length
+            || self.public.second.is_some() != second_length.is_some()
+            || second_length.map_or(false, |n| n != proof_length)
+        {
+            return Err(QrError::InvalidLength);
+        }
+
+        let mut wtr = match (self.version >= STREAM_MASK_VERSION, self.key_id) {
+            (true, key_id) => vec![self.version, self.circuit, key_id.unwrap_or(0)],
+            (false, Some(key_id)) => vec![3, self.circuit, key_id],
+            (false, None) => vec![2, self.circuit],
+        };
+        wtr.write_i32::<BigEndian>(self.public.today).unwrap();
+        wtr.write_i32::<BigEndian>(self.public.now).unwrap();
+        wtr.write_i32::<BigEndian>(self.public.delta).unwrap();
+        wtr.push(self.public.relation as u8);
+        wtr.extend_from_slice(&self.proof);
+        let mut challenge = self.challenge.as_bytes().to_vec();
+        challenge.resize(FIELD_LENGTH, 0);
+        wtr.extend_from_slice(&challenge);
+        if let (Some(bound), Some(second_proof)) = (&self.public.second, &self.second_proof) {
+            write_bound(&mut wtr, &Some(bound.clone()));
+            wtr.extend_from_slice(second_proof);
+        }
+        if let Some(nonce) = &self.public.nonce {
+            let mut nonce = nonce.as_bytes().to_vec();
+            nonce.resize(FIELD_LENGTH, 0);
+            wtr.extend_from_slice(&nonce);
+        }
+        if self.version >= CHECKSUM_VERSION {
+            let checksum = payload_checksum(&wtr);
+            wtr.extend_from_slice(&checksum);
+        }
+        Ok(wtr)
+    }
+
+    /// Decodes the binary layout described at `to_bytes`. Version 1
+    /// codes have no circuit id and were all generated by the
+    /// embedded circuit, versions 1 and 2 have no key id. The checksum
+    /// of version 6 and later is checked before any field is decoded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, QrError> {
+        let (version, header) = match bytes.first() {
+            Some(&version) if version >= 1 && version <= QR_FORMAT_VERSION => {
+                // Versions 1 to 3 each added one header byte.
+                (version, HEADER_LENGTH.min(version as usize))
+            }
+            Some(&version) => return Err(QrError::UnsupportedVersion(version)),
+            None => return Err(QrError::Truncated(QrSection::Payload)),
+        };
+        let proof_length = hidden_proof_length(version);
+        let trailer = checksum_length(version);
+        let single = payload_length(version) - HEADER_LENGTH + header - trailer;
+        let band = single + band_payload_length(version) - payload_length(version);
+        let length = match bytes.len().saturating_sub(trailer) {
+            n if n == single || n == band => n,
+            n if n == single + FIELD_LENGTH || n == band + FIELD_LENGTH => n - FIELD_LENGTH,
+            _ => return Err(QrError::InvalidLength),
+        };
+        let (bytes, checksum) = bytes.split_at(bytes.len() - trailer);
+        if !checksum.is_empty() && checksum[..] != payload_checksum(bytes)[..] {
+            return Err(QrError::ChecksumMismatch);
+        }
+        let nonce = if length < bytes.len() {
+            Some(Nonce::new(bytes[length..].to_vec()).map_err(|_| QrError::FieldRange)?)
+        } else {
+            None
+        };
+        let circuit = if version >= 2 {
+            bytes[1]
+        } else {
+            EMBEDDED_CIRCUIT
+        };
+        let key_id = match version {
+            1 | 2 => None,
+            3 => Some(bytes[2]),
+            _ if bytes[2] == 0 => None,
+            _ => Some(bytes[2]),
+        };
+        let mut rdr = Cursor::new(bytes[header..length].to_vec());
+        let today = rdr.read_i32::<BigEndian>()?;
+        let now = rdr.read_i32::<BigEndian>()?;
+        let delta = rdr.read_i32::<BigEndian>()?;
+        let relation = read_relation(&mut rdr)?;
+        let mut proof = vec![0u8; proof_length];
+        rdr.read_exact(&mut proof)?;
+        let mut challenge = vec![0u8; FIELD_LENGTH];
+        rdr.read_exact(&mut challenge)?;
+        let challenge = Challenge::new(challenge).map_err(|_| QrError::FieldRange)?;
+        let (second, second_proof) = if length == band {
+            let relation = read_relation(&mut rdr)?;
+            let delta = rdr.read_i32::<BigEndian>()?;
+            let mut second_proof = vec![0u8; proof_length];
+            rdr.read_exact(&mut second_proof)?;
+            (
+                Some(Bound {
+                    relation: relation,
+                    delta: delta,
+                }),
+                Some(second_proof),
+            )
+        } else {
+            (None, None)
+        };
+        Ok(ProofQrCode {
+            version: version,
+            circuit: circuit,
+            key_id: key_id,
+            public: Public {
+                today: today,
+                now: now,
+                relation: relation,
+                delta: delta,
+                second: second,
+                nonce: nonce,
+            },
+            proof: proof,
+            challenge: challenge,
+            second_proof: second_proof,
+        })
+    }
+
+    /// Splits `to_bytes` into QR code frames with at most `max_chunk`
+    /// bytes each, for cameras which cannot scan the whole code.
+    pub fn to_chunked_strings(&self, max_chunk: usize) -> Result<Vec<String>, QrError> {
+        Ok(qr_chunking::split_payload(&self.to_bytes()?, max_chunk))
+    }
+
+    /// Decodes the QR code from all frames of `to_chunked_strings` in
+    /// any order.
+    pub fn from_chunks<'a, I>(frames: I) -> Result<Self, QrError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Self::from_bytes(&qr_chunking::join_frames(frames)?)
+    }
+
+    /// Base32 of `to_bytes` in space separated groups of `chunk`
+    /// characters, each followed by a check character, for typing the
+    /// code by hand when it cannot be scanned. The alphabet has digits
+    /// and upper case letters only. Panics if `chunk` is zero.
+    pub fn to_chunked_string(&self, chunk: usize) -> Result<String, QrError> {
+        Ok(manual_entry::encode(&self.to_bytes()?, chunk))
+    }
+
+    /// Reads `to_chunked_string` of the same `chunk` as typed by the
+    /// verifier. Whitespace is ignored anywhere and letters are read in
+    /// any case, `O` as `0`, `I` and `L` as `1`. A group which fails its
+    /// check is reported as `QrError::ChunkCheck`.
+    pub fn from_chunked_string(s: &str, chunk: usize) -> Result<Self, QrError> {
+        Self::from_bytes(&manual_entry::decode(s, chunk).map_err(QrError::ChunkCheck)?)
+    }
+
+    /// Encodes `to_bytes` in base45 after `COMPACT_PREFIX`. The string
+    /// is longer than `to_string`, but all its characters fit the
+    /// alphanumeric mode of QR codes, which needs about a quarter less
+    /// modules than the byte mode of base58. Such a code is easier to
+    /// scan in poor light. `from_str` reads both.
+    pub fn to_string_compact(&self) -> Result<String, QrError> {
+        let bytes = self.to_bytes()?;
+        Ok(format!("{}{}", COMPACT_PREFIX, base45::encode(&bytes)))
+    }
+
+    /// Encodes the QR code in the original format of three base58
+    /// parts separated by `;`. Kept for verifiers which do not
+    /// understand the binary layout. The legacy format has no circuit
+    /// id, it is meant for the embedded circuit only. Proofs are read
+    /// with the repeated mask of version 0, so codes of version 4 and
+    /// later do not verify in this format.
+    pub fn to_legacy_string(&self) -> String {
+        let parts = vec![
+            self.public_to_string(),
             self.proof_to_string(),
             self.challenge_to_string(),
         ];
         parts.join(";")
     }
-}
 
-impl FromStr for ProofQrCode {
-    type Err = QrError;
+    /// Same as `to_legacy_string` followed by a fourth part, the base58
+    /// checksum of the three parts. A corrupted scan is then reported
+    /// as `QrError::ChecksumMismatch` instead of decoding to garbage,
+    /// the same as for the binary layout of `to_string`. Verifiers
+    /// which do not know the checksum refuse the code.
+    pub fn to_checked_legacy_string(&self) -> String {
+        let legacy = self.to_legacy_string();
+        let checksum = legacy_checksum(&legacy);
+        format!("{};{}", legacy, bs58::encode(checksum).into_string())
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split(";").collect();
+    /// Parses three parts and, if there is a fourth, checks the checksum
+    /// before decoding anything. Both are version 0.
+    fn from_legacy_str(s: &str) -> Result<Self, QrError> {
+        if s.len() > MAX_QR_STRING_LENGTH {
+            return Err(QrError::InvalidLength);
+        }
+        let mut parts: Vec<&str> = s.split(";").collect();
+        if parts.len() == 4 {
+            let checksum = decode_base58(parts[3], CHECKSUM_LENGTH)
+                .map_err(|e| e.in_section(QrSection::Checksum))?;
+            let checked = &s[..s.len() - parts[3].len() - 1];
+            if checksum[..] != legacy_checksum(checked)[..] {
+                return Err(QrError::ChecksumMismatch);
+            }
+            parts.pop();
+        }
         if parts.len() != 3 {
-            Err(QrError {})
+            Err(QrError::PartCount(parts.len()))
         } else {
+            let public = Self::public_from_str(parts[0])?;
+            let mut proof = Self::proof_from_str(parts[1])?;
+            let second_proof = if public.second.is_some() {
+                if proof.len() != 2 * PROOF_LENGTH {
+                    return Err(QrError::InvalidLength);
+                }
+                Some(proof.split_off(PROOF_LENGTH))
+            } else {
+                if proof.len() != PROOF_LENGTH {
+                    return Err(QrError::InvalidLength);
+                }
+                None
+            };
             Ok(ProofQrCode {
-                public: Self::public_from_str(parts[0])?,
-                proof: Self::proof_from_str(parts[1])?,
+                version: 0,
+                circuit: EMBEDDED_CIRCUIT,
+                key_id: None,
+                public: public,
+                proof: proof,
                 challenge: Self::challenge_from_str(parts[2])?,
+                second_proof: second_proof,
             })
         }
     }
 }
+
+impl ToString for ProofQrCode {
+    /// Base58 encoding of `to_bytes`, or the legacy format for
+    /// version 0 codes of the embedded circuit without a nonce. Panics
+    /// if `to_bytes` refuses the proofs, which does not happen for the
+    /// codes of `zk::generate_proof`.
+    fn to_string(&self) -> String {
+        if self.version == 0
+            && self.circuit == EMBEDDED_CIRCUIT
+            && self.key_id.is_none()
+            && self.public.nonce.is_none()
+        {
+            self.to_legacy_string()
+        } else {
+            let bytes = self.to_bytes().expect("proofs of the format version");
+            bs58::encode(bytes).into_string()
+        }
+    }
+}
+
+/// First `CHECKSUM_LENGTH` bytes of SHA-256 of the binary payload
+/// before the checksum.
+fn payload_checksum(payload: &[u8]) -> Vec<u8> {
+    Sha256::digest(payload)[..CHECKSUM_LENGTH].to_vec()
+}
+
+/// First `CHECKSUM_LENGTH` bytes of SHA-256 of the three `;`
+/// separated parts of the legacy format.
+fn legacy_checksum(legacy: &str) -> Vec<u8> {
+    Sha256::digest(legacy.as_bytes())[..CHECKSUM_LENGTH].to_vec()
+}
+
+impl FromStr for ProofQrCode {
+    type Err = QrError;
+
+    /// Parses the base58 and the compact base45 binary layout and the
+    /// legacy `;` separated format, which is considered to be version
+    /// 0. Strings longer than the longest payload are refused before
+    /// decoding.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(compact) = s.strip_prefix(COMPACT_PREFIX) {
+            let bytes = base45::decode(compact, MAX_PAYLOAD_LENGTH).ok_or(QrError::Base45)?;
+            Self::from_bytes(&bytes)
+        } else if s.contains(';') {
+            Self::from_legacy_str(s)
+        } else {
+            Self::from_bytes(&decode_base58(s, MAX_PAYLOAD_LENGTH)?)
+        }
+    }
+}
+
+/// Serde helper encoding byte vectors as base58 strings, the same
+/// encoding as used in the QR code.
+pub(crate) mod base58_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&bs58::encode(bytes).into_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        bs58::decode(&s)
+            .into_vec()
+            .map_err(|e| D::Error::custom(format!("invalid base58: {}", e)))
+    }
+}
+
+/// Serde helper for the hidden proof. The points cannot be checked
+/// before unhiding, but a proof which is neither masked nor sealed is
+/// refused.
+mod base58_proof {
+    use super::{PROOF_LENGTH, SEALED_PROOF_LENGTH};
+    use serde::{de::Error, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        super::base58_bytes::serialize(bytes, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let proof = super::base58_bytes::deserialize(deserializer)?;
+        if proof.len() != PROOF_LENGTH && proof.len() != SEALED_PROOF_LENGTH {
+            return Err(D::Error::custom(format!(
+                "invalid proof length {}, expected {} or {}",
+                proof.len(),
+                PROOF_LENGTH,
+                SEALED_PROOF_LENGTH
+            )));
+        }
+        Ok(proof)
+    }
+}
+
+/// Serde helper for the optional second proof.
+mod base58_second_proof {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => super::base58_proof::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super::base58_proof")] Vec<u8>);
+
+        let wrapper = Option::<Wrapper>::deserialize(deserializer)?;
+        Ok(wrapper.map(|Wrapper(bytes)| bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_alloc::{wiped_on_drop, wiped_when_freed};
+
+    fn public() -> Public {
+        Public {
+            today: 2459000,
+            now: 720,
+            relation: Relation::Older,
+            delta: 6575,
+            second: None,
+            nonce: None,
+        }
+    }
+
+    fn private() -> Private {
+        Private {
+            birthday: 2451000,
+            private_key: PrivateKey::new(vec![1, 2, 3]).unwrap(),
+            photos_digest: PhotosDigest::new(vec![4, 5, 6, 7]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn public_json_round_trip() {
+        let p = public();
+        let json = serde_json::to_string(&p).unwrap();
+        assert_eq!(serde_json::from_str::<Public>(&json).unwrap(), p);
+    }
+
+    #[test]
+    fn private_json_round_trip() {
+        let p = private();
+        let json = serde_json::to_string(&p).unwrap();
+        assert!(json.contains(&bs58::encode(p.private_key.as_bytes()).into_string()));
+        assert_eq!(serde_json::from_str::<Private>(&json).unwrap(), p);
+    }
+
+    #[test]
+    fn private_json_rejects_malformed_bytes() {
+        let p = private();
+        let json = serde_json::to_string(&p).unwrap();
+        let key = bs58::encode(p.private_key.as_bytes()).into_string();
+        for bad in &["0OIl", "zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz"] {
+            let malformed = json.replace(&key, bad);
+            assert!(
+                serde_json::from_str::<Private>(&malformed).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn relation_json() {
+        assert_eq!(
+            serde_json::to_string(&Relation::Older).unwrap(),
+            "\"older\""
+        );
+        assert_eq!(
+            serde_json::to_string(&Relation::Younger).unwrap(),
+            "\"younger\""
+        );
+        for (json, relation) in &[
+            ("\"younger\"", Relation::Younger),
+            ("\"Younger\"", Relation::Younger),
+            ("\"older\"", Relation::Older),
+            ("\"Older\"", Relation::Older),
+        ] {
+            assert_eq!(serde_json::from_str::<Relation>(json).unwrap(), *relation);
+        }
+        assert!(serde_json::from_str::<Relation>("\"OLDER\"").is_err());
+    }
+
+    #[test]
+    fn qr_request_json_round_trip() {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: public(),
+            private: private(),
+            allow_unbound_proof: false,
+        };
+        let json = serde_json::to_string(&rq).unwrap();
+        assert_eq!(serde_json::from_str::<QrRequest>(&json).unwrap(), rq);
+    }
+
+    #[test]
+    fn qr_request_string_round_trip() {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: public(),
+            private: private(),
+            allow_unbound_proof: false,
+        };
+        let s = rq.to_qr_code_string();
+        assert_eq!(QrRequest::from_qr_code_string(&s).unwrap(), rq);
+
+        let other = QrRequest {
+            circuit: 2,
+            public: public(),
+            private: private(),
+            allow_unbound_proof: false,
+        };
+        let s = other.to_qr_code_string();
+        assert_eq!(QrRequest::from_qr_code_string(&s).unwrap(), other);
+
+        let empty = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public::younger_than(0, 1, 0).unwrap(),
+            private: Private::with(1, PrivateKey::default(), PhotosDigest::default()).unwrap(),
+            allow_unbound_proof: false,
+        };
+        let s = empty.to_qr_code_string();
+        assert_eq!(QrRequest::from_qr_code_string(&s).unwrap(), empty);
+    }
+
+    fn request_bytes() -> Vec<u8> {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: public(),
+            private: private(),
+            allow_unbound_proof: false,
+        };
+        bs58::decode(rq.to_qr_code_string()).into_vec().unwrap()
+    }
+
+    #[test]
+    fn qr_request_string_rejects_truncated_input() {
+        let bytes = request_bytes();
+        for len in 0..bytes.len() {
+            let s = bs58::encode(&bytes[..len]).into_string();
+            assert!(QrRequest::from_qr_code_string(&s).is_err());
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        let s = bs58::encode(&longer).into_string();
+        assert!(QrRequest::from_qr_code_string(&s).is_err());
+        assert!(QrRequest::from_qr_code_string("0OIl").is_err());
+    }
+
+    #[test]
+    fn qr_request_string_rejects_wrong_field_length() {
+        let mut bytes = request_bytes();
+        // Length of the private key follows the 18 byte header.
+        bytes[18] = (FIELD_LENGTH + 1) as u8;
+        let s = bs58::encode(&bytes).into_string();
+        assert!(QrRequest::from_qr_code_string(&s).is_err());
+    }
+
+    #[test]
+    fn qr_request_string_rejects_unknown_relation() {
+        let mut bytes = request_bytes();
+        bytes[13] = 2;
+        let s = bs58::encode(&bytes).into_string();
+        assert!(QrRequest::from_qr_code_string(&s).is_err());
+    }
+
+    #[test]
+    fn relation_from_byte() {
+        assert_eq!(Relation::try_from(0), Ok(Relation::Younger));
+        assert_eq!(Relation::try_from(1), Ok(Relation::Older));
+        assert_eq!(Relation::try_from(2), Err(QrError::RelationByte(2)));
+        assert_eq!(Relation::try_from(255), Err(QrError::RelationByte(255)));
+        assert_eq!(Relation::Younger as u8, 0);
+        assert_eq!(Relation::Older as u8, 1);
+    }
+
+    #[test]
+    fn public_from_str_relation_byte() {
+        let decode = |relation: u8| {
+            let mut bytes = vec![0u8; 12];
+            bytes.push(relation);
+            ProofQrCode::public_from_str(&bs58::encode(&bytes).into_string())
+                .map(|public| public.relation)
+        };
+        assert_eq!(decode(0), Ok(Relation::Younger));
+        assert_eq!(decode(1), Ok(Relation::Older));
+        assert_eq!(decode(2), Err(QrError::RelationByte(2)));
+        assert_eq!(decode(255), Err(QrError::RelationByte(255)));
+    }
+
+    fn valid_request() -> QrRequest {
+        QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: public(),
+            private: private(),
+            allow_unbound_proof: false,
+        }
+    }
+
+    #[test]
+    fn validate_accepts_valid_request() {
+        assert_eq!(valid_request().validate(), Ok(()));
+        let mut rq = valid_request();
+        rq.private.birthday = rq.public.today;
+        rq.public.delta = 0;
+        assert_eq!(rq.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_birthday() {
+        let mut rq = valid_request();
+        rq.private.birthday = 0;
+        assert_eq!(rq.validate(), Err(ValidationError::Birthday));
+        rq.private.birthday = -1;
+        assert_eq!(rq.validate(), Err(ValidationError::Birthday));
+        rq.private.birthday = rq.public.today + 1;
+        assert_eq!(rq.validate(), Err(ValidationError::Birthday));
+    }
+
+    #[test]
+    fn validate_today() {
+        let mut rq = valid_request();
+        rq.public.today = MAX_JULIAN_DAY + 1;
+        assert_eq!(rq.validate(), Err(ValidationError::Today));
+    }
+
+    #[test]
+    fn relation_of_values_out_of_range() {
+        for relation in &[Relation::Older, Relation::Younger] {
+            let mut rq = valid_request();
+            rq.public.relation = *relation;
+            rq.public.today = i32::MAX;
+            rq.private.birthday = i32::MAX - 1;
+            assert_eq!(rq.is_relation_valid(), Err(ValidationError::Today));
+
+            let mut rq = valid_request();
+            rq.public.relation = *relation;
+            rq.public.delta = i32::MAX;
+            assert_eq!(rq.is_relation_valid(), Err(ValidationError::Delta));
+            rq.public.delta = MAX_JULIAN_DAY + 1;
+            assert_eq!(rq.is_relation_valid(), Err(ValidationError::Delta));
+            rq.public.delta = -1;
+            assert_eq!(rq.is_relation_valid(), Err(ValidationError::Delta));
+
+            let mut rq = valid_request();
+            rq.public.relation = *relation;
+            rq.private.birthday = -5;
+            assert_eq!(rq.is_relation_valid(), Err(ValidationError::Birthday));
+            rq.private.birthday = i32::MIN;
+            assert_eq!(rq.is_relation_valid(), Err(ValidationError::Birthday));
+        }
+        // The largest valid values do not overflow.
+        let mut rq = valid_request();
+        rq.public.today = MAX_JULIAN_DAY;
+        rq.private.birthday = MAX_JULIAN_DAY;
+        rq.public.delta = MAX_JULIAN_DAY;
+        rq.public.relation = Relation::Younger;
+        assert_eq!(rq.is_relation_valid(), Ok(true));
+        rq.public.relation = Relation::Older;
+        assert_eq!(rq.is_relation_valid(), Ok(false));
+    }
+
+    #[test]
+    fn public_validate() {
+        let public = Public::older_than(18, 2020, 1200).unwrap();
+        assert_eq!(public.validate(), Ok(()));
+        for (today, delta, error) in &[
+            (i32::MAX, 18, ValidationError::Today),
+            (-1, 18, ValidationError::Today),
+            (MAX_JULIAN_DAY + 1, 18, ValidationError::Today),
+            (2020, i32::MAX, ValidationError::Delta),
+            (2020, -1, ValidationError::Delta),
+            (2020, MAX_JULIAN_DAY + 1, ValidationError::Delta),
+        ] {
+            let mut invalid = public.clone();
+            invalid.today = *today;
+            invalid.delta = *delta;
+            assert_eq!(invalid.validate(), Err(error.clone()));
+        }
+    }
+
+    #[test]
+    fn validate_delta() {
+        let mut rq = valid_request();
+        rq.public.delta = -1;
+        assert_eq!(rq.validate(), Err(ValidationError::Delta));
+        rq.public.delta = MAX_JULIAN_DAY + 1;
+        assert_eq!(rq.validate(), Err(ValidationError::Delta));
+    }
+
+    #[test]
+    fn public_constructors() {
+        let p = Public::older_than(6575, 2459000, 720).unwrap();
+        assert_eq!(p, public());
+        let p = Public::younger_than(6575, 2459000, 720).unwrap();
+        assert_eq!(p.relation, Relation::Younger);
+        assert_eq!(p.second, None);
+    }
+
+    #[test]
+    fn public_constructors_check_ranges() {
+        assert_eq!(
+            Public::older_than(-1, 2459000, 720),
+            Err(ValidationError::Delta)
+        );
+        assert_eq!(
+            Public::older_than(6575, 0, 720),
+            Err(ValidationError::Today)
+        );
+        assert_eq!(
+            Public::younger_than(6575, MAX_JULIAN_DAY + 1, 720),
+            Err(ValidationError::Today)
+        );
+        assert_eq!(
+            Public::older_than(6575, 2459000, -1),
+            Err(ValidationError::Now)
+        );
+        assert_eq!(
+            Public::older_than(6575, 2459000, MINUTES_PER_DAY),
+            Err(ValidationError::Now)
+        );
+    }
+
+    #[test]
+    fn private_constructor() {
+        let p = Private::with(
+            2451000,
+            PrivateKey::new(vec![1, 2, 3]).unwrap(),
+            PhotosDigest::new(vec![4, 5, 6, 7]).unwrap(),
+        );
+        assert_eq!(p, Ok(private()));
+        assert_eq!(
+            Private::with(0, PrivateKey::default(), PhotosDigest::default()),
+            Err(ValidationError::Birthday)
+        );
+    }
+
+    #[test]
+    fn build_request() {
+        assert_eq!(QrRequest::build(public(), private()), Ok(valid_request()));
+        let mut late = private();
+        late.birthday = public().today + 1;
+        assert_eq!(
+            QrRequest::build(public(), late),
+            Err(ValidationError::Birthday)
+        );
+    }
+
+    #[test]
+    fn build_request_checks_band() {
+        let mut band = public();
+        band.second = Some(Bound {
+            relation: Relation::Younger,
+            delta: 9497,
+        });
+        assert!(QrRequest::build(band.clone(), private()).is_ok());
+        band.second = Some(Bound {
+            relation: Relation::Older,
+            delta: 9497,
+        });
+        assert_eq!(
+            QrRequest::build(band, private()),
+            Err(ValidationError::SecondBound)
+        );
+    }
+
+    #[test]
+    fn validate_now() {
+        let mut rq = valid_request();
+        rq.public.now = MINUTES_PER_DAY;
+        assert_eq!(rq.validate(), Err(ValidationError::Now));
+    }
+
+    #[test]
+    fn private_key_range() {
+        assert_eq!(
+            PrivateKey::new(vec![1; FIELD_LENGTH + 1]),
+            Err(ValidationError::PrivateKey)
+        );
+        assert_eq!(
+            PrivateKey::new(FIELD_MODULUS.to_vec()),
+            Err(ValidationError::PrivateKey)
+        );
+    }
+
+    #[test]
+    fn photos_digest_range() {
+        assert_eq!(
+            PhotosDigest::new(vec![1; FIELD_LENGTH + 1]),
+            Err(ValidationError::PhotosDigest)
+        );
+        assert_eq!(
+            PhotosDigest::new(vec![0xff; FIELD_LENGTH]),
+            Err(ValidationError::PhotosDigest)
+        );
+        let mut below = FIELD_MODULUS.to_vec();
+        below[0] -= 1;
+        assert!(PhotosDigest::new(below).is_ok());
+    }
+
+    #[test]
+    fn secrets_are_wiped_on_drop() {
+        let key = PrivateKey::new(vec![0xab; 31]).unwrap();
+        let buffer = key.as_bytes().as_ptr();
+        assert!(wiped_on_drop(key, buffer));
+
+        let private = private();
+        let buffer = private.private_key.as_bytes().as_ptr();
+        assert!(wiped_on_drop(private, buffer));
+
+        let rq = valid_request();
+        let buffer = rq.private.private_key.as_bytes().as_ptr();
+        assert!(wiped_on_drop(rq, buffer));
+
+        // Rejected bytes are wiped too.
+        let bytes = vec![0xff; FIELD_LENGTH];
+        let buffer = bytes.as_ptr();
+        assert!(wiped_when_freed(buffer, || {
+            assert!(PrivateKey::new(bytes).is_err());
+        }));
+    }
+
+    #[test]
+    fn bytes_taken_out_are_kept() {
+        let key = PrivateKey::new(vec![0xab; 31]).unwrap();
+        assert_eq!(Vec::<u8>::from(key), vec![0xab; 31]);
+    }
+
+    #[test]
+    fn field_bytes_string_round_trip() {
+        let digest = PhotosDigest::new(vec![4, 5, 6, 7]).unwrap();
+        assert_eq!(digest.to_string().parse::<PhotosDigest>(), Ok(digest));
+        let above = bs58::encode(&FIELD_MODULUS).into_string();
+        assert_eq!(above.parse::<CardKey>(), Err(QrError::FieldRange));
+        assert_eq!(
+            "0OIl".parse::<Challenge>(),
+            Err(QrError::Base58(QrSection::Payload))
+        );
+        let json = serde_json::to_string(&above).unwrap();
+        assert!(serde_json::from_str::<PrivateKey>(&json).is_err());
+    }
+
+    #[test]
+    fn field_element_range() {
+        assert!(is_field_element(&[]));
+        let mut below = FIELD_MODULUS.to_vec();
+        below[0] -= 1;
+        assert!(is_field_element(&below));
+        assert!(!is_field_element(&FIELD_MODULUS));
+        let mut above = FIELD_MODULUS.to_vec();
+        above[0] += 1;
+        assert!(!is_field_element(&above));
+    }
+
+    #[test]
+    fn proof_qr_code_json_round_trip() {
+        let qr = ProofQrCode {
+            public: public(),
+            proof: (0..SEALED_PROOF_LENGTH as u8).collect(),
+            challenge: Challenge::new(vec![9; 32]).unwrap(),
+            second_proof: None,
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
+        };
+        let json = serde_json::to_string(&qr).unwrap();
+        assert_eq!(serde_json::from_str::<ProofQrCode>(&json).unwrap(), qr);
+    }
+
+    #[test]
+    fn proof_qr_code_band_round_trip() {
+        let mut band = public();
+        band.second = Some(Bound {
+            relation: Relation::Younger,
+            delta: 9500,
+        });
+        let qr = ProofQrCode {
+            public: band,
+            proof: vec![1; SEALED_PROOF_LENGTH],
+            challenge: Challenge::new(vec![9; 32]).unwrap(),
+            second_proof: Some(vec![2; SEALED_PROOF_LENGTH]),
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
+        };
+        assert_eq!(ProofQrCode::from_str(&qr.to_string()).unwrap(), qr);
+        let json = serde_json::to_string(&qr).unwrap();
+        assert_eq!(serde_json::from_str::<ProofQrCode>(&json).unwrap(), qr);
+
+        let single = ProofQrCode {
+            public: public(),
+            proof: vec![1; SEALED_PROOF_LENGTH],
+            challenge: Challenge::new(vec![9; 32]).unwrap(),
+            second_proof: None,
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
+        };
+        assert_eq!(ProofQrCode::from_str(&single.to_string()).unwrap(), single);
+        let json = serde_json::to_string(&single).unwrap();
+        assert!(!json.contains("second"));
+        assert_eq!(serde_json::from_str::<ProofQrCode>(&json).unwrap(), single);
+    }
+
+    fn proof_qr_code() -> ProofQrCode {
+        ProofQrCode {
+            public: public(),
+            proof: (0..SEALED_PROOF_LENGTH as u8).collect(),
+            challenge: Challenge::new(vec![9; FIELD_LENGTH]).unwrap(),
+            second_proof: None,
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
+        }
+    }
+
+    /// The same code in the legacy format, its proofs are masked.
+    fn legacy_qr_code(qr: &ProofQrCode) -> ProofQrCode {
+        ProofQrCode {
+            version: 0,
+            key_id: None,
+            proof: qr.proof[..PROOF_LENGTH].to_vec(),
+            second_proof: qr.second_proof.as_ref().map(|p| p[..PROOF_LENGTH].to_vec()),
+            ..qr.clone()
+        }
+    }
+
+    /// The fixture in the format `version` older than the sealing.
+    fn masked_qr_code(version: u8) -> ProofQrCode {
+        ProofQrCode {
+            version: version,
+            key_id: Some(EMBEDDED_KEY),
+            ..legacy_qr_code(&proof_qr_code())
+        }
+    }
+
+    /// The payload with its checksum computed again after it was
+    /// tampered with.
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.truncate(bytes.len() - CHECKSUM_LENGTH);
+        let checksum = payload_checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+        bytes
+    }
+
+    #[test]
+    fn proof_qr_code_bytes_round_trip() {
+        let qr = proof_qr_code();
+        let bytes = qr.to_bytes().unwrap();
+        assert_eq!(bytes.len(), PAYLOAD_LENGTH);
+        assert_eq!(PAYLOAD_LENGTH, 208);
+        assert_eq!(bytes[0], 6);
+        assert_eq!(bytes[1], EMBEDDED_CIRCUIT);
+        assert_eq!(bytes[2], EMBEDDED_KEY);
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), qr);
+        assert_eq!(ProofQrCode::from_str(&qr.to_string()).unwrap(), qr);
+
+        let mut other = proof_qr_code();
+        other.circuit = 2;
+        assert_eq!(other.to_bytes().unwrap()[1], 2);
+        assert_eq!(ProofQrCode::from_str(&other.to_string()).unwrap(), other);
+
+        // Without a key id the version 2 layout is written.
+        let mut v2 = masked_qr_code(2);
+        v2.key_id = None;
+        let bytes = v2.to_bytes().unwrap();
+        assert_eq!(bytes.len(), payload_length(2) - 1);
+        assert_eq!(bytes[0], 2);
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), v2);
+
+        // Version 3 and 4 codes keep their version and masked proofs,
+        // version 4 and later mark a missing key id by 0.
+        let v3 = masked_qr_code(3);
+        assert_eq!(v3.to_bytes().unwrap()[0], 3);
+        assert_eq!(
+            ProofQrCode::from_bytes(&v3.to_bytes().unwrap()).unwrap(),
+            v3
+        );
+        let v4 = masked_qr_code(STREAM_MASK_VERSION);
+        let bytes = v4.to_bytes().unwrap();
+        assert_eq!((bytes.len(), bytes[0]), (176, 4));
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), v4);
+        // Version 5 codes have no checksum.
+        let v5 = ProofQrCode {
+            version: SEALED_VERSION,
+            ..proof_qr_code()
+        };
+        let bytes = v5.to_bytes().unwrap();
+        assert_eq!((bytes.len(), bytes[0]), (204, 5));
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), v5);
+        let mut no_key = proof_qr_code();
+        no_key.key_id = None;
+        let bytes = no_key.to_bytes().unwrap();
+        assert_eq!((bytes.len(), bytes[0], bytes[2]), (PAYLOAD_LENGTH, 6, 0));
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), no_key);
+    }
+
+    #[test]
+    fn proof_qr_code_version_1() {
+        let mut qr = masked_qr_code(2);
+        qr.key_id = None;
+        let mut bytes = qr.to_bytes().unwrap();
+        bytes.remove(1);
+        bytes[0] = 1;
+        qr.version = 1;
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), qr);
+
+        qr.public.second = Some(Bound {
+            relation: Relation::Younger,
+            delta: 9500,
+        });
+        qr.second_proof = Some(vec![7; PROOF_LENGTH]);
+        let mut bytes = qr.to_bytes().unwrap();
+        bytes.remove(1);
+        bytes[0] = 1;
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), qr);
+        assert_eq!(
+            ProofQrCode::from_bytes(&bytes[..payload_length(1) - 2]),
+            Err(QrError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn proof_qr_code_bytes_band() {
+        let mut qr = proof_qr_code();
+        qr.public.second = Some(Bound {
+            relation: Relation::Younger,
+            delta: 9500,
+        });
+        qr.second_proof = Some(vec![7; SEALED_PROOF_LENGTH]);
+        let bytes = qr.to_bytes().unwrap();
+        assert_eq!(bytes.len(), BAND_PAYLOAD_LENGTH);
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), qr);
+    }
+
+    #[test]
+    fn proof_qr_code_bytes_nonce() {
+        let mut qr = proof_qr_code();
+        let without_nonce = qr.to_bytes().unwrap();
+        qr.public.nonce = Some(Nonce::new(vec![5; FIELD_LENGTH]).unwrap());
+        let bytes = qr.to_bytes().unwrap();
+        assert_eq!(bytes.len(), PAYLOAD_LENGTH + FIELD_LENGTH);
+        let checked = PAYLOAD_LENGTH - CHECKSUM_LENGTH;
+        assert_eq!(bytes[..checked], without_nonce[..checked]);
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), qr);
+        assert_eq!(ProofQrCode::from_str(&qr.to_string()).unwrap(), qr);
+
+        qr.public.second = Some(Bound {
+            relation: Relation::Younger,
+            delta: 9500,
+        });
+        qr.second_proof = Some(vec![7; SEALED_PROOF_LENGTH]);
+        let bytes = qr.to_bytes().unwrap();
+        assert_eq!(bytes.len(), MAX_PAYLOAD_LENGTH);
+        assert_eq!(ProofQrCode::from_bytes(&bytes).unwrap(), qr);
+        assert!(qr.to_string().len() <= MAX_QR_STRING_LENGTH);
+    }
+
+    #[test]
+    fn proof_qr_code_bytes_pads_challenge() {
+        let mut qr = proof_qr_code();
+        qr.challenge = Challenge::new(vec![9; 31]).unwrap();
+        let parsed = ProofQrCode::from_bytes(&qr.to_bytes().unwrap()).unwrap();
+        let challenge = parsed.challenge.as_bytes();
+        assert_eq!(challenge.len(), FIELD_LENGTH);
+        assert_eq!(challenge[..31], qr.challenge.as_bytes()[..]);
+        assert_eq!(challenge[31], 0);
+    }
+
+    #[test]
+    fn proof_qr_code_bytes_rejects_wrong_length() {
+        let bytes = proof_qr_code().to_bytes().unwrap();
+        assert_eq!(
+            ProofQrCode::from_bytes(&bytes[..PAYLOAD_LENGTH - 1]),
+            Err(QrError::InvalidLength)
+        );
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            ProofQrCode::from_bytes(&longer),
+            Err(QrError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn proof_qr_code_legacy_string() {
+        let qr = masked_qr_code(STREAM_MASK_VERSION);
+        let legacy = qr.to_legacy_string();
+        assert_eq!(legacy.split(';').count(), 3);
+        let parsed = ProofQrCode::from_str(&legacy).unwrap();
+        assert_eq!(parsed.version, 0);
+        assert_eq!(
+            ProofQrCode {
+                version: STREAM_MASK_VERSION,
+                key_id: Some(EMBEDDED_KEY),
+                ..parsed.clone()
+            },
+            qr
+        );
+        assert_eq!(parsed.to_string(), legacy);
+        assert!(qr.to_string().len() < legacy.len());
+    }
+
+    #[test]
+    fn proof_qr_code_unsupported_version() {
+        let mut bytes = proof_qr_code().to_bytes().unwrap();
+        bytes[0] = QR_FORMAT_VERSION + 1;
+        assert_eq!(
+            ProofQrCode::from_bytes(&bytes),
+            Err(QrError::UnsupportedVersion(QR_FORMAT_VERSION + 1))
+        );
+        bytes[0] = 0;
+        let s = bs58::encode(&bytes).into_string();
+        assert_eq!(
+            ProofQrCode::from_str(&s),
+            Err(QrError::UnsupportedVersion(0))
+        );
+    }
+
+    #[test]
+    fn proof_qr_code_truncated() {
+        let bytes = proof_qr_code().to_bytes().unwrap();
+        assert_eq!(
+            ProofQrCode::from_bytes(&[]),
+            Err(QrError::Truncated(QrSection::Payload))
+        );
+        for len in 1..bytes.len() {
+            assert_eq!(
+                ProofQrCode::from_bytes(&bytes[..len]),
+                Err(QrError::InvalidLength)
+            );
+        }
+    }
+
+    #[test]
+    fn proof_qr_code_json_rejects_malformed_proof() {
+        let mut qr = ProofQrCode {
+            public: public(),
+            proof: vec![1; SEALED_PROOF_LENGTH - 1],
+            challenge: Challenge::new(vec![9; 32]).unwrap(),
+            second_proof: None,
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
+        };
+        let json = serde_json::to_string(&qr).unwrap();
+        assert!(serde_json::from_str::<ProofQrCode>(&json).is_err());
+
+        qr.proof = vec![1; SEALED_PROOF_LENGTH];
+        let json = serde_json::to_string(&qr)
+            .unwrap()
+            .replace(&bs58::encode(&qr.proof).into_string(), "0OIl");
+        assert!(serde_json::from_str::<ProofQrCode>(&json).is_err());
+    }
+
+    #[test]
+    fn to_bytes_refuses_proof_of_wrong_length() {
+        let mut qr = proof_qr_code();
+        qr.proof.pop();
+        assert_eq!(qr.to_bytes(), Err(QrError::InvalidLength));
+        assert_eq!(qr.to_string_compact(), Err(QrError::InvalidLength));
+        // A masked proof does not fit a sealed version.
+        qr.proof = vec![1; PROOF_LENGTH];
+        assert_eq!(qr.to_bytes(), Err(QrError::InvalidLength));
+        assert!(masked_qr_code(STREAM_MASK_VERSION).to_bytes().is_ok());
+
+        let mut band = band_qr_code();
+        band.second_proof = None;
+        assert_eq!(band.to_bytes(), Err(QrError::InvalidLength));
+        band.second_proof = Some(vec![2; PROOF_LENGTH]);
+        assert_eq!(band.to_chunked_strings(40), Err(QrError::InvalidLength));
+        band.public.second = None;
+        assert_eq!(band.to_bytes(), Err(QrError::InvalidLength));
+    }
+
+    #[test]
+    fn relation_text() {
+        assert_eq!(Relation::Older.to_string(), "older");
+        assert_eq!(Relation::Younger.to_string(), "younger");
+        assert_eq!("older".parse::<Relation>(), Ok(Relation::Older));
+        assert_eq!("younger".parse::<Relation>(), Ok(Relation::Younger));
+        assert_eq!("Older".parse::<Relation>(), Err(QrError::InvalidRelation));
+    }
+
+    // 2024-05-17
+    const TEXT_TODAY: i32 = 2460448;
+
+    #[test]
+    fn public_text() {
+        let older = Public::older_than(6575, TEXT_TODAY, 12 * 60 + 30).unwrap();
+        let young = Public::younger_than(1, TEXT_TODAY, 5).unwrap();
+        let mut band = Public::older_than(6576, TEXT_TODAY, 23 * 60 + 59).unwrap();
+        band.second = Some(Bound {
+            relation: Relation::Younger,
+            delta: 9497,
+        });
+        let mut invalid = Public::older_than(18, 1, 0).unwrap();
+        invalid.today = 0;
+        invalid.now = MINUTES_PER_DAY;
+        for (public, text) in &[
+            (
+                older,
+                "older than 6575 days (18 years) as of 2024-05-17 12:30 UTC",
+            ),
+            (
+                young,
+                "younger than 1 day (0 years 1 day) as of 2024-05-17 00:05 UTC",
+            ),
+            (
+                band,
+                "older than 6576 days (18 years 1 day) and younger than 9497 days (26 years) \
+                 as of 2024-05-17 23:59 UTC",
+            ),
+            (invalid, "older than 18 days as of JD0 1440min UTC"),
+        ] {
+            assert_eq!(public.to_string(), *text);
+            assert_eq!(text.parse::<Public>().as_ref(), Ok(public));
+        }
+    }
+
+    #[test]
+    fn public_text_round_trip() {
+        for today in &[1, 2451545, TEXT_TODAY, MAX_JULIAN_DAY] {
+            for delta in &[0, 1, 365, 6575, 40000] {
+                for now in &[0, 1, 719, MINUTES_PER_DAY - 1] {
+                    for relation in &[Relation::Older, Relation::Younger] {
+                        let public = Public {
+                            today: *today,
+                            now: *now,
+                            relation: *relation,
+                            delta: *delta,
+                            second: None,
+                            nonce: None,
+                        };
+                        assert_eq!(public.to_string().parse::<Public>(), Ok(public));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn public_text_rejects_malformed() {
+        for text in &[
+            "",
+            "older than 18 years as of 2024-05-17 12:30 UTC",
+            "older than 1 days as of 2024-05-17 12:30 UTC",
+            "older than 18 days",
+            "older than 18 days as of 2024-05-17 12:30",
+            "older than 18 days as of 2024-02-30 12:30 UTC",
+            "older than 18 days as of 2024-5-17 12:30 UTC",
+            "older than 18 days as of 2024-05-17 24:00 UTC",
+            "older than 18 days as of 2024-05-17 12:30 CET",
+            "older than 18 days and younger than 20 days and older than 19 days \
+             as of 2024-05-17 12:30 UTC",
+        ] {
+            assert_eq!(
+                text.parse::<Public>(),
+                Err(QrError::InvalidText),
+                "{}",
+                text
+            );
+        }
+        assert_eq!(
+            "elder than 18 days as of 2024-05-17 12:30 UTC".parse::<Public>(),
+            Err(QrError::InvalidRelation)
+        );
+    }
+
+    /// Bits of the data of a QR code segment, without the mode and
+    /// length indicators.
+    fn qr_data_bits(s: &str) -> usize {
+        let alphanumeric = s
+            .bytes()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase() || b" $%*+-./:".contains(&c));
+        if alphanumeric {
+            s.len() / 2 * 11 + s.len() % 2 * 6
+        } else {
+            s.len() * 8
+        }
+    }
+
+    #[test]
+    fn compact_string_round_trip() {
+        for qr in &[proof_qr_code(), band_qr_code()] {
+            let compact = qr.to_string_compact().unwrap();
+            assert!(compact.starts_with(COMPACT_PREFIX));
+            assert_eq!(ProofQrCode::from_str(&compact).unwrap(), *qr);
+            assert_eq!(ProofQrCode::from_str(&qr.to_string()).unwrap(), *qr);
+            let legacy = legacy_qr_code(qr).to_checked_legacy_string();
+            assert_eq!(ProofQrCode::from_str(&legacy).unwrap().public, qr.public);
+            // About 1700 against 2250 bits of a single proof.
+            assert!(qr_data_bits(&compact) * 5 < qr_data_bits(&qr.to_string()) * 4);
+        }
+    }
+
+    #[test]
+    fn invalid_compact_string() {
+        let compact = proof_qr_code().to_string_compact().unwrap();
+        assert_eq!(
+            ProofQrCode::from_str(&compact.to_lowercase().replace("la:", COMPACT_PREFIX)),
+            Err(QrError::Base45)
+        );
+        assert_eq!(
+            ProofQrCode::from_str(&compact[..compact.len() - 3]),
+            Err(QrError::InvalidLength)
+        );
+        let long = format!("{}{}", compact, "0".repeat(MAX_PAYLOAD_LENGTH * 3));
+        assert_eq!(ProofQrCode::from_str(&long), Err(QrError::Base45));
+    }
+
+    #[test]
+    fn chunked_string_round_trip() {
+        for qr in &[proof_qr_code(), band_qr_code()] {
+            let chunked = qr.to_chunked_string(5).unwrap();
+            assert!(chunked.split(' ').all(|group| group.len() <= 6));
+            assert_eq!(ProofQrCode::from_chunked_string(&chunked, 5).unwrap(), *qr);
+            let typed = chunked.replacen(' ', "\n", 7).replacen(' ', "", 3);
+            assert_eq!(ProofQrCode::from_chunked_string(&typed, 5).unwrap(), *qr);
+            let typed = chunked.to_lowercase();
+            assert_eq!(ProofQrCode::from_chunked_string(&typed, 5).unwrap(), *qr);
+        }
+    }
+
+    #[test]
+    fn wrong_chunk_is_reported() {
+        let qr = proof_qr_code();
+        let chunked = qr.to_chunked_string(5).unwrap();
+        let mut groups: Vec<String> = chunked.split(' ').map(String::from).collect();
+        let mut typo = groups.clone();
+        let c = if typo[2].starts_with('A') { "B" } else { "A" };
+        typo[2].replace_range(0..1, c);
+        assert_eq!(
+            ProofQrCode::from_chunked_string(&typo.join(" "), 5),
+            Err(QrError::ChunkCheck(3))
+        );
+        // The check covers the position of the group.
+        groups.swap(0, 1);
+        assert_eq!(
+            ProofQrCode::from_chunked_string(&groups.join(" "), 5),
+            Err(QrError::ChunkCheck(1))
+        );
+    }
+
+    fn band_qr_code() -> ProofQrCode {
+        let mut qr = proof_qr_code();
+        qr.public.second = Some(Bound {
+            relation: Relation::Younger,
+            delta: MAX_JULIAN_DAY,
+        });
+        qr.second_proof = Some(vec![0xff; SEALED_PROOF_LENGTH]);
+        let mut challenge = FIELD_MODULUS.to_vec();
+        challenge[0] -= 1;
+        qr.challenge = Challenge::new(challenge).unwrap();
+        qr
+    }
+
+    #[test]
+    fn tampered_relation_byte_is_refused() {
+        // The relation follows today, now and delta.
+        let relation = HEADER_LENGTH + 12;
+        let mut bytes = proof_qr_code().to_bytes().unwrap();
+        bytes[relation] = 7;
+        assert_eq!(
+            ProofQrCode::from_bytes(&bytes),
+            Err(QrError::ChecksumMismatch)
+        );
+        assert_eq!(
+            ProofQrCode::from_bytes(&with_checksum(bytes)),
+            Err(QrError::RelationByte(7))
+        );
+        let mut bytes = band_qr_code().to_bytes().unwrap();
+        let second = PAYLOAD_LENGTH - CHECKSUM_LENGTH;
+        assert_eq!(bytes[second], Relation::Younger as u8);
+        bytes[second] = 7;
+        assert_eq!(
+            ProofQrCode::from_bytes(&with_checksum(bytes)),
+            Err(QrError::RelationByte(7))
+        );
+
+        let legacy = legacy_qr_code(&proof_qr_code());
+        let parts: Vec<String> = legacy
+            .to_legacy_string()
+            .split(';')
+            .map(String::from)
+            .collect();
+        let mut public = bs58::decode(&parts[0]).into_vec().unwrap();
+        public[12] = 7;
+        let tampered = format!(
+            "{};{};{}",
+            bs58::encode(&public).into_string(),
+            parts[1],
+            parts[2]
+        );
+        assert_eq!(
+            ProofQrCode::from_str(&tampered),
+            Err(QrError::RelationByte(7))
+        );
+        assert_eq!(
+            ProofQrCode::from_str(&legacy.to_legacy_string()),
+            Ok(legacy)
+        );
+    }
+
+    #[test]
+    fn checked_legacy_string_round_trip() {
+        for qr in &[proof_qr_code(), band_qr_code()] {
+            let legacy = legacy_qr_code(qr);
+            let checked = legacy.to_checked_legacy_string();
+            assert_eq!(checked.split(';').count(), 4);
+            assert!(checked.starts_with(&legacy.to_legacy_string()));
+            assert!(checked.len() <= MAX_QR_STRING_LENGTH);
+            assert_eq!(ProofQrCode::from_str(&checked), Ok(legacy.clone()));
+            // Strings without the checksum still parse.
+            assert_eq!(
+                ProofQrCode::from_str(&legacy.to_legacy_string()),
+                Ok(legacy)
+            );
+        }
+    }
+
+    #[test]
+    fn checked_legacy_string_detects_corruption() {
+        let legacy = legacy_qr_code(&band_qr_code());
+        let checked = legacy.to_checked_legacy_string();
+        let parts: Vec<&str> = checked.split(';').collect();
+        for i in 0..3 {
+            // Replace a character by another base58 character, so that
+            // the part still decodes.
+            let mut part: Vec<char> = parts[i].chars().collect();
+            let middle = part.len() / 2;
+            part[middle] = if part[middle] == '2' { '3' } else { '2' };
+            let mut corrupted = parts.clone();
+            let part: String = part.into_iter().collect();
+            corrupted[i] = &part;
+            assert_eq!(
+                ProofQrCode::from_str(&corrupted.join(";")),
+                Err(QrError::ChecksumMismatch),
+                "part {}",
+                i
+            );
+        }
+        let long_checksum = format!("{};{}", legacy.to_legacy_string(), "2".repeat(20));
+        assert_eq!(
+            ProofQrCode::from_str(&long_checksum),
+            Err(QrError::InvalidLength)
+        );
+        assert_eq!(
+            ProofQrCode::from_str(&format!("{};a;b", checked)),
+            Err(QrError::PartCount(6))
+        );
+    }
+
+    #[test]
+    fn payload_checksum_detects_corruption() {
+        let qr = band_qr_code();
+        let bytes = qr.to_bytes().unwrap();
+        assert_eq!(bytes.len(), BAND_PAYLOAD_LENGTH);
+        let proof = HEADER_LENGTH + 13;
+        let challenge = proof + SEALED_PROOF_LENGTH;
+        let second_proof = challenge + FIELD_LENGTH + 5;
+        // Public values, proof, challenge, second proof and the
+        // checksum itself.
+        for i in &[
+            3,
+            proof + 7,
+            challenge + 1,
+            second_proof + 100,
+            bytes.len() - 1,
+        ] {
+            let mut corrupted = bytes.clone();
+            corrupted[*i] ^= 0x10;
+            assert_eq!(
+                ProofQrCode::from_bytes(&corrupted),
+                Err(QrError::ChecksumMismatch),
+                "byte {}",
+                i
+            );
+            assert_eq!(
+                ProofQrCode::from_str(&bs58::encode(&corrupted).into_string()),
+                Err(QrError::ChecksumMismatch)
+            );
+        }
+        // Codes written before the checksum still parse.
+        let v5 = ProofQrCode {
+            version: SEALED_VERSION,
+            ..qr.clone()
+        };
+        let bytes = v5.to_bytes().unwrap();
+        assert_eq!(bytes.len(), BAND_PAYLOAD_LENGTH - CHECKSUM_LENGTH);
+        assert_eq!(ProofQrCode::from_str(&v5.to_string()).unwrap(), v5);
+    }
+
+    #[test]
+    fn legacy_errors_name_the_section() {
+        let legacy = legacy_qr_code(&proof_qr_code());
+        let s = legacy.to_legacy_string();
+        let parts: Vec<&str> = s.split(';').collect();
+        for (i, section) in [QrSection::Public, QrSection::Proof, QrSection::Challenge]
+            .iter()
+            .enumerate()
+        {
+            let mut corrupted = parts.clone();
+            corrupted[i] = "0OIl";
+            assert_eq!(
+                ProofQrCode::from_str(&corrupted.join(";")),
+                Err(QrError::Base58(*section))
+            );
+        }
+        assert_eq!(
+            ProofQrCode::from_str(&format!("{};0OIl", s)),
+            Err(QrError::Base58(QrSection::Checksum))
+        );
+        let short = bs58::encode(&[0u8; 5]).into_string();
+        assert_eq!(
+            ProofQrCode::from_str(&format!("{};{};{}", short, parts[1], parts[2])),
+            Err(QrError::Truncated(QrSection::Public))
+        );
+        assert_eq!(
+            ProofQrCode::from_str(&bs58::encode(&[QR_FORMAT_VERSION]).into_string()),
+            Err(QrError::InvalidLength)
+        );
+        assert_eq!(
+            QrError::Base58(QrSection::Proof).to_string(),
+            "invalid base58 encoding of the proof"
+        );
+        assert_eq!(
+            QrError::Truncated(QrSection::Public).to_string(),
+            "truncated public part"
+        );
+    }
+
+    #[test]
+    fn qr_error_codes_are_distinct() {
+        let errors = [
+            QrError::Base58(QrSection::Payload),
+            QrError::Truncated(QrSection::Payload),
+            QrError::InvalidLength,
+            QrError::InvalidRelation,
+            QrError::RelationByte(7),
+            QrError::PartCount(2),
+            QrError::ChecksumMismatch,
+            QrError::ProofPoints,
+            QrError::UnsupportedVersion(9),
+            QrError::FieldRange,
+            QrError::Chunk(ChunkError::InvalidFrame),
+            QrError::InvalidText,
+            QrError::Base45,
+            QrError::ChunkCheck(1),
+            QrError::ProofTag,
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(QrError::code).collect();
+        assert!(codes.iter().all(|code| *code > 0));
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        // The section does not change the code.
+        assert_eq!(
+            QrError::Base58(QrSection::Proof).code(),
+            QrError::Base58(QrSection::Payload).code()
+        );
+        assert_eq!(
+            QrError::Truncated(QrSection::Public).section(),
+            Some(QrSection::Public)
+        );
+        assert_eq!(QrError::RelationByte(7).section(), None);
+    }
+
+    #[test]
+    fn qr_string_length_limit() {
+        let band = band_qr_code();
+        assert!(band.to_string().len() <= MAX_QR_STRING_LENGTH);
+        let legacy = legacy_qr_code(&band);
+        assert!(legacy.to_legacy_string().len() <= MAX_QR_STRING_LENGTH);
+        let legacy_length = base58_length(MAX_PUBLIC_LENGTH)
+            + base58_length(2 * PROOF_LENGTH)
+            + base58_length(FIELD_LENGTH)
+            + 2;
+        assert!(legacy_length <= MAX_QR_STRING_LENGTH);
+        assert_eq!(ProofQrCode::from_str(&band.to_string()), Ok(band.clone()));
+        assert_eq!(
+            ProofQrCode::from_str(&legacy.to_legacy_string()),
+            Ok(legacy.clone())
+        );
+
+        let oversized = "2".repeat(10_000_000);
+        assert_eq!(
+            ProofQrCode::from_str(&oversized),
+            Err(QrError::InvalidLength)
+        );
+        let separators = ";".repeat(10_000_000);
+        assert_eq!(
+            ProofQrCode::from_str(&separators),
+            Err(QrError::InvalidLength)
+        );
+        let legacy = legacy.to_legacy_string();
+        let parts: Vec<&str> = legacy.split(';').collect();
+        for i in 0..3 {
+            let mut long = parts.clone();
+            let part = format!("{}{}", parts[i], "2".repeat(MAX_QR_STRING_LENGTH));
+            long[i] = &part;
+            assert_eq!(
+                ProofQrCode::from_str(&long.join(";")),
+                Err(QrError::InvalidLength)
+            );
+        }
+        // Each part is limited even if the whole string is short.
+        let part = "2".repeat(base58_length(2 * PROOF_LENGTH) + 1);
+        assert_eq!(
+            ProofQrCode::public_from_str(&part[..base58_length(MAX_PUBLIC_LENGTH) + 1]),
+            Err(QrError::InvalidLength)
+        );
+        assert_eq!(
+            ProofQrCode::proof_from_str(&part),
+            Err(QrError::InvalidLength)
+        );
+        assert_eq!(
+            ProofQrCode::challenge_from_str(&part[..base58_length(FIELD_LENGTH) + 1]),
+            Err(QrError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn legacy_proof_length_is_checked() {
+        let qr = legacy_qr_code(&proof_qr_code());
+        let s = format!(
+            "{};{};{}",
+            qr.public_to_string(),
+            bs58::encode(&qr.proof[1..]).into_string(),
+            qr.challenge_to_string()
+        );
+        assert_eq!(ProofQrCode::from_str(&s), Err(QrError::InvalidLength));
+    }
+
+    /// Regression inputs of the `parse_qr` fuzz target, see
+    /// `fuzz/fuzz_targets/parse_qr.rs`.
+    #[test]
+    fn qr_string_corpus() {
+        for s in &[
+            "",
+            ";",
+            ";;",
+            ";;;",
+            "1",
+            "1;1;1",
+            "11111111111111111111111111111111",
+            "\u{0};\u{0};\u{0}",
+            "é;ü;ž",
+            "ž",
+            "2;\u{fffd};2",
+            "LA11/1:2111",
+            "LA:",
+            "LA:0",
+            "LA:GGW",
+            "LA:bb8",
+            "LA:é;",
+            "LA:LA:",
+        ] {
+            assert!(ProofQrCode::from_str(s).is_err(), "{:?}", s);
+        }
+        let long = format!("{}{}", COMPACT_PREFIX, "Z".repeat(1 << 20));
+        assert_eq!(ProofQrCode::from_str(&long), Err(QrError::Base45));
+    }
+
+    #[test]
+    fn bit_flipped_qr_codes_do_not_panic() {
+        for qr in &[proof_qr_code(), band_qr_code()] {
+            let bytes = qr.to_bytes().unwrap();
+            for i in 0..bytes.len() {
+                for bit in 0..8 {
+                    let mut flipped = bytes.clone();
+                    flipped[i] ^= 1 << bit;
+                    let s = bs58::encode(&flipped).into_string();
+                    let _ = ProofQrCode::from_bytes(&flipped);
+                    let _ = ProofQrCode::from_str(&s);
+                    let compact = format!("{}{}", COMPACT_PREFIX, base45::encode(&flipped));
+                    let _ = ProofQrCode::from_str(&compact);
+                }
+            }
+            // Flipping the high bit does not give valid UTF-8, the
+            // scanner shows it as a replacement character.
+            let legacy = qr.to_legacy_string().into_bytes();
+            for i in 0..legacy.len() {
+                for bit in 0..8 {
+                    let mut flipped = legacy.clone();
+                    flipped[i] ^= 1 << bit;
+                    let _ = ProofQrCode::from_str(&String::from_utf8_lossy(&flipped));
+                }
+            }
+        }
+    }
+}
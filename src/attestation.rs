@@ -0,0 +1,322 @@
+// Ed25519 signatures of QR codes by app builds enrolled with the
+// certification server. A signed QR code is the QR code string
+// followed by a `;` and the base58 signature of `ProofQrCode::to_bytes`,
+// i.e. a fourth segment of the legacy format.
+
+#[cfg(feature = "verifier")]
+use crate::api::PhotosDigest;
+use crate::api::{decode_base58, ProofQrCode, QrError, VerifierLevel};
+#[cfg(feature = "verifier")]
+use crate::zk::{self, ZkError};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier as _};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Length of the signature.
+pub const SIGNATURE_LENGTH: usize = 64;
+
+/// Error signing or checking a signed QR code.
+#[derive(PartialEq, Debug, Clone)]
+pub enum AttestationError {
+    /// The signing or the public key is not a valid Ed25519 key.
+    InvalidKey,
+    /// The signature is malformed or does not match the QR code.
+    InvalidSignature,
+    /// The signature is valid but the key is not trusted.
+    UntrustedKey,
+    /// The QR code cannot be decoded.
+    QrCode(QrError),
+    /// The proof does not verify.
+    #[cfg(feature = "verifier")]
+    Proof(ZkError),
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttestationError::InvalidKey => write!(f, "invalid key"),
+            AttestationError::InvalidSignature => write!(f, "invalid signature"),
+            AttestationError::UntrustedKey => write!(f, "signed by an untrusted key"),
+            AttestationError::QrCode(e) => write!(f, "invalid QR code: {}", e),
+            #[cfg(feature = "verifier")]
+            AttestationError::Proof(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for AttestationError {}
+
+impl From<QrError> for AttestationError {
+    fn from(e: QrError) -> Self {
+        AttestationError::QrCode(e)
+    }
+}
+
+#[cfg(feature = "verifier")]
+impl From<ZkError> for AttestationError {
+    fn from(e: ZkError) -> Self {
+        AttestationError::Proof(e)
+    }
+}
+
+/// QR code with an optional signature of the app.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SignedQr {
+    pub qr: ProofQrCode,
+    pub signature: Option<Vec<u8>>,
+}
+
+impl SignedQr {
+    /// QR code without a signature, e.g. produced by a self-built app.
+    pub fn unsigned(qr: ProofQrCode) -> Self {
+        SignedQr {
+            qr: qr,
+            signature: None,
+        }
+    }
+}
+
+impl fmt::Display for SignedQr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.qr.to_string())?;
+        if let Some(signature) = &self.signature {
+            write!(f, ";{}", bs58::encode(signature).into_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SignedQr {
+    type Err = AttestationError;
+
+    /// Parses both signed and unsigned QR codes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = match s.parse::<ProofQrCode>() {
+            Ok(qr) => return Ok(SignedQr::unsigned(qr)),
+            Err(e) => e,
+        };
+        let separator = s.rfind(';').ok_or(error)?;
+        let qr = s[..separator].parse::<ProofQrCode>()?;
+        let signature = decode_base58(&s[separator + 1..], SIGNATURE_LENGTH)
+            .map_err(|_| AttestationError::InvalidSignature)?;
+        if signature.len() != SIGNATURE_LENGTH {
+            return Err(AttestationError::InvalidSignature);
+        }
+        Ok(SignedQr {
+            qr: qr,
+            signature: Some(signature),
+        })
+    }
+}
+
+/// Signs the QR code with the 32 byte Ed25519 secret key of the app.
+pub fn sign_qr(qr: &ProofQrCode, signing_key: &[u8]) -> Result<SignedQr, AttestationError> {
+    let secret = SecretKey::from_bytes(signing_key).map_err(|_| AttestationError::InvalidKey)?;
+    let public = PublicKey::from(&secret);
+    let keypair = Keypair {
+        secret: secret,
+        public: public,
+    };
+    let signature = keypair.sign(&qr.to_bytes()?);
+    Ok(SignedQr {
+        qr: qr.clone(),
+        signature: Some(signature.to_bytes().to_vec()),
+    })
+}
+
+/// Public key of the 32 byte Ed25519 secret key.
+pub fn public_key(signing_key: &[u8]) -> Result<Vec<u8>, AttestationError> {
+    let secret = SecretKey::from_bytes(signing_key).map_err(|_| AttestationError::InvalidKey)?;
+    Ok(PublicKey::from(&secret).to_bytes().to_vec())
+}
+
+/// Public keys of enrolled app builds and the trust level they give.
+#[derive(Debug, Default)]
+pub struct TrustedKeys {
+    keys: Vec<(PublicKey, VerifierLevel)>,
+}
+
+impl TrustedKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts QR codes signed by the 32 byte Ed25519 public key.
+    pub fn add(&mut self, public_key: &[u8], level: VerifierLevel) -> Result<(), AttestationError> {
+        let key = PublicKey::from_bytes(public_key).map_err(|_| AttestationError::InvalidKey)?;
+        self.keys.push((key, level));
+        Ok(())
+    }
+
+    /// Level of the signature. Unsigned QR codes are
+    /// `VerifierLevel::SelfSignedTest`, a signature which does not
+    /// verify under any trusted key is an error.
+    pub fn level(&self, signed: &SignedQr) -> Result<VerifierLevel, AttestationError> {
+        let signature = match &signed.signature {
+            None => return Ok(VerifierLevel::SelfSignedTest),
+            Some(signature) => Signature::try_from(&signature[..])
+                .map_err(|_| AttestationError::InvalidSignature)?,
+        };
+        let payload = signed.qr.to_bytes()?;
+        self.keys
+            .iter()
+            .find(|(key, _)| key.verify(&payload, &signature).is_ok())
+            .map(|(_, level)| *level)
+            .ok_or(AttestationError::UntrustedKey)
+    }
+}
+
+/// Verifies the proof and the signature. Returns the trust level of
+/// the app which produced the QR code.
+#[cfg(feature = "verifier")]
+pub fn verify_signed(
+    signed: &SignedQr,
+    photo_digest: &PhotosDigest,
+    trusted: &TrustedKeys,
+) -> Result<VerifierLevel, AttestationError> {
+    zk::verify_proof(&signed.qr, photo_digest)?;
+    trusted.level(signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{
+        Challenge, Public, Relation, EMBEDDED_CIRCUIT, EMBEDDED_KEY, FIELD_LENGTH,
+        QR_FORMAT_VERSION, SEALED_PROOF_LENGTH,
+    };
+
+    const APP_KEY: [u8; 32] = [7; 32];
+
+    fn qr() -> ProofQrCode {
+        ProofQrCode {
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            proof: vec![1; SEALED_PROOF_LENGTH],
+            challenge: Challenge::new(vec![9; FIELD_LENGTH]).unwrap(),
+            second_proof: None,
+        }
+    }
+
+    fn trusted() -> TrustedKeys {
+        let mut trusted = TrustedKeys::new();
+        trusted
+            .add(&public_key(&APP_KEY).unwrap(), VerifierLevel::Professional)
+            .unwrap();
+        trusted
+    }
+
+    #[test]
+    fn signed_qr_string_round_trip() {
+        let signed = sign_qr(&qr(), &APP_KEY).unwrap();
+        let s = signed.to_string();
+        assert!(s.starts_with(&qr().to_string()));
+        assert_eq!(s.parse::<SignedQr>(), Ok(signed));
+
+        let unsigned = SignedQr::unsigned(qr());
+        assert_eq!(unsigned.to_string(), qr().to_string());
+        assert_eq!(unsigned.to_string().parse::<SignedQr>(), Ok(unsigned));
+    }
+
+    #[test]
+    fn signed_legacy_qr_has_four_parts() {
+        let mut legacy = qr();
+        legacy.version = 0;
+        legacy.key_id = None;
+        let s = sign_qr(&legacy, &APP_KEY).unwrap().to_string();
+        assert_eq!(s.split(';').count(), 4);
+        let parsed: SignedQr = s.parse().unwrap();
+        assert_eq!(parsed.qr, legacy);
+        assert_eq!(trusted().level(&parsed), Ok(VerifierLevel::Professional));
+    }
+
+    #[test]
+    fn signed_with_trusted_key() {
+        let signed = sign_qr(&qr(), &APP_KEY).unwrap();
+        assert_eq!(trusted().level(&signed), Ok(VerifierLevel::Professional));
+    }
+
+    #[test]
+    fn signed_with_wrong_key() {
+        let signed = sign_qr(&qr(), &[8; 32]).unwrap();
+        assert_eq!(
+            trusted().level(&signed),
+            Err(AttestationError::UntrustedKey)
+        );
+
+        let mut tampered = sign_qr(&qr(), &APP_KEY).unwrap();
+        tampered.qr.public.delta = 17;
+        assert_eq!(
+            trusted().level(&tampered),
+            Err(AttestationError::UntrustedKey)
+        );
+    }
+
+    #[test]
+    fn unsigned_is_self_signed_test() {
+        let unsigned = SignedQr::unsigned(qr());
+        assert_eq!(
+            trusted().level(&unsigned),
+            Ok(VerifierLevel::SelfSignedTest)
+        );
+        assert_eq!(
+            TrustedKeys::new().level(&unsigned),
+            Ok(VerifierLevel::SelfSignedTest)
+        );
+    }
+
+    #[test]
+    fn invalid_keys_and_signatures() {
+        assert_eq!(
+            sign_qr(&qr(), &[1, 2, 3]).err(),
+            Some(AttestationError::InvalidKey)
+        );
+        assert_eq!(
+            TrustedKeys::new().add(&[1, 2, 3], VerifierLevel::Professional),
+            Err(AttestationError::InvalidKey)
+        );
+        let s = format!(
+            "{};{}",
+            qr().to_string(),
+            bs58::encode(&[1, 2]).into_string()
+        );
+        assert_eq!(
+            s.parse::<SignedQr>(),
+            Err(AttestationError::InvalidSignature)
+        );
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn verify_signed_proof() {
+        use crate::api::{Private, PrivateKey, QrRequest};
+
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        let rq = QrRequest::build(
+            Public::older_than(18, 2020, 1200).unwrap(),
+            Private::with(2001, PrivateKey::default(), photos_digest.clone()).unwrap(),
+        )
+        .unwrap();
+        let p = zk::generate_proof(rq).unwrap();
+        let signed: SignedQr = sign_qr(&p, &APP_KEY).unwrap().to_string().parse().unwrap();
+        assert_eq!(
+            verify_signed(&signed, &photos_digest, &trusted()),
+            Ok(VerifierLevel::Professional)
+        );
+        assert_eq!(
+            verify_signed(&SignedQr::unsigned(p), &photos_digest, &trusted()),
+            Ok(VerifierLevel::SelfSignedTest)
+        );
+        assert!(verify_signed(&signed, &PhotosDigest::default(), &trusted()).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+// Base45 of RFC 9285, the encoding of the EU digital COVID
+// certificate. The alphabet is the alphanumeric mode of QR codes,
+// which stores a character in 5.5 bits instead of the 8 bits of the
+// byte mode. A base45 string is longer than base58, but its QR code is
+// smaller.
+
+const ALPHABET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Encodes two bytes in three characters, a last odd byte in two.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(base45_length(bytes.len()));
+    for chunk in bytes.chunks(2) {
+        let (mut n, digits) = match chunk {
+            [a, b] => ((*a as usize) * 256 + *b as usize, 3),
+            [a] => (*a as usize, 2),
+            _ => unreachable!(),
+        };
+        for _ in 0..digits {
+            s.push(ALPHABET[n % 45] as char);
+            n /= 45;
+        }
+    }
+    s
+}
+
+/// Decodes base45 of at most `max_bytes` bytes. Fails on characters
+/// out of the alphabet, a dangling character and triples above
+/// 0xffff.
+pub fn decode(s: &str, max_bytes: usize) -> Option<Vec<u8>> {
+    if s.len() > base45_length(max_bytes) || s.len() % 3 == 1 {
+        return None;
+    }
+    let digits = s
+        .bytes()
+        .map(|c| ALPHABET.iter().position(|a| *a == c))
+        .collect::<Option<Vec<usize>>>()?;
+    let mut bytes = Vec::with_capacity(digits.len() * 2 / 3);
+    for chunk in digits.chunks(3) {
+        let n = chunk.iter().rev().fold(0, |n, d| n * 45 + d);
+        if chunk.len() == 3 {
+            if n > 0xffff {
+                return None;
+            }
+            bytes.push((n >> 8) as u8);
+            bytes.push(n as u8);
+        } else {
+            if n > 0xff {
+                return None;
+            }
+            bytes.push(n as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Length of `bytes` bytes encoded in base45.
+pub const fn base45_length(bytes: usize) -> usize {
+    bytes / 2 * 3 + bytes % 2 * 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_vectors() {
+        for (bytes, s) in &[
+            (&b"AB"[..], "BB8"),
+            (&b"Hello!!"[..], "%69 VD92EX0"),
+            (&b"base-45"[..], "UJCLQE7W581"),
+            (&b"ietf!"[..], "QED8WEX0"),
+            (&b""[..], ""),
+        ] {
+            assert_eq!(encode(bytes), *s);
+            assert_eq!(decode(s, 16).unwrap(), bytes.to_vec());
+        }
+    }
+
+    #[test]
+    fn invalid_strings() {
+        assert_eq!(decode("GGW", 16), None);
+        assert_eq!(decode("BB", 16), None);
+        assert_eq!(decode("B", 16), None);
+        assert_eq!(decode("bb8", 16), None);
+        assert_eq!(decode("BB8BB8", 3), None);
+        assert_eq!(decode("BB8BB8", 4), Some(b"ABAB".to_vec()));
+    }
+}
@@ -0,0 +1,20 @@
+// Generates a test QR code and prints its string.
+//
+//     legalage_prove --birthday DAY --delta DAYS [--relation older|younger]
+//         [--today DAY] [--now MINUTES] [--private-key BASE58]
+//         [--photo-digest DIGEST]
+
+use legalage_logic::cli::{self, EXIT_MALFORMED};
+use std::process;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let qr = cli::parse_prove_args(&args).and_then(|parsed| cli::prove(&parsed));
+    match qr {
+        Ok(qr) => println!("{}", qr),
+        Err(e) => {
+            eprintln!("legalage_prove: {}", e);
+            process::exit(EXIT_MALFORMED);
+        }
+    }
+}
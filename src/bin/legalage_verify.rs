@@ -0,0 +1,45 @@
+// Verifies a QR code string given as the argument or on stdin. Prints
+// the report as text and as one line of JSON. Exits 0 for a valid
+// code, 1 for a code which does not verify and 2 for a malformed code.
+//
+//     legalage_verify [--photo-digest DIGEST] [--today DAY] [--allow-unbound] [QR]
+
+use legalage_logic::cli::{self, EXIT_MALFORMED};
+use std::io::Read;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parsed = match cli::parse_verify_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("legalage_verify: {}", e);
+            eprintln!(
+                "usage: legalage_verify [--photo-digest DIGEST] [--today DAY] [--allow-unbound] [QR]"
+            );
+            process::exit(EXIT_MALFORMED);
+        }
+    };
+    let qr = match &parsed.qr {
+        Some(qr) => qr.clone(),
+        None => {
+            let mut input = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut input) {
+                eprintln!("legalage_verify: cannot read stdin: {}", e);
+                process::exit(EXIT_MALFORMED);
+            }
+            input
+        }
+    };
+    match cli::verify(&qr, &parsed) {
+        Ok(report) => {
+            println!("{}", report.to_text());
+            println!("{}", report.to_json());
+            process::exit(report.exit_code());
+        }
+        Err(e) => {
+            eprintln!("legalage_verify: {}", e);
+            process::exit(EXIT_MALFORMED);
+        }
+    }
+}
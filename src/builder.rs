@@ -0,0 +1,229 @@
+// Builder of QR code requests from calendar dates. The caller gives
+// the birthday as a date, the age in years and the current UTC time;
+// the builder converts them to the julian days, the delta and the
+// minutes since midnight of `Public`.
+
+use crate::api::{PhotosDigest, Private, PrivateKey, QrRequest, Relation, ValidationError};
+use crate::date::{julian_from_datetime, julian_from_ymd, DateError};
+use crate::policy::{AgePolicy, PolicyError};
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+/// Error building a request.
+#[derive(PartialEq, Debug, Clone)]
+pub enum BuildError {
+    /// A field of the builder has not been set.
+    MissingField(&'static str),
+    /// The birthday or the time is not a valid date in range.
+    Date(DateError),
+    /// The years are not positive or too large.
+    Policy(PolicyError),
+    /// The request is not valid, e.g. the birthday is after today.
+    Invalid(ValidationError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::MissingField(field) => write!(f, "missing field '{}'", field),
+            BuildError::Date(e) => write!(f, "{}", e),
+            BuildError::Policy(e) => write!(f, "{}", e),
+            BuildError::Invalid(e) => write!(f, "invalid request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<DateError> for BuildError {
+    fn from(e: DateError) -> Self {
+        BuildError::Date(e)
+    }
+}
+
+impl From<PolicyError> for BuildError {
+    fn from(e: PolicyError) -> Self {
+        BuildError::Policy(e)
+    }
+}
+
+impl From<ValidationError> for BuildError {
+    fn from(e: ValidationError) -> Self {
+        BuildError::Invalid(e)
+    }
+}
+
+/// Builder of a request for one relation, see `QrRequest::builder`.
+#[derive(Debug, Default)]
+pub struct RequestBuilder {
+    relation: Option<Relation>,
+    years: Option<i32>,
+    birthday: Option<Result<i32, DateError>>,
+    now: Option<DateTime<Utc>>,
+    private_key: Option<PrivateKey>,
+    photos_digest: Option<PhotosDigest>,
+}
+
+impl QrRequest {
+    /// Builder of a request from calendar dates.
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder::default()
+    }
+}
+
+impl RequestBuilder {
+    pub fn relation(mut self, relation: Relation) -> Self {
+        self.relation = Some(relation);
+        self
+    }
+
+    /// Age in years: "older" holds from the birthday of that age on,
+    /// "younger" until the day before it, see `AgePolicy`.
+    pub fn min_age_years(mut self, years: i32) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    /// Gregorian birthday. An invalid date is reported by `build`.
+    pub fn birthday_date(mut self, year: i32, month: u32, day: u32) -> Self {
+        self.birthday = Some(julian_from_ymd(year, month, day));
+        self
+    }
+
+    /// Time of the request, giving `today` and `now` in UTC.
+    pub fn now_utc(mut self, now: DateTime<Utc>) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    pub fn private_key(mut self, private_key: PrivateKey) -> Self {
+        self.private_key = Some(private_key);
+        self
+    }
+
+    pub fn photos_digest(mut self, photos_digest: PhotosDigest) -> Self {
+        self.photos_digest = Some(photos_digest);
+        self
+    }
+
+    /// Request which passes `QrRequest::validate`. Whether the relation
+    /// holds is not checked, a false relation is proved with a decoy.
+    pub fn build(self) -> Result<QrRequest, BuildError> {
+        let relation = self.relation.ok_or(BuildError::MissingField("relation"))?;
+        let years = self
+            .years
+            .ok_or(BuildError::MissingField("min_age_years"))?;
+        let birthday = self
+            .birthday
+            .ok_or(BuildError::MissingField("birthday"))??;
+        let now = self.now.ok_or(BuildError::MissingField("now"))?;
+        let private_key = self
+            .private_key
+            .ok_or(BuildError::MissingField("private_key"))?;
+        let photos_digest = self
+            .photos_digest
+            .ok_or(BuildError::MissingField("photos_digest"))?;
+
+        let (today, minutes) = julian_from_datetime(now)?;
+        let policy = match relation {
+            Relation::Older => AgePolicy::at_least_years(years),
+            Relation::Younger => AgePolicy::under_years(years),
+        };
+        let public = policy.public(today, minutes)?;
+        let private = Private::with(birthday, private_key, photos_digest)?;
+        QrRequest::build(public, private).map_err(BuildError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn builder(relation: Relation, now: DateTime<Utc>) -> RequestBuilder {
+        QrRequest::builder()
+            .relation(relation)
+            .min_age_years(18)
+            .now_utc(now)
+            .private_key(PrivateKey::new(vec![10]).unwrap())
+            .photos_digest(PhotosDigest::new(vec![2, 7]).unwrap())
+    }
+
+    fn holds(relation: Relation, birthday: (i32, u32, u32), now: DateTime<Utc>) -> bool {
+        builder(relation, now)
+            .birthday_date(birthday.0, birthday.1, birthday.2)
+            .build()
+            .unwrap()
+            .is_relation_valid()
+            .unwrap()
+    }
+
+    #[test]
+    fn eighteen_years_ago_today() {
+        let now = Utc.ymd(2020, 5, 17).and_hms(13, 45, 0);
+        let rq = builder(Relation::Older, now)
+            .birthday_date(2002, 5, 17)
+            .build()
+            .unwrap();
+        assert_eq!(rq.public.today, julian_from_ymd(2020, 5, 17).unwrap());
+        assert_eq!(rq.public.now, 13 * 60 + 45);
+        assert_eq!(rq.private.birthday, julian_from_ymd(2002, 5, 17).unwrap());
+        assert_eq!(rq.is_relation_valid(), Ok(true));
+        assert!(!holds(Relation::Older, (2002, 5, 18), now));
+        assert!(!holds(Relation::Younger, (2002, 5, 17), now));
+        assert!(holds(Relation::Younger, (2002, 5, 18), now));
+    }
+
+    #[test]
+    fn leap_day_birthday() {
+        let birthday = (2004, 2, 29);
+        assert!(!holds(
+            Relation::Older,
+            birthday,
+            Utc.ymd(2022, 2, 28).and_hms(23, 59, 0)
+        ));
+        assert!(holds(
+            Relation::Older,
+            birthday,
+            Utc.ymd(2022, 3, 1).and_hms(0, 0, 0)
+        ));
+    }
+
+    #[test]
+    fn missing_fields() {
+        let now = Utc.ymd(2020, 5, 17).and_hms(12, 0, 0);
+        assert_eq!(
+            builder(Relation::Older, now).build(),
+            Err(BuildError::MissingField("birthday"))
+        );
+        assert_eq!(
+            QrRequest::builder().build(),
+            Err(BuildError::MissingField("relation"))
+        );
+        let rq = QrRequest::builder()
+            .relation(Relation::Older)
+            .min_age_years(18)
+            .birthday_date(2002, 5, 17)
+            .now_utc(now)
+            .build();
+        assert_eq!(rq, Err(BuildError::MissingField("private_key")));
+    }
+
+    #[test]
+    fn invalid_dates() {
+        let now = Utc.ymd(2020, 5, 17).and_hms(12, 0, 0);
+        assert_eq!(
+            builder(Relation::Older, now)
+                .birthday_date(2001, 2, 29)
+                .build(),
+            Err(BuildError::Date(DateError::InvalidDate))
+        );
+        assert_eq!(
+            builder(Relation::Older, now)
+                .birthday_date(2001, 5, 17)
+                .min_age_years(0)
+                .build(),
+            Err(BuildError::Policy(PolicyError::InvalidYears))
+        );
+    }
+}
@@ -0,0 +1,634 @@
+// C interface for the iOS app. Compiled with the `ffi` feature. The
+// declarations are in `include/legalage.h`.
+
+use crate::api::{PhotosDigest, Private, PrivateKey};
+#[cfg(feature = "verifier")]
+use crate::api::{ProofQrCode, QrError};
+#[cfg(feature = "prover")]
+use crate::api::{Public, QrRequest, Relation, EMBEDDED_CIRCUIT};
+use crate::zk::{self, ZkError};
+use std::convert::TryFrom;
+#[cfg(feature = "verifier")]
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{ptr, slice, str};
+
+/// Success. Other positive codes are given by `ZkError::code`.
+pub const LEGALAGE_OK: i32 = 0;
+
+/// A null pointer with non-zero length or a string which is not UTF-8.
+pub const LEGALAGE_INVALID_ARGUMENT: i32 = -1;
+
+/// Unexpected internal error.
+pub const LEGALAGE_PANIC: i32 = -2;
+
+/// Heap buffer returned to the caller. Must be released by
+/// `legalage_free_buffer`. Empty buffers have a null `data`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct LegalageBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl LegalageBuffer {
+    fn empty() -> Self {
+        LegalageBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(v: Vec<u8>) -> Self {
+        if v.is_empty() {
+            return Self::empty();
+        }
+        let boxed = v.into_boxed_slice();
+        let len = boxed.len();
+        LegalageBuffer {
+            data: Box::into_raw(boxed) as *mut u8,
+            len: len,
+        }
+    }
+}
+
+/// Public part of the request, `Public` without the band and the
+/// nonce.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LegalagePublic {
+    pub today: i32,
+    pub now: i32,
+    /// `Relation` as `u8`.
+    pub relation: u8,
+    pub delta: i32,
+}
+
+/// Reads a byte slice passed from C. A null pointer is accepted for an
+/// empty slice only.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], i32> {
+    if len == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(LEGALAGE_INVALID_ARGUMENT)
+    } else {
+        Ok(slice::from_raw_parts(data, len))
+    }
+}
+
+fn error_code(e: ZkError) -> i32 {
+    e.code()
+}
+
+/// Runs `body`, stores the error code and converts errors and panics
+/// to `on_error`.
+unsafe fn guard<T, F>(error: *mut i32, on_error: T, body: F) -> T
+where
+    F: FnOnce() -> Result<T, i32>,
+{
+    let (value, code) = match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => (value, LEGALAGE_OK),
+        Ok(Err(code)) => (on_error, code),
+        Err(_) => (on_error, LEGALAGE_PANIC),
+    };
+    if !error.is_null() {
+        *error = code;
+    }
+    value
+}
+
+#[cfg(feature = "prover")]
+unsafe fn generate_qr(
+    public: &LegalagePublic,
+    birthday: i32,
+    private_key: *const u8,
+    private_key_len: usize,
+    photos_digest: *const u8,
+    photos_digest_len: usize,
+) -> Result<String, i32> {
+    let relation = Relation::try_from(public.relation).map_err(|e| error_code(e.into()))?;
+    let rq = QrRequest {
+        circuit: EMBEDDED_CIRCUIT,
+        public: Public {
+            today: public.today,
+            now: public.now,
+            relation: relation,
+            delta: public.delta,
+            second: None,
+            nonce: None,
+        },
+        private: Private {
+            birthday: birthday,
+            private_key: PrivateKey::new(bytes(private_key, private_key_len)?.to_vec())
+                .map_err(|e| error_code(e.into()))?,
+            photos_digest: PhotosDigest::new(bytes(photos_digest, photos_digest_len)?.to_vec())
+                .map_err(|e| error_code(e.into()))?,
+        },
+        allow_unbound_proof: false,
+    };
+    let qr = zk::generate_proof(rq).map_err(error_code)?;
+    Ok(qr.to_string())
+}
+
+#[cfg(feature = "verifier")]
+unsafe fn verify_qr(
+    qr: &[u8],
+    photo_digest: *const u8,
+    photo_digest_len: usize,
+) -> Result<bool, i32> {
+    let qr = str::from_utf8(qr).map_err(|_| LEGALAGE_INVALID_ARGUMENT)?;
+    let photo_digest = bytes(photo_digest, photo_digest_len)?;
+    let parsed: ProofQrCode = qr.parse().map_err(|e: QrError| error_code(e.into()))?;
+    zk::verify_proof_with_digest_bytes(&parsed, photo_digest).map_err(error_code)?;
+    Ok(true)
+}
+
+/// Generates the proof and returns the UTF-8 encoded QR code string,
+/// not terminated by zero.
+///
+/// # Safety
+///
+/// Each pointer must be valid for reads of its length and `error` must
+/// be null or valid for writes.
+#[cfg(feature = "prover")]
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn legalage_generate_qr(
+    today: i32,
+    now: i32,
+    relation: u8,
+    delta: i32,
+    birthday: i32,
+    private_key: *const u8,
+    private_key_len: usize,
+    photos_digest: *const u8,
+    photos_digest_len: usize,
+    error: *mut i32,
+) -> LegalageBuffer {
+    let public = LegalagePublic {
+        today: today,
+        now: now,
+        relation: relation,
+        delta: delta,
+    };
+    guard(error, LegalageBuffer::empty(), || {
+        let qr = generate_qr(
+            &public,
+            birthday,
+            private_key,
+            private_key_len,
+            photos_digest,
+            photos_digest_len,
+        )?;
+        Ok(LegalageBuffer::from_vec(qr.into_bytes()))
+    })
+}
+
+/// Same as `legalage_generate_qr`, stores the zero terminated QR code
+/// string to `out` and returns the error code. The string must be
+/// released by `legalage_string_free`; `out` is set to null on error.
+///
+/// # Safety
+///
+/// `public` must be null or valid for reads, each byte pointer must be
+/// valid for reads of its length and `out` must be null or valid for
+/// writes.
+#[cfg(feature = "prover")]
+#[no_mangle]
+pub unsafe extern "C" fn legalage_generate_qr_string(
+    public: *const LegalagePublic,
+    birthday: i32,
+    private_key: *const u8,
+    private_key_len: usize,
+    photos_digest: *const u8,
+    photos_digest_len: usize,
+    out: *mut *mut c_char,
+) -> i32 {
+    if public.is_null() || out.is_null() {
+        return LEGALAGE_INVALID_ARGUMENT;
+    }
+    let mut code = LEGALAGE_OK;
+    *out = guard(&mut code, ptr::null_mut(), || {
+        let qr = generate_qr(
+            &*public,
+            birthday,
+            private_key,
+            private_key_len,
+            photos_digest,
+            photos_digest_len,
+        )?;
+        // The QR code string is base58 or ASCII, without zeros.
+        Ok(CString::new(qr).map_err(|_| LEGALAGE_PANIC)?.into_raw())
+    });
+    code
+}
+
+/// Decodes and verifies the UTF-8 encoded QR code string. Returns true
+/// if the proof is valid, otherwise the reason is stored in `error`.
+///
+/// # Safety
+///
+/// Each pointer must be valid for reads of its length and `error` must
+/// be null or valid for writes.
+#[cfg(feature = "verifier")]
+#[no_mangle]
+pub unsafe extern "C" fn legalage_verify_qr(
+    qr: *const u8,
+    qr_len: usize,
+    photo_digest: *const u8,
+    photo_digest_len: usize,
+    error: *mut i32,
+) -> bool {
+    guard(error, false, || {
+        verify_qr(bytes(qr, qr_len)?, photo_digest, photo_digest_len)
+    })
+}
+
+/// Same as `legalage_verify_qr` for a zero terminated QR code string.
+/// Returns `LEGALAGE_OK` if the proof is valid, otherwise the error
+/// code.
+///
+/// # Safety
+///
+/// `qr` must be null or a zero terminated string and `photo_digest`
+/// must be valid for reads of `photo_digest_len` bytes.
+#[cfg(feature = "verifier")]
+#[no_mangle]
+pub unsafe extern "C" fn legalage_verify_qr_string(
+    qr: *const c_char,
+    photo_digest: *const u8,
+    photo_digest_len: usize,
+) -> i32 {
+    if qr.is_null() {
+        return LEGALAGE_INVALID_ARGUMENT;
+    }
+    let mut code = LEGALAGE_OK;
+    guard(&mut code, false, || {
+        verify_qr(
+            CStr::from_ptr(qr).to_bytes(),
+            photo_digest,
+            photo_digest_len,
+        )
+    });
+    code
+}
+
+/// Generates a random private key.
+///
+/// # Safety
+///
+/// Each pointer must be valid for reads of its length and `error` must
+/// be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn legalage_generate_private_key(error: *mut i32) -> LegalageBuffer {
+    guard(error, LegalageBuffer::empty(), || {
+        Ok(LegalageBuffer::from_vec(
+            zk::generate_random_private_key().into(),
+        ))
+    })
+}
+
+/// Computes the card key from the private values.
+///
+/// # Safety
+///
+/// Each pointer must be valid for reads of its length and `error` must
+/// be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn legalage_compute_card_key(
+    birthday: i32,
+    private_key: *const u8,
+    private_key_len: usize,
+    photos_digest: *const u8,
+    photos_digest_len: usize,
+    error: *mut i32,
+) -> LegalageBuffer {
+    guard(error, LegalageBuffer::empty(), || {
+        let private = Private {
+            birthday: birthday,
+            private_key: PrivateKey::new(bytes(private_key, private_key_len)?.to_vec())
+                .map_err(|e| error_code(e.into()))?,
+            photos_digest: PhotosDigest::new(bytes(photos_digest, photos_digest_len)?.to_vec())
+                .map_err(|e| error_code(e.into()))?,
+        };
+        let card_key = zk::generate_card_key(&private).map_err(|e| error_code(e.into()))?;
+        Ok(LegalageBuffer::from_vec(card_key.into()))
+    })
+}
+
+/// Releases a string returned by this library, null is ignored.
+///
+/// # Safety
+///
+/// The string must have been returned by this library and must not be
+/// used or released again.
+#[no_mangle]
+pub unsafe extern "C" fn legalage_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Releases a buffer returned by this library.
+///
+/// # Safety
+///
+/// The buffer must have been returned by this library and must not be
+/// used or released again.
+#[no_mangle]
+pub unsafe extern "C" fn legalage_free_buffer(buffer: LegalageBuffer) {
+    if !buffer.data.is_null() {
+        let raw = slice::from_raw_parts_mut(buffer.data, buffer.len) as *mut [u8];
+        drop(Box::from_raw(raw));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{QrError, QrSection, ValidationError};
+
+    unsafe fn to_vec(buffer: &LegalageBuffer) -> Vec<u8> {
+        bytes(buffer.data, buffer.len).unwrap().to_vec()
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn generate_and_verify() {
+        let private_key = [10u8];
+        let photos_digest = [2u8, 7];
+        let mut error = -100;
+        unsafe {
+            let qr = legalage_generate_qr(
+                2020,
+                1200,
+                Relation::Older as u8,
+                18,
+                2001,
+                private_key.as_ptr(),
+                private_key.len(),
+                photos_digest.as_ptr(),
+                photos_digest.len(),
+                &mut error,
+            );
+            assert_eq!(error, LEGALAGE_OK);
+            assert!(legalage_verify_qr(
+                qr.data,
+                qr.len,
+                photos_digest.as_ptr(),
+                photos_digest.len(),
+                &mut error
+            ));
+            assert_eq!(error, LEGALAGE_OK);
+            legalage_free_buffer(qr);
+        }
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn null_pointers() {
+        let mut error = 0;
+        unsafe {
+            let qr = legalage_generate_qr(
+                2020,
+                1200,
+                1,
+                18,
+                2001,
+                ptr::null(),
+                1,
+                ptr::null(),
+                0,
+                &mut error,
+            );
+            assert!(qr.data.is_null());
+            assert_eq!(error, LEGALAGE_INVALID_ARGUMENT);
+
+            assert!(!legalage_verify_qr(
+                ptr::null(),
+                5,
+                ptr::null(),
+                0,
+                &mut error
+            ));
+            assert_eq!(error, LEGALAGE_INVALID_ARGUMENT);
+
+            // The error may be ignored.
+            assert!(!legalage_verify_qr(
+                ptr::null(),
+                5,
+                ptr::null(),
+                0,
+                ptr::null_mut()
+            ));
+            legalage_free_buffer(LegalageBuffer::empty());
+        }
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn generate_and_verify_strings() {
+        let public = LegalagePublic {
+            today: 2020,
+            now: 1200,
+            relation: Relation::Older as u8,
+            delta: 18,
+        };
+        let private_key = [10u8];
+        let photos_digest = [2u8, 7];
+        let mut qr = ptr::null_mut();
+        unsafe {
+            let code = legalage_generate_qr_string(
+                &public,
+                2001,
+                private_key.as_ptr(),
+                private_key.len(),
+                photos_digest.as_ptr(),
+                photos_digest.len(),
+                &mut qr,
+            );
+            assert_eq!(code, LEGALAGE_OK);
+            assert!(!qr.is_null());
+            assert_eq!(
+                legalage_verify_qr_string(qr, photos_digest.as_ptr(), photos_digest.len()),
+                LEGALAGE_OK
+            );
+            let wrong = [3u8];
+            assert_ne!(
+                legalage_verify_qr_string(qr, wrong.as_ptr(), wrong.len()),
+                LEGALAGE_OK
+            );
+            legalage_string_free(qr);
+        }
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn strings_refuse_invalid_arguments() {
+        let public = LegalagePublic {
+            today: 2020,
+            now: 1200,
+            relation: 2,
+            delta: 18,
+        };
+        let mut qr = ptr::null_mut();
+        unsafe {
+            let code =
+                legalage_generate_qr_string(&public, 2001, ptr::null(), 0, ptr::null(), 0, &mut qr);
+            assert_eq!(code, ZkError::QrCode(QrError::RelationByte(2)).code());
+            assert!(qr.is_null());
+            let code = legalage_generate_qr_string(
+                ptr::null(),
+                2001,
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                &mut qr,
+            );
+            assert_eq!(code, LEGALAGE_INVALID_ARGUMENT);
+            let code = legalage_generate_qr_string(
+                &public,
+                2001,
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                ptr::null_mut(),
+            );
+            assert_eq!(code, LEGALAGE_INVALID_ARGUMENT);
+
+            assert_eq!(
+                legalage_verify_qr_string(ptr::null(), ptr::null(), 0),
+                LEGALAGE_INVALID_ARGUMENT
+            );
+            let invalid = [0xffu8, 0xfe, 0];
+            assert_eq!(
+                legalage_verify_qr_string(invalid.as_ptr() as *const c_char, ptr::null(), 0),
+                LEGALAGE_INVALID_ARGUMENT
+            );
+            legalage_string_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn header_declares_all_functions() {
+        let header = include_str!("../include/legalage.h");
+        let source = include_str!("c_api.rs");
+        let exported: Vec<&str> = source
+            .split("pub unsafe extern \"C\" fn ")
+            .skip(1)
+            .map(|rest| rest.split('(').next().unwrap())
+            .collect();
+        assert!(exported.len() >= 7);
+        for name in exported {
+            assert!(header.contains(&format!(" {}(", name)), "{}", name);
+        }
+        assert!(header.contains("} LegalagePublic;"));
+    }
+
+    #[cfg(feature = "verifier")]
+    #[test]
+    fn zero_length_qr() {
+        let mut error = 0;
+        let empty: [u8; 0] = [];
+        unsafe {
+            assert!(!legalage_verify_qr(
+                empty.as_ptr(),
+                0,
+                ptr::null(),
+                0,
+                &mut error
+            ));
+        }
+        assert_eq!(
+            error,
+            ZkError::QrCode(QrError::Truncated(QrSection::Payload)).code()
+        );
+    }
+
+    #[cfg(feature = "verifier")]
+    #[test]
+    fn invalid_utf8() {
+        let qr = [0xffu8, 0xfe, 0x3b];
+        let mut error = 0;
+        unsafe {
+            assert!(!legalage_verify_qr(
+                qr.as_ptr(),
+                qr.len(),
+                ptr::null(),
+                0,
+                &mut error
+            ));
+        }
+        assert_eq!(error, LEGALAGE_INVALID_ARGUMENT);
+    }
+
+    #[cfg(feature = "prover")]
+    #[test]
+    fn invalid_relation_and_key() {
+        let private_key = [0xffu8; 32];
+        let mut error = 0;
+        unsafe {
+            let qr = legalage_generate_qr(
+                2020,
+                1200,
+                2,
+                18,
+                2001,
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                &mut error,
+            );
+            assert!(qr.data.is_null());
+            assert_eq!(error, ZkError::QrCode(QrError::RelationByte(2)).code());
+
+            let key = legalage_compute_card_key(
+                2001,
+                private_key.as_ptr(),
+                private_key.len(),
+                ptr::null(),
+                0,
+                &mut error,
+            );
+            assert!(key.data.is_null());
+            assert_eq!(
+                error,
+                ZkError::InvalidInput(ValidationError::PrivateKey).code()
+            );
+        }
+    }
+
+    #[test]
+    fn private_key_and_card_key() {
+        let mut error = -100;
+        unsafe {
+            let key = legalage_generate_private_key(&mut error);
+            assert_eq!(error, LEGALAGE_OK);
+            assert_eq!(key.len, 32);
+            let photos_digest = [2u8, 7];
+            let card_key = legalage_compute_card_key(
+                2001,
+                key.data,
+                key.len,
+                photos_digest.as_ptr(),
+                photos_digest.len(),
+                &mut error,
+            );
+            assert_eq!(error, LEGALAGE_OK);
+            let expected = zk::generate_card_key(&Private {
+                birthday: 2001,
+                private_key: PrivateKey::new(to_vec(&key)).unwrap(),
+                photos_digest: PhotosDigest::new(photos_digest.to_vec()).unwrap(),
+            })
+            .unwrap();
+            assert_eq!(to_vec(&card_key), Vec::<u8>::from(expected));
+            legalage_free_buffer(key);
+            legalage_free_buffer(card_key);
+        }
+    }
+}
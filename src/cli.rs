@@ -0,0 +1,531 @@
+// Reference command line tools for partners integrating scanners,
+// compiled with the `cli` feature. The binaries `legalage_verify` and
+// `legalage_prove` only read the input and print; parsing of the
+// arguments and formatting of the output live here, so that admin
+// tooling of the web service can reuse them.
+
+use crate::api::{PhotosDigest, PrivateKey, ProofQrCode, Public, Relation, MINUTES_PER_DAY};
+#[cfg(feature = "prover")]
+use crate::api::{Private, QrRequest};
+use crate::date::{self, julian_from_ymd};
+use crate::zk::{self, VerificationOutcome, ZkError};
+use chrono::{Datelike, Timelike, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Exit code of a valid QR code.
+pub const EXIT_VALID: i32 = 0;
+
+/// Exit code of a well formed QR code which does not verify, or which
+/// is not from the verifier's day.
+pub const EXIT_NOT_SATISFIED: i32 = 1;
+
+/// Exit code of a malformed QR code or of invalid arguments, e.g. a
+/// zero photo digest without `--allow-unbound`.
+pub const EXIT_MALFORMED: i32 = 2;
+
+/// Error of the command line, always exits with `EXIT_MALFORMED`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum CliError {
+    /// The arguments cannot be parsed, the text is the reason.
+    Usage(String),
+    /// The library failed, e.g. the prover cannot be prepared.
+    Zk(ZkError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(e) => write!(f, "{}", e),
+            CliError::Zk(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<ZkError> for CliError {
+    fn from(e: ZkError) -> Self {
+        CliError::Zk(e)
+    }
+}
+
+fn usage<T>(message: String) -> Result<T, CliError> {
+    Err(CliError::Usage(message))
+}
+
+/// Julian day of the system clock.
+pub fn today() -> i32 {
+    let now = Utc::now().naive_utc();
+    julian_from_ymd(now.year(), now.month(), now.day()).expect("the clock is in range")
+}
+
+/// Minutes since midnight UTC of the system clock.
+pub fn now_minutes() -> i32 {
+    let now = Utc::now().naive_utc();
+    (now.hour() * 60 + now.minute()) as i32
+}
+
+/// Parses a day given as `YYYY-MM-DD` or as a julian day number.
+pub fn parse_day(s: &str) -> Result<i32, CliError> {
+    let invalid = || CliError::Usage(format!("invalid day '{}'", s));
+    let parts: Vec<&str> = s.split('-').collect();
+    match parts[..] {
+        [y, m, d] => {
+            let (y, m, d) = (
+                y.parse().map_err(|_| invalid())?,
+                m.parse().map_err(|_| invalid())?,
+                d.parse().map_err(|_| invalid())?,
+            );
+            julian_from_ymd(y, m, d).map_err(|_| invalid())
+        }
+        [j] => {
+            let j: i32 = j.parse().map_err(|_| invalid())?;
+            date::ymd_from_julian(j).map_err(|_| invalid())?;
+            Ok(j)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a photo digest, hex with a `0x` prefix or base58 otherwise.
+pub fn parse_photo_digest(s: &str) -> Result<PhotosDigest, CliError> {
+    let invalid = || CliError::Usage(format!("invalid photo digest '{}'", s));
+    match s.strip_prefix("0x") {
+        Some(h) => PhotosDigest::new(hex::decode(h).map_err(|_| invalid())?).map_err(|_| invalid()),
+        None => PhotosDigest::from_str(s).map_err(|_| invalid()),
+    }
+}
+
+/// Arguments of `legalage_verify`.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct VerifyArgs {
+    /// The QR code string, read from stdin when missing.
+    pub qr: Option<String>,
+    /// Without the photo digest the QR code is only decoded.
+    pub photo_digest: Option<PhotosDigest>,
+    /// Verifier's julian day, the system date when missing.
+    pub today: Option<i32>,
+    /// Accepts a zero photo digest, see `PhotosDigest::is_unbound`. The
+    /// photo digest then defaults to the zero test digest of
+    /// `legalage_prove`.
+    pub allow_unbound: bool,
+}
+
+/// Value of the option at `args[i]`, advances `i`.
+fn value<'a>(args: &'a [String], i: &mut usize) -> Result<&'a str, CliError> {
+    let option = &args[*i];
+    *i += 1;
+    match args.get(*i) {
+        Some(value) => Ok(value),
+        None => usage(format!("missing value of {}", option)),
+    }
+}
+
+/// Parses the arguments of `legalage_verify` without the program name:
+/// `[--photo-digest DIGEST] [--today DAY] [--allow-unbound] [QR]`.
+pub fn parse_verify_args(args: &[String]) -> Result<VerifyArgs, CliError> {
+    let mut parsed = VerifyArgs::default();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--photo-digest" => {
+                parsed.photo_digest = Some(parse_photo_digest(value(args, &mut i)?)?)
+            }
+            "--today" => parsed.today = Some(parse_day(value(args, &mut i)?)?),
+            "--allow-unbound" => parsed.allow_unbound = true,
+            option if option.starts_with("--") => {
+                return usage(format!("unknown option {}", option))
+            }
+            qr if parsed.qr.is_none() => parsed.qr = Some(qr.to_string()),
+            _ => return usage("more than one QR code".to_string()),
+        }
+        i += 1;
+    }
+    Ok(parsed)
+}
+
+/// Result of `legalage_verify`, printed as text and as JSON.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    /// Decoded public part, `None` if the string cannot be decoded.
+    pub public: Option<Public>,
+    /// Text form of the public part.
+    pub text: Option<String>,
+    /// The QR code is from the verifier's day.
+    pub date_matches: bool,
+    /// `valid`, `valid unbound`, `not satisfied`, `malformed` or
+    /// `unbound`, `None` if the proof was not verified for lack of a
+    /// photo digest. `valid unbound` marks a proof accepted with the
+    /// zero photo digest of `--allow-unbound`.
+    pub outcome: Option<String>,
+    /// Why the QR code cannot be decoded.
+    pub error: Option<String>,
+}
+
+fn outcome_name(outcome: VerificationOutcome) -> &'static str {
+    match outcome {
+        VerificationOutcome::Valid => "valid",
+        VerificationOutcome::ValidUnbound => "valid unbound",
+        VerificationOutcome::NotSatisfied => "not satisfied",
+        VerificationOutcome::Malformed => "malformed",
+        VerificationOutcome::Unbound => "unbound",
+    }
+}
+
+impl VerifyReport {
+    /// Exit code of the report, the same classification as
+    /// `VerificationOutcome`. A code which only decoded is valid.
+    pub fn exit_code(&self) -> i32 {
+        match self.outcome.as_deref() {
+            _ if self.public.is_none() => EXIT_MALFORMED,
+            Some("malformed") | Some("unbound") => EXIT_MALFORMED,
+            Some("not satisfied") => EXIT_NOT_SATISFIED,
+            _ if !self.date_matches => EXIT_NOT_SATISFIED,
+            _ => EXIT_VALID,
+        }
+    }
+
+    /// Human readable report.
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![];
+        if let Some(public) = &self.public {
+            lines.push(format!("claim: {}", public));
+            lines.push(format!("today: {}", public.today));
+            lines.push(format!("now: {}", public.now));
+            lines.push(format!("relation: {}", public.relation));
+            lines.push(format!("delta: {}", public.delta));
+            if let Some(bound) = &public.second {
+                lines.push(format!("second: {} {}", bound.relation, bound.delta));
+            }
+            if let Some(nonce) = &public.nonce {
+                lines.push(format!("nonce: {}", nonce));
+            }
+            if !self.date_matches {
+                lines.push("date: not the verifier's day".to_string());
+            }
+        }
+        match (&self.outcome, &self.error) {
+            (_, Some(error)) => lines.push(format!("error: {}", error)),
+            (Some(outcome), None) => lines.push(format!("result: {}", outcome)),
+            (None, None) => lines.push("result: not verified, no photo digest".to_string()),
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("report serializes")
+    }
+}
+
+/// Decodes the QR code and verifies it if the photo digest is known.
+/// Errors of the verifier itself are returned, a broken QR code is
+/// reported.
+#[cfg(feature = "verifier")]
+pub fn verify(qr: &str, args: &VerifyArgs) -> Result<VerifyReport, CliError> {
+    let parsed = match ProofQrCode::from_str(qr.trim()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(VerifyReport {
+                public: None,
+                text: None,
+                date_matches: false,
+                outcome: Some(outcome_name(VerificationOutcome::Malformed).to_string()),
+                error: Some(e.to_string()),
+            })
+        }
+    };
+    let today = args.today.unwrap_or_else(today);
+    let unbound = PhotosDigest::default();
+    let digest = match &args.photo_digest {
+        None if args.allow_unbound => Some(&unbound),
+        digest => digest.as_ref(),
+    };
+    let outcome = match digest {
+        Some(digest) => {
+            let result = zk::verify_proof_binding(&parsed, digest, args.allow_unbound);
+            Some(outcome_name(VerificationOutcome::from_binding(result, digest)?).to_string())
+        }
+        None => None,
+    };
+    Ok(VerifyReport {
+        text: Some(parsed.public.to_string()),
+        date_matches: parsed.public.today == today,
+        public: Some(parsed.public),
+        outcome: outcome,
+        error: None,
+    })
+}
+
+/// Arguments of `legalage_prove`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ProveArgs {
+    pub birthday: i32,
+    pub relation: Relation,
+    pub delta: i32,
+    /// Julian day of the proof, the system date when missing.
+    pub today: Option<i32>,
+    /// Minutes after midnight UTC, the system time when missing.
+    pub now: Option<i32>,
+    pub private_key: PrivateKey,
+    pub photo_digest: PhotosDigest,
+}
+
+/// Parses the arguments of `legalage_prove` without the program name:
+/// `--birthday DAY --delta DAYS [--relation older|younger] [--today
+/// DAY] [--now MINUTES] [--private-key BASE58] [--photo-digest DIGEST]`.
+/// The relation defaults to `older`, the key and digest to test values.
+pub fn parse_prove_args(args: &[String]) -> Result<ProveArgs, CliError> {
+    let (mut birthday, mut delta, mut relation) = (None, None, Relation::Older);
+    let (mut today, mut now) = (None, None);
+    let mut private_key = PrivateKey::default();
+    let mut photo_digest = PhotosDigest::default();
+    let number = |s: &str| {
+        s.parse::<i32>()
+            .or_else(|_| usage(format!("invalid number '{}'", s)))
+    };
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--birthday" => birthday = Some(parse_day(value(args, &mut i)?)?),
+            "--delta" => delta = Some(number(value(args, &mut i)?)?),
+            "--relation" => {
+                let s = value(args, &mut i)?;
+                relation = s
+                    .parse()
+                    .or_else(|_| usage(format!("invalid relation '{}'", s)))?
+            }
+            "--today" => today = Some(parse_day(value(args, &mut i)?)?),
+            "--now" => {
+                let minutes = number(value(args, &mut i)?)?;
+                if minutes < 0 || minutes >= MINUTES_PER_DAY {
+                    return usage(format!("invalid time {}", minutes));
+                }
+                now = Some(minutes)
+            }
+            "--private-key" => {
+                let s = value(args, &mut i)?;
+                private_key = s
+                    .parse()
+                    .or_else(|_| usage(format!("invalid private key '{}'", s)))?
+            }
+            "--photo-digest" => photo_digest = parse_photo_digest(value(args, &mut i)?)?,
+            other => return usage(format!("unknown argument {}", other)),
+        }
+        i += 1;
+    }
+    Ok(ProveArgs {
+        birthday: birthday.ok_or_else(|| CliError::Usage("missing --birthday".to_string()))?,
+        relation: relation,
+        delta: delta.ok_or_else(|| CliError::Usage("missing --delta".to_string()))?,
+        today: today,
+        now: now,
+        private_key: private_key,
+        photo_digest: photo_digest,
+    })
+}
+
+impl ProveArgs {
+    /// The request to be proved.
+    #[cfg(feature = "prover")]
+    pub fn request(&self) -> Result<QrRequest, CliError> {
+        let today = self.today.unwrap_or_else(today);
+        let now = self.now.unwrap_or_else(now_minutes);
+        let public = match self.relation {
+            Relation::Older => Public::older_than(self.delta, today, now),
+            Relation::Younger => Public::younger_than(self.delta, today, now),
+        }
+        .map_err(|e| CliError::Usage(e.to_string()))?;
+        let private = Private::with(
+            self.birthday,
+            self.private_key.clone(),
+            self.photo_digest.clone(),
+        )
+        .map_err(|e| CliError::Usage(e.to_string()))?;
+        let mut rq =
+            QrRequest::build(public, private).map_err(|e| CliError::Usage(e.to_string()))?;
+        // The default test digest is zero, its proofs are only for
+        // trying the tool.
+        rq.allow_unbound_proof = self.photo_digest.is_unbound();
+        Ok(rq)
+    }
+}
+
+/// Generates the QR code string of the arguments. A relation which
+/// does not hold gives a decoy proof which does not verify, as on the
+/// phone.
+#[cfg(feature = "prover")]
+pub fn prove(args: &ProveArgs) -> Result<String, CliError> {
+    Ok(zk::generate_proof(args.request()?)?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn days() {
+        assert_eq!(parse_day("2000-01-01"), Ok(2451545));
+        assert_eq!(parse_day("2451545"), Ok(2451545));
+        for invalid in &["2001-02-29", "0", "x", "2000-1", "-5", ""] {
+            assert!(parse_day(invalid).is_err(), "{}", invalid);
+        }
+        assert!(today() > parse_day("2020-01-01").unwrap());
+    }
+
+    #[test]
+    fn photo_digests() {
+        let digest = PhotosDigest::new(vec![2, 7]).unwrap();
+        assert_eq!(parse_photo_digest("0x0207"), Ok(digest.clone()));
+        assert_eq!(parse_photo_digest(&digest.to_string()), Ok(digest));
+        assert!(parse_photo_digest("0xzz").is_err());
+        assert!(parse_photo_digest(&format!("0x{}", "ff".repeat(32))).is_err());
+        assert!(parse_photo_digest("0OIl").is_err());
+    }
+
+    #[test]
+    fn verify_arguments() {
+        assert_eq!(
+            parse_verify_args(&args("--photo-digest 0x0207 --today 2020-05-17 QR")),
+            Ok(VerifyArgs {
+                qr: Some("QR".to_string()),
+                photo_digest: Some(PhotosDigest::new(vec![2, 7]).unwrap()),
+                today: Some(parse_day("2020-05-17").unwrap()),
+                allow_unbound: false,
+            })
+        );
+        assert_eq!(parse_verify_args(&[]), Ok(VerifyArgs::default()));
+        assert!(
+            parse_verify_args(&args("--allow-unbound QR"))
+                .unwrap()
+                .allow_unbound
+        );
+        for invalid in &["--today", "--color red", "QR QR", "--today x"] {
+            assert!(parse_verify_args(&args(invalid)).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn prove_arguments() {
+        let parsed = parse_prove_args(&args(
+            "--birthday 2001-01-01 --delta 6575 --relation younger",
+        ))
+        .unwrap();
+        assert_eq!(parsed.birthday, parse_day("2001-01-01").unwrap());
+        assert_eq!(parsed.relation, Relation::Younger);
+        assert_eq!(parsed.delta, 6575);
+        assert_eq!(parsed.today, None);
+        assert_eq!(parsed.photo_digest, PhotosDigest::default());
+        for invalid in &[
+            "--delta 1",
+            "--birthday 2001-01-01",
+            "--birthday 2001-01-01 --delta x",
+            "--birthday 2001-01-01 --delta 1 --relation same",
+            "--birthday 2001-01-01 --delta 1 --now 1440",
+            "--birthday 2001-01-01 --delta 1 extra",
+        ] {
+            assert!(parse_prove_args(&args(invalid)).is_err(), "{}", invalid);
+        }
+    }
+
+    fn report(outcome: Option<&str>, date_matches: bool) -> VerifyReport {
+        let public = Public::older_than(6575, 2459000, 750).unwrap();
+        VerifyReport {
+            text: Some(public.to_string()),
+            public: Some(public),
+            date_matches: date_matches,
+            outcome: outcome.map(String::from),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(report(Some("valid"), true).exit_code(), EXIT_VALID);
+        assert_eq!(report(None, true).exit_code(), EXIT_VALID);
+        assert_eq!(report(Some("valid"), false).exit_code(), EXIT_NOT_SATISFIED);
+        assert_eq!(
+            report(Some("not satisfied"), true).exit_code(),
+            EXIT_NOT_SATISFIED
+        );
+        assert_eq!(report(Some("malformed"), true).exit_code(), EXIT_MALFORMED);
+        assert_eq!(report(Some("unbound"), true).exit_code(), EXIT_MALFORMED);
+        assert_eq!(report(Some("valid unbound"), true).exit_code(), EXIT_VALID);
+    }
+
+    #[test]
+    fn report_formats() {
+        let r = report(Some("valid"), true);
+        let text = r.to_text();
+        assert!(text.contains("claim: older than 6575 days"));
+        assert!(text.contains("delta: 6575"));
+        assert!(text.ends_with("result: valid"));
+        let json: serde_json::Value = serde_json::from_str(&r.to_json()).unwrap();
+        assert_eq!(json["public"]["delta"], 6575);
+        assert_eq!(json["outcome"], "valid");
+        assert!(report(None, false)
+            .to_text()
+            .contains("not verified, no photo digest"));
+    }
+
+    #[cfg(feature = "verifier")]
+    #[test]
+    fn verify_garbage() {
+        let r = verify("abc", &VerifyArgs::default()).unwrap();
+        assert_eq!(r.exit_code(), EXIT_MALFORMED);
+        assert!(r.to_text().starts_with("error: "));
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn prove_and_verify() {
+        let prove_args = parse_prove_args(&args(
+            "--birthday 2001-01-01 --delta 6575 --today 2020-05-17 --now 750 --photo-digest 0x0207",
+        ))
+        .unwrap();
+        let qr = prove(&prove_args).unwrap();
+        let verify_args =
+            parse_verify_args(&args("--photo-digest 0x0207 --today 2020-05-17")).unwrap();
+        let r = verify(&qr, &verify_args).unwrap();
+        assert_eq!(r.outcome.as_deref(), Some("valid"));
+        assert_eq!(r.exit_code(), EXIT_VALID);
+        assert_eq!(r.public.unwrap().delta, 6575);
+
+        // The sealed proof does not open with a wrong digest.
+        let wrong = parse_verify_args(&args("--photo-digest 0x0208 --today 2020-05-17")).unwrap();
+        assert_eq!(verify(&qr, &wrong).unwrap().exit_code(), EXIT_MALFORMED);
+        let later = parse_verify_args(&args("--photo-digest 0x0207 --today 2020-05-18")).unwrap();
+        assert_eq!(verify(&qr, &later).unwrap().exit_code(), EXIT_NOT_SATISFIED);
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn prove_and_verify_default_digest() {
+        // Both tools default to the zero test digest, the verifier
+        // accepts it only with `--allow-unbound`.
+        let prove_args = parse_prove_args(&args(
+            "--birthday 2001-01-01 --delta 6575 --today 2020-05-17 --now 750",
+        ))
+        .unwrap();
+        let qr = prove(&prove_args).unwrap();
+        let allowed = parse_verify_args(&args("--allow-unbound --today 2020-05-17")).unwrap();
+        let r = verify(&qr, &allowed).unwrap();
+        assert_eq!(r.outcome.as_deref(), Some("valid unbound"));
+        assert_eq!(r.exit_code(), EXIT_VALID);
+
+        let refused = parse_verify_args(&args("--photo-digest 0x --today 2020-05-17")).unwrap();
+        let r = verify(&qr, &refused).unwrap();
+        assert_eq!(r.outcome.as_deref(), Some("unbound"));
+        assert_eq!(r.exit_code(), EXIT_MALFORMED);
+        let decoded = verify(
+            &qr,
+            &parse_verify_args(&args("--today 2020-05-17")).unwrap(),
+        );
+        assert_eq!(decoded.unwrap().outcome, None);
+    }
+}
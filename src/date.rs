@@ -0,0 +1,217 @@
+// Conversion between calendar dates and the julian day numbers used
+// by the circuit.
+
+use crate::api::MAX_JULIAN_DAY;
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use std::fmt;
+
+/// Julian day number of 0000-12-31, the day before chrono's day 1.
+const JULIAN_CE_OFFSET: i32 = 1721425;
+
+/// Error converting dates.
+#[derive(PartialEq, Debug, Clone)]
+pub enum DateError {
+    /// The year, month and day do not form a valid date.
+    InvalidDate,
+    /// The date is outside the range `1..=MAX_JULIAN_DAY`.
+    OutOfRange,
+}
+
+impl fmt::Display for DateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DateError::InvalidDate => write!(f, "invalid date"),
+            DateError::OutOfRange => write!(f, "date out of range"),
+        }
+    }
+}
+
+impl std::error::Error for DateError {}
+
+fn to_julian(date: NaiveDate) -> Result<i32, DateError> {
+    let j = date.num_days_from_ce() + JULIAN_CE_OFFSET;
+    if j < 1 || j > MAX_JULIAN_DAY {
+        Err(DateError::OutOfRange)
+    } else {
+        Ok(j)
+    }
+}
+
+fn from_julian(j: i32) -> Result<NaiveDate, DateError> {
+    if j < 1 || j > MAX_JULIAN_DAY {
+        return Err(DateError::OutOfRange);
+    }
+    NaiveDate::from_num_days_from_ce_opt(j - JULIAN_CE_OFFSET).ok_or(DateError::OutOfRange)
+}
+
+/// Converts a gregorian calendar date to a julian day number.
+pub fn julian_from_ymd(y: i32, m: u32, d: u32) -> Result<i32, DateError> {
+    to_julian(NaiveDate::from_ymd_opt(y, m, d).ok_or(DateError::InvalidDate)?)
+}
+
+/// Julian day and minutes since midnight of the UTC time, the `today`
+/// and `now` of `Public`.
+pub fn julian_from_datetime(at: DateTime<Utc>) -> Result<(i32, i32), DateError> {
+    let minutes = (at.hour() * 60 + at.minute()) as i32;
+    Ok((to_julian(at.naive_utc().date())?, minutes))
+}
+
+/// Converts a julian day number to a gregorian (year, month, day).
+pub fn ymd_from_julian(j: i32) -> Result<(i32, u32, u32), DateError> {
+    let date = from_julian(j)?;
+    Ok((date.year(), date.month(), date.day()))
+}
+
+/// Returns the delta to be used in `Public` for the relation "older
+/// than `years`" or "younger than `years`" on day `today`.
+///
+/// The date `years` years before today is computed in the calendar,
+/// so leap years are accounted for. A person born on 29th February
+/// has birthday on 1st March in non-leap years. The circuit refuses
+/// equality, so on the birthday itself neither relation holds: the
+/// person becomes older than `years` the next day.
+pub fn delta_for_years(years: i32, today: i32) -> Result<i32, DateError> {
+    let t = from_julian(today)?;
+    let then = NaiveDate::from_ymd_opt(t.year() - years, t.month(), t.day())
+        .or_else(|| NaiveDate::from_ymd_opt(t.year() - years, 3, 1))
+        .ok_or(DateError::OutOfRange)?;
+    Ok(today - to_julian(then)?)
+}
+
+/// Upper bound of `delta_for_years(years, today)` over all days, for
+/// callers which do not know the day yet. `years` years contain at most
+/// `years / 4 + 1` leap days. "Older than" this delta implies the age
+/// on any day, but it may demand one day more than the calendar.
+pub fn max_delta_for_years(years: i32) -> Result<i32, DateError> {
+    if years < 0 || years > MAX_JULIAN_DAY / 366 {
+        return Err(DateError::OutOfRange);
+    }
+    Ok(years * 365 + years / 4 + 1)
+}
+
+/// Splits `delta` days before `today` into whole calendar years and
+/// the remaining days, the inverse of `delta_for_years`.
+pub fn years_and_days(delta: i32, today: i32) -> Result<(i32, i32), DateError> {
+    if delta < 0 {
+        return Err(DateError::OutOfRange);
+    }
+    from_julian(today.checked_sub(delta).ok_or(DateError::OutOfRange)?)?;
+    // A year has at most 366 days, so this is a lower bound.
+    let mut years = delta / 366;
+    while delta_for_years(years + 1, today).map_or(false, |d| d <= delta) {
+        years += 1;
+    }
+    Ok((years, delta - delta_for_years(years, today)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_julian_days() {
+        assert_eq!(julian_from_ymd(2000, 1, 1), Ok(2451545));
+        assert_eq!(julian_from_ymd(1970, 1, 1), Ok(2440588));
+        assert_eq!(ymd_from_julian(2451545), Ok((2000, 1, 1)));
+    }
+
+    #[test]
+    fn julian_of_utc_time() {
+        use chrono::TimeZone;
+        let at = Utc.ymd(2000, 1, 1).and_hms(23, 58, 59);
+        assert_eq!(julian_from_datetime(at), Ok((2451545, 23 * 60 + 58)));
+        let at = Utc.ymd(2000, 1, 2).and_hms(0, 0, 0);
+        assert_eq!(julian_from_datetime(at), Ok((2451546, 0)));
+    }
+
+    #[test]
+    fn julian_round_trip() {
+        let start = julian_from_ymd(1899, 12, 25).unwrap();
+        for j in start..start + 3 * 366 * 50 {
+            let (y, m, d) = ymd_from_julian(j).unwrap();
+            assert_eq!(julian_from_ymd(y, m, d), Ok(j));
+        }
+    }
+
+    #[test]
+    fn invalid_dates() {
+        assert_eq!(julian_from_ymd(2001, 2, 29), Err(DateError::InvalidDate));
+        assert_eq!(julian_from_ymd(2000, 13, 1), Err(DateError::InvalidDate));
+        assert_eq!(julian_from_ymd(2000, 4, 31), Err(DateError::InvalidDate));
+        assert!(julian_from_ymd(2000, 2, 29).is_ok());
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(ymd_from_julian(0), Err(DateError::OutOfRange));
+        assert_eq!(
+            ymd_from_julian(MAX_JULIAN_DAY + 1),
+            Err(DateError::OutOfRange)
+        );
+        assert!(ymd_from_julian(MAX_JULIAN_DAY).is_ok());
+        assert_eq!(julian_from_ymd(-4800, 1, 1), Err(DateError::OutOfRange));
+        assert_eq!(julian_from_ymd(30000, 1, 1), Err(DateError::OutOfRange));
+        assert_eq!(delta_for_years(18, -1), Err(DateError::OutOfRange));
+    }
+
+    #[test]
+    fn delta_accounts_for_leap_years() {
+        // 2002-05-17 .. 2020-05-17 contains 5 leap days.
+        let today = julian_from_ymd(2020, 5, 17).unwrap();
+        assert_eq!(delta_for_years(18, today), Ok(18 * 365 + 5));
+        // 2005-05-17 .. 2023-05-17 contains 4 leap days.
+        let today = julian_from_ymd(2023, 5, 17).unwrap();
+        assert_eq!(delta_for_years(18, today), Ok(18 * 365 + 4));
+    }
+
+    #[test]
+    fn delta_on_leap_day() {
+        // Born 2002-03-01 turns 18 on 2020-03-01.
+        let today = julian_from_ymd(2020, 2, 29).unwrap();
+        let delta = delta_for_years(18, today).unwrap();
+        assert_eq!(today - delta, julian_from_ymd(2002, 3, 1).unwrap());
+    }
+
+    #[test]
+    fn max_delta_bounds_the_calendar() {
+        let start = julian_from_ymd(1990, 1, 1).unwrap();
+        for years in &[1, 4, 18, 21, 65] {
+            let max = max_delta_for_years(*years).unwrap();
+            let deltas: Vec<i32> = (start..start + 40 * 366)
+                .map(|today| delta_for_years(*years, today).unwrap())
+                .collect();
+            assert!(deltas.iter().all(|delta| *delta <= max), "{}", years);
+            assert!(deltas.iter().any(|delta| *delta >= max - 1), "{}", years);
+        }
+        assert_eq!(max_delta_for_years(18), Ok(6575));
+        assert_eq!(max_delta_for_years(0), Ok(1));
+        assert_eq!(max_delta_for_years(-1), Err(DateError::OutOfRange));
+        assert_eq!(max_delta_for_years(i32::MAX), Err(DateError::OutOfRange));
+    }
+
+    #[test]
+    fn year_boundary() {
+        let new_year = julian_from_ymd(2021, 1, 1).unwrap();
+        assert_eq!(julian_from_ymd(2020, 12, 31), Ok(new_year - 1));
+        assert_eq!(ymd_from_julian(new_year - 1), Ok((2020, 12, 31)));
+        // Born on new year's eve, 18 on the next new year's eve.
+        let today = julian_from_ymd(2020, 12, 31).unwrap();
+        let delta = delta_for_years(18, today).unwrap();
+        assert_eq!(ymd_from_julian(today - delta), Ok((2002, 12, 31)));
+        let delta = delta_for_years(18, new_year).unwrap();
+        assert_eq!(ymd_from_julian(new_year - delta), Ok((2003, 1, 1)));
+    }
+
+    #[test]
+    fn years_and_days_inverts_delta() {
+        let today = julian_from_ymd(2024, 5, 17).unwrap();
+        for years in 0..120 {
+            let delta = delta_for_years(years, today).unwrap();
+            assert_eq!(years_and_days(delta, today), Ok((years, 0)));
+            assert_eq!(years_and_days(delta + 1, today), Ok((years, 1)));
+        }
+        assert_eq!(years_and_days(6574, today), Ok((17, 364)));
+        assert_eq!(years_and_days(-1, today), Err(DateError::OutOfRange));
+        assert_eq!(years_and_days(today, today), Err(DateError::OutOfRange));
+    }
+}
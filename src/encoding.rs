@@ -0,0 +1,203 @@
+// Conversion between Bn128 field elements and bytes. The library, the
+// QR code and the `api` value types use the little-endian form of
+// `Bn128Field::into_byte_vector`, shortest or zero padded to
+// `FIELD_LENGTH` bytes. The big-endian helpers are meant for services
+// which keep the values in the usual big-endian 32 byte form. Both
+// directions refuse values at or above the modulus instead of reducing
+// them.
+
+use crate::api::{is_field_element, FIELD_LENGTH};
+use std::fmt;
+use zokrates_field::{Bn128Field, Field};
+
+/// Error decoding a field element.
+#[derive(PartialEq, Debug, Clone)]
+pub enum EncodingError {
+    /// The number of bytes is wrong, the big-endian form has exactly
+    /// `FIELD_LENGTH` bytes, the little-endian at most that many.
+    Length(usize),
+    /// The number is not below the modulus.
+    OutOfRange,
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodingError::Length(n) => write!(f, "invalid field element length {}", n),
+            EncodingError::OutOfRange => write!(f, "value out of field range"),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+/// Reads a little-endian field element of at most `FIELD_LENGTH` bytes.
+pub fn field_from_le_bytes(bytes: &[u8]) -> Result<Bn128Field, EncodingError> {
+    if bytes.len() > FIELD_LENGTH {
+        return Err(EncodingError::Length(bytes.len()));
+    }
+    if !is_field_element(bytes) {
+        return Err(EncodingError::OutOfRange);
+    }
+    Ok(Bn128Field::from_byte_vector(bytes.to_vec()))
+}
+
+/// Reduces little-endian bytes of any length modulo the field
+/// modulus, giving the shortest little-endian form. Keys and digests do
+/// not reduce silently, a caller which wants reduction must call this
+/// first, e.g. for a hash output.
+pub fn normalize_field_bytes(bytes: &[u8]) -> Vec<u8> {
+    let base = Bn128Field::from(256);
+    let value = bytes.iter().rev().fold(Bn128Field::from(0), |value, byte| {
+        value * base.clone() + Bn128Field::from(*byte as i32)
+    });
+    field_to_le_bytes(&value)
+}
+
+/// Shortest little-endian form of the field element, as stored in the
+/// `api` value types.
+pub fn field_to_le_bytes(value: &Bn128Field) -> Vec<u8> {
+    value.into_byte_vector()
+}
+
+/// Reads a big-endian field element of exactly `FIELD_LENGTH` bytes.
+pub fn field_from_be_bytes(bytes: &[u8]) -> Result<Bn128Field, EncodingError> {
+    if bytes.len() != FIELD_LENGTH {
+        return Err(EncodingError::Length(bytes.len()));
+    }
+    let le: Vec<u8> = bytes.iter().rev().cloned().collect();
+    field_from_le_bytes(&le)
+}
+
+/// Big-endian form of the field element, `FIELD_LENGTH` bytes.
+pub fn field_to_be_bytes(value: &Bn128Field) -> [u8; FIELD_LENGTH] {
+    le_to_be(&field_to_le_bytes(value))
+}
+
+/// Converts a little-endian field element of at most `FIELD_LENGTH`
+/// bytes, which has been range checked, to the big-endian form.
+pub(crate) fn le_to_be(le: &[u8]) -> [u8; FIELD_LENGTH] {
+    let mut be = [0u8; FIELD_LENGTH];
+    for (b, l) in be.iter_mut().rev().zip(le) {
+        *b = *l;
+    }
+    be
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{CardKey, Challenge, PhotosDigest, ValidationError};
+    use crate::hash::compute_mimc7r10_hash;
+
+    fn bn128(s: &str) -> Bn128Field {
+        Bn128Field::try_from_dec_str(s).unwrap()
+    }
+
+    fn be(hex_string: &str) -> Vec<u8> {
+        hex::decode(hex_string).unwrap()
+    }
+
+    const MODULUS: &str = "30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
+
+    #[test]
+    fn big_endian_of_mimc_vectors() {
+        // mimc7R10(0, 0) and mimc7R10(100, 0) of the ZoKrates tests.
+        let zero = bn128("0");
+        let h = compute_mimc7r10_hash(&zero, &zero);
+        assert_eq!(
+            h,
+            bn128("6004544488495356385698286530147974336054653445122716140990101827963729149289")
+        );
+        let h_be = be("0d4673a57cefafb8e9ff935d4f8436444fab1f12dc754868d20ad2ad867efd69");
+        assert_eq!(field_to_be_bytes(&h).to_vec(), h_be);
+        assert_eq!(field_from_be_bytes(&h_be), Ok(h.clone()));
+        // The wire form is the reversed big-endian form.
+        let mut le = h_be.clone();
+        le.reverse();
+        assert_eq!(field_to_le_bytes(&h), le);
+        assert_eq!(field_from_le_bytes(&le), Ok(h));
+
+        let h = compute_mimc7r10_hash(&bn128("100"), &zero);
+        let h_be = be("06953be7fc6f26f1b2fd11d0500e5f86f9286636dc02fffe20dd58e3e988e859");
+        assert_eq!(field_to_be_bytes(&h).to_vec(), h_be);
+    }
+
+    #[test]
+    fn challenge_of_known_vector() {
+        // compute_challenge(0, 0) is mimc7R10(0, 0).
+        let card_key = CardKey::from_be_bytes(&[0u8; FIELD_LENGTH]).unwrap();
+        let challenge = crate::zk::compute_challenge(&card_key, 0);
+        assert_eq!(
+            challenge.to_be_bytes().to_vec(),
+            be("0d4673a57cefafb8e9ff935d4f8436444fab1f12dc754868d20ad2ad867efd69")
+        );
+        assert_eq!(
+            Challenge::from_be_bytes(&challenge.to_be_bytes()).unwrap(),
+            challenge
+        );
+    }
+
+    #[test]
+    fn modulus_is_rejected() {
+        let modulus = be(MODULUS);
+        assert_eq!(
+            field_from_be_bytes(&modulus),
+            Err(EncodingError::OutOfRange)
+        );
+        let mut le = modulus.clone();
+        le.reverse();
+        assert_eq!(field_from_le_bytes(&le), Err(EncodingError::OutOfRange));
+        assert_eq!(
+            field_from_be_bytes(&[0xff; FIELD_LENGTH]),
+            Err(EncodingError::OutOfRange)
+        );
+        assert_eq!(
+            PhotosDigest::from_be_bytes(&modulus),
+            Err(ValidationError::PhotosDigest)
+        );
+
+        let mut below = modulus;
+        below[FIELD_LENGTH - 1] = 0;
+        assert_eq!(
+            field_from_be_bytes(&below),
+            Ok(bn128(
+                "21888242871839275222246405745257275088548364400416034343698204186575808495616"
+            ))
+        );
+    }
+
+    #[test]
+    fn normalize_reduces_modulo() {
+        let mut modulus = be(MODULUS);
+        modulus.reverse();
+        assert_eq!(
+            field_from_le_bytes(&normalize_field_bytes(&modulus)),
+            Ok(bn128("0"))
+        );
+        modulus[0] += 5;
+        assert_eq!(normalize_field_bytes(&modulus), vec![5]);
+        assert_eq!(normalize_field_bytes(&[5, 0, 0]), vec![5]);
+        let wide = normalize_field_bytes(&[0xff; 2 * FIELD_LENGTH]);
+        assert!(field_from_le_bytes(&wide).is_ok());
+        assert_eq!(
+            PhotosDigest::new(normalize_field_bytes(&[0xff; FIELD_LENGTH])).map(|_| ()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(field_from_be_bytes(&[1]), Err(EncodingError::Length(1)));
+        assert_eq!(
+            field_from_le_bytes(&[0; FIELD_LENGTH + 1]),
+            Err(EncodingError::Length(FIELD_LENGTH + 1))
+        );
+        assert_eq!(field_from_le_bytes(&[]), Ok(bn128("0")));
+        // Zero padding does not change the value.
+        assert_eq!(field_from_le_bytes(&[5, 0, 0]), Ok(bn128("5")));
+        let mut five = [0u8; FIELD_LENGTH];
+        five[FIELD_LENGTH - 1] = 5;
+        assert_eq!(field_to_be_bytes(&bn128("5")), five);
+    }
+}
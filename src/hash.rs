@@ -0,0 +1,195 @@
+// MiMC hash used by the circuit, on byte-encoded field elements.
+
+use crate::api::PhotosDigest;
+
+use ff_mimc::{PrimeField, PrimeFieldRepr};
+use mimc_rs;
+use zokrates_field::{Bn128Field, Field};
+
+/// Length of the photo chunks hashed by `photos_digest`. Any 31 byte
+/// little-endian number is a field element.
+pub const PHOTO_CHUNK_LENGTH: usize = 31;
+
+fn zok2mimc(value: &Bn128Field) -> mimc_rs::Fr {
+    // Zokrates uses internal BigInt representation, mimc uses ff with private Repr.
+
+    let s = value.to_dec_string();
+    mimc_rs::Fr::from_str(&s).unwrap()
+}
+
+fn mimc2zok(value: mimc_rs::Fr) -> Bn128Field {
+    let mut res: Vec<u8> = vec![];
+    value.into_repr().write_le(&mut res).unwrap();
+    Bn128Field::from_byte_vector(res)
+}
+
+pub(crate) fn compute_mimc7r10_hash(x: &Bn128Field, k: &Bn128Field) -> Bn128Field {
+    let mimc7r10 = mimc_rs::Mimc7::new(10);
+    let hash = mimc7r10.hash(&zok2mimc(x), &zok2mimc(k));
+    mimc2zok(hash)
+}
+
+/// MiMC7 hash of `x` with the key `k` in 10 rounds, the same as
+/// `mimc7R10` in the circuit. Inputs must be little-endian field
+/// elements, see `api::is_field_element`. The output is a
+/// little-endian field element.
+pub fn mimc7r10(x: &[u8], k: &[u8]) -> Vec<u8> {
+    let x = Bn128Field::from_byte_vector(x.to_vec());
+    let k = Bn128Field::from_byte_vector(k.to_vec());
+    compute_mimc7r10_hash(&x, &k).into_byte_vector()
+}
+
+/// Hash of one photo. The hash starts with the photo length in bytes
+/// and each `PHOTO_CHUNK_LENGTH` chunk, read as a little-endian
+/// number, is hashed with the previous value as the key. The last
+/// chunk may be shorter.
+fn photo_hash(photo: &[u8]) -> Bn128Field {
+    photo
+        .chunks(PHOTO_CHUNK_LENGTH)
+        .fold(Bn128Field::from(photo.len() as i32), |h, chunk| {
+            compute_mimc7r10_hash(&Bn128Field::from_byte_vector(chunk.to_vec()), &h)
+        })
+}
+
+/// Digest of the photo set printed on the card. The digest starts
+/// with the number of photos and the hash of each photo, see
+/// `photo_hash`, is hashed with the previous value as the key. The
+/// order of the photos matters, it is the order on the card. Both the
+/// phone app and the web service must use this function, it is
+/// re-exported by `phone_api` and `web_api`, so that the digests
+/// agree. Cards have been issued with this digest, so it cannot be
+/// replaced by another hash.
+///
+/// The digest is always a field element, MiMC works in the field.
+pub fn photos_digest(photos: &[Vec<u8>]) -> PhotosDigest {
+    let photos: Vec<&[u8]> = photos.iter().map(|photo| photo.as_slice()).collect();
+    digest_of(&photos)
+}
+
+/// `photos_digest` of borrowed photos, as the bytes of `PhotosDigest`.
+pub fn compute_photos_digest(photos: &[&[u8]]) -> Vec<u8> {
+    digest_of(photos).into()
+}
+
+fn digest_of(photos: &[&[u8]]) -> PhotosDigest {
+    let digest = photos
+        .iter()
+        .fold(Bn128Field::from(photos.len() as i32), |d, photo| {
+            compute_mimc7r10_hash(&photo_hash(photo), &d)
+        });
+    PhotosDigest::from_field(&digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bn128(s: &str) -> Bn128Field {
+        Bn128Field::try_from_dec_str(s).unwrap()
+    }
+
+    fn hash(x: &str, k: &str) -> Bn128Field {
+        let out = mimc7r10(&bn128(x).into_byte_vector(), &bn128(k).into_byte_vector());
+        Bn128Field::from_byte_vector(out)
+    }
+
+    #[test]
+    fn mimc7r10_vectors() {
+        // values from ZoKrartes test
+
+        assert_eq!(
+            hash("0", "0"),
+            bn128("6004544488495356385698286530147974336054653445122716140990101827963729149289")
+        );
+        assert_eq!(
+            hash("100", "0"),
+            bn128("2977550761518141183167168643824354554080911485709001361112529600968315693145")
+        );
+        assert_eq!(
+            compute_mimc7r10_hash(
+                &bn128("100"),
+                &bn128(
+                    "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+                )
+            ),
+            bn128("2977550761518141183167168643824354554080911485709001361112529600968315693145")
+        );
+        assert_eq!(
+            compute_mimc7r10_hash(
+                &bn128(
+                    "21888242871839275222246405745257275088548364400416034343698204186575808495618"
+                ),
+                &bn128("1")
+            ),
+            bn128("11476724043755138071320043459606423473319855817296339514744600646762741571430")
+        );
+        assert_eq!(
+            compute_mimc7r10_hash(
+                &bn128(
+                    "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+                ),
+                &bn128(
+                    "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+                )
+            ),
+            bn128("6004544488495356385698286530147974336054653445122716140990101827963729149289")
+        );
+        assert_eq!(
+            hash("1", "1"),
+            bn128("11476724043755138071320043459606423473319855817296339514744600646762741571430")
+        );
+    }
+
+    fn digest(photos: &[Vec<u8>]) -> Bn128Field {
+        Bn128Field::from_byte_vector(photos_digest(photos).into())
+    }
+
+    #[test]
+    fn photos_digest_is_stable_field_element() {
+        let photos: Vec<Vec<u8>> = (0..5u8)
+            .map(|i| {
+                (0..200u32)
+                    .map(|j| (j * 31 + i as u32 * 7) as u8 ^ 0xff)
+                    .collect()
+            })
+            .collect();
+        let digest = photos_digest(&photos);
+        assert!(crate::api::is_field_element(digest.as_bytes()));
+        assert_eq!(photos_digest(&photos), digest);
+        assert_eq!(crate::phone_api::photos_digest(&photos), digest);
+        let borrowed: Vec<&[u8]> = photos.iter().map(|photo| photo.as_slice()).collect();
+        assert_eq!(compute_photos_digest(&borrowed), digest.as_bytes());
+        assert_eq!(
+            crate::web_api::compute_photos_digest(&borrowed),
+            crate::phone_api::compute_photos_digest(&borrowed)
+        );
+        let mut reversed = photos.clone();
+        reversed.reverse();
+        assert_ne!(photos_digest(&reversed), digest);
+        // Chunks of 0xff are below the modulus, see PHOTO_CHUNK_LENGTH.
+        let high = photos_digest(&[vec![0xff; PHOTO_CHUNK_LENGTH * 3]]);
+        assert!(crate::api::is_field_element(high.as_bytes()));
+    }
+
+    #[test]
+    fn photos_digest_vectors() {
+        assert_eq!(digest(&[]), bn128("0"));
+        assert_eq!(
+            digest(&[vec![]]),
+            bn128("4089446331166813152736303080763707500322902521138561921621314076663572104171")
+        );
+        assert_eq!(
+            digest(&[vec![1, 2, 3]]),
+            bn128("10612482716680697293640602103495416218580243784213027261655658421946295496113")
+        );
+        let long: Vec<u8> = (0..40).collect();
+        assert_eq!(
+            digest(&[vec![1, 2, 3], long.clone()]),
+            bn128("16661301759052639533537654971548439292022055755523870959810959000789243316144")
+        );
+        assert_eq!(
+            digest(&[long, vec![1, 2, 3]]),
+            bn128("10710826749472537508967471215629084350041062823108660603372189552534640241499")
+        );
+    }
+}
@@ -1,6 +1,35 @@
 // legalage_logic is a background library for legalage web service and
 // legalage phone app. 
 
+#[cfg(not(any(feature = "prover", feature = "verifier")))]
+compile_error!("enable at least one of the `prover` and `verifier` features");
+
 pub mod api;
 pub mod android_api;
+pub mod attestation;
+mod base45;
+pub mod builder;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "ffi")]
+pub mod c_api;
+pub mod date;
+pub mod encoding;
+pub mod hash;
+mod manual_entry;
+#[cfg(feature = "async")]
+pub mod offload;
+pub mod phone_api;
+pub mod policy;
+pub mod qr_chunking;
+pub mod recovery;
+pub mod storage;
+#[cfg(test)]
+mod test_alloc;
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
+#[cfg(feature = "wasm")]
+pub mod wasm_api;
+#[cfg(feature = "verifier")]
+pub mod web_api;
 pub mod zk;
@@ -0,0 +1,211 @@
+// Chunked base32 for typing a QR code by hand when the camera cannot
+// scan it. The payload is written in the Crockford base32 alphabet and
+// split into groups of `chunk` characters, each followed by a check
+// character of the Luhn mod 32 algorithm. The check covers the number
+// of the group: a mistyped character or two swapped groups are always
+// reported by the number of the group, and only that group is read
+// again. There are at most `MAX_GROUPS` groups so that their numbers
+// fit the check.
+//
+// The alphabet has one case and leaves out `I`, `L`, `O` and `U`.
+// People type them anyway, and in any case, so the letters are read
+// as upper case, `O` as `0` and `I` and `L` as `1`.
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Largest number of groups, the group number is checked as two digits.
+pub const MAX_GROUPS: usize = ALPHABET.len() * ALPHABET.len();
+
+/// Groups base32 of `bytes` into `chunk` characters and a check
+/// character, separated by spaces. Panics if there would be more than
+/// `MAX_GROUPS` groups.
+pub fn encode(bytes: &[u8], chunk: usize) -> String {
+    let base32 = to_base32(bytes);
+    let groups = base32.chunks(chunk);
+    assert!(
+        groups.len() <= MAX_GROUPS,
+        "at most {} groups can be checked",
+        MAX_GROUPS
+    );
+    groups
+        .enumerate()
+        .map(|(index, group)| {
+            let mut s = String::from_utf8(group.to_vec()).expect("base32 is ASCII");
+            s.push(ALPHABET[check_digit(index, group) as usize] as char);
+            s
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reads `encode` of the same `chunk` typed with any whitespace, in any
+/// case and with the tolerated confusions. Fails with the 1-based
+/// number of the first group which does not pass its check.
+pub fn decode(s: &str, chunk: usize) -> Result<Vec<u8>, usize> {
+    let typed: Vec<u8> = s
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(normalize)
+        .collect();
+    let mut base32 = Vec::with_capacity(typed.len());
+    for (index, group) in typed.chunks(chunk + 1).enumerate() {
+        let (data, check) = group.split_at(group.len() - 1);
+        match digit(check[0]) {
+            Some(check)
+                if !data.is_empty() && index < MAX_GROUPS && check == check_digit(index, data) =>
+            {
+                base32.extend_from_slice(data)
+            }
+            _ => return Err(index + 1),
+        }
+    }
+    Ok(from_base32(&base32))
+}
+
+/// Base32 characters of `bytes`, five bits each with the most
+/// significant first. The last character is padded with zero bits.
+fn to_base32(bytes: &[u8]) -> Vec<u8> {
+    let mut base32 = Vec::with_capacity((bytes.len() * 8 + 4) / 5);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = buffer << 8 | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            base32.push(ALPHABET[(buffer >> bits) as usize]);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bits > 0 {
+        base32.push(ALPHABET[(buffer << (5 - bits)) as usize]);
+    }
+    base32
+}
+
+/// Bytes of checked base32 characters, the padding bits are dropped.
+fn from_base32(base32: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(base32.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in base32 {
+        buffer = buffer << 5 | digit(*c).expect("checked group");
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    bytes
+}
+
+/// The character of the alphabet in place of a typed one.
+fn normalize(c: u8) -> u8 {
+    match c.to_ascii_uppercase() {
+        b'O' => b'0',
+        b'I' | b'L' => b'1',
+        c => c,
+    }
+}
+
+fn digit(c: u8) -> Option<u32> {
+    ALPHABET.iter().position(|a| *a == c).map(|d| d as u32)
+}
+
+/// Luhn mod 32 check digit of the group `index`. The index, less than
+/// `MAX_GROUPS`, gives the first two digits, so that the groups cannot
+/// be swapped. Every digit of the group must be in the alphabet, a
+/// character out of it gives a digit which no check character matches.
+fn check_digit(index: usize, group: &[u8]) -> u32 {
+    let n = ALPHABET.len() as u32;
+    debug_assert!(index < MAX_GROUPS);
+    let position = [Some(index as u32 / n), Some(index as u32 % n)];
+    let digits = position
+        .iter()
+        .cloned()
+        .chain(group.iter().map(|c| digit(*c)));
+    let mut sum = 0;
+    // From the right, every second digit is doubled starting with the
+    // last one, which is next to the check character.
+    for (i, d) in digits.collect::<Vec<_>>().into_iter().rev().enumerate() {
+        let d = match d {
+            Some(d) => d,
+            None => return n,
+        };
+        let addend = if i % 2 == 0 { 2 * d } else { d };
+        sum += addend / n + addend % n;
+    }
+    (n - sum % n) % n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_digit_of_every_substitution() {
+        let group = b"3MJR7A0VXZ";
+        let check = check_digit(0, group);
+        for i in 0..group.len() {
+            for c in ALPHABET.iter().filter(|c| **c != group[i]) {
+                let mut typo = group.to_vec();
+                typo[i] = *c;
+                assert_ne!(check_digit(0, &typo), check);
+            }
+        }
+        assert_ne!(check_digit(1, group), check);
+        assert_eq!(check_digit(0, b"3MJR7A0VXU"), ALPHABET.len() as u32);
+    }
+
+    #[test]
+    fn group_numbers_do_not_alias() {
+        let group = b"3MJR";
+        let checks: Vec<u32> = (0..MAX_GROUPS).map(|i| check_digit(i, group)).collect();
+        for i in 0..ALPHABET.len() {
+            assert_ne!(checks[i], checks[i + ALPHABET.len()]);
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for chunk in 1..8 {
+            let chunked = encode(&bytes, chunk);
+            assert!(chunked.split(' ').all(|group| group.len() <= chunk + 1));
+            assert_eq!(decode(&chunked, chunk), Ok(bytes.clone()));
+            assert_eq!(decode(&chunked.replace(' ', ""), chunk), Ok(bytes.clone()));
+        }
+        assert_eq!(encode(&[0xff, 0], 4), "ZW008");
+        assert_eq!(encode(&[], 4), "");
+        assert_eq!(decode("", 4), Ok(vec![]));
+    }
+
+    #[test]
+    #[should_panic(expected = "groups can be checked")]
+    fn too_many_groups_are_refused() {
+        encode(&vec![0; MAX_GROUPS], 1);
+    }
+
+    #[test]
+    fn tolerated_substitutions() {
+        let bytes = b"legal age 18";
+        let chunked = encode(bytes, 4);
+        assert_eq!(chunked, "DHJPH ERBCW 41GPS ES90G 64W0E");
+        assert_eq!(decode(&chunked.to_lowercase(), 4), Ok(bytes.to_vec()));
+        for (typed, valid) in &[
+            ('O', '0'),
+            ('o', '0'),
+            ('I', '1'),
+            ('i', '1'),
+            ('L', '1'),
+            ('l', '1'),
+        ] {
+            let mistyped = chunked.replace(*valid, &typed.to_string());
+            assert_ne!(mistyped, chunked);
+            assert_eq!(decode(&mistyped, 4), Ok(bytes.to_vec()));
+        }
+        // A group without data.
+        assert_eq!(decode(&format!("{} 1", chunked), 4), Err(6));
+        assert_eq!(decode("3MJ0!", 4), Err(1));
+        assert_eq!(decode("3MJ0U", 4), Err(1));
+    }
+}
@@ -0,0 +1,280 @@
+// Running the long proof generation and verification off the async
+// executor. Compiled with the `async` feature. The work is handed to a
+// `Spawner`, either the small internal `BlockingPool` or a closure
+// forwarding to the executor's own blocking pool, e.g.
+// `|job| { tokio::task::spawn_blocking(job); }`.
+
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Work handed to a `Spawner`.
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs jobs on threads where blocking is allowed.
+pub trait Spawner: Send + Sync {
+    fn spawn(&self, job: Job);
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(Job) + Send + Sync,
+{
+    fn spawn(&self, job: Job) {
+        self(job)
+    }
+}
+
+/// Number of threads of the shared pool.
+pub const SHARED_POOL_THREADS: usize = 4;
+
+/// Fixed number of threads taking jobs from a queue. A panicking job
+/// does not stop its thread.
+#[derive(Debug)]
+pub struct BlockingPool {
+    queue: Mutex<Sender<Job>>,
+}
+
+impl BlockingPool {
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "the pool needs a thread");
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("legalage-blocking-{}", i))
+                .spawn(move || worker(&receiver))
+                .expect("cannot start a pool thread");
+        }
+        BlockingPool {
+            queue: Mutex::new(sender),
+        }
+    }
+}
+
+fn worker(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        match job {
+            Ok(job) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+            // The pool has been dropped.
+            Err(_) => return,
+        }
+    }
+}
+
+impl Spawner for BlockingPool {
+    fn spawn(&self, job: Job) {
+        self.queue
+            .lock()
+            .expect("pool queue poisoned")
+            .send(job)
+            .expect("pool threads stopped");
+    }
+}
+
+static SHARED_POOL: Lazy<BlockingPool> = Lazy::new(|| BlockingPool::new(SHARED_POOL_THREADS));
+
+/// Pool used by the `_async` functions without an explicit spawner.
+pub fn shared_pool() -> &'static BlockingPool {
+    &SHARED_POOL
+}
+
+/// Tells the offloaded work that its future has been dropped.
+#[derive(Debug, Clone)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancellation {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Debug)]
+enum State<T> {
+    Running(Option<Waker>),
+    Done(T),
+    /// The work panicked or gave up.
+    Abandoned,
+    Taken,
+}
+
+/// Completes the future when the work returns, or abandons it when the
+/// work panics or gives up.
+struct Completion<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Completion<T> {
+    fn finish(&self, next: State<T>) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        if let State::Running(waker) = &mut *state {
+            let waker = waker.take();
+            *state = next;
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        self.finish(State::Abandoned);
+    }
+}
+
+/// Result of work running on a `Spawner`. Dropping the future cancels
+/// the work, see `offload`.
+#[derive(Debug)]
+pub struct Offloaded<T> {
+    state: Arc<Mutex<State<T>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T> Future for Offloaded<T> {
+    type Output = T;
+
+    /// Panics if the work panicked or returned `None` although the
+    /// future was not dropped.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let mut state = self.state.lock().expect("offload state poisoned");
+        match std::mem::replace(&mut *state, State::Taken) {
+            State::Running(_) => {
+                *state = State::Running(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+            State::Done(result) => Poll::Ready(result),
+            State::Abandoned => panic!("offloaded work did not complete"),
+            State::Taken => panic!("offloaded future polled after completion"),
+        }
+    }
+}
+
+impl<T> Drop for Offloaded<T> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+/// Runs `work` on the spawner and returns a future of its result. The
+/// work gets a `Cancellation` and may return `None` once the future
+/// has been dropped, so that nothing more is computed or encoded.
+pub fn offload<S, T, F>(spawner: &S, work: F) -> Offloaded<T>
+where
+    S: Spawner + ?Sized,
+    T: Send + 'static,
+    F: FnOnce(&Cancellation) -> Option<T> + Send + 'static,
+{
+    let state = Arc::new(Mutex::new(State::Running(None)));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let completion = Completion {
+        state: state.clone(),
+    };
+    let cancellation = Cancellation {
+        cancelled: cancelled.clone(),
+    };
+    spawner.spawn(Box::new(move || {
+        if cancellation.is_cancelled() {
+            return;
+        }
+        if let Some(result) = work(&cancellation) {
+            completion.finish(State::Done(result));
+        }
+    }));
+    Offloaded {
+        state: state,
+        cancelled: cancelled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::sync_channel;
+    use std::time::Duration;
+
+    #[test]
+    fn offloaded_result() {
+        let pool = BlockingPool::new(2);
+        assert_eq!(block_on(offload(&pool, |_| Some(6 * 7))), 42);
+        let threads = |job: Job| {
+            thread::spawn(job);
+        };
+        assert_eq!(block_on(offload(&threads, |_| Some("done"))), "done");
+    }
+
+    #[test]
+    fn dropped_future_cancels() {
+        let pool = BlockingPool::new(1);
+        let (started, wait_started) = sync_channel(0);
+        let (release, wait_release) = sync_channel::<()>(0);
+        let completed = Arc::new(AtomicUsize::new(0));
+        let counter = completed.clone();
+        let future = offload(&pool, move |cancellation| {
+            started.send(()).unwrap();
+            wait_release.recv().unwrap();
+            if cancellation.is_cancelled() {
+                return None;
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            Some(())
+        });
+        wait_started.recv().unwrap();
+        drop(future);
+        release.send(()).unwrap();
+        // The next job runs after the cancelled one on the only thread.
+        block_on(offload(&pool, |_| Some(())));
+        assert_eq!(completed.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn cancelled_before_start_does_not_run() {
+        let pool = BlockingPool::new(1);
+        let (release, wait_release) = sync_channel::<()>(0);
+        let blocker = offload(&pool, move |_| wait_release.recv().ok());
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+        drop(offload(&pool, move |_| {
+            flag.store(true, Ordering::SeqCst);
+            Some(())
+        }));
+        release.send(()).unwrap();
+        block_on(blocker);
+        block_on(offload(&pool, |_| Some(())));
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn panicking_work_panics_the_future() {
+        let pool = BlockingPool::new(1);
+        let future = offload(&pool, |_| -> Option<()> { panic!("failed") });
+        let result = panic::catch_unwind(AssertUnwindSafe(|| block_on(future)));
+        assert!(result.is_err());
+        // The thread survives the panic.
+        let late = offload(&pool, |_| {
+            thread::sleep(Duration::from_millis(10));
+            Some(1)
+        });
+        assert_eq!(block_on(late), 1);
+    }
+}
@@ -0,0 +1,311 @@
+// Interface for the legalage phone app.
+
+pub use crate::api::EMBEDDED_CIRCUIT;
+pub use crate::hash::{compute_photos_digest, photos_digest};
+use crate::zk;
+pub use crate::zk::RelationOutcome;
+pub use crate::zk::VerificationFailure;
+
+pub use crate::api::{
+    relation_holds, PhotosDigest, Private, PrivateKey, ProofQrCode, Public, QrError, QrRequest,
+    Relation,
+};
+pub use crate::builder::{BuildError, RequestBuilder};
+#[cfg(feature = "async")]
+use crate::offload;
+#[cfg(feature = "async")]
+pub use crate::offload::{Offloaded, Spawner};
+pub use crate::recovery::{generate_mnemonic, private_key_from_mnemonic, KeyError};
+pub use crate::storage::{Card, CardError};
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub use crate::zk::ZkContext;
+pub use crate::zk::ZkError;
+#[cfg(feature = "prover")]
+pub use crate::zk::{last_proof_stats, CancellationToken, ProofStage, ProofStats, Prover};
+#[cfg(all(feature = "prover", feature = "verifier"))]
+use std::path::Path;
+
+/// Prepares the shared prover so that later calls of `generate_qr`
+/// only compute the proof. Call it when the app starts.
+#[cfg(feature = "prover")]
+pub fn prepare() -> Result<(), ZkError> {
+    zk::shared_prover().map(|_| ())
+}
+
+/// Prepares the shared verifier so that the first `verify_qr` does
+/// not parse the verification key. Call it when the scanner starts.
+#[cfg(feature = "verifier")]
+pub fn prewarm_verifier() -> Result<(), ZkError> {
+    zk::shared_verifier().map(|_| ())
+}
+
+/// Generates the proof and encodes it as a QR code string.
+#[cfg(feature = "prover")]
+pub fn generate_qr(public: Public, private: Private) -> Result<String, ZkError> {
+    let rq = QrRequest {
+        circuit: EMBEDDED_CIRCUIT,
+        public: public,
+        private: private,
+        allow_unbound_proof: false,
+    };
+    Ok(zk::generate_proof(rq)?.to_string())
+}
+
+/// Same as `generate_qr`, calls `progress` when a stage of the proof
+/// generation is entered so that the app can show a progress bar.
+#[cfg(feature = "prover")]
+pub fn generate_qr_with_progress<F>(
+    public: Public,
+    private: Private,
+    progress: F,
+) -> Result<String, ZkError>
+where
+    F: Fn(ProofStage),
+{
+    let rq = QrRequest {
+        circuit: EMBEDDED_CIRCUIT,
+        public: public,
+        private: private,
+        allow_unbound_proof: false,
+    };
+    Ok(zk::generate_proof_with_progress(rq, progress)?.to_string())
+}
+
+/// Same as `generate_qr_with_progress`, fails with
+/// `ZkError::Cancelled` at the next stage once `cancellation` is
+/// cancelled, e.g. by the progress callback or by the UI thread.
+#[cfg(feature = "prover")]
+pub fn generate_qr_cancellable<F>(
+    public: Public,
+    private: Private,
+    progress: F,
+    cancellation: &CancellationToken,
+) -> Result<String, ZkError>
+where
+    F: Fn(ProofStage),
+{
+    let rq = QrRequest {
+        circuit: EMBEDDED_CIRCUIT,
+        public: public,
+        private: private,
+        allow_unbound_proof: false,
+    };
+    Ok(zk::generate_proof_cancellable(rq, progress, cancellation)?.to_string())
+}
+
+/// Same as `generate_qr`, runs on the shared pool of `offload` so that
+/// the async caller is not blocked. Dropping the future cancels the
+/// generation, the proof is then neither encoded nor returned.
+#[cfg(all(feature = "prover", feature = "async"))]
+pub fn generate_qr_async(public: Public, private: Private) -> Offloaded<Result<String, ZkError>> {
+    generate_qr_async_with(offload::shared_pool(), public, private)
+}
+
+/// Same as `generate_qr_async`, runs on `spawner`.
+#[cfg(all(feature = "prover", feature = "async"))]
+pub fn generate_qr_async_with<S: Spawner + ?Sized>(
+    spawner: &S,
+    public: Public,
+    private: Private,
+) -> Offloaded<Result<String, ZkError>> {
+    offload::offload(spawner, move |cancellation| {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: public,
+            private: private,
+            allow_unbound_proof: false,
+        };
+        let proof = zk::generate_proof(rq);
+        if cancellation.is_cancelled() {
+            return None;
+        }
+        Some(proof.map(|proof| proof.to_string()))
+    })
+}
+
+/// Loads the artifacts of a circuit downloaded by the app and
+/// registers them for the QR codes of `circuit`, see
+/// `zk::ZkContext::from_paths`.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub fn load_circuit(
+    circuit: u8,
+    program: &Path,
+    abi: &Path,
+    proving_key: &Path,
+    verification_key: &Path,
+) -> Result<(), ZkError> {
+    zk::ZkContext::from_paths(program, abi, proving_key, verification_key)?.register(circuit)
+}
+
+/// Runs `zk::self_test` and returns the report as a JSON string. An
+/// error means that the installation is corrupt.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub fn self_test() -> Result<String, ZkError> {
+    let report = zk::self_test()?;
+    serde_json::to_string(&report).map_err(|e| ZkError::CorruptInstallation(e.to_string()))
+}
+
+/// Tells whether `generate_qr` would give a QR code which verifies,
+/// without generating the proof.
+pub fn evaluate_relation(public: Public, private: Private) -> RelationOutcome {
+    zk::evaluate_relation(&QrRequest {
+        circuit: EMBEDDED_CIRCUIT,
+        public: public,
+        private: private,
+        allow_unbound_proof: false,
+    })
+}
+
+/// Decodes a pending request stored by `QrRequest::to_qr_code_string`.
+pub fn parse_request(qr: &str) -> Result<QrRequest, ZkError> {
+    Ok(QrRequest::from_qr_code_string(qr)?)
+}
+
+/// Decodes and verifies a QR code string. Returns the public part
+/// of the proof when the proof is valid.
+#[cfg(feature = "verifier")]
+pub fn verify_qr(qr: &str, photo_digest: &PhotosDigest) -> Result<Public, ZkError> {
+    let parsed: ProofQrCode = qr.parse()?;
+    zk::verify_proof(&parsed, photo_digest)?;
+    Ok(parsed.public)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public() -> Public {
+        Public {
+            today: 2020,
+            now: 1200,
+            relation: Relation::Older,
+            delta: 18,
+            second: None,
+            nonce: None,
+        }
+    }
+
+    fn private(photos_digest: &PhotosDigest) -> Private {
+        Private {
+            birthday: 2001,
+            private_key: PrivateKey::new(vec![10]).unwrap(),
+            photos_digest: photos_digest.clone(),
+        }
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn generate_and_verify_qr() {
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        assert_eq!(prepare(), Ok(()));
+        let qr = generate_qr(public(), private(&photos_digest)).unwrap();
+        assert_eq!(verify_qr(&qr, &photos_digest).unwrap(), public());
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn consecutive_proofs_with_prepared_prover() {
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        assert_eq!(prepare(), Ok(()));
+        let first = generate_qr(public(), private(&photos_digest)).unwrap();
+        let second = generate_qr(public(), private(&photos_digest)).unwrap();
+        assert_eq!(verify_qr(&first, &photos_digest).unwrap(), public());
+        assert_eq!(verify_qr(&second, &photos_digest).unwrap(), public());
+    }
+
+    #[cfg(feature = "prover")]
+    #[test]
+    fn generate_qr_rejects_invalid_input() {
+        use crate::api::{ValidationError, MAX_JULIAN_DAY};
+
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        let invalid = |public: Public, private: Private, error: ValidationError| {
+            assert_eq!(
+                generate_qr(public, private),
+                Err(ZkError::InvalidInput(error))
+            );
+        };
+        let mut p = private(&photos_digest);
+        p.birthday = 0;
+        invalid(public(), p, ValidationError::Birthday);
+        let mut p = private(&photos_digest);
+        p.birthday = MAX_JULIAN_DAY + 1;
+        invalid(public(), p, ValidationError::Birthday);
+        let mut q = public();
+        q.today = MAX_JULIAN_DAY + 1;
+        invalid(q, private(&photos_digest), ValidationError::Today);
+        let mut q = public();
+        q.delta = -1;
+        invalid(q, private(&photos_digest), ValidationError::Delta);
+
+        // Keys and digests out of the field are refused when created.
+        assert_eq!(
+            PrivateKey::new(vec![0xff; 32]),
+            Err(ValidationError::PrivateKey)
+        );
+        assert_eq!(
+            PhotosDigest::new(vec![0; 33]),
+            Err(ValidationError::PhotosDigest)
+        );
+    }
+
+    #[cfg(feature = "verifier")]
+    #[test]
+    fn verify_qr_rejects_garbage() {
+        assert_eq!(
+            verify_qr("a;b", &PhotosDigest::default()),
+            Err(ZkError::QrCode(QrError::PartCount(2)))
+        );
+        assert_eq!(
+            verify_qr("abc", &PhotosDigest::default()),
+            Err(ZkError::QrCode(QrError::InvalidLength))
+        );
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier", feature = "async"))]
+    #[test]
+    fn generate_qr_async_verifies() {
+        use futures::executor::block_on;
+
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        let qr = block_on(generate_qr_async(public(), private(&photos_digest))).unwrap();
+        assert_eq!(verify_qr(&qr, &photos_digest).unwrap(), public());
+
+        let mut impossible = public();
+        impossible.delta = 50;
+        assert_eq!(
+            block_on(generate_qr_async(
+                impossible.clone(),
+                private(&photos_digest)
+            )),
+            generate_qr(impossible, private(&photos_digest))
+        );
+    }
+
+    #[test]
+    fn evaluate_relation_before_proving() {
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        assert_eq!(
+            evaluate_relation(public(), private(&photos_digest)),
+            RelationOutcome::Satisfied
+        );
+        let mut older = public();
+        older.delta = 50;
+        assert_eq!(
+            evaluate_relation(older, private(&photos_digest)),
+            RelationOutcome::NotSatisfied
+        );
+    }
+
+    #[test]
+    fn parse_request_round_trip() {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: public(),
+            private: private(&PhotosDigest::new(vec![2u8, 7]).unwrap()),
+            allow_unbound_proof: false,
+        };
+        assert_eq!(parse_request(&rq.to_qr_code_string()).unwrap(), rq);
+        assert!(parse_request("").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+// Age policies of verifiers. A policy is given in calendar years, it
+// produces the `Public` part the prover must satisfy on a day and
+// checks that a verified QR code claims at least what it requires.
+
+use crate::api::{Bound, Public, Relation, ValidationError};
+use crate::date::{delta_for_years, DateError};
+use std::fmt;
+
+/// Error building the public part of a policy.
+#[derive(PartialEq, Debug, Clone)]
+pub enum PolicyError {
+    /// The years are not positive or the band is empty.
+    InvalidYears,
+    /// The day is out of range.
+    Date(DateError),
+    /// The public part is not valid, e.g. the delta is too large.
+    Public(ValidationError),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyError::InvalidYears => write!(f, "invalid years of the policy"),
+            PolicyError::Date(e) => write!(f, "{}", e),
+            PolicyError::Public(e) => write!(f, "invalid public part: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+impl From<DateError> for PolicyError {
+    fn from(e: DateError) -> Self {
+        PolicyError::Date(e)
+    }
+}
+
+impl From<ValidationError> for PolicyError {
+    fn from(e: ValidationError) -> Self {
+        PolicyError::Public(e)
+    }
+}
+
+/// Age required by a verifier, e.g. by a venue selling alcohol.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct AgePolicy {
+    /// The holder is at least this many years old.
+    min_years: Option<i32>,
+    /// The holder is less than this many years old.
+    max_years: Option<i32>,
+}
+
+impl AgePolicy {
+    /// The holder is at least `years` old, from the birthday on.
+    pub fn at_least_years(years: i32) -> Self {
+        AgePolicy {
+            min_years: Some(years),
+            max_years: None,
+        }
+    }
+
+    /// The holder is less than `years` old, until the day before the
+    /// birthday.
+    pub fn under_years(years: i32) -> Self {
+        AgePolicy {
+            min_years: None,
+            max_years: Some(years),
+        }
+    }
+
+    /// The holder is at least `min` and less than `max` years old.
+    pub fn between_years(min: i32, max: i32) -> Self {
+        AgePolicy {
+            min_years: Some(min),
+            max_years: Some(max),
+        }
+    }
+
+    fn check_years(&self) -> Result<(), PolicyError> {
+        let positive = |years: Option<i32>| years.map_or(true, |y| y > 0);
+        if !positive(self.min_years) || !positive(self.max_years) {
+            return Err(PolicyError::InvalidYears);
+        }
+        if let (Some(min), Some(max)) = (self.min_years, self.max_years) {
+            if min >= max {
+                return Err(PolicyError::InvalidYears);
+            }
+        }
+        Ok(())
+    }
+
+    /// Bounds required on the julian day `today`. The circuit refuses
+    /// equality, so "at least" is "older than one day less".
+    fn bounds(&self, today: i32) -> Result<Vec<Bound>, PolicyError> {
+        self.check_years()?;
+        let mut bounds = vec![];
+        if let Some(years) = self.min_years {
+            bounds.push(Bound {
+                relation: Relation::Older,
+                delta: delta_for_years(years, today)? - 1,
+            });
+        }
+        if let Some(years) = self.max_years {
+            bounds.push(Bound {
+                relation: Relation::Younger,
+                delta: delta_for_years(years, today)?,
+            });
+        }
+        Ok(bounds)
+    }
+
+    /// Public part the prover must satisfy on the julian day `today`
+    /// at `now` minutes after midnight UTC.
+    pub fn public(&self, today: i32, now: i32) -> Result<Public, PolicyError> {
+        let mut bounds = self.bounds(today)?.into_iter();
+        let first = bounds.next().ok_or(PolicyError::InvalidYears)?;
+        let mut public = match first.relation {
+            Relation::Older => Public::older_than(first.delta, today, now)?,
+            Relation::Younger => Public::younger_than(first.delta, today, now)?,
+        };
+        public.second = bounds.next();
+        Ok(public)
+    }
+
+    /// Tells whether the claims of `public`, which the proof verified,
+    /// imply the policy on the verifier's julian day `verifier_today`.
+    /// A larger "older" or a smaller "younger" delta than required is
+    /// fine. The claims are compared as bounds of the birthday, so a
+    /// code generated on an earlier day matches if it still implies the
+    /// policy.
+    pub fn matches(&self, public: &Public, verifier_today: i32) -> bool {
+        let required = match self.bounds(verifier_today) {
+            Ok(bounds) => bounds,
+            Err(_) => return false,
+        };
+        let mut claims = vec![(public.relation, public.delta)];
+        if let Some(bound) = &public.second {
+            claims.push((bound.relation, bound.delta));
+        }
+        required.iter().all(|bound| {
+            // The birthday is before (Older) or after (Younger) the
+            // day `today - delta`.
+            let limit = verifier_today - bound.delta;
+            claims.iter().any(|(relation, delta)| {
+                *relation == bound.relation
+                    && match relation {
+                        Relation::Older => public.today - delta <= limit,
+                        Relation::Younger => public.today - delta >= limit,
+                    }
+            })
+        })
+    }
+}
+
+impl fmt::Display for AgePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.min_years, self.max_years) {
+            (Some(min), Some(max)) => write!(f, "{} to {} years", min, max),
+            (Some(min), None) => write!(f, "at least {} years", min),
+            (None, Some(max)) => write!(f, "under {} years", max),
+            (None, None) => write!(f, "any age"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{PhotosDigest, Private, PrivateKey, QrRequest, EMBEDDED_CIRCUIT};
+    use crate::date::julian_from_ymd;
+
+    fn day(y: i32, m: u32, d: u32) -> i32 {
+        julian_from_ymd(y, m, d).unwrap()
+    }
+
+    /// The relation proved by the public part holds for the birthday.
+    fn satisfies(policy: AgePolicy, birthday: i32, today: i32) -> bool {
+        QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: policy.public(today, 600).unwrap(),
+            private: Private::with(birthday, PrivateKey::default(), PhotosDigest::default())
+                .unwrap(),
+            allow_unbound_proof: false,
+        }
+        .is_relation_valid()
+        .unwrap()
+    }
+
+    #[test]
+    fn at_least_from_the_birthday() {
+        let birthday = day(2002, 5, 17);
+        let policy = AgePolicy::at_least_years(18);
+        assert!(!satisfies(policy, birthday, day(2020, 5, 16)));
+        assert!(satisfies(policy, birthday, day(2020, 5, 17)));
+        assert!(satisfies(policy, birthday, day(2020, 5, 18)));
+    }
+
+    #[test]
+    fn under_until_the_birthday() {
+        let birthday = day(1994, 5, 17);
+        let policy = AgePolicy::under_years(26);
+        assert!(satisfies(policy, birthday, day(2020, 5, 16)));
+        assert!(!satisfies(policy, birthday, day(2020, 5, 17)));
+    }
+
+    #[test]
+    fn leap_day_birthday() {
+        // Born on 29th February, 18 years old on 1st March in non-leap
+        // years and on 29th February in leap years.
+        let birthday = day(2004, 2, 29);
+        let policy = AgePolicy::at_least_years(18);
+        assert!(!satisfies(policy, birthday, day(2022, 2, 28)));
+        assert!(satisfies(policy, birthday, day(2022, 3, 1)));
+        let birthday = day(2000, 2, 29);
+        let policy = AgePolicy::at_least_years(20);
+        assert!(!satisfies(policy, birthday, day(2020, 2, 28)));
+        assert!(satisfies(policy, birthday, day(2020, 2, 29)));
+    }
+
+    #[test]
+    fn band_public() {
+        let today = day(2020, 5, 17);
+        let public = AgePolicy::between_years(18, 26).public(today, 600).unwrap();
+        assert_eq!(public.relation, Relation::Older);
+        assert_eq!(public.delta, delta_for_years(18, today).unwrap() - 1);
+        assert_eq!(
+            public.second,
+            Some(Bound {
+                relation: Relation::Younger,
+                delta: delta_for_years(26, today).unwrap(),
+            })
+        );
+        assert!(satisfies(
+            AgePolicy::between_years(18, 26),
+            day(2000, 1, 1),
+            today
+        ));
+        assert!(!satisfies(
+            AgePolicy::between_years(18, 26),
+            day(1990, 1, 1),
+            today
+        ));
+    }
+
+    #[test]
+    fn larger_claim_matches() {
+        let today = day(2020, 5, 17);
+        let public = AgePolicy::at_least_years(21).public(today, 600).unwrap();
+        assert!(AgePolicy::at_least_years(18).matches(&public, today));
+        assert!(AgePolicy::at_least_years(21).matches(&public, today));
+        let public = AgePolicy::under_years(20).public(today, 600).unwrap();
+        assert!(AgePolicy::under_years(26).matches(&public, today));
+    }
+
+    #[test]
+    fn smaller_claim_does_not_match() {
+        let today = day(2020, 5, 17);
+        let public = AgePolicy::at_least_years(16).public(today, 600).unwrap();
+        assert!(!AgePolicy::at_least_years(18).matches(&public, today));
+        let public = AgePolicy::under_years(26).public(today, 600).unwrap();
+        assert!(!AgePolicy::under_years(20).matches(&public, today));
+        assert!(!AgePolicy::between_years(18, 26).matches(&public, today));
+        let older = AgePolicy::at_least_years(18).public(today, 600).unwrap();
+        assert!(!AgePolicy::under_years(26).matches(&older, today));
+    }
+
+    #[test]
+    fn band_matches_its_parts() {
+        let today = day(2020, 5, 17);
+        let band = AgePolicy::between_years(18, 26).public(today, 600).unwrap();
+        assert!(AgePolicy::between_years(18, 26).matches(&band, today));
+        assert!(AgePolicy::at_least_years(18).matches(&band, today));
+        assert!(AgePolicy::under_years(26).matches(&band, today));
+        assert!(!AgePolicy::between_years(18, 25).matches(&band, today));
+    }
+
+    #[test]
+    fn claim_of_an_earlier_day() {
+        let today = day(2020, 5, 17);
+        let public = AgePolicy::at_least_years(18)
+            .public(today - 1, 600)
+            .unwrap();
+        // Still 18 the next day, but under 26 may have ended.
+        assert!(AgePolicy::at_least_years(18).matches(&public, today));
+        let public = AgePolicy::under_years(26).public(today - 1, 600).unwrap();
+        assert!(!AgePolicy::under_years(26).matches(&public, today));
+    }
+
+    #[test]
+    fn invalid_years() {
+        for policy in &[
+            AgePolicy::at_least_years(0),
+            AgePolicy::under_years(-1),
+            AgePolicy::between_years(26, 18),
+            AgePolicy::between_years(18, 18),
+        ] {
+            assert_eq!(policy.public(2459000, 0), Err(PolicyError::InvalidYears));
+            assert!(!policy.matches(&Public::older_than(10000, 2459000, 0).unwrap(), 2459000));
+        }
+        assert_eq!(
+            AgePolicy::at_least_years(18).public(0, 0),
+            Err(PolicyError::Date(DateError::OutOfRange))
+        );
+    }
+}
@@ -0,0 +1,348 @@
+// Splitting of long payloads into several QR code frames which are
+// scanned one after another.
+//
+// A frame is `LA<version><index>/<total>:<base58 data>`. The version
+// is a single digit, the index starts at 1. The data of each frame
+// starts with a 4 byte identifier of the whole payload, so that frames
+// of two different payloads are never mixed.
+
+use std::fmt;
+
+/// Version of the frame format.
+pub const CHUNK_FORMAT_VERSION: u8 = 1;
+
+/// Maximal number of frames of a payload. The assembler allocates a
+/// slot for each frame, so the number read from a frame is limited.
+pub const MAX_FRAMES: usize = 1024;
+
+const FRAME_PREFIX: &str = "LA";
+
+const ID_LENGTH: usize = 4;
+
+/// Error assembling the payload from frames.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ChunkError {
+    /// The frame does not have the `LA<version><index>/<total>:<data>`
+    /// form or the data is not valid base58.
+    InvalidFrame,
+    /// The frame format version is not known.
+    UnsupportedVersion(u8),
+    /// The frame with this index has already been added.
+    Duplicate(usize),
+    /// The frame belongs to a payload with a different number of
+    /// frames.
+    TotalMismatch { expected: usize, found: usize },
+    /// The frame belongs to a different payload.
+    ForeignChunk,
+    /// Some frames are missing, the indices are listed.
+    Missing(Vec<usize>),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChunkError::InvalidFrame => write!(f, "invalid frame"),
+            ChunkError::UnsupportedVersion(v) => write!(f, "unsupported frame version {}", v),
+            ChunkError::Duplicate(i) => write!(f, "duplicate frame {}", i),
+            ChunkError::TotalMismatch { expected, found } => {
+                write!(f, "expected {} frames, found {}", expected, found)
+            }
+            ChunkError::ForeignChunk => write!(f, "frame of a different payload"),
+            ChunkError::Missing(indices) => write!(f, "missing frames {:?}", indices),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// 32-bit FNV-1a hash identifying the payload.
+fn payload_id(payload: &[u8]) -> [u8; ID_LENGTH] {
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in payload {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash.to_be_bytes()
+}
+
+/// Splits the payload into frames with at most `max_chunk` payload
+/// bytes each. An empty payload gives one empty frame. There may be
+/// at most `MAX_FRAMES` frames.
+pub fn split_payload(payload: &[u8], max_chunk: usize) -> Vec<String> {
+    assert!(max_chunk > 0, "max_chunk must be positive");
+    assert!(
+        payload.len() <= max_chunk.saturating_mul(MAX_FRAMES),
+        "too many frames, max_chunk must be larger"
+    );
+    let id = payload_id(payload);
+    let chunks: Vec<&[u8]> = if payload.is_empty() {
+        vec![payload]
+    } else {
+        payload.chunks(max_chunk).collect()
+    };
+    let total = chunks.len();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut data = id.to_vec();
+            data.extend_from_slice(chunk);
+            format!(
+                "{}{}{}/{}:{}",
+                FRAME_PREFIX,
+                CHUNK_FORMAT_VERSION,
+                i + 1,
+                total,
+                bs58::encode(data).into_string()
+            )
+        })
+        .collect()
+}
+
+/// Decoded frame.
+#[derive(PartialEq, Debug)]
+struct Frame {
+    index: usize,
+    total: usize,
+    id: [u8; ID_LENGTH],
+    data: Vec<u8>,
+}
+
+fn parse_frame(frame: &str) -> Result<Frame, ChunkError> {
+    let rest = frame
+        .strip_prefix(FRAME_PREFIX)
+        .ok_or(ChunkError::InvalidFrame)?;
+    let version = rest
+        .chars()
+        .next()
+        .and_then(|c| c.to_digit(10))
+        .ok_or(ChunkError::InvalidFrame)? as u8;
+    if version != CHUNK_FORMAT_VERSION {
+        return Err(ChunkError::UnsupportedVersion(version));
+    }
+    let rest = &rest[1..];
+    let colon = rest.find(':').ok_or(ChunkError::InvalidFrame)?;
+    let (position, data) = (&rest[..colon], &rest[colon + 1..]);
+    let slash = position.find('/').ok_or(ChunkError::InvalidFrame)?;
+    let parse = |s: &str| {
+        // Reject signs and empty numbers accepted by `parse`.
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ChunkError::InvalidFrame);
+        }
+        s.parse::<usize>().map_err(|_| ChunkError::InvalidFrame)
+    };
+    let index = parse(&position[..slash])?;
+    let total = parse(&position[slash + 1..])?;
+    if index == 0 || index > total || total > MAX_FRAMES {
+        return Err(ChunkError::InvalidFrame);
+    }
+    let data = bs58::decode(data)
+        .into_vec()
+        .map_err(|_| ChunkError::InvalidFrame)?;
+    if data.len() < ID_LENGTH {
+        return Err(ChunkError::InvalidFrame);
+    }
+    let mut id = [0u8; ID_LENGTH];
+    id.copy_from_slice(&data[..ID_LENGTH]);
+    Ok(Frame {
+        index: index,
+        total: total,
+        id: id,
+        data: data[ID_LENGTH..].to_vec(),
+    })
+}
+
+/// Collects frames in any order and reassembles the payload.
+#[derive(Debug, Default)]
+pub struct ChunkAssembler {
+    id: Option<[u8; ID_LENGTH]>,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl ChunkAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a scanned frame. Returns the payload once all frames have
+    /// been added. A rejected frame does not change the state, so the
+    /// scanner may simply go on scanning.
+    pub fn add(&mut self, frame: &str) -> Result<Option<Vec<u8>>, ChunkError> {
+        let frame = parse_frame(frame)?;
+        match self.id {
+            None => {
+                self.id = Some(frame.id);
+                self.chunks = vec![None; frame.total];
+            }
+            Some(id) => {
+                if self.chunks.len() != frame.total {
+                    return Err(ChunkError::TotalMismatch {
+                        expected: self.chunks.len(),
+                        found: frame.total,
+                    });
+                }
+                if id != frame.id {
+                    return Err(ChunkError::ForeignChunk);
+                }
+            }
+        }
+        let slot = &mut self.chunks[frame.index - 1];
+        if slot.is_some() {
+            return Err(ChunkError::Duplicate(frame.index));
+        }
+        *slot = Some(frame.data);
+        match self.payload() {
+            None if self.is_complete() => Err(ChunkError::ForeignChunk),
+            payload => Ok(payload),
+        }
+    }
+
+    /// Indices of frames which have not been added yet. The number of
+    /// frames is not known before the first one is added.
+    pub fn missing(&self) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.is_none())
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.id.is_some() && self.chunks.iter().all(|chunk| chunk.is_some())
+    }
+
+    /// The reassembled payload if all frames have been added and it
+    /// matches the identifier.
+    fn payload(&self) -> Option<Vec<u8>> {
+        if !self.is_complete() {
+            return None;
+        }
+        let payload: Vec<u8> = self.chunks.iter().flatten().flatten().cloned().collect();
+        if Some(payload_id(&payload)) != self.id {
+            return None;
+        }
+        Some(payload)
+    }
+
+    /// Consumes the assembler, returns the payload or the missing
+    /// frames.
+    pub fn finish(self) -> Result<Vec<u8>, ChunkError> {
+        match self.payload() {
+            Some(payload) => Ok(payload),
+            None if self.is_complete() => Err(ChunkError::ForeignChunk),
+            None => Err(ChunkError::Missing(self.missing())),
+        }
+    }
+}
+
+/// Reassembles the payload from all its frames in any order.
+pub fn join_frames<'a, I>(frames: I) -> Result<Vec<u8>, ChunkError>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut assembler = ChunkAssembler::new();
+    for frame in frames {
+        assembler.add(frame)?;
+    }
+    assembler.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..100).collect()
+    }
+
+    #[test]
+    fn frame_format() {
+        let frames = split_payload(&payload(), 40);
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].starts_with("LA11/3:"));
+        assert!(frames[2].starts_with("LA13/3:"));
+        assert_eq!(split_payload(&[], 40).len(), 1);
+    }
+
+    #[test]
+    fn out_of_order_delivery() {
+        let frames = split_payload(&payload(), 30);
+        let mut assembler = ChunkAssembler::new();
+        assert_eq!(assembler.add(&frames[3]), Ok(None));
+        assert_eq!(assembler.add(&frames[1]), Ok(None));
+        assert_eq!(assembler.add(&frames[0]), Ok(None));
+        assert_eq!(assembler.missing(), vec![3]);
+        assert_eq!(assembler.add(&frames[2]), Ok(Some(payload())));
+        assert_eq!(assembler.finish(), Ok(payload()));
+    }
+
+    #[test]
+    fn missing_chunk() {
+        let frames = split_payload(&payload(), 30);
+        assert_eq!(
+            join_frames(frames.iter().skip(1).map(String::as_str)),
+            Err(ChunkError::Missing(vec![1]))
+        );
+    }
+
+    #[test]
+    fn duplicate_chunk() {
+        let frames = split_payload(&payload(), 30);
+        let mut assembler = ChunkAssembler::new();
+        assert_eq!(assembler.add(&frames[1]), Ok(None));
+        assert_eq!(assembler.add(&frames[1]), Err(ChunkError::Duplicate(2)));
+    }
+
+    #[test]
+    fn chunk_of_other_payload() {
+        let frames = split_payload(&payload(), 30);
+        let mut other = payload();
+        other[0] = 1;
+        let other_frames = split_payload(&other, 30);
+        let mut assembler = ChunkAssembler::new();
+        assert_eq!(assembler.add(&frames[0]), Ok(None));
+        assert_eq!(
+            assembler.add(&other_frames[1]),
+            Err(ChunkError::ForeignChunk)
+        );
+        let shorter = split_payload(&payload(), 50);
+        assert_eq!(
+            assembler.add(&shorter[1]),
+            Err(ChunkError::TotalMismatch {
+                expected: 4,
+                found: 2
+            })
+        );
+        // Rejected frames do not spoil the assembler.
+        for frame in &frames[1..] {
+            assembler.add(frame).unwrap();
+        }
+        assert_eq!(assembler.finish(), Ok(payload()));
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let mut assembler = ChunkAssembler::new();
+        for frame in &[
+            "",
+            "LA",
+            "XX11/1:1",
+            "LA10/1:2111",
+            "LA12/1:2111",
+            "LA1+1/1:2111",
+            "LA11/1",
+            "LA11/1:0OIl",
+            "LA11/1:2",
+            "LA11/1025:2111",
+            "LA11/99999999999999999999:2111",
+        ] {
+            assert_eq!(assembler.add(frame), Err(ChunkError::InvalidFrame));
+        }
+        assert_eq!(
+            assembler.add("LA21/1:2111"),
+            Err(ChunkError::UnsupportedVersion(2))
+        );
+        assert!(!assembler.is_complete());
+    }
+}
@@ -0,0 +1,154 @@
+// Recovery phrases of the private key. The phone app shows the phrase
+// once, when the key is created, so that a new phone can derive the
+// same key and the certified card keeps working. The phrase is a
+// 12-word BIP39 mnemonic of the English wordlist.
+
+use crate::api::PrivateKey;
+use crate::encoding::field_from_le_bytes;
+use bip39::{ErrorKind, Language, Mnemonic, MnemonicType, Seed};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use zeroize::Zeroize;
+
+/// Error reading a recovery phrase.
+#[derive(PartialEq, Debug, Clone)]
+pub enum KeyError {
+    /// A word is not in the wordlist.
+    InvalidWord,
+    /// The checksum of the phrase does not match, e.g. a word has been
+    /// swapped for another one.
+    InvalidChecksum,
+    /// The phrase has a wrong number of words.
+    InvalidLength,
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::InvalidWord => write!(f, "unknown word in the recovery phrase"),
+            KeyError::InvalidChecksum => write!(f, "invalid recovery phrase checksum"),
+            KeyError::InvalidLength => write!(f, "invalid number of words"),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// Generates a random 12-word recovery phrase.
+pub fn generate_mnemonic() -> String {
+    Mnemonic::new(MnemonicType::Words12, Language::English).into_phrase()
+}
+
+/// Derives the private key from the recovery phrase. The phrase is
+/// stretched by the BIP39 seed function without a passphrase, the
+/// seed hashed with SHA-256 and the 3 top bits cleared, so that the
+/// key is a field element like the keys of
+/// `zk::generate_random_private_key`. Words may be separated by any
+/// whitespace and are not case sensitive.
+pub fn private_key_from_mnemonic(phrase: &str) -> Result<PrivateKey, KeyError> {
+    let phrase = phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if phrase.split(' ').count() != 12 {
+        return Err(KeyError::InvalidLength);
+    }
+    let mnemonic = Mnemonic::from_phrase(&phrase, Language::English).map_err(|e| match e
+        .downcast_ref::<ErrorKind>()
+    {
+        Some(ErrorKind::InvalidChecksum) => KeyError::InvalidChecksum,
+        Some(ErrorKind::InvalidWord) => KeyError::InvalidWord,
+        _ => KeyError::InvalidLength,
+    })?;
+    let seed = Seed::new(&mnemonic, "");
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"legalage private key");
+    hasher.update(seed.as_bytes());
+    let mut key = hasher.finalize();
+    // Below 2^253, which is below the modulus.
+    key[31] &= 0x1f;
+    let value = field_from_le_bytes(&key).expect("253 bits are a field element");
+    key.as_mut_slice().zeroize();
+    Ok(PrivateKey::from_field(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{PhotosDigest, Private};
+    use crate::zk;
+
+    const ABANDON: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                           abandon abandon abandon about";
+
+    #[test]
+    fn known_phrases() {
+        // BIP39 test vectors, the seeds of an empty passphrase are
+        // 5eb00bbd... and 878386ef...
+        assert_eq!(
+            hex::encode(private_key_from_mnemonic(ABANDON).unwrap().to_be_bytes()),
+            "17134481dc3eb249cad0d3767e0a1b0d000f532e0d3c662f7fd8d9bd01d2bef6"
+        );
+        let phrase = "legal winner thank year wave sausage worth useful legal winner thank yellow";
+        assert_eq!(
+            hex::encode(private_key_from_mnemonic(phrase).unwrap().to_be_bytes()),
+            "1367929884df77d857798386086acf5e3139e672b9dc77745857c283fbe57e26"
+        );
+        let spaced = format!("  {}\n", ABANDON.to_uppercase().replace(' ', "\t "));
+        assert_eq!(
+            private_key_from_mnemonic(&spaced),
+            private_key_from_mnemonic(ABANDON)
+        );
+    }
+
+    #[test]
+    fn invalid_phrases() {
+        let swapped = ABANDON.replace("about", "abandon");
+        assert_eq!(
+            private_key_from_mnemonic(&swapped),
+            Err(KeyError::InvalidChecksum)
+        );
+        let unknown = ABANDON.replace("about", "legalage");
+        assert_eq!(
+            private_key_from_mnemonic(&unknown),
+            Err(KeyError::InvalidWord)
+        );
+        assert_eq!(
+            private_key_from_mnemonic("abandon about"),
+            Err(KeyError::InvalidLength)
+        );
+        assert_eq!(private_key_from_mnemonic(""), Err(KeyError::InvalidLength));
+    }
+
+    #[test]
+    fn generated_phrase_derives_key() {
+        let phrase = generate_mnemonic();
+        assert_eq!(phrase.split(' ').count(), 12);
+        assert_ne!(phrase, generate_mnemonic());
+        // A new phone derives the same card key.
+        let card_key = || {
+            let key = private_key_from_mnemonic(&phrase).unwrap();
+            let photos_digest = PhotosDigest::new(vec![2, 7]).unwrap();
+            zk::generate_card_key(&Private::with(2001, key, photos_digest).unwrap()).unwrap()
+        };
+        assert_eq!(card_key(), card_key());
+    }
+
+    #[cfg(all(feature = "prover", feature = "verifier"))]
+    #[test]
+    fn derived_key_proves() {
+        use crate::api::{Public, QrRequest};
+
+        let photos_digest = PhotosDigest::new(vec![2, 7]).unwrap();
+        let private_key = private_key_from_mnemonic(ABANDON).unwrap();
+        let rq = QrRequest::build(
+            Public::older_than(18, 2020, 1200).unwrap(),
+            Private::with(2001, private_key, photos_digest.clone()).unwrap(),
+        )
+        .unwrap();
+        let qr = zk::generate_proof(rq).unwrap();
+        assert_eq!(zk::verify_proof(&qr, &photos_digest), Ok(()));
+    }
+}
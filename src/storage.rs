@@ -0,0 +1,241 @@
+// Encrypted storage of the card on the phone. The saved card is
+//
+// | bytes | field                                   |
+// |-------|-----------------------------------------|
+// | 1     | format version                          |
+// | 16    | Argon2id salt                           |
+// | 12    | ChaCha20-Poly1305 nonce                 |
+// | rest  | encrypted JSON of the card with the tag |
+//
+// The key is stretched from the passphrase by Argon2id with the
+// parameters of the version. The header is authenticated with the
+// card, so a changed version or salt is refused like a wrong
+// passphrase.
+
+use crate::api::{CardKey, PhotosDigest, Private, PrivateKey, ValidationError};
+use argon2::{Algorithm, Argon2, Version};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zeroize::Zeroize;
+
+/// Version of the format written by `Card::save_encrypted`.
+pub const CARD_FORMAT_VERSION: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+
+const NONCE_LENGTH: usize = 12;
+
+const HEADER_LENGTH: usize = 1 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Argon2id memory in KiB, passes and lanes of version 1. About a
+/// second on a mid-range phone.
+const ARGON2_MEMORY: u32 = 19 * 1024;
+const ARGON2_PASSES: u32 = 2;
+const ARGON2_LANES: u32 = 1;
+
+/// Error loading a saved card.
+#[derive(PartialEq, Debug, Clone)]
+pub enum CardError {
+    /// The passphrase is wrong or the saved card has been modified.
+    BadPassphrase,
+    /// The card was saved by a newer version of the library.
+    UnsupportedVersion(u8),
+    /// The data is shorter than the header and the tag.
+    Truncated,
+    /// The decrypted card is not valid.
+    InvalidCard,
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardError::BadPassphrase => write!(f, "wrong passphrase or corrupted card"),
+            CardError::UnsupportedVersion(v) => write!(f, "unsupported card format version {}", v),
+            CardError::Truncated => write!(f, "truncated card"),
+            CardError::InvalidCard => write!(f, "invalid card"),
+        }
+    }
+}
+
+impl std::error::Error for CardError {}
+
+/// Card of the user, as issued by the certifier.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Card {
+    /// Julian day of the birthday.
+    pub birthday: i32,
+    pub private_key: PrivateKey,
+    pub photos_digest: PhotosDigest,
+    pub card_key: CardKey,
+    /// Julian day the card was issued.
+    pub issued: i32,
+}
+
+impl Card {
+    /// Private part of the QR code requests of the card.
+    pub fn private(&self) -> Result<Private, ValidationError> {
+        Private::with(
+            self.birthday,
+            self.private_key.clone(),
+            self.photos_digest.clone(),
+        )
+    }
+
+    /// Encrypts the card with a key stretched from `passphrase`. Each
+    /// call uses a new salt and nonce.
+    pub fn save_encrypted(&self, passphrase: &str) -> Vec<u8> {
+        let mut header = vec![CARD_FORMAT_VERSION];
+        header.extend_from_slice(&thread_rng().gen::<[u8; SALT_LENGTH]>());
+        header.extend_from_slice(&thread_rng().gen::<[u8; NONCE_LENGTH]>());
+        let mut json = serde_json::to_vec(self).expect("card is serializable");
+        let encrypted = cipher(passphrase, &header[1..1 + SALT_LENGTH])
+            .encrypt(
+                Nonce::from_slice(&header[1 + SALT_LENGTH..]),
+                Payload {
+                    msg: &json,
+                    aad: &header,
+                },
+            )
+            .expect("encryption of a short card");
+        json.zeroize();
+        header.extend_from_slice(&encrypted);
+        header
+    }
+
+    /// Decrypts a card of `save_encrypted`.
+    pub fn load_encrypted(bytes: &[u8], passphrase: &str) -> Result<Card, CardError> {
+        match bytes.first() {
+            Some(&CARD_FORMAT_VERSION) => (),
+            Some(&version) => return Err(CardError::UnsupportedVersion(version)),
+            None => return Err(CardError::Truncated),
+        }
+        // The tag has 16 bytes.
+        if bytes.len() < HEADER_LENGTH + 16 {
+            return Err(CardError::Truncated);
+        }
+        let (header, encrypted) = bytes.split_at(HEADER_LENGTH);
+        let mut json = cipher(passphrase, &header[1..1 + SALT_LENGTH])
+            .decrypt(
+                Nonce::from_slice(&header[1 + SALT_LENGTH..]),
+                Payload {
+                    msg: encrypted,
+                    aad: header,
+                },
+            )
+            .map_err(|_| CardError::BadPassphrase)?;
+        let card = serde_json::from_slice(&json).map_err(|_| CardError::InvalidCard);
+        json.zeroize();
+        card
+    }
+}
+
+impl Drop for Card {
+    fn drop(&mut self) {
+        self.birthday.zeroize();
+    }
+}
+
+/// Cipher with the key stretched from the passphrase and the salt.
+fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let argon2 = Argon2::new(
+        None,
+        ARGON2_PASSES,
+        ARGON2_MEMORY,
+        ARGON2_LANES,
+        Version::V0x13,
+    )
+    .expect("valid Argon2 parameters");
+    let mut key = [0u8; 32];
+    argon2
+        .hash_password_into(
+            Algorithm::Argon2id,
+            passphrase.as_bytes(),
+            salt,
+            &[],
+            &mut key,
+        )
+        .expect("valid Argon2 output length");
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    key.zeroize();
+    cipher
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card() -> Card {
+        Card {
+            birthday: 2451000,
+            private_key: PrivateKey::new(vec![10]).unwrap(),
+            photos_digest: PhotosDigest::new(vec![2, 7]).unwrap(),
+            card_key: CardKey::new(vec![27]).unwrap(),
+            issued: 2459000,
+        }
+    }
+
+    #[test]
+    fn encrypted_card_round_trip() {
+        let saved = card().save_encrypted("correct horse");
+        assert_eq!(saved[0], CARD_FORMAT_VERSION);
+        assert_eq!(Card::load_encrypted(&saved, "correct horse"), Ok(card()));
+        // A new salt and nonce each time.
+        assert_ne!(saved, card().save_encrypted("correct horse"));
+        assert_eq!(
+            card().private().unwrap().private_key,
+            PrivateKey::new(vec![10]).unwrap()
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let saved = card().save_encrypted("correct horse");
+        assert_eq!(
+            Card::load_encrypted(&saved, "correct horsE"),
+            Err(CardError::BadPassphrase)
+        );
+        assert_eq!(
+            Card::load_encrypted(&saved, ""),
+            Err(CardError::BadPassphrase)
+        );
+    }
+
+    #[test]
+    fn corrupted_card() {
+        let saved = card().save_encrypted("correct horse");
+        // The salt, the nonce, the ciphertext and the tag.
+        for i in &[1, 1 + SALT_LENGTH, HEADER_LENGTH, saved.len() - 1] {
+            let mut corrupted = saved.clone();
+            corrupted[*i] ^= 1;
+            assert_eq!(
+                Card::load_encrypted(&corrupted, "correct horse"),
+                Err(CardError::BadPassphrase)
+            );
+        }
+        assert_eq!(
+            Card::load_encrypted(&saved[..HEADER_LENGTH + 15], "correct horse"),
+            Err(CardError::Truncated)
+        );
+        assert_eq!(
+            Card::load_encrypted(&[], "correct horse"),
+            Err(CardError::Truncated)
+        );
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let mut saved = card().save_encrypted("correct horse");
+        saved[0] = CARD_FORMAT_VERSION + 1;
+        assert_eq!(
+            Card::load_encrypted(&saved, "correct horse"),
+            Err(CardError::UnsupportedVersion(CARD_FORMAT_VERSION + 1))
+        );
+        assert_eq!(
+            Card::load_encrypted(&[0], "correct horse"),
+            Err(CardError::UnsupportedVersion(0))
+        );
+    }
+}
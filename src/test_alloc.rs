@@ -0,0 +1,60 @@
+// Global allocator of the tests. A crate has a single global
+// allocator, so the tests of all modules share this one: it counts the
+// allocations and checks that a watched buffer is wiped before it is
+// freed. The state is kept per thread, so that tests running in
+// parallel do not disturb each other.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct TestAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+    static WATCHED: Cell<usize> = Cell::new(0);
+    static WIPED: Cell<bool> = Cell::new(false);
+}
+
+unsafe impl GlobalAlloc for TestAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let watched = WATCHED.try_with(|w| w.get()).unwrap_or(0);
+        if watched != 0 && ptr as usize == watched {
+            let bytes = std::slice::from_raw_parts(ptr, layout.size());
+            let _ = WIPED.try_with(|w| w.set(bytes.iter().all(|b| *b == 0)));
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: TestAllocator = TestAllocator;
+
+/// Runs `f`, returns its result and the number of allocations of the
+/// current thread.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub fn count_allocations<T, F: FnOnce() -> T>(f: F) -> (T, usize) {
+    let before = ALLOCATIONS.with(|n| n.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|n| n.get()) - before)
+}
+
+/// Runs `f` and returns true if `buffer` has been freed by it and was
+/// zero just before.
+pub fn wiped_when_freed<F: FnOnce()>(buffer: *const u8, f: F) -> bool {
+    WIPED.with(|w| w.set(false));
+    WATCHED.with(|w| w.set(buffer as usize));
+    f();
+    WATCHED.with(|w| w.set(0));
+    WIPED.with(|w| w.get())
+}
+
+/// Drops the value and returns true if the buffer has been wiped
+/// before it was freed.
+pub fn wiped_on_drop<T>(value: T, buffer: *const u8) -> bool {
+    wiped_when_freed(buffer, || drop(value))
+}
@@ -0,0 +1,167 @@
+// Fixed vectors of the card key, the challenge and the QR code for the
+// other implementations, e.g. the Kotlin verifier and the web
+// service. Compiled with the `test-vectors` feature. The QR codes are
+// generated by the seeded prover, so they are the same on every run
+// and machine; they must never be used by a real card.
+
+use crate::api::{
+    relation_holds, CardKey, Challenge, PhotosDigest, Private, PrivateKey, Public, QrRequest,
+    Relation, EMBEDDED_CIRCUIT,
+};
+use crate::zk;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// One vector. The keys, digests and challenges are base58 of the
+/// little-endian bytes, the same as in the QR code.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct TestVector {
+    /// Julian days.
+    pub birthday: i32,
+    pub today: i32,
+    /// Minutes since midnight.
+    pub now: i32,
+    pub delta: i32,
+    pub relation: Relation,
+    pub private_key: PrivateKey,
+    pub photos_digest: PhotosDigest,
+    /// `zk::generate_card_key` of the private values.
+    pub card_key: CardKey,
+    /// `zk::compute_challenge` of the card key and today.
+    pub challenge: Challenge,
+    /// Seed of `zk::generate_proof_seeded`.
+    pub seed: [u8; 32],
+    /// QR code string, see `ProofQrCode::to_string`.
+    pub qr_code: String,
+    /// The relation holds and the QR code verifies with the photo
+    /// digest. Otherwise the QR code holds a decoy proof.
+    pub verifies: bool,
+}
+
+/// (birthday, today, delta, relation, private key, photos digest)
+const FIXTURES: [(i32, i32, i32, Relation, &[u8], &[u8]); 5] = [
+    // Born 2000-01-01, today 2020-05-17.
+    (2451545, 2458987, 6574, Relation::Older, &[10], &[2, 7]),
+    (2451545, 2458987, 6574, Relation::Younger, &[10], &[2, 7]),
+    (
+        2455000,
+        2458987,
+        6574,
+        Relation::Younger,
+        &[1, 2, 3],
+        &[4, 5, 6, 7],
+    ),
+    // On the day `birthday + delta == today` neither relation holds.
+    (
+        2452413,
+        2458987,
+        6574,
+        Relation::Older,
+        &[0xff; 31],
+        &[0x2a; 32],
+    ),
+    (2415021, 2459000, 18, Relation::Older, &[7; 16], &[1, 2, 3]),
+];
+
+static VECTORS: Lazy<Vec<TestVector>> = Lazy::new(|| {
+    FIXTURES
+        .iter()
+        .enumerate()
+        .map(
+            |(index, &(birthday, today, delta, relation, key, digest))| {
+                vector(index as u8, birthday, today, delta, relation, key, digest)
+            },
+        )
+        .collect()
+});
+
+fn vector(
+    index: u8,
+    birthday: i32,
+    today: i32,
+    delta: i32,
+    relation: Relation,
+    private_key: &[u8],
+    photos_digest: &[u8],
+) -> TestVector {
+    let private_key = PrivateKey::new(private_key.to_vec()).expect("fixture key");
+    let photos_digest = PhotosDigest::new(photos_digest.to_vec()).expect("fixture digest");
+    let private = Private::with(birthday, private_key.clone(), photos_digest.clone())
+        .expect("fixture birthday");
+    let card_key = zk::generate_card_key(&private).expect("fixture values are not zero");
+    let public = Public {
+        today: today,
+        now: 720,
+        relation: relation,
+        delta: delta,
+        second: None,
+        nonce: None,
+    };
+    let seed = [index + 1; 32];
+    let rq = QrRequest {
+        circuit: EMBEDDED_CIRCUIT,
+        public: public,
+        private: private,
+        allow_unbound_proof: false,
+    };
+    let qr = zk::generate_proof_seeded(rq, seed).expect("fixture request is valid");
+    TestVector {
+        birthday: birthday,
+        today: today,
+        now: qr.public.now,
+        delta: delta,
+        relation: relation,
+        private_key: private_key,
+        photos_digest: photos_digest,
+        challenge: zk::compute_challenge(&card_key, today),
+        card_key: card_key,
+        seed: seed,
+        qr_code: qr.to_string(),
+        verifies: relation_holds(birthday, today, delta, relation),
+    }
+}
+
+/// All vectors. They are generated on the first call, which takes a
+/// proof generation per vector.
+pub fn vectors() -> Vec<TestVector> {
+    VECTORS.clone()
+}
+
+#[cfg(all(test, feature = "verifier"))]
+mod tests {
+    use super::*;
+    use crate::api::ProofQrCode;
+
+    #[test]
+    fn vectors_verify() {
+        let vectors = vectors();
+        assert_eq!(vectors.len(), FIXTURES.len());
+        assert_eq!(
+            vectors.iter().map(|v| v.verifies).collect::<Vec<_>>(),
+            vec![true, false, true, false, true]
+        );
+        for v in &vectors {
+            let qr: ProofQrCode = v.qr_code.parse().unwrap();
+            assert_eq!(qr.challenge.to_field(), v.challenge.to_field());
+            assert_eq!(
+                zk::verify_proof(&qr, &v.photos_digest).is_ok(),
+                v.verifies,
+                "{:?}",
+                v
+            );
+        }
+    }
+
+    #[test]
+    fn vectors_are_deterministic() {
+        let (birthday, today, delta, relation, key, digest) = FIXTURES[2];
+        assert_eq!(
+            vector(2, birthday, today, delta, relation, key, digest),
+            vectors()[2]
+        );
+        let json = serde_json::to_value(&vectors()).unwrap();
+        assert_eq!(json[0]["relation"], "older");
+        assert_eq!(json[0]["private_key"], "B");
+        assert_eq!(json[0]["verifies"], true);
+    }
+}
@@ -0,0 +1,191 @@
+// Interface for verification in a web browser. Compiled with the
+// `wasm` feature, build with `wasm-pack build -- --features wasm`.
+// The verification key is embedded, the proving key is left out of
+// wasm builds.
+
+use crate::api::{CardKey, PhotosDigest, ProofQrCode, Public, QrError};
+use crate::hash::photos_digest;
+use crate::web_api::{self, VerificationReport};
+use crate::zk::{self, ZkError};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// Result of `verify_qr` passed to JavaScript.
+#[derive(PartialEq, Debug, Serialize)]
+pub struct WasmVerification {
+    /// The proof verifies and the date matches.
+    pub accepted: bool,
+    /// Verification details, missing if the QR code cannot be decoded.
+    pub report: Option<VerificationReport>,
+    /// Description of the error.
+    pub error: Option<String>,
+}
+
+fn verification(qr: &str, photo_digest: &[u8], today: i32) -> WasmVerification {
+    let digest = PhotosDigest::new(photo_digest.to_vec()).map_err(ZkError::from);
+    verification_of(qr, digest, today)
+}
+
+fn verification_of(
+    qr: &str,
+    photo_digest: Result<PhotosDigest, ZkError>,
+    today: i32,
+) -> WasmVerification {
+    let result = photo_digest.and_then(|digest| web_api::verify_qr(qr, &digest, today));
+    match result {
+        Ok(report) => WasmVerification {
+            accepted: report.accepted(),
+            report: Some(report),
+            error: None,
+        },
+        Err(e) => WasmVerification {
+            accepted: false,
+            report: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Decodes and verifies a scanned QR code string. Returns a
+/// `WasmVerification` object.
+#[wasm_bindgen]
+pub fn verify_qr(qr: &str, photo_digest: &[u8], today: i32) -> JsValue {
+    JsValue::from_serde(&verification(qr, photo_digest, today)).unwrap()
+}
+
+/// Same as `verify_qr` with the base58 photo digest of
+/// `compute_photo_digest`.
+#[wasm_bindgen]
+pub fn verify_qr_base58(qr: &str, photo_digest: &str, today: i32) -> JsValue {
+    let digest = photo_digest.parse::<PhotosDigest>().map_err(ZkError::from);
+    JsValue::from_serde(&verification_of(qr, digest, today)).unwrap()
+}
+
+/// Base58 digest of a single photo, computed in the page so that the
+/// photo is not sent anywhere.
+#[wasm_bindgen]
+pub fn compute_photo_digest(photo: &[u8]) -> String {
+    photos_digest(&[photo.to_vec()]).to_string()
+}
+
+fn public_of(qr: &str) -> Result<Public, QrError> {
+    let parsed: ProofQrCode = qr.parse()?;
+    Ok(parsed.public)
+}
+
+/// Decodes the public part of a QR code string without verifying the
+/// proof.
+#[wasm_bindgen]
+pub fn decode_public(qr: &str) -> Result<JsValue, JsValue> {
+    let public = public_of(qr).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    JsValue::from_serde(&public).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Computes the challenge expected from the card for the day.
+#[wasm_bindgen]
+pub fn compute_challenge(card_key: &[u8], today: i32) -> Result<Vec<u8>, JsValue> {
+    let card_key =
+        CardKey::new(card_key.to_vec()).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(zk::compute_challenge(&card_key, today).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{
+        Challenge, Relation, EMBEDDED_CIRCUIT, EMBEDDED_KEY, FIELD_LENGTH, QR_FORMAT_VERSION,
+        SEALED_PROOF_LENGTH,
+    };
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn unverifiable_qr() -> String {
+        ProofQrCode {
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            proof: vec![1; SEALED_PROOF_LENGTH],
+            challenge: Challenge::new(vec![9; FIELD_LENGTH]).unwrap(),
+            second_proof: None,
+        }
+        .to_string()
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn verify_well_formed_qr() {
+        // Proofs cannot be generated in wasm. The QR code is decoded
+        // but the proof does not verify.
+        let v = verification(&unverifiable_qr(), &[2, 7], 2020);
+        assert!(!v.accepted);
+        assert!(v.report.unwrap().date_matches);
+        assert_eq!(public_of(&unverifiable_qr()).unwrap().delta, 18);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn verify_invalid_qr() {
+        let v = verification("not a qr code", &[2, 7], 2020);
+        assert!(!v.accepted);
+        assert!(v.report.is_none());
+        assert!(v.error.is_some());
+        assert!(public_of("not a qr code").is_err());
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn base58_photo_digest() {
+        let digest = compute_photo_digest(&[1, 2, 3]);
+        assert_eq!(digest, photos_digest(&[vec![1, 2, 3]]).to_string());
+        let qr = unverifiable_qr();
+        let v = verification_of(&qr, digest.parse().map_err(ZkError::from), 2020);
+        assert!(!v.accepted);
+        assert!(v.report.is_some());
+
+        let v = verification_of(&qr, "0OIl".parse().map_err(ZkError::from), 2020);
+        assert!(!v.accepted);
+        assert!(v.report.is_none());
+        assert!(v.error.is_some());
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), feature = "prover"))]
+    #[test]
+    fn verify_generated_qr() {
+        use crate::api::{Private, PrivateKey, QrRequest};
+
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: 2001,
+                private_key: PrivateKey::default(),
+                photos_digest: photos_digest.clone(),
+            },
+            allow_unbound_proof: false,
+        };
+        let qr = zk::generate_proof(rq).unwrap().to_string();
+        let v = verification(&qr, photos_digest.as_bytes(), 2020);
+        assert!(v.accepted);
+        assert_eq!(v.error, None);
+        let v = verification_of(&qr, Ok(photos_digest.clone()), 2020);
+        assert!(v.accepted);
+        let digest = photos_digest.to_string().parse().map_err(ZkError::from);
+        assert!(verification_of(&qr, digest, 2020).accepted);
+    }
+}
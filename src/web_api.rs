@@ -0,0 +1,1047 @@
+// Interface for the legalage web service. Compiled with the
+// `verifier` feature.
+
+use crate::api::{
+    CardKey, Challenge, PhotosDigest, PrivateKey, ProofQrCode, Public, QrError, QrSection,
+    ValidationError, VerifierLevel, EMBEDDED_CIRCUIT, EMBEDDED_KEY, FIELD_LENGTH, MAX_JULIAN_DAY,
+    QR_FORMAT_VERSION,
+};
+use crate::attestation::AttestationError;
+use crate::encoding::field_to_le_bytes;
+pub use crate::hash::{compute_photos_digest, photos_digest};
+#[cfg(feature = "async")]
+use crate::offload;
+#[cfg(feature = "async")]
+pub use crate::offload::{Offloaded, Spawner};
+pub use crate::policy::AgePolicy;
+use crate::zk::{self, Verifier, ZkError};
+#[cfg(feature = "prover")]
+pub use crate::zk::{CircuitInfo, SelfTestReport};
+pub use crate::zk::{VerificationFailure, VerifyOptions};
+use byteorder::{BigEndian, WriteBytesExt};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use zeroize::Zeroize;
+
+pub mod certification;
+
+/// Result of QR code verification.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct VerificationReport {
+    /// Public part decoded from the QR code.
+    pub public: Public,
+
+    /// The proof has been verified. Codes with wrong date are not
+    /// verified at all.
+    pub proof_verified: bool,
+
+    /// The date in the QR code is the verifier's today, or within the
+    /// allowed clock skew. Old QR codes may be replayed.
+    pub date_matches: bool,
+
+    /// Why the proof has not been verified, `None` if it has been or
+    /// if the date does not match.
+    pub failure: Option<VerificationFailure>,
+}
+
+impl VerificationReport {
+    /// The QR code is accepted if the proof verifies and is fresh.
+    pub fn accepted(&self) -> bool {
+        self.proof_verified && self.date_matches
+    }
+}
+
+/// Decodes and verifies a scanned QR code string. Errors are
+/// returned only for codes which cannot be decoded or when the
+/// verifier itself fails; a code which does not verify is reported
+/// in the `VerificationReport`.
+pub fn verify_qr(
+    qr: &str,
+    photo_digest: &PhotosDigest,
+    today: i32,
+) -> Result<VerificationReport, ZkError> {
+    verify_qr_with_skew(qr, photo_digest, today, 0)
+}
+
+/// Same as `verify_qr`, the date of the QR code may differ from the
+/// julian day `expected_today` by up to `max_clock_skew_days`, e.g.
+/// when the phone's clock is wrong or the code was generated just
+/// before midnight in another time zone. A negative skew accepts no
+/// date.
+pub fn verify_qr_with_skew(
+    qr: &str,
+    photo_digest: &PhotosDigest,
+    expected_today: i32,
+    max_clock_skew_days: i32,
+) -> Result<VerificationReport, ZkError> {
+    let parsed: ProofQrCode = qr.parse()?;
+    let skew = (parsed.public.today as i64 - expected_today as i64).abs();
+    let date_matches = skew <= max_clock_skew_days as i64;
+    let failure = if date_matches {
+        match zk::verify_proof(&parsed, photo_digest) {
+            Ok(()) => None,
+            Err(e @ ZkError::VerificationFailed)
+            | Err(e @ ZkError::PhotoMismatch)
+            | Err(e @ ZkError::TagMismatch) => VerificationFailure::from_error(&e),
+            Err(e) => return Err(e),
+        }
+    } else {
+        None
+    };
+    Ok(VerificationReport {
+        public: parsed.public,
+        proof_verified: date_matches && failure.is_none(),
+        date_matches: date_matches,
+        failure: failure,
+    })
+}
+
+/// Error of a request in the form logged and returned by the web
+/// service. The codes remain the same across versions.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    /// `ZkError::code`.
+    pub code: i32,
+    /// `QrError::code` of a QR code which cannot be decoded.
+    pub qr_error: Option<i32>,
+    /// Section of the QR code in which decoding failed, if known.
+    pub section: Option<QrSection>,
+    pub message: String,
+}
+
+impl From<&ZkError> for ErrorReport {
+    fn from(e: &ZkError) -> Self {
+        let qr_error = match e {
+            ZkError::QrCode(qr_error) => Some(qr_error),
+            _ => None,
+        };
+        ErrorReport {
+            code: e.code(),
+            qr_error: qr_error.map(QrError::code),
+            section: qr_error.and_then(QrError::section),
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Same as `verify_qr`, runs on the shared pool of `offload` so that
+/// the async caller is not blocked.
+#[cfg(feature = "async")]
+pub fn verify_qr_async(
+    qr: &str,
+    photo_digest: &PhotosDigest,
+    today: i32,
+) -> Offloaded<Result<VerificationReport, ZkError>> {
+    verify_qr_async_with(offload::shared_pool(), qr, photo_digest, today)
+}
+
+/// Same as `verify_qr_async`, runs on `spawner`. Dropping the future
+/// before the verification starts cancels it.
+#[cfg(feature = "async")]
+pub fn verify_qr_async_with<S: Spawner + ?Sized>(
+    spawner: &S,
+    qr: &str,
+    photo_digest: &PhotosDigest,
+    today: i32,
+) -> Offloaded<Result<VerificationReport, ZkError>> {
+    let qr = qr.to_string();
+    let photo_digest = photo_digest.clone();
+    offload::offload(spawner, move |_| Some(verify_qr(&qr, &photo_digest, today)))
+}
+
+/// Decodes and verifies a scanned QR code string which must have been
+/// generated within the freshness window of `options`. Returns the
+/// public part of a valid and fresh code.
+/// A code out of the window fails with `ZkError::Stale` before its
+/// proof is checked, so the verifier can ask for a fresh code instead
+/// of refusing the holder.
+pub fn verify_with_options(
+    qr: &str,
+    photo_digest: &PhotosDigest,
+    options: &VerifyOptions,
+) -> Result<Public, ZkError> {
+    let parsed: ProofQrCode = qr.parse()?;
+    zk::verify_with_options(&parsed, photo_digest, options)?;
+    Ok(parsed.public)
+}
+
+/// Same as `verify_with_options` without a freshness window, with the
+/// verification key `vk_json` instead of the embedded one, so that a
+/// deployment can pin the key it trusts. The key gets the id
+/// `EMBEDDED_KEY`.
+pub fn verify_with_key(
+    qr: &str,
+    photo_digest: &PhotosDigest,
+    vk_json: &str,
+) -> Result<Public, ZkError> {
+    let parsed: ProofQrCode = qr.parse()?;
+    Verifier::from_verification_key(vk_json.as_bytes())?.verify(&parsed, photo_digest)?;
+    Ok(parsed.public)
+}
+
+/// Verifier of a backlog of QR codes, e.g. an audit of the scans of a
+/// venue. The verification key is parsed once, at construction.
+pub struct BatchVerifier {
+    verifier: Verifier,
+}
+
+impl BatchVerifier {
+    /// Verifier of the embedded key.
+    pub fn new() -> Result<Self, ZkError> {
+        Ok(BatchVerifier {
+            verifier: Verifier::embedded()?,
+        })
+    }
+
+    /// Verifier of the key `vk_json`, see `verify_with_key`.
+    pub fn from_verification_key(vk_json: &str) -> Result<Self, ZkError> {
+        Ok(BatchVerifier {
+            verifier: Verifier::from_verification_key(vk_json.as_bytes())?,
+        })
+    }
+
+    /// Verifies the QR code with the photo digest in the wire form of
+    /// `PhotosDigest`.
+    pub fn verify(&self, qr: &ProofQrCode, photo_digest: &[u8]) -> Result<(), ZkError> {
+        let photo_digest = PhotosDigest::new(photo_digest.to_vec())?;
+        self.verifier.verify(qr, &photo_digest)
+    }
+
+    /// Verifies the items in parallel, the result at index `i` is the
+    /// result of `verify` of the item `i`.
+    pub fn verify_many(&self, items: &[(ProofQrCode, Vec<u8>)]) -> Vec<Result<(), ZkError>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use rayon::prelude::*;
+            items
+                .par_iter()
+                .map(|(qr, photo_digest)| self.verify(qr, photo_digest))
+                .collect()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            items
+                .iter()
+                .map(|(qr, photo_digest)| self.verify(qr, photo_digest))
+                .collect()
+        }
+    }
+}
+
+/// Version of the manifest written by `export_verifier_manifest`.
+pub const VERIFIER_MANIFEST_VERSION: u8 = 1;
+
+fn embedded_key_json() -> Value {
+    sort_keys(
+        serde_json::from_slice(zk::embedded_verification_key())
+            .expect("embedded verification key is JSON"),
+    )
+}
+
+/// Sorts the keys of all objects, so that the bytes do not depend on
+/// the `preserve_order` feature of serde_json.
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: BTreeMap<String, Value> =
+                map.into_iter().map(|(k, v)| (k, sort_keys(v))).collect();
+            Value::Object(sorted.into_iter().collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_keys).collect()),
+        other => other,
+    }
+}
+
+/// Embedded Groth16 verification key as compact JSON with sorted keys,
+/// for verifiers which do not link this library.
+pub fn export_verification_key() -> String {
+    embedded_key_json().to_string()
+}
+
+/// What a verifier needs besides the key, see
+/// `export_verifier_manifest`.
+#[derive(Serialize)]
+struct VerifierManifest {
+    version: u8,
+    circuit: u8,
+    key_id: u8,
+    qr_format_version: u8,
+    public_inputs: [&'static str; 4],
+    relation: &'static str,
+    max_julian_day: i32,
+    verification_key: Value,
+}
+
+/// JSON manifest for external verifiers: the verification key, the
+/// ids written to the QR codes and the order of the public inputs of
+/// the Groth16 verification. The `younger` input is 1 for "younger
+/// than" and 0 for "older than"; the circuit checks `birthday + delta
+/// > today` or `birthday + delta < today` respectively, with all days
+/// in `1..=max_julian_day`.
+pub fn export_verifier_manifest() -> String {
+    let manifest = VerifierManifest {
+        version: VERIFIER_MANIFEST_VERSION,
+        circuit: EMBEDDED_CIRCUIT,
+        key_id: EMBEDDED_KEY,
+        qr_format_version: QR_FORMAT_VERSION,
+        public_inputs: ["delta", "today", "younger", "challenge"],
+        relation: "younger ? birthday + delta > today : birthday + delta < today",
+        max_julian_day: MAX_JULIAN_DAY,
+        verification_key: embedded_key_json(),
+    };
+    serde_json::to_string(&manifest).expect("manifest is serializable")
+}
+
+/// Checks on startup that the embedded program and keys belong
+/// together. An error means that the installation is corrupt.
+#[cfg(feature = "prover")]
+pub fn self_test() -> Result<SelfTestReport, ZkError> {
+    zk::self_test()
+}
+
+/// Inputs and fingerprints of the embedded circuit, e.g. for the
+/// health endpoint of the service.
+#[cfg(feature = "prover")]
+pub fn circuit_info() -> Result<CircuitInfo, ZkError> {
+    zk::circuit_info()
+}
+
+/// Outcome of `verify_full`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum FullVerification {
+    /// Both the challenge and the proof are valid.
+    Verified,
+    /// The challenge was not computed from the card key. The QR code
+    /// belongs to another card or to another day.
+    ChallengeMismatch,
+    /// The challenge matches but the proof does not verify.
+    ProofFailed,
+    /// The QR code claims less than the policy of the verifier
+    /// requires, e.g. 16+ for an 18+ venue.
+    PolicyNotMet,
+    /// The card is revoked, or not issued yet, on the day of the QR
+    /// code. Reported by `CardRegistry::verify_full` only.
+    Revoked,
+    /// The card is not registered. Reported by
+    /// `CardRegistry::verify_full` only.
+    UnknownCard,
+}
+
+/// Verifies that the QR code belongs to the card with `card_key`, that
+/// it claims what `policy` requires on the verifier's julian day
+/// `today` and that the proof is valid. The cheap checks run first.
+/// Revocation is checked by `certification::CardRegistry::verify_full`.
+pub fn verify_full(
+    qr: &ProofQrCode,
+    card_key: &CardKey,
+    photo_digest: &PhotosDigest,
+    policy: &AgePolicy,
+    today: i32,
+) -> Result<FullVerification, ZkError> {
+    if !zk::verify_challenge(card_key, &qr.public, &qr.challenge) {
+        return Ok(FullVerification::ChallengeMismatch);
+    }
+    if !policy.matches(&qr.public, today) {
+        return Ok(FullVerification::PolicyNotMet);
+    }
+    match zk::verify_proof(qr, photo_digest) {
+        Ok(()) => Ok(FullVerification::Verified),
+        Err(ZkError::VerificationFailed)
+        | Err(ZkError::PhotoMismatch)
+        | Err(ZkError::TagMismatch) => Ok(FullVerification::ProofFailed),
+        Err(e) => Err(e),
+    }
+}
+
+/// Card key together with the intermediate values of its
+/// computation, kept by the certification server for audits.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CardKeyDerivation {
+    /// Final card key, `m1 * photos_digest`.
+    pub card_key: CardKey,
+    /// Product `birthday * private_key`.
+    pub k: Vec<u8>,
+    /// MiMC hash of `photos_digest` with the key `k`.
+    pub m1: Vec<u8>,
+}
+
+impl Drop for CardKeyDerivation {
+    fn drop(&mut self) {
+        self.k.zeroize();
+        self.m1.zeroize();
+    }
+}
+
+/// Derives the card key from the applicant's documents. The result is
+/// the same as `zk::generate_card_key`. The private key and the photo
+/// digest are range-checked by their types, zero values are refused.
+pub fn derive_card_key(
+    birthday: i32,
+    private_key: &PrivateKey,
+    photos_digest: &PhotosDigest,
+) -> Result<CardKeyDerivation, ZkError> {
+    if birthday <= 0 || birthday > MAX_JULIAN_DAY {
+        return Err(ValidationError::Birthday.into());
+    }
+    zk::check_card_values(private_key, photos_digest)?;
+    let (k, m1, card_key) = zk::derive_card_key(birthday, private_key, photos_digest);
+    Ok(CardKeyDerivation {
+        card_key: CardKey::from_field(&card_key),
+        k: field_to_le_bytes(&k),
+        m1: field_to_le_bytes(&m1),
+    })
+}
+
+/// Keys of a new card. The private key is handed to the phone and not
+/// stored, the service keeps the card key and the photo digest.
+#[derive(Debug)]
+pub struct CardRegistration {
+    pub private_key: PrivateKey,
+    pub card_key: CardKey,
+}
+
+/// Generates a random private key for the applicant and derives the
+/// card key, see `derive_card_key`.
+pub fn register_card(
+    birthday: i32,
+    photos_digest: &PhotosDigest,
+) -> Result<CardRegistration, ZkError> {
+    let private_key = zk::generate_random_private_key();
+    let mut derivation = derive_card_key(birthday, &private_key, photos_digest)?;
+    Ok(CardRegistration {
+        private_key: private_key,
+        card_key: std::mem::take(&mut derivation.card_key),
+    })
+}
+
+/// Challenge which the QR codes of the card generated on the julian
+/// day `today` must contain, see `zk::compute_challenge`.
+pub fn expected_challenge(card_key: &CardKey, today: i32) -> Result<Challenge, ZkError> {
+    if today <= 0 || today > MAX_JULIAN_DAY {
+        return Err(ValidationError::Today.into());
+    }
+    Ok(zk::compute_challenge(card_key, today))
+}
+
+/// Scan result countersigned by the venue, so that the certifier can
+/// audit the scans of `Professional` venues. Receipts of other levels
+/// are signed the same way; the relying party reads the level from
+/// `check_scan_signature` and decides whether to accept it.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct ScanReceipt {
+    /// Public part of the scanned QR code.
+    pub qr_public: Public,
+    /// The code has been accepted, see `VerificationReport::accepted`.
+    pub verified: bool,
+    pub verifier_level: VerifierLevel,
+    /// Seconds since the Unix epoch, UTC.
+    pub timestamp: i64,
+    /// Ed25519 signature of `scan_payload`.
+    #[serde(with = "crate::api::base58_bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// Signed bytes of the receipt: a tag, the public part as in the QR
+/// code with presence bytes for the optional fields, then the result,
+/// the level and the timestamp, all integers big-endian.
+fn scan_payload(receipt: &ScanReceipt) -> Vec<u8> {
+    let public = &receipt.qr_public;
+    let mut wtr = b"legalage scan receipt 1".to_vec();
+    wtr.write_i32::<BigEndian>(public.today).unwrap();
+    wtr.write_i32::<BigEndian>(public.now).unwrap();
+    wtr.write_i32::<BigEndian>(public.delta).unwrap();
+    wtr.push(public.relation as u8);
+    match &public.second {
+        Some(bound) => {
+            wtr.push(1);
+            wtr.push(bound.relation as u8);
+            wtr.write_i32::<BigEndian>(bound.delta).unwrap();
+        }
+        None => wtr.push(0),
+    }
+    match &public.nonce {
+        Some(nonce) => {
+            let mut nonce = nonce.as_bytes().to_vec();
+            nonce.resize(FIELD_LENGTH, 0);
+            wtr.push(1);
+            wtr.extend_from_slice(&nonce);
+        }
+        None => wtr.push(0),
+    }
+    wtr.push(receipt.verified as u8);
+    wtr.push(match receipt.verifier_level {
+        VerifierLevel::SelfSignedTest => 0,
+        VerifierLevel::HasPublicCertificate => 1,
+        VerifierLevel::Professional => 2,
+    });
+    wtr.write_i64::<BigEndian>(receipt.timestamp).unwrap();
+    wtr
+}
+
+/// Signs the result of a scan with the 32 byte Ed25519 secret key of
+/// the venue. `timestamp` is the time of the scan in seconds since the
+/// Unix epoch.
+pub fn sign_scan(
+    report: &VerificationReport,
+    verifier_level: VerifierLevel,
+    timestamp: i64,
+    signing_key: &[u8],
+) -> Result<ScanReceipt, AttestationError> {
+    let secret = SecretKey::from_bytes(signing_key).map_err(|_| AttestationError::InvalidKey)?;
+    let public = PublicKey::from(&secret);
+    let keypair = Keypair {
+        secret: secret,
+        public: public,
+    };
+    let mut receipt = ScanReceipt {
+        qr_public: report.public.clone(),
+        verified: report.accepted(),
+        verifier_level: verifier_level,
+        timestamp: timestamp,
+        signature: vec![],
+    };
+    receipt.signature = keypair.sign(&scan_payload(&receipt)).to_bytes().to_vec();
+    Ok(receipt)
+}
+
+/// Checks the signature of the receipt with the 32 byte Ed25519 public
+/// key of the venue, see `attestation::public_key`. Returns the level
+/// of the receipt; `SelfSignedTest` receipts are valid but should not
+/// be trusted for audits.
+pub fn check_scan_signature(
+    receipt: &ScanReceipt,
+    public_key: &[u8],
+) -> Result<VerifierLevel, AttestationError> {
+    let key = PublicKey::from_bytes(public_key).map_err(|_| AttestationError::InvalidKey)?;
+    let signature = Signature::try_from(&receipt.signature[..])
+        .map_err(|_| AttestationError::InvalidSignature)?;
+    key.verify(&scan_payload(receipt), &signature)
+        .map_err(|_| AttestationError::InvalidSignature)?;
+    Ok(receipt.verifier_level)
+}
+
+#[cfg(all(test, feature = "prover"))]
+mod tests {
+    use super::*;
+    use crate::api::{Private, QrRequest, Relation, EMBEDDED_CIRCUIT};
+    use crate::attestation;
+    use std::time::Instant;
+
+    fn digest(bytes: &[u8]) -> PhotosDigest {
+        PhotosDigest::new(bytes.to_vec()).unwrap()
+    }
+
+    fn proof(photos_digest: &PhotosDigest) -> ProofQrCode {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: 2001,
+                private_key: PrivateKey::new(vec![10]).unwrap(),
+                photos_digest: photos_digest.clone(),
+            },
+            allow_unbound_proof: false,
+        };
+        zk::generate_proof(rq).unwrap()
+    }
+
+    #[test]
+    fn verify_valid_qr() {
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest).to_string();
+        let report = verify_qr(&qr, &photos_digest, 2020).unwrap();
+        assert!(report.accepted());
+        assert_eq!(report.public.delta, 18);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn concurrent_async_verifications() {
+        use futures::executor::block_on;
+        use futures::future::join_all;
+
+        let photos_digest = digest(&[2u8, 7]);
+        let wrong = digest(&[3u8, 1, 4]);
+        let qr = proof(&photos_digest).to_string();
+        let expected = verify_qr(&qr, &photos_digest, 2020).unwrap();
+        let rejected = verify_qr(&qr, &wrong, 2020).unwrap();
+        assert!(!rejected.accepted());
+
+        let threads = |job: offload::Job| {
+            std::thread::spawn(job);
+        };
+        let futures: Vec<_> = (0..32)
+            .map(|i| match i % 4 {
+                0 => verify_qr_async(&qr, &wrong, 2020),
+                1 => verify_qr_async_with(&threads, &qr, &photos_digest, 2020),
+                _ => verify_qr_async(&qr, &photos_digest, 2020),
+            })
+            .collect();
+        for (i, report) in block_on(join_all(futures)).into_iter().enumerate() {
+            let report = report.unwrap();
+            if i % 4 == 0 {
+                assert_eq!(report, rejected);
+            } else {
+                assert_eq!(report, expected);
+            }
+        }
+        assert_eq!(
+            block_on(verify_qr_async("abc", &photos_digest, 2020)),
+            verify_qr("abc", &photos_digest, 2020)
+        );
+    }
+
+    #[test]
+    fn verify_stale_qr() {
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest).to_string();
+        let report = verify_qr(&qr, &photos_digest, 2021).unwrap();
+        assert!(!report.date_matches);
+        assert!(!report.accepted());
+    }
+
+    #[test]
+    fn verify_qr_within_skew() {
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest).to_string();
+        for today in &[2019, 2020, 2021] {
+            let report = verify_qr_with_skew(&qr, &photos_digest, *today, 1).unwrap();
+            assert!(report.accepted(), "{}", today);
+        }
+        for today in &[2018, 2022] {
+            let report = verify_qr_with_skew(&qr, &photos_digest, *today, 1).unwrap();
+            assert!(!report.date_matches);
+            assert!(!report.proof_verified);
+            assert_eq!(report.failure, None);
+        }
+        let report = verify_qr_with_skew(&qr, &photos_digest, 2020, -1).unwrap();
+        assert!(!report.accepted());
+        assert_eq!(
+            verify_qr_with_skew(&qr, &photos_digest, 2020, 0),
+            verify_qr(&qr, &photos_digest, 2020)
+        );
+    }
+
+    #[test]
+    fn verify_bad_proof_within_skew() {
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest).to_string();
+        let report = verify_qr_with_skew(&qr, &digest(&[3u8, 1, 4]), 2021, 1).unwrap();
+        assert!(report.date_matches);
+        assert!(!report.proof_verified);
+        // The sealed proof does not open with a wrong digest.
+        assert_eq!(report.failure, Some(VerificationFailure::TagMismatch));
+        assert!(verify_qr_with_skew("abc", &photos_digest, 2020, 1).is_err());
+    }
+
+    #[test]
+    fn batch_verifier_results() {
+        let photos_digest = digest(&[2u8, 7]);
+        let valid = proof(&photos_digest);
+        let mut tampered = valid.clone();
+        tampered.public.delta = 17;
+        let items = vec![
+            (valid.clone(), vec![2u8, 7]),
+            (tampered, vec![2u8, 7]),
+            (valid.clone(), vec![3u8]),
+            (valid.clone(), vec![0xff; 33]),
+        ];
+        let verifier = BatchVerifier::new().unwrap();
+        assert_eq!(verifier.verify(&valid, &[2u8, 7]), Ok(()));
+        let results = verifier.verify_many(&items);
+        assert_eq!(results.len(), items.len());
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(results[1], Err(ZkError::VerificationFailed));
+        assert!(results[2].is_err());
+        assert_eq!(
+            results[3],
+            Err(ZkError::InvalidInput(ValidationError::PhotosDigest))
+        );
+        let verifier = BatchVerifier::from_verification_key(&export_verification_key()).unwrap();
+        assert_eq!(verifier.verify_many(&items[..1]), vec![Ok(())]);
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_batch_verifier() {
+        // Run with `cargo test --release -- --ignored --nocapture`.
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest);
+        let text = qr.to_string();
+        let key = export_verification_key();
+        let n = 100;
+
+        // Parses the key for every code.
+        let start = Instant::now();
+        for _ in 0..n {
+            verify_with_key(&text, &photos_digest, &key).unwrap();
+        }
+        let standalone = start.elapsed();
+
+        let items: Vec<_> = (0..n).map(|_| (qr.clone(), vec![2u8, 7])).collect();
+        let start = Instant::now();
+        let verifier = BatchVerifier::from_verification_key(&key).unwrap();
+        let results = verifier.verify_many(&items);
+        let batch = start.elapsed();
+        assert!(results.iter().all(|r| r.is_ok()));
+
+        println!("standalone: {:?}, batch: {:?}", standalone, batch);
+        assert!(batch * 2 < standalone);
+    }
+
+    #[test]
+    fn exported_key_verifies() {
+        let manifest: Value = serde_json::from_str(&export_verifier_manifest()).unwrap();
+        assert_eq!(manifest["version"], 1);
+        assert_eq!(
+            manifest["public_inputs"],
+            serde_json::json!(["delta", "today", "younger", "challenge"])
+        );
+        assert_eq!(manifest["max_julian_day"], MAX_JULIAN_DAY);
+        let key = manifest["verification_key"].to_string();
+        assert_eq!(key, export_verification_key());
+        let embedded: Value = serde_json::from_slice(zk::embedded_verification_key()).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&key).unwrap(), embedded);
+
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest).to_string();
+        assert_eq!(
+            verify_with_key(&qr, &photos_digest, &key).map(|public| public.delta),
+            Ok(18)
+        );
+        assert!(verify_with_key(&qr, &digest(&[3u8]), &key).is_err());
+        assert!(verify_with_key(&qr, &photos_digest, "{}").is_err());
+    }
+
+    #[test]
+    fn manifest_bytes_are_stable() {
+        // External verifiers may pin the hash of the manifest.
+        use sha2::{Digest, Sha256};
+        let manifest = export_verifier_manifest();
+        assert!(manifest.starts_with(concat!(
+            r#"{"version":1,"circuit":1,"key_id":1,"qr_format_version":6,"#,
+            r#""public_inputs":["delta","today","younger","challenge"],"#,
+            r#""relation":"younger ? birthday + delta > today : birthday + delta < today","#,
+            r#""max_julian_day":9999999,"verification_key":{"alpha":["0x1936"#
+        )));
+        assert_eq!(manifest.len(), 1966);
+        assert_eq!(
+            hex::encode(Sha256::digest(manifest.as_bytes())),
+            "580d02fcc8bd5bed32114baa3e47211bb1be0d964d510147d3d19e4f6eda258f"
+        );
+    }
+
+    #[test]
+    fn sort_keys_sorts_nested_objects() {
+        let value: Value =
+            serde_json::from_str(r#"{"b":{"d":1,"c":[{"f":0,"e":0}]},"a":2}"#).unwrap();
+        assert_eq!(
+            sort_keys(value).to_string(),
+            r#"{"a":2,"b":{"c":[{"e":0,"f":0}],"d":1}}"#
+        );
+    }
+
+    #[test]
+    fn verify_tampered_challenge() {
+        let photos_digest = digest(&[2u8, 7]);
+        let mut p = proof(&photos_digest);
+        let mut challenge = p.challenge.as_bytes().to_vec();
+        challenge[0] ^= 1;
+        p.challenge = Challenge::new(challenge).unwrap();
+        let report = verify_qr(&p.to_string(), &photos_digest, 2020).unwrap();
+        assert!(report.date_matches);
+        assert!(!report.proof_verified);
+        // The challenge is also part of the mask.
+        assert!(report.failure.is_some());
+        assert!(!report.accepted());
+    }
+
+    /// Julian day of the full verification, 7000 days after the
+    /// birthday of the test proofs.
+    const TODAY: i32 = 9001;
+
+    /// Proof of the public part of the policy on `TODAY`.
+    fn policy_proof(photos_digest: &PhotosDigest, policy: &AgePolicy) -> ProofQrCode {
+        let rq = QrRequest::build(
+            policy.public(TODAY, 1200).unwrap(),
+            Private {
+                birthday: 2001,
+                private_key: PrivateKey::new(vec![10]).unwrap(),
+                photos_digest: photos_digest.clone(),
+            },
+        )
+        .unwrap();
+        zk::generate_proof(rq).unwrap()
+    }
+
+    fn card_key(photos_digest: &PhotosDigest, private_key: Vec<u8>) -> CardKey {
+        zk::generate_card_key(&Private {
+            birthday: 2001,
+            private_key: PrivateKey::new(private_key).unwrap(),
+            photos_digest: photos_digest.clone(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn verify_full_valid() {
+        let photos_digest = digest(&[2u8, 7]);
+        let adult = AgePolicy::at_least_years(18);
+        let p = policy_proof(&photos_digest, &adult);
+        let key = card_key(&photos_digest, vec![10]);
+        assert_eq!(
+            verify_full(&p, &key, &photos_digest, &adult, TODAY),
+            Ok(FullVerification::Verified)
+        );
+    }
+
+    #[test]
+    fn verify_full_policy() {
+        let photos_digest = digest(&[2u8, 7]);
+        let key = card_key(&photos_digest, vec![10]);
+        let adult = AgePolicy::at_least_years(18);
+        let p = policy_proof(&photos_digest, &AgePolicy::at_least_years(19));
+        assert_eq!(
+            verify_full(&p, &key, &photos_digest, &adult, TODAY),
+            Ok(FullVerification::Verified)
+        );
+        // The proof of 16+ verifies, but the venue requires 18+.
+        let p = policy_proof(&photos_digest, &AgePolicy::at_least_years(16));
+        assert!(zk::verify_proof(&p, &photos_digest).is_ok());
+        assert_eq!(
+            verify_full(&p, &key, &photos_digest, &adult, TODAY),
+            Ok(FullVerification::PolicyNotMet)
+        );
+    }
+
+    #[test]
+    fn verify_full_replayed_proof() {
+        // A valid proof of one card presented as another card.
+        let photos_digest = digest(&[2u8, 7]);
+        let adult = AgePolicy::at_least_years(18);
+        let p = policy_proof(&photos_digest, &adult);
+        assert!(zk::verify_proof(&p, &photos_digest).is_ok());
+        let other_key = card_key(&photos_digest, vec![11]);
+        assert_eq!(
+            verify_full(&p, &other_key, &photos_digest, &adult, TODAY),
+            Ok(FullVerification::ChallengeMismatch)
+        );
+    }
+
+    #[test]
+    fn verify_full_wrong_photo() {
+        let photos_digest = digest(&[2u8, 7]);
+        let adult = AgePolicy::at_least_years(18);
+        let p = policy_proof(&photos_digest, &adult);
+        let key = card_key(&photos_digest, vec![10]);
+        assert_eq!(
+            verify_full(&p, &key, &digest(&[3u8, 1, 4]), &adult, TODAY),
+            Ok(FullVerification::ProofFailed)
+        );
+    }
+
+    #[test]
+    fn derive_card_key_matches_phone() {
+        let photos_digest = digest(&[2u8, 7]);
+        let private_key = PrivateKey::new(vec![10]).unwrap();
+        let d = derive_card_key(2001, &private_key, &photos_digest).unwrap();
+        assert_eq!(d.card_key, card_key(&photos_digest, vec![10]));
+        assert_ne!(d.k, d.m1);
+
+        let other = derive_card_key(2001, &private_key, &digest(&[2u8, 8])).unwrap();
+        assert_eq!(other.k, d.k);
+        assert_ne!(other.card_key, d.card_key);
+    }
+
+    #[test]
+    fn derive_card_key_rejects_invalid_birthday() {
+        let private_key = PrivateKey::new(vec![10]).unwrap();
+        assert_eq!(
+            derive_card_key(0, &private_key, &digest(&[2u8])),
+            Err(ZkError::InvalidInput(ValidationError::Birthday))
+        );
+    }
+
+    #[test]
+    fn registered_card_gives_expected_challenge() {
+        let photos_digest = digest(&[2u8, 7]);
+        let registration = register_card(2001, &photos_digest).unwrap();
+        let other = register_card(2001, &photos_digest).unwrap();
+        assert_ne!(other.card_key, registration.card_key);
+
+        // The phone proves with the private key it was handed.
+        let rq = QrRequest::build(
+            Public::older_than(18, 2020, 1200).unwrap(),
+            Private::with(
+                2001,
+                registration.private_key.clone(),
+                photos_digest.clone(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let qr = zk::generate_proof(rq).unwrap();
+        // The challenge of the QR code is zero padded.
+        let expected = |today| {
+            expected_challenge(&registration.card_key, today)
+                .unwrap()
+                .to_field()
+        };
+        assert_eq!(expected(2020), qr.challenge.to_field());
+        assert_ne!(expected(2021), qr.challenge.to_field());
+        assert_eq!(
+            expected_challenge(&registration.card_key, 0),
+            Err(ZkError::InvalidInput(ValidationError::Today))
+        );
+        assert_eq!(
+            register_card(MAX_JULIAN_DAY + 1, &photos_digest).map(|_| ()),
+            Err(ZkError::InvalidInput(ValidationError::Birthday))
+        );
+    }
+
+    #[test]
+    fn verify_with_freshness_window() {
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest).to_string();
+        let mut options = VerifyOptions {
+            max_age_minutes: 15,
+            verifier_now: 1210,
+            verifier_today: 2020,
+            allow_unbound_proof: false,
+        };
+        assert_eq!(
+            verify_with_options(&qr, &photos_digest, &options).map(|p| p.now),
+            Ok(1200)
+        );
+        options.verifier_now = 1300;
+        assert_eq!(
+            verify_with_options(&qr, &photos_digest, &options),
+            Err(ZkError::Stale)
+        );
+    }
+
+    #[test]
+    fn stale_code_across_midnight() {
+        // The time is not proved, a code generated at 23:58 is checked
+        // at 00:03 of the next day.
+        let photos_digest = digest(&[2u8, 7]);
+        let mut p = proof(&photos_digest);
+        p.public.now = 23 * 60 + 58;
+        let qr = p.to_string();
+        let mut options = VerifyOptions {
+            max_age_minutes: 5,
+            verifier_now: 3,
+            verifier_today: 2021,
+            allow_unbound_proof: false,
+        };
+        assert!(verify_with_options(&qr, &photos_digest, &options).is_ok());
+        options.verifier_now = 4;
+        assert_eq!(
+            verify_with_options(&qr, &photos_digest, &options),
+            Err(ZkError::Stale)
+        );
+        // A stale code is reported as stale even if it is not valid.
+        assert_eq!(
+            verify_with_options(&qr, &digest(&[3u8, 1, 4]), &options),
+            Err(ZkError::Stale)
+        );
+    }
+
+    const VENUE_KEY: [u8; 32] = [5; 32];
+
+    fn receipt(level: VerifierLevel) -> ScanReceipt {
+        let photos_digest = digest(&[2u8, 7]);
+        let qr = proof(&photos_digest).to_string();
+        let report = verify_qr(&qr, &photos_digest, 2020).unwrap();
+        sign_scan(&report, level, 1_600_000_000, &VENUE_KEY).unwrap()
+    }
+
+    #[test]
+    fn scan_receipt_signature() {
+        let public_key = attestation::public_key(&VENUE_KEY).unwrap();
+        let signed = receipt(VerifierLevel::Professional);
+        assert!(signed.verified);
+        assert_eq!(signed.qr_public.delta, 18);
+        assert_eq!(
+            check_scan_signature(&signed, &public_key),
+            Ok(VerifierLevel::Professional)
+        );
+        assert_eq!(
+            check_scan_signature(&receipt(VerifierLevel::SelfSignedTest), &public_key),
+            Ok(VerifierLevel::SelfSignedTest)
+        );
+        let other = attestation::public_key(&[6; 32]).unwrap();
+        assert_eq!(
+            check_scan_signature(&signed, &other),
+            Err(AttestationError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn tampered_scan_receipt() {
+        let public_key = attestation::public_key(&VENUE_KEY).unwrap();
+        let mut tampered = receipt(VerifierLevel::Professional);
+        tampered.timestamp += 1;
+        assert_eq!(
+            check_scan_signature(&tampered, &public_key),
+            Err(AttestationError::InvalidSignature)
+        );
+        let mut tampered = receipt(VerifierLevel::SelfSignedTest);
+        tampered.verifier_level = VerifierLevel::Professional;
+        assert_eq!(
+            check_scan_signature(&tampered, &public_key),
+            Err(AttestationError::InvalidSignature)
+        );
+        let mut tampered = receipt(VerifierLevel::Professional);
+        tampered.signature.pop();
+        assert_eq!(
+            check_scan_signature(&tampered, &public_key),
+            Err(AttestationError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn scan_receipt_json() {
+        let signed = receipt(VerifierLevel::HasPublicCertificate);
+        let json = serde_json::to_string(&signed).unwrap();
+        let parsed: ScanReceipt = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, signed);
+        let public_key = attestation::public_key(&VENUE_KEY).unwrap();
+        assert_eq!(
+            check_scan_signature(&parsed, &public_key),
+            Ok(VerifierLevel::HasPublicCertificate)
+        );
+    }
+
+    #[test]
+    fn verify_undecodable_qr() {
+        assert!(verify_qr("not a qr code", &PhotosDigest::default(), 2020).is_err());
+    }
+
+    #[test]
+    fn error_reports() {
+        let e = verify_qr("0OIl;a;b", &PhotosDigest::default(), 2020).unwrap_err();
+        let report = ErrorReport::from(&e);
+        assert_eq!(report.code, ZkError::QrCode(QrError::InvalidLength).code());
+        assert_eq!(
+            report.qr_error,
+            Some(QrError::Base58(QrSection::Public).code())
+        );
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["section"], "public");
+        assert_eq!(json["message"], e.to_string());
+        let report = ErrorReport::from(&ZkError::Stale);
+        assert_eq!(report.code, ZkError::Stale.code());
+        assert_eq!((report.qr_error, report.section), (None, None));
+    }
+}
@@ -0,0 +1,320 @@
+// Card registry of the certification authority. Card keys are
+// registered on enrollment and revoked when the phone is lost or the
+// card was obtained by fraud. The records are kept by a `CardStore`,
+// the service backs it with its database, `MemoryCardStore` keeps them
+// in memory.
+
+use super::{verify_full, AgePolicy, FullVerification};
+use crate::api::{CardKey, PhotosDigest, ProofQrCode, VerifierLevel};
+use crate::zk::ZkError;
+use std::fmt;
+
+/// Identifier of a registered card.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct CardId(pub u64);
+
+/// Registered card.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CardRecord {
+    pub id: CardId,
+    pub card_key: CardKey,
+    /// Julian day of the enrollment.
+    pub issued_on: i32,
+    /// Julian day from which the card is revoked.
+    pub revoked_on: Option<i32>,
+    /// Level of the certification.
+    pub level: VerifierLevel,
+}
+
+impl CardRecord {
+    /// The card is active from the day of issue until the day before
+    /// the revocation.
+    pub fn is_active(&self, on: i32) -> bool {
+        self.issued_on <= on && self.revoked_on.map_or(true, |revoked| on < revoked)
+    }
+}
+
+/// Error of the card registry.
+#[derive(PartialEq, Debug, Clone)]
+pub enum RegistryError {
+    /// The card key is already registered with this id.
+    DuplicateCard(CardId),
+    /// No card with this id is registered.
+    UnknownCard(CardId),
+    /// The card has already been revoked.
+    AlreadyRevoked(CardId),
+    /// The store failed, e.g. the database is not reachable.
+    Storage(String),
+    /// The QR code cannot be verified.
+    Proof(ZkError),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::DuplicateCard(id) => write!(f, "card already registered as {}", id.0),
+            RegistryError::UnknownCard(id) => write!(f, "unknown card {}", id.0),
+            RegistryError::AlreadyRevoked(id) => write!(f, "card {} already revoked", id.0),
+            RegistryError::Storage(e) => write!(f, "card store failed: {}", e),
+            RegistryError::Proof(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<ZkError> for RegistryError {
+    fn from(e: ZkError) -> Self {
+        RegistryError::Proof(e)
+    }
+}
+
+/// Storage of the card records. The registry checks duplicates and
+/// revocations, the store only keeps the records.
+pub trait CardStore {
+    /// Stores a new active card and assigns it an id.
+    fn insert(
+        &mut self,
+        card_key: &CardKey,
+        issued_on: i32,
+        level: VerifierLevel,
+    ) -> Result<CardId, RegistryError>;
+
+    /// The card with the id.
+    fn get(&self, card_id: CardId) -> Result<Option<CardRecord>, RegistryError>;
+
+    /// The card with the card key.
+    fn find(&self, card_key: &CardKey) -> Result<Option<CardRecord>, RegistryError>;
+
+    /// Records the revocation of an existing card.
+    fn set_revoked(&mut self, card_id: CardId, revoked_on: i32) -> Result<(), RegistryError>;
+}
+
+/// Card store in memory, for tests and small deployments.
+#[derive(Debug, Default)]
+pub struct MemoryCardStore {
+    cards: Vec<CardRecord>,
+}
+
+impl MemoryCardStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CardStore for MemoryCardStore {
+    fn insert(
+        &mut self,
+        card_key: &CardKey,
+        issued_on: i32,
+        level: VerifierLevel,
+    ) -> Result<CardId, RegistryError> {
+        let id = CardId(self.cards.len() as u64 + 1);
+        self.cards.push(CardRecord {
+            id: id,
+            card_key: card_key.clone(),
+            issued_on: issued_on,
+            revoked_on: None,
+            level: level,
+        });
+        Ok(id)
+    }
+
+    fn get(&self, card_id: CardId) -> Result<Option<CardRecord>, RegistryError> {
+        Ok(self.cards.iter().find(|card| card.id == card_id).cloned())
+    }
+
+    fn find(&self, card_key: &CardKey) -> Result<Option<CardRecord>, RegistryError> {
+        Ok(self
+            .cards
+            .iter()
+            .find(|card| card.card_key == *card_key)
+            .cloned())
+    }
+
+    fn set_revoked(&mut self, card_id: CardId, revoked_on: i32) -> Result<(), RegistryError> {
+        let card = self
+            .cards
+            .iter_mut()
+            .find(|card| card.id == card_id)
+            .ok_or(RegistryError::UnknownCard(card_id))?;
+        card.revoked_on = Some(revoked_on);
+        Ok(())
+    }
+}
+
+/// Cards issued by the certification authority.
+#[derive(Debug, Default)]
+pub struct CardRegistry<S: CardStore = MemoryCardStore> {
+    store: S,
+}
+
+impl CardRegistry<MemoryCardStore> {
+    /// Registry keeping the cards in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: CardStore> CardRegistry<S> {
+    /// Registry keeping the cards in `store`.
+    pub fn with_store(store: S) -> Self {
+        CardRegistry { store: store }
+    }
+
+    /// Registers the card key derived on enrollment. A card key can be
+    /// registered only once, also after it has been revoked.
+    pub fn register_card(
+        &mut self,
+        card_key: CardKey,
+        issued_on: i32,
+        level: VerifierLevel,
+    ) -> Result<CardId, RegistryError> {
+        if let Some(card) = self.store.find(&card_key)? {
+            return Err(RegistryError::DuplicateCard(card.id));
+        }
+        self.store.insert(&card_key, issued_on, level)
+    }
+
+    /// Revokes the card from the day `revoked_on` on. QR codes
+    /// generated on that day are refused already.
+    pub fn revoke_card(&mut self, card_id: CardId, revoked_on: i32) -> Result<(), RegistryError> {
+        let card = self
+            .store
+            .get(card_id)?
+            .ok_or(RegistryError::UnknownCard(card_id))?;
+        if card.revoked_on.is_some() {
+            return Err(RegistryError::AlreadyRevoked(card_id));
+        }
+        self.store.set_revoked(card_id, revoked_on)
+    }
+
+    /// The card record, `None` if the card key is not registered.
+    pub fn card(&self, card_key: &CardKey) -> Result<Option<CardRecord>, RegistryError> {
+        self.store.find(card_key)
+    }
+
+    /// Tells whether the card is registered and not revoked on the
+    /// julian day `on`. A failing store gives `false`.
+    pub fn is_card_active(&self, card_key: &CardKey, on: i32) -> bool {
+        match self.store.find(card_key) {
+            Ok(Some(card)) => card.is_active(on),
+            _ => false,
+        }
+    }
+
+    /// Same as `web_api::verify_full`, the card must also be active on
+    /// the day the QR code was generated.
+    pub fn verify_full(
+        &self,
+        qr: &ProofQrCode,
+        card_key: &CardKey,
+        photo_digest: &PhotosDigest,
+        policy: &AgePolicy,
+        today: i32,
+    ) -> Result<FullVerification, RegistryError> {
+        let card = match self.store.find(card_key)? {
+            Some(card) => card,
+            None => return Ok(FullVerification::UnknownCard),
+        };
+        if !card.is_active(qr.public.today) {
+            return Ok(FullVerification::Revoked);
+        }
+        Ok(verify_full(qr, card_key, photo_digest, policy, today)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> CardKey {
+        CardKey::new(vec![byte; 4]).unwrap()
+    }
+
+    #[test]
+    fn register_and_revoke() {
+        let mut registry = CardRegistry::new();
+        let id = registry
+            .register_card(key(1), 2020, VerifierLevel::Professional)
+            .unwrap();
+        let other = registry
+            .register_card(key(2), 2020, VerifierLevel::HasPublicCertificate)
+            .unwrap();
+        assert_ne!(id, other);
+        assert!(!registry.is_card_active(&key(1), 2019));
+        assert!(registry.is_card_active(&key(1), 2020));
+
+        registry.revoke_card(id, 2030).unwrap();
+        assert!(registry.is_card_active(&key(1), 2029));
+        // Revoked on the day of the revocation.
+        assert!(!registry.is_card_active(&key(1), 2030));
+        assert!(!registry.is_card_active(&key(1), 2031));
+        assert!(registry.is_card_active(&key(2), 2030));
+        assert_eq!(
+            registry.card(&key(1)).unwrap().map(|card| card.revoked_on),
+            Some(Some(2030))
+        );
+    }
+
+    #[test]
+    fn registry_errors() {
+        let mut registry = CardRegistry::new();
+        let id = registry
+            .register_card(key(1), 2020, VerifierLevel::Professional)
+            .unwrap();
+        assert_eq!(
+            registry.register_card(key(1), 2021, VerifierLevel::Professional),
+            Err(RegistryError::DuplicateCard(id))
+        );
+        assert_eq!(
+            registry.revoke_card(CardId(99), 2030),
+            Err(RegistryError::UnknownCard(CardId(99)))
+        );
+        registry.revoke_card(id, 2030).unwrap();
+        assert_eq!(
+            registry.revoke_card(id, 2031),
+            Err(RegistryError::AlreadyRevoked(id))
+        );
+        assert!(!registry.is_card_active(&key(3), 2020));
+    }
+
+    #[cfg(feature = "prover")]
+    #[test]
+    fn verify_register_revoke_verify_again() {
+        use crate::api::{Private, PrivateKey, QrRequest};
+        use crate::zk;
+
+        let today = 9001;
+        let adult = AgePolicy::at_least_years(18);
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        let private = Private::with(
+            2001,
+            PrivateKey::new(vec![10]).unwrap(),
+            photos_digest.clone(),
+        )
+        .unwrap();
+        let card_key = zk::generate_card_key(&private).unwrap();
+        let rq = QrRequest::build(adult.public(today, 1200).unwrap(), private).unwrap();
+        let qr = zk::generate_proof(rq).unwrap();
+
+        let mut registry = CardRegistry::new();
+        assert_eq!(
+            registry.verify_full(&qr, &card_key, &photos_digest, &adult, today),
+            Ok(FullVerification::UnknownCard)
+        );
+        let id = registry
+            .register_card(card_key.clone(), 2010, VerifierLevel::Professional)
+            .unwrap();
+        assert_eq!(
+            registry.verify_full(&qr, &card_key, &photos_digest, &adult, today),
+            Ok(FullVerification::Verified)
+        );
+
+        registry.revoke_card(id, today).unwrap();
+        assert_eq!(
+            registry.verify_full(&qr, &card_key, &photos_digest, &adult, today),
+            Ok(FullVerification::Revoked)
+        );
+    }
+}
@@ -1,102 +1,717 @@
 // Zero-knowledge algorithms.
 
-use crate::api::{Private, ProofQrCode, QrError, QrRequest, Relation};
+// Proof generation is compiled with the `prover` feature and
+// verification with the `verifier` feature.
+
+use crate::api::QrRequest;
+#[cfg(feature = "verifier")]
+use crate::api::MINUTES_PER_DAY;
+#[cfg(feature = "prover")]
+use crate::api::QR_FORMAT_VERSION;
+use crate::api::{
+    hidden_proof_length, CardKey, Challenge, Nonce, PhotosDigest, Private, PrivateKey, ProofQrCode,
+    Public, QrError, Relation, ValidationError, EMBEDDED_CIRCUIT, EMBEDDED_KEY, FIELD_LENGTH,
+    PROOF_LENGTH, SEALED_VERSION, SEAL_NONCE_LENGTH, STREAM_MASK_VERSION,
+};
+use crate::encoding::field_to_le_bytes;
+use crate::hash::compute_mimc7r10_hash;
 
 use bellman_ce::groth16::Proof as BellmanProof;
+#[cfg(feature = "prover")]
+use bellman_ce::groth16::{create_random_proof, Parameters};
+#[cfg(feature = "verifier")]
+use bellman_ce::groth16::{
+    prepare_verifying_key, verify_proof as verify_groth16, PreparedVerifyingKey, VerifyingKey,
+};
+#[cfg(feature = "verifier")]
+use bellman_ce::pairing::bn256::{Fq, Fq2, FqRepr, G1Affine, G2Affine};
+#[cfg(feature = "verifier")]
+use bellman_ce::pairing::ff::{PrimeField, PrimeFieldRepr};
+#[cfg(feature = "verifier")]
+use bellman_ce::pairing::CurveAffine;
 use bellman_ce::pairing::{bn256::Bn256, ff::ScalarEngine};
-use ff_mimc::{PrimeField, PrimeFieldRepr};
-use mimc_rs;
+use byteorder::{ByteOrder, LittleEndian};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key};
+use hkdf::Hkdf;
+use log::debug;
+#[cfg(feature = "prover")]
+use log::trace;
+use once_cell::sync::Lazy;
 use rand::{thread_rng, ChaChaRng, Rng, SeedableRng};
+use serde::Serialize;
 use serde_json;
-use std::io::Cursor;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(all(feature = "prover", feature = "verifier"))]
+use std::path::Path;
+#[cfg(feature = "prover")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(all(feature = "prover", feature = "verifier"))]
+use std::time::Instant;
+#[cfg(feature = "prover")]
 use zokrates_core::ir::{self, ProgEnum};
-use zokrates_core::proof_system::{
-    bellman::groth16::{ProofPoints, G16},
-    Proof, ProofSystem,
-};
+#[cfg(feature = "prover")]
+use zokrates_core::proof_system::bellman::Computation;
+#[cfg(feature = "verifier")]
+use zokrates_core::proof_system::{bellman::groth16::G16, ProofSystem};
+#[cfg(feature = "prover")]
 use zokrates_core::typed_absy::abi::Abi;
 use zokrates_field::{Bn128Field, Field};
 
+#[cfg(feature = "prover")]
 static PROGRAM: &'static [u8] = include_bytes!("../zokrates/out");
+#[cfg(feature = "prover")]
 static ABI: &'static [u8] = include_bytes!("../zokrates/abi.json");
+#[cfg(feature = "prover")]
 static PROVING_KEY: &'static [u8] = include_bytes!("../zokrates/proving.key");
+#[cfg(feature = "verifier")]
 static VERIFICATION_KEY: &'static [u8] = include_bytes!("../zokrates/verification.key");
 
 type Fr = <Bn256 as ScalarEngine>::Fr;
 
-pub fn generate_random_private_key() -> Vec<u8> {
-    let seed = thread_rng().gen::<[u32; 4]>();
-    let mut rng = ChaChaRng::from_seed(&seed);
-    let r: Fr = rng.gen();
-    Bn128Field::from_bellman(r).into_byte_vector()
+#[cfg(feature = "verifier")]
+type VerificationKey = <G16 as ProofSystem<Bn128Field>>::VerificationKey;
+
+/// Public inputs of the circuit: delta, today, younger and the
+/// challenge.
+const PUBLIC_INPUTS: usize = 4;
+
+/// Length of the ChaCha20-Poly1305 key of a sealed proof.
+const SEAL_KEY_LENGTH: usize = 32;
+
+/// Prover context shared by `generate_proof`, created on the first
+/// proof generation.
+#[cfg(feature = "prover")]
+static SHARED_PROVER: Lazy<Result<Prover, ZkError>> = Lazy::new(|| {
+    #[cfg(all(test, feature = "verifier"))]
+    tests::count_deserialization();
+    Prover::prepare()
+});
+
+/// Verifier context shared by `verify_proof`, created on the first
+/// verification.
+#[cfg(feature = "verifier")]
+static SHARED_VERIFIER: Lazy<Result<Verifier, ZkError>> = Lazy::new(|| {
+    #[cfg(all(test, feature = "prover"))]
+    tests::count_deserialization();
+    Verifier::new()
+});
+
+/// Logs the duration of an expensive phase at debug level when it
+/// ends and, with the `tracing` feature, enters a span for it. Only the
+/// end is logged in the browser, which has no clock. Phases never log
+/// their inputs.
+struct Phase {
+    name: &'static str,
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+}
+
+impl Phase {
+    fn start(name: &'static str) -> Self {
+        Phase {
+            name: name,
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            #[cfg(feature = "tracing")]
+            _span: tracing::debug_span!("legalage", phase = name).entered(),
+        }
+    }
+
+    /// Milliseconds since the start, 0 in the browser.
+    #[cfg(feature = "prover")]
+    fn elapsed_ms(&self) -> u64 {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.start.elapsed().as_millis() as u64
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            0
+        }
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        debug!("{} took {} ms", self.name, self.start.elapsed().as_millis());
+        #[cfg(target_arch = "wasm32")]
+        debug!("{} done", self.name);
+    }
+}
+
+/// Error of proof generation or verification.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ZkError {
+    /// The request contains a value out of range.
+    InvalidInput(ValidationError),
+    /// The QR code cannot be decoded.
+    QrCode(QrError),
+    /// Witness computation in the circuit interpreter failed.
+    CircuitExecution(String),
+    /// The proof cannot be deserialized.
+    ProofDeserialization,
+    /// The embedded program or key cannot be deserialized.
+    KeyDeserialization,
+    /// The proof is well formed but does not verify.
+    VerificationFailed,
+    /// The proof cannot be unhidden with the photo digest, the photo
+    /// does not match or the proof is corrupted.
+    PhotoMismatch,
+    /// The QR code was generated outside of the freshness window.
+    Stale,
+    /// The program or key is well formed but does not belong to this
+    /// circuit.
+    InvalidArtifact(String),
+    /// No circuit with this id is registered.
+    UnsupportedCircuit(u8),
+    /// The verification key is not valid on the day of the QR code.
+    KeyExpired,
+    /// The verifier has no key with this id.
+    UnknownKey(u8),
+    /// The self test failed, the program and the keys do not belong
+    /// together.
+    CorruptInstallation(String),
+    /// The QR code was not generated for the nonce of the verifier.
+    NonceMismatch,
+    /// The tag of a sealed proof does not match, the photo digest does
+    /// not belong to the card or the QR code was tampered with.
+    TagMismatch,
+    /// The proof generation was cancelled by a `CancellationToken`.
+    Cancelled,
+    /// The photo digest is zero and the verifier does not accept proofs
+    /// unbound to the photo, see `PhotosDigest::is_unbound`.
+    UnboundProof,
+}
+
+impl ZkError {
+    /// Integer code of the error reported over JNI. Zero is reserved
+    /// for success.
+    pub fn code(&self) -> i32 {
+        match self {
+            ZkError::VerificationFailed => 1,
+            ZkError::InvalidInput(_) => 2,
+            ZkError::QrCode(_) => 3,
+            ZkError::ProofDeserialization => 4,
+            ZkError::KeyDeserialization => 5,
+            ZkError::CircuitExecution(_) => 6,
+            ZkError::PhotoMismatch => 7,
+            ZkError::Stale => 8,
+            ZkError::InvalidArtifact(_) => 9,
+            ZkError::UnsupportedCircuit(_) => 10,
+            ZkError::KeyExpired => 11,
+            ZkError::UnknownKey(_) => 12,
+            ZkError::CorruptInstallation(_) => 13,
+            ZkError::NonceMismatch => 14,
+            ZkError::TagMismatch => 15,
+            ZkError::Cancelled => 16,
+            ZkError::UnboundProof => 17,
+        }
+    }
+}
+
+impl fmt::Display for ZkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZkError::InvalidInput(e) => write!(f, "invalid input: {}", e),
+            ZkError::QrCode(e) => write!(f, "invalid QR code: {}", e),
+            ZkError::CircuitExecution(e) => write!(f, "circuit execution failed: {}", e),
+            ZkError::ProofDeserialization => write!(f, "cannot deserialize proof"),
+            ZkError::KeyDeserialization => write!(f, "cannot deserialize program or key"),
+            ZkError::VerificationFailed => write!(f, "proof verification failed"),
+            ZkError::PhotoMismatch => write!(f, "proof does not match photo"),
+            ZkError::Stale => write!(f, "QR code is too old"),
+            ZkError::InvalidArtifact(e) => write!(f, "invalid artifact: {}", e),
+            ZkError::UnsupportedCircuit(id) => write!(f, "unsupported circuit {}", id),
+            ZkError::KeyExpired => write!(f, "verification key has expired"),
+            ZkError::UnknownKey(id) => write!(f, "unknown verification key {}", id),
+            ZkError::CorruptInstallation(e) => write!(f, "installation corrupt: {}", e),
+            ZkError::NonceMismatch => write!(f, "QR code was generated for another nonce"),
+            ZkError::TagMismatch => write!(f, "proof tag does not match photo"),
+            ZkError::Cancelled => write!(f, "proof generation cancelled"),
+            ZkError::UnboundProof => write!(f, "proof is not bound to a photo"),
+        }
+    }
 }
 
-fn zok2mimc(value: &Bn128Field) -> mimc_rs::Fr {
-    // Zokrates uses internal BigInt representation, mimc uses ff with private Repr.
+impl std::error::Error for ZkError {}
 
-    let s = value.to_dec_string();
-    mimc_rs::Fr::from_str(&s).unwrap()
+impl From<ValidationError> for ZkError {
+    fn from(e: ValidationError) -> Self {
+        ZkError::InvalidInput(e)
+    }
+}
+
+impl From<QrError> for ZkError {
+    fn from(e: QrError) -> Self {
+        ZkError::QrCode(e)
+    }
 }
 
-fn mimc2zok(value: mimc_rs::Fr) -> Bn128Field {
-    let mut res: Vec<u8> = vec![];
-    value.into_repr().write_le(&mut res).unwrap();
-    Bn128Field::from_byte_vector(res)
+pub fn generate_random_private_key() -> PrivateKey {
+    generate_random_private_key_with_seed(thread_rng().gen::<[u32; 4]>())
+}
+
+/// Generates the private key from a fixed seed. Only for reproducible
+/// tests, production keys must come from `generate_random_private_key`.
+pub fn generate_random_private_key_with_seed(seed: [u32; 4]) -> PrivateKey {
+    let mut rng = ChaChaRng::from_seed(&seed);
+    let r: Fr = rng.gen();
+    PrivateKey::from_field(&Bn128Field::from_bellman(r))
 }
 
-fn compute_mimc7r10_hash(x: &Bn128Field, k: &Bn128Field) -> Bn128Field {
-    let mimc7r10 = mimc_rs::Mimc7::new(10);
-    let hash = mimc7r10.hash(&zok2mimc(x), &zok2mimc(k));
-    mimc2zok(hash)
+/// Nonce of `NONCE_LENGTH` random bytes to be shown by the verifier.
+/// It is short enough to be typed in as base58.
+pub fn generate_nonce() -> Nonce {
+    let bytes: [u8; NONCE_LENGTH] = thread_rng().gen();
+    Nonce::new(bytes.to_vec()).expect("short value is a field element")
 }
 
-pub fn generate_card_key(rq: Private) -> Vec<u8> {
-    let private_key = Bn128Field::from_byte_vector(rq.private_key);
-    let birthday = Bn128Field::from(rq.birthday);
-    let photos_digest = Bn128Field::from_byte_vector(rq.photos_digest);
+/// Length of the nonces of `generate_nonce`.
+pub const NONCE_LENGTH: usize = 8;
+
+/// Computes k, m1 and the card key in the same way as the circuit.
+pub(crate) fn derive_card_key(
+    birthday: i32,
+    private_key: &PrivateKey,
+    photos_digest: &PhotosDigest,
+) -> (Bn128Field, Bn128Field, Bn128Field) {
+    let private_key = private_key.to_field();
+    let birthday = Bn128Field::from(birthday);
+    let photos_digest = photos_digest.to_field();
 
     let k = birthday * private_key;
     let m1 = compute_mimc7r10_hash(&photos_digest, &k);
-    let card_key = m1 * photos_digest;
-    card_key.into_byte_vector()
+    let card_key = m1.clone() * photos_digest;
+    (k, m1, card_key)
+}
+
+/// Card key of the private values. A zero private key would not
+/// contribute to the card key and a zero photo digest makes the card
+/// key zero, both are refused. Keys and digests at or above the
+/// modulus cannot be constructed, see `encoding::normalize_field_bytes`
+/// for reducing other bytes explicitly.
+pub fn generate_card_key(rq: &Private) -> Result<CardKey, ValidationError> {
+    check_card_values(&rq.private_key, &rq.photos_digest)?;
+    Ok(card_key_unchecked(rq))
+}
+
+/// Refuses the zero private key and photo digest of a new card.
+pub(crate) fn check_card_values(
+    private_key: &PrivateKey,
+    photos_digest: &PhotosDigest,
+) -> Result<(), ValidationError> {
+    if private_key.to_field() == Bn128Field::from(0) {
+        return Err(ValidationError::PrivateKey);
+    }
+    if photos_digest.to_field() == Bn128Field::from(0) {
+        return Err(ValidationError::PhotosDigest);
+    }
+    Ok(())
+}
+
+/// Card key of any private values, as computed by the circuit.
+fn card_key_unchecked(rq: &Private) -> CardKey {
+    let (_, _, card_key) = derive_card_key(rq.birthday, &rq.private_key, &rq.photos_digest);
+    CardKey::from_field(&card_key)
 }
 
-pub fn compute_challenge(card_key: Vec<u8>, today: i32) -> Vec<u8> {
-    let card_key = Bn128Field::from_byte_vector(card_key);
+pub fn compute_challenge(card_key: &CardKey, today: i32) -> Challenge {
     let today = Bn128Field::from(today);
-    let challenge = compute_mimc7r10_hash(&today, &card_key);
-    challenge.into_byte_vector()
+    Challenge::from_field(&compute_mimc7r10_hash(&today, &card_key.to_field()))
+}
+
+/// Binds the challenge to the nonce of the verifier,
+/// `mimc(mimc(today, card_key), nonce)`.
+pub fn bind_nonce(challenge: &Challenge, nonce: &Nonce) -> Challenge {
+    Challenge::from_field(&compute_mimc7r10_hash(
+        &challenge.to_field(),
+        &nonce.to_field(),
+    ))
+}
+
+/// Challenge of the card on the julian day `today`, bound to the
+/// verifier's nonce if there is one, see `bind_nonce`. The circuit has
+/// no nonce input, the challenge public input is always the one of
+/// `compute_challenge`. The bound value keys the hiding mask instead,
+/// so that a proof unhides only with the nonce it was generated for.
+/// The binding is not proved: the holder, and every verifier who knows
+/// the photo digest, can unhide the proof and hide it again for another
+/// nonce. The nonce stops a captured picture of the code from being
+/// shown to another verifier, it does not stop replay by the holder.
+pub fn compute_challenge_with_nonce(
+    card_key: &CardKey,
+    today: i32,
+    nonce: Option<&Nonce>,
+) -> Challenge {
+    let challenge = compute_challenge(card_key, today);
+    match nonce {
+        Some(nonce) => bind_nonce(&challenge, nonce),
+        None => challenge,
+    }
 }
 
-pub fn generate_proof(rq: QrRequest) -> Result<ProofQrCode, String> {
-    let prg = match ProgEnum::deserialize(&mut PROGRAM.clone())? {
-        ProgEnum::Bn128Program(p) => p,
-        _ => panic!("Invalid program type"),
+/// Mask hiding the proofs of a QR code of the format `version`. The
+/// secret of the mask is the photo digest, with a nonce it is the MiMC
+/// hash of the photo digest keyed with `bind_nonce`, so that the proof
+/// unhides only with the nonce it was generated for. Whoever knows the
+/// photo digest can hide it again for another nonce, see
+/// `compute_challenge_with_nonce`.
+///
+/// Before `STREAM_MASK_VERSION` the secret itself is repeated over the
+/// proof bytes, which lets anybody who sees several QR codes of the
+/// same card recover it from the structure of the curve points.
+/// Version 4 uses a ChaCha20 keystream of `2 * PROOF_LENGTH` bytes, one
+/// block for each proof of a band, keyed by SHA-256 of the secret and
+/// all public values, so that different codes get unrelated masks.
+///
+/// The masks hide the proof but do not authenticate it. From
+/// `SEALED_VERSION` on the result is not a mask but two
+/// ChaCha20-Poly1305 keys, one for each proof of a band, derived by
+/// HKDF-SHA256 from the secret and all public values. A sealed proof
+/// which was tampered with or is opened with a wrong photo digest is
+/// refused by its tag, see `ZkError::TagMismatch`.
+pub fn hiding_mask(
+    version: u8,
+    public: &Public,
+    challenge: &Challenge,
+    photos_digest: &PhotosDigest,
+) -> Vec<u8> {
+    let secret = match &public.nonce {
+        None => photos_digest.as_bytes().to_vec(),
+        Some(nonce) => {
+            let key = bind_nonce(challenge, nonce).to_field();
+            field_to_le_bytes(&compute_mimc7r10_hash(&photos_digest.to_field(), &key))
+        }
     };
+    if version < STREAM_MASK_VERSION {
+        return secret;
+    }
+
+    let mut public_values = vec![];
+    for value in &[public.today, public.now, public.delta] {
+        public_values.extend_from_slice(&value.to_be_bytes());
+    }
+    public_values.push(public.relation as u8);
+    if let Some(bound) = &public.second {
+        public_values.push(bound.relation as u8);
+        public_values.extend_from_slice(&bound.delta.to_be_bytes());
+    }
+    if version >= SEALED_VERSION {
+        let mut info = vec![challenge.as_bytes().len() as u8];
+        info.extend_from_slice(challenge.as_bytes());
+        info.extend_from_slice(&public_values);
+        let mut keys = vec![0u8; 2 * SEAL_KEY_LENGTH];
+        Hkdf::<Sha256>::new(Some(&b"legalage proof seal"[..]), &secret)
+            .expand(&info, &mut keys)
+            .expect("two keys fit the HKDF output");
+        return keys;
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"legalage proof mask");
+    for field in &[&secret[..], challenge.as_bytes()] {
+        hasher.update(&[field.len() as u8]);
+        hasher.update(field);
+    }
+    hasher.update(&public_values);
+    let mut seed = [0u32; 8];
+    LittleEndian::read_u32_into(&hasher.finalize(), &mut seed);
+
+    let mut stream = vec![0u8; 2 * PROOF_LENGTH];
+    ChaChaRng::from_seed(&seed).fill_bytes(&mut stream);
+    stream
+}
 
-    let abi: Abi = serde_json::from_reader(&mut ABI.clone()).unwrap();
-    let _signature = abi.signature();
+/// Tells whether the challenge was computed from the card key on the
+/// day of the public part, e.g. to bind a QR code to a registered card.
+/// The comparison takes the same time wherever the values differ.
+pub fn verify_challenge(card_key: &CardKey, public: &Public, challenge: &Challenge) -> bool {
+    let expected = compute_challenge(card_key, public.today);
+    // Compare the canonical forms, the challenge in the QR code is zero
+    // padded.
+    let difference = expected
+        .to_be_bytes()
+        .iter()
+        .zip(challenge.to_be_bytes().iter())
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+    difference == 0
+}
 
-    let interpreter = ir::Interpreter::default();
+/// Whether a request would give a proof which verifies.
+#[derive(PartialEq, Debug, Clone)]
+pub enum RelationOutcome {
+    /// The relation, and the second bound if there is one, holds.
+    Satisfied,
+    /// The prover would generate a decoy proof.
+    NotSatisfied,
+    /// The request is rejected by `QrRequest::validate`.
+    InvalidInput(ValidationError),
+}
 
-    let mut arguments: Vec<Bn128Field> = Vec::new();
+/// Checks the request without running the circuit, so that the app
+/// can warn the user before generating a proof which does not verify.
+/// Only the ranges and the relation are checked; the time does not
+/// depend on the outcome in any noticeable way.
+pub fn evaluate_relation(rq: &QrRequest) -> RelationOutcome {
+    match rq.is_relation_valid() {
+        Ok(true) => RelationOutcome::Satisfied,
+        Ok(false) => RelationOutcome::NotSatisfied,
+        Err(e) => RelationOutcome::InvalidInput(e),
+    }
+}
 
-    let birthday = rq.private.birthday;
-    let mut delta = rq.public.delta;
-    let today = rq.public.today;
+/// Stage of the proof generation, reported by
+/// `Prover::prove_with_progress` when the stage is entered. The
+/// discriminants are passed to the phone app.
+#[cfg(feature = "prover")]
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum ProofStage {
+    /// Deserializing the program and the proving key. Takes most of
+    /// the time of the first proof.
+    ParsingProgram = 0,
+    /// Running the circuit interpreter.
+    ComputingWitness = 1,
+    /// Groth16 proving, the longest stage.
+    GeneratingProof = 2,
+    /// Hiding the proof and building the QR code.
+    Encoding = 3,
+}
 
-    let mut younger = 0;
+/// Timings of the last proof generated on the thread, for the
+/// diagnostics of the phone app. All times are 0 in the browser.
+#[cfg(feature = "prover")]
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+pub struct ProofStats {
+    /// Witness computation of all bounds.
+    pub witness_ms: u64,
+    /// Groth16 proving of all bounds.
+    pub proving_ms: u64,
+    /// The whole generation without the preparation of the prover.
+    pub total_ms: u64,
+    /// Constraints of the circuit.
+    pub constraint_count: usize,
+}
 
-    println!("generate proof today: {}", today);
+#[cfg(feature = "prover")]
+thread_local! {
+    static LAST_PROOF_STATS: std::cell::Cell<Option<ProofStats>> = std::cell::Cell::new(None);
+}
 
-    if rq.is_relation_valid() {
-	if rq.public.relation == Relation::Younger {
-            younger =  1;
-	}
-    } else {
-        // Generating invalid proof.
-        //
-        // The user wants us to proof something what is not
+/// Stats of the last proof generated on this thread, `None` before
+/// the first proof. The `_async` variants generate on another thread.
+#[cfg(feature = "prover")]
+pub fn last_proof_stats() -> Option<ProofStats> {
+    LAST_PROOF_STATS.with(|stats| stats.get())
+}
+
+/// Cancels a proof generation, e.g. when the user leaves the screen.
+/// The token is checked when a stage has been reported, so a
+/// cancellation from the progress callback of a stage stops the
+/// generation before that stage runs. Clones share the token.
+#[cfg(feature = "prover")]
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "prover")]
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    fn check(&self) -> Result<(), ZkError> {
+        if self.is_cancelled() {
+            return Err(ZkError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Options of the prover.
+#[cfg(feature = "prover")]
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ZkConfig {
+    /// Seed of the random number generator used by Groth16 proving.
+    /// When set, the same request always gives the same proof bytes,
+    /// which is useful for golden-file tests. Production code must
+    /// never set the seed: equal proofs let the verifier link QR codes
+    /// of the same card.
+    pub rng_seed: Option<[u8; 32]>,
+}
+
+/// Parsed program, ABI and proving key. Create it once and share it
+/// between threads to generate many proofs.
+#[cfg(feature = "prover")]
+pub struct Prover {
+    program: ir::Prog<Bn128Field>,
+    abi: Abi,
+    params: Parameters<Bn256>,
+    config: ZkConfig,
+    key_id: u8,
+}
+
+#[cfg(feature = "prover")]
+impl Prover {
+    /// Does all the work which does not depend on the request:
+    /// deserializes the embedded program and ABI and parses the
+    /// proving key. This takes most of the time of the first proof.
+    pub fn prepare() -> Result<Self, ZkError> {
+        Self::embedded()
+    }
+
+    /// Prover of the program and key embedded in the library.
+    pub fn embedded() -> Result<Self, ZkError> {
+        Self::with_config(ZkConfig::default())
+    }
+
+    /// Same as `embedded` with explicit options.
+    pub fn with_config(config: ZkConfig) -> Result<Self, ZkError> {
+        Self::load(PROGRAM, ABI, PROVING_KEY, config)
+    }
+
+    /// Prover of artifacts loaded at runtime, e.g. the files written by
+    /// `zokrates/compile_circuit_and_make_setup.sh`. The program must be
+    /// compiled for bn128 and the key must match the public inputs of
+    /// the ABI.
+    pub fn from_artifacts(program: &[u8], abi: &[u8], proving_key: &[u8]) -> Result<Self, ZkError> {
+        Self::load(program, abi, proving_key, ZkConfig::default())
+    }
+
+    fn load(
+        mut program: &[u8],
+        abi: &[u8],
+        proving_key: &[u8],
+        config: ZkConfig,
+    ) -> Result<Self, ZkError> {
+        let _phase = Phase::start("program deserialization");
+        let program =
+            match ProgEnum::deserialize(&mut program).map_err(|_| ZkError::KeyDeserialization)? {
+                ProgEnum::Bn128Program(p) => p,
+                _ => {
+                    return Err(ZkError::InvalidArtifact(
+                        "program is not compiled for bn128".to_string(),
+                    ))
+                }
+            };
+        let abi: Abi = serde_json::from_slice(abi).map_err(|_| ZkError::KeyDeserialization)?;
+        let params =
+            Parameters::read(proving_key, true).map_err(|_| ZkError::KeyDeserialization)?;
+
+        // Public inputs are the public arguments followed by the
+        // outputs, the key has one more point for the constant.
+        let public_inputs: usize = abi
+            .inputs
+            .iter()
+            .filter(|input| input.public)
+            .map(|input| input.ty.get_primitive_count())
+            .sum::<usize>()
+            + abi
+                .outputs
+                .iter()
+                .map(|ty| ty.get_primitive_count())
+                .sum::<usize>();
+        if params.vk.ic.len() != public_inputs + 1 {
+            return Err(ZkError::InvalidArtifact(format!(
+                "proving key has {} public inputs, the program has {}",
+                params.vk.ic.len().saturating_sub(1),
+                public_inputs
+            )));
+        }
+        Ok(Prover {
+            program: program,
+            abi: abi,
+            params: params,
+            config: config,
+            key_id: EMBEDDED_KEY,
+        })
+    }
+
+    /// Sets the id of the proving key written to the QR codes, so
+    /// that the verifier can select the matching verification key.
+    /// It is `EMBEDDED_KEY` by default.
+    pub fn with_key_id(mut self, key_id: u8) -> Self {
+        self.key_id = key_id;
+        self
+    }
+
+    /// Random number generator for one `prove` call, seeded by `seed`
+    /// or by the seed of the configuration.
+    fn proof_rng(&self, seed: Option<&[u8; 32]>) -> ChaChaRng {
+        match seed.or_else(|| self.config.rng_seed.as_ref()) {
+            Some(seed) => {
+                let mut words = [0u32; 8];
+                for (i, word) in words.iter_mut().enumerate() {
+                    *word = u32::from_le_bytes([
+                        seed[4 * i],
+                        seed[4 * i + 1],
+                        seed[4 * i + 2],
+                        seed[4 * i + 3],
+                    ]);
+                }
+                ChaChaRng::from_seed(&words)
+            }
+            None => thread_rng().gen(),
+        }
+    }
+
+    /// Validates the request, computes the witness and generates the
+    /// proof.
+    pub fn prove(&self, rq: QrRequest) -> Result<ProofQrCode, ZkError> {
+        self.prove_stages(rq, &|_| (), &CancellationToken::default(), None)
+    }
+
+    /// Same as `prove` with the random number generator seeded by
+    /// `seed`, see `generate_proof_seeded`.
+    pub fn prove_seeded(&self, rq: QrRequest, seed: [u8; 32]) -> Result<ProofQrCode, ZkError> {
+        self.prove_stages(rq, &|_| (), &CancellationToken::default(), Some(&seed))
+    }
+
+    /// Same as `prove`, reports each `ProofStage` when it is entered.
+    /// The program has already been parsed, so `ParsingProgram` is
+    /// passed immediately.
+    pub fn prove_with_progress<F>(&self, rq: QrRequest, progress: F) -> Result<ProofQrCode, ZkError>
+    where
+        F: Fn(ProofStage),
+    {
+        progress(ProofStage::ParsingProgram);
+        self.prove_stages(rq, &progress, &CancellationToken::default(), None)
+    }
+
+    /// Proves the request reporting all stages after `ParsingProgram`.
+    /// A `seed` overrides the seed of the configuration.
+    fn prove_stages(
+        &self,
+        rq: QrRequest,
+        progress: &dyn Fn(ProofStage),
+        cancellation: &CancellationToken,
+        seed: Option<&[u8; 32]>,
+    ) -> Result<ProofQrCode, ZkError> {
+        rq.validate()?;
+        rq.check_photo_binding()?;
+        let total = Phase::start("proof generation total");
+
+        let _signature = self.abi.signature();
+
+        trace!("generate proof today: {}", rq.public.today);
+
+        // The user may want us to proof something what is not
         // true. Maybe someone is trying to abuse the phone to learn
         // about the users age. We do not want to report an error because
         // this will allow annyone to guess the age by trial and
@@ -104,243 +719,3074 @@ pub fn generate_proof(rq: QrRequest) -> Result<ProofQrCode, String> {
         // another set of input variables. The proof will fail to be
         // verified but it will look similar to a real proof and the
         // generation will take about the same time.
-        delta = 0;
-        // Delta is the only parameter which can be changed or else
-        // the challenge will be also changed.
-        // TODO smazat
-        println!(
-            "Generating invalid proof delta={} birthday={} today={} OLDER ",
-            delta, birthday, today
+        //
+        // Delta and the relation are the only parameters which can be
+        // changed or else the challenge will be also changed. Both paths
+        // run the same code and nothing about the outcome is logged. If
+        // one bound of a band does not hold, both proofs are decoys so
+        // that the verifier cannot learn which one failed.
+        let valid = rq.is_relation_valid()? as i32;
+        let mut relations = vec![(rq.public.relation, rq.public.delta)];
+        if let Some(bound) = &rq.public.second {
+            relations.push((bound.relation, bound.delta));
+        }
+
+        // All witnesses are computed before proving so that each stage
+        // is reported once also for a band.
+        progress(ProofStage::ComputingWitness);
+        cancellation.check()?;
+        let phase = Phase::start("witness computation");
+        let mut witnesses = Vec::with_capacity(relations.len());
+        let mut challenge = None;
+        for (relation, delta) in &relations {
+            let delta = valid * delta;
+            let younger = valid * (*relation == Relation::Younger) as i32;
+            let (witness, computed) = self.compute_witness(&rq, delta, younger)?;
+            // The challenge does not depend on the relation.
+            challenge.get_or_insert(computed);
+            witnesses.push(witness);
+        }
+        let challenge = challenge.expect("at least one relation");
+        let witness_ms = phase.elapsed_ms();
+        drop(phase);
+
+        progress(ProofStage::GeneratingProof);
+        cancellation.check()?;
+        let phase = Phase::start("proof generation");
+        let mut rng = self.proof_rng(seed);
+        let mut proofs = Vec::with_capacity(witnesses.len());
+        for witness in witnesses {
+            let computation = Computation::with_witness(self.program.clone(), witness);
+            proofs.push(
+                create_random_proof(computation, &self.params, &mut rng)
+                    .map_err(|e| ZkError::CircuitExecution(e.to_string()))?,
+            );
+        }
+        let proving_ms = phase.elapsed_ms();
+        drop(phase);
+
+        progress(ProofStage::Encoding);
+        cancellation.check()?;
+        let mask = hiding_mask(
+            QR_FORMAT_VERSION,
+            &rq.public,
+            &challenge,
+            &rq.private.photos_digest,
         );
+        let mut hidden: Vec<Vec<u8>> = proofs
+            .iter()
+            .enumerate()
+            .map(|(index, proof)| hide_bellman_proof(proof, QR_FORMAT_VERSION, &mask, index))
+            .collect();
+        let second_proof = if hidden.len() > 1 { hidden.pop() } else { None };
+
+        let qr = ProofQrCode {
+            public: rq.public,
+            proof: hidden.remove(0),
+            challenge: challenge,
+            second_proof: second_proof,
+            version: QR_FORMAT_VERSION,
+            circuit: rq.circuit,
+            key_id: Some(self.key_id),
+        };
+        let stats = ProofStats {
+            witness_ms: witness_ms,
+            proving_ms: proving_ms,
+            total_ms: total.elapsed_ms(),
+            constraint_count: self.program.constraint_count(),
+        };
+        LAST_PROOF_STATS.with(|last| last.set(Some(stats)));
+        Ok(qr)
+    }
+
+    /// Runs the circuit interpreter without proving. Fails with
+    /// `ZkError::CircuitExecution` if the relation given by `delta` and
+    /// `younger` does not hold for the birthday.
+    pub(crate) fn compute_witness(
+        &self,
+        rq: &QrRequest,
+        delta: i32,
+        younger: i32,
+    ) -> Result<(ir::Witness<Bn128Field>, Challenge), ZkError> {
+        let mut arguments: Vec<Bn128Field> = Vec::new();
+        arguments.push(Bn128Field::from(rq.private.birthday));
+        arguments.extend_from_slice(&public_arguments(delta, rq.public.today, younger));
+        arguments.push(rq.private.photos_digest.to_field());
+        arguments.push(rq.private.private_key.to_field());
+
+        let interpreter = ir::Interpreter::default();
+        let witness = interpreter
+            .execute(&self.program, &arguments)
+            .map_err(|e| ZkError::CircuitExecution(e.to_string()))?;
+
+        // The challenge is the only output, a program of runtime
+        // artifacts may be different.
+        let outs = witness.return_values();
+        if outs.len() != 1 {
+            return Err(ZkError::InvalidArtifact(format!(
+                "program has {} outputs, the circuit has 1",
+                outs.len()
+            )));
+        }
+        let mut challenge = field_to_le_bytes(&outs[0]);
+        challenge.resize(FIELD_LENGTH, 0);
+        let challenge =
+            Challenge::new(challenge).map_err(|e| ZkError::CircuitExecution(e.to_string()))?;
+        Ok((witness, challenge))
+    }
+}
+
+/// Prover context shared by the whole process. It is prepared on the
+/// first call.
+#[cfg(feature = "prover")]
+pub fn shared_prover() -> Result<&'static Prover, ZkError> {
+    SHARED_PROVER.as_ref().map_err(|e| e.clone())
+}
+
+/// Generates the proof with the prover of the circuit of the request,
+/// the shared prover context for the embedded circuit.
+#[cfg(feature = "prover")]
+pub fn generate_proof(rq: QrRequest) -> Result<ProofQrCode, ZkError> {
+    generate_proof_with_progress(rq, |_| ())
+}
+
+/// Same as `generate_proof`, reports each `ProofStage` when it is
+/// entered. `ParsingProgram` covers the preparation of the shared
+/// prover on the first call. An invalid request is refused before
+/// the prover is prepared and no stage is reported.
+#[cfg(feature = "prover")]
+pub fn generate_proof_with_progress<F>(rq: QrRequest, progress: F) -> Result<ProofQrCode, ZkError>
+where
+    F: Fn(ProofStage),
+{
+    generate_proof_cancellable(rq, progress, &CancellationToken::default())
+}
+
+/// Same as `generate_proof_with_progress`, fails with `Cancelled` at
+/// the next stage once `cancellation` is cancelled.
+#[cfg(feature = "prover")]
+pub fn generate_proof_cancellable<F>(
+    rq: QrRequest,
+    progress: F,
+    cancellation: &CancellationToken,
+) -> Result<ProofQrCode, ZkError>
+where
+    F: Fn(ProofStage),
+{
+    rq.validate()?;
+    rq.check_photo_binding()?;
+    progress(ProofStage::ParsingProgram);
+    cancellation.check()?;
+    if rq.circuit == EMBEDDED_CIRCUIT {
+        return shared_prover()?.prove_stages(rq, &progress, cancellation, None);
+    }
+    // Do not hold the lock while proving.
+    let prover = registry().prover(rq.circuit)?;
+    prover.prove_stages(rq, &progress, cancellation, None)
+}
+
+/// Same as `generate_proof`, Groth16 proving draws its randomness
+/// from a ChaCha generator seeded by `seed`, so that
+/// the same request always gives the same QR code string. Only for
+/// golden-file tests and for reproducing field reports: equal proofs
+/// let the verifier link QR codes of the same card, and a known seed
+/// gives up the zero knowledge of the proof.
+#[cfg(feature = "prover")]
+pub fn generate_proof_seeded(rq: QrRequest, seed: [u8; 32]) -> Result<ProofQrCode, ZkError> {
+    rq.validate()?;
+    rq.check_photo_binding()?;
+    if rq.circuit == EMBEDDED_CIRCUIT {
+        return shared_prover()?.prove_seeded(rq, seed);
+    }
+    let prover = registry().prover(rq.circuit)?;
+    prover.prove_seeded(rq, seed)
+}
+
+/// Provers and verifiers of the circuits by their id, which is stored
+/// in the QR code. All circuits compute the same relation with the
+/// same public inputs; a new id is needed whenever the program or the
+/// keys change, e.g. after a new trusted setup. The embedded circuit
+/// `EMBEDDED_CIRCUIT` is always available and cannot be replaced.
+#[derive(Default)]
+pub struct CircuitRegistry {
+    #[cfg(feature = "prover")]
+    provers: HashMap<u8, Arc<Prover>>,
+    #[cfg(feature = "verifier")]
+    verifiers: HashMap<u8, Arc<Verifier>>,
+}
+
+impl CircuitRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn check_id(circuit: u8) -> Result<(), ZkError> {
+        if circuit == EMBEDDED_CIRCUIT {
+            return Err(ZkError::InvalidArtifact(format!(
+                "circuit {} is the embedded circuit",
+                circuit
+            )));
+        }
+        Ok(())
+    }
+
+    /// Registers the prover of the circuit, replacing the previous
+    /// one.
+    #[cfg(feature = "prover")]
+    pub fn register_prover(&mut self, circuit: u8, prover: Prover) -> Result<(), ZkError> {
+        Self::check_id(circuit)?;
+        self.provers.insert(circuit, Arc::new(prover));
+        Ok(())
+    }
+
+    /// Registers the verifier of the circuit, replacing the previous
+    /// one.
+    #[cfg(feature = "verifier")]
+    pub fn register_verifier(&mut self, circuit: u8, verifier: Verifier) -> Result<(), ZkError> {
+        Self::check_id(circuit)?;
+        self.verifiers.insert(circuit, Arc::new(verifier));
+        Ok(())
+    }
+
+    #[cfg(feature = "prover")]
+    pub fn prover(&self, circuit: u8) -> Result<Arc<Prover>, ZkError> {
+        self.provers
+            .get(&circuit)
+            .cloned()
+            .ok_or(ZkError::UnsupportedCircuit(circuit))
+    }
+
+    #[cfg(feature = "verifier")]
+    pub fn verifier(&self, circuit: u8) -> Result<Arc<Verifier>, ZkError> {
+        self.verifiers
+            .get(&circuit)
+            .cloned()
+            .ok_or(ZkError::UnsupportedCircuit(circuit))
+    }
+}
+
+/// Circuits used by `generate_proof` and `verify_proof` besides the
+/// embedded one.
+static CIRCUITS: Lazy<RwLock<CircuitRegistry>> = Lazy::new(Default::default);
+
+fn registry() -> RwLockReadGuard<'static, CircuitRegistry> {
+    // The registry is consistent even if a thread panicked.
+    CIRCUITS.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn registry_mut() -> RwLockWriteGuard<'static, CircuitRegistry> {
+    CIRCUITS.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Makes `generate_proof` prove requests of the circuit with the
+/// prover, e.g. one created by `Prover::from_artifacts`.
+#[cfg(feature = "prover")]
+pub fn register_prover(circuit: u8, prover: Prover) -> Result<(), ZkError> {
+    registry_mut().register_prover(circuit, prover)
+}
+
+/// Makes `verify_proof` verify QR codes of the circuit with the
+/// verifier, e.g. one created by `Verifier::from_verification_key`.
+#[cfg(feature = "verifier")]
+pub fn register_verifier(circuit: u8, verifier: Verifier) -> Result<(), ZkError> {
+    registry_mut().register_verifier(circuit, verifier)
+}
+
+/// Julian days on which a verification key is accepted, both ends
+/// inclusive. An unset end is unlimited.
+#[cfg(feature = "verifier")]
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct KeyValidity {
+    pub valid_from: Option<i32>,
+    pub valid_to: Option<i32>,
+}
+
+#[cfg(feature = "verifier")]
+impl KeyValidity {
+    /// Validity without limits.
+    pub fn always() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, today: i32) -> bool {
+        self.valid_from.map_or(true, |from| from <= today)
+            && self.valid_to.map_or(true, |to| today <= to)
+    }
+}
+
+/// Verification key accepted by a `Verifier`.
+#[cfg(feature = "verifier")]
+struct AcceptedKey {
+    id: u8,
+    vk: PreparedVerifyingKey<Bn256>,
+    validity: KeyValidity,
+}
+
+/// Decodes a big-endian hex coordinate of the ZoKrates key.
+#[cfg(feature = "verifier")]
+fn fq(hex_coordinate: &str) -> Result<Fq, ZkError> {
+    let digits = hex_coordinate.trim_start_matches("0x");
+    let bytes = hex::decode(digits).map_err(|_| ZkError::KeyDeserialization)?;
+    if bytes.len() > FIELD_LENGTH {
+        return Err(ZkError::KeyDeserialization);
+    }
+    let mut padded = [0u8; FIELD_LENGTH];
+    padded[FIELD_LENGTH - bytes.len()..].copy_from_slice(&bytes);
+    let mut repr = FqRepr::default();
+    repr.read_be(&padded[..])
+        .map_err(|_| ZkError::KeyDeserialization)?;
+    Fq::from_repr(repr).map_err(|_| ZkError::KeyDeserialization)
+}
+
+#[cfg(feature = "verifier")]
+fn g1_point(x: &str, y: &str) -> Result<G1Affine, ZkError> {
+    G1Affine::from_xy_checked(fq(x)?, fq(y)?).map_err(|_| ZkError::KeyDeserialization)
+}
+
+#[cfg(feature = "verifier")]
+fn g2_point(x: &(String, String), y: &(String, String)) -> Result<G2Affine, ZkError> {
+    let fq2 = |c: &(String, String), swap: bool| -> Result<Fq2, ZkError> {
+        let (c0, c1) = if swap { (&c.1, &c.0) } else { (&c.0, &c.1) };
+        Ok(Fq2 {
+            c0: fq(c0)?,
+            c1: fq(c1)?,
+        })
+    };
+    // ZoKrates versions differ in the order of the coefficients, only
+    // one of the orders gives a point on the curve.
+    for &swap in &[false, true] {
+        if let Ok(point) = G2Affine::from_xy_checked(fq2(x, swap)?, fq2(y, swap)?) {
+            return Ok(point);
+        }
+    }
+    Err(ZkError::KeyDeserialization)
+}
+
+/// Converts the ZoKrates key and prepares it for the pairing checks.
+/// The ZoKrates verifier clones and prepares the key on every call,
+/// which dominates the cost of a verification.
+#[cfg(feature = "verifier")]
+fn prepare_key(vk: &VerificationKey) -> Result<PreparedVerifyingKey<Bn256>, ZkError> {
+    let ic = vk
+        .gamma_abc
+        .iter()
+        .map(|p| g1_point(&p.0, &p.1))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = VerifyingKey::<Bn256> {
+        alpha_g1: g1_point(&vk.alpha.0, &vk.alpha.1)?,
+        // beta and delta in G1 are needed by the prover only.
+        beta_g1: G1Affine::zero(),
+        beta_g2: g2_point(&vk.beta.0, &vk.beta.1)?,
+        gamma_g2: g2_point(&vk.gamma.0, &vk.gamma.1)?,
+        delta_g1: G1Affine::zero(),
+        delta_g2: g2_point(&vk.delta.0, &vk.delta.1)?,
+        ic: ic,
+    };
+    Ok(prepare_verifying_key(&key))
+}
+
+/// Parsed verification keys. Create it once and share it between
+/// threads to verify many QR codes.
+///
+/// When the trusted setup is re-run, the verifier accepts the old and
+/// the new key for a transition period. The key is selected by the key
+/// id in the QR code and must be valid on the day the QR code was
+/// generated. Codes without a key id are tried with all keys valid on
+/// that day, in the order in which the keys were added.
+#[cfg(feature = "verifier")]
+pub struct Verifier {
+    keys: Vec<AcceptedKey>,
+}
+
+#[cfg(feature = "verifier")]
+impl Verifier {
+    /// Parses the embedded verification key.
+    pub fn new() -> Result<Self, ZkError> {
+        Self::embedded()
+    }
+
+    /// Verifier of the key embedded in the library.
+    pub fn embedded() -> Result<Self, ZkError> {
+        Self::from_verification_key(VERIFICATION_KEY)
+    }
+
+    /// Verifier of a key loaded at runtime, e.g. `verification.key`
+    /// written by `zokrates setup`. Allows to replace the key without
+    /// rebuilding the service. The key has the id `EMBEDDED_KEY` and
+    /// no validity limits.
+    pub fn from_verification_key(vk_json: &[u8]) -> Result<Self, ZkError> {
+        let mut verifier = Self::without_keys();
+        verifier.add_key(EMBEDDED_KEY, vk_json, KeyValidity::always())?;
+        Ok(verifier)
+    }
+
+    /// Verifier which refuses everything until keys are added.
+    pub fn without_keys() -> Self {
+        Verifier { keys: vec![] }
+    }
+
+    /// Accepts proofs of the key with the id during the validity.
+    pub fn add_key(
+        &mut self,
+        key_id: u8,
+        vk_json: &[u8],
+        validity: KeyValidity,
+    ) -> Result<(), ZkError> {
+        let _phase = Phase::start("verification key deserialization");
+        let vk: VerificationKey =
+            serde_json::from_slice(vk_json).map_err(|_| ZkError::KeyDeserialization)?;
+        if vk.gamma_abc.len() != PUBLIC_INPUTS + 1 {
+            return Err(ZkError::InvalidArtifact(format!(
+                "verification key has {} public inputs, the circuit has {}",
+                vk.gamma_abc.len().saturating_sub(1),
+                PUBLIC_INPUTS
+            )));
+        }
+        if self.keys.iter().any(|key| key.id == key_id) {
+            return Err(ZkError::InvalidArtifact(format!(
+                "duplicate key id {}",
+                key_id
+            )));
+        }
+        self.keys.push(AcceptedKey {
+            id: key_id,
+            vk: prepare_key(&vk)?,
+            validity: validity,
+        });
+        Ok(())
+    }
+
+    /// Verifies the proof and the second proof of a band with the key
+    /// selected by the key id of the QR code. A photo digest of zero is
+    /// refused with `ZkError::UnboundProof`, see
+    /// `PhotosDigest::is_unbound`.
+    pub fn verify(&self, qr: &ProofQrCode, photo_digest: &PhotosDigest) -> Result<(), ZkError> {
+        self.verify_binding(qr, photo_digest, false)
+    }
+
+    /// Same as `verify`, accepts a photo digest of zero if
+    /// `allow_unbound` is set, e.g. for proofs of
+    /// `QrRequest::allow_unbound_proof`.
+    pub fn verify_binding(
+        &self,
+        qr: &ProofQrCode,
+        photo_digest: &PhotosDigest,
+        allow_unbound: bool,
+    ) -> Result<(), ZkError> {
+        // Values out of range would wrap in the field.
+        qr.public.validate()?;
+        if photo_digest.is_unbound() && !allow_unbound {
+            return Err(ZkError::UnboundProof);
+        }
+        let _phase = Phase::start("verification");
+        let today = qr.public.today;
+        match qr.key_id {
+            Some(key_id) => {
+                let key = self
+                    .keys
+                    .iter()
+                    .find(|key| key.id == key_id)
+                    .ok_or(ZkError::UnknownKey(key_id))?;
+                if !key.validity.contains(today) {
+                    return Err(ZkError::KeyExpired);
+                }
+                Self::verify_with_key(&key.vk, qr, photo_digest)
+            }
+            None => {
+                let mut result = Err(ZkError::KeyExpired);
+                for key in self.keys.iter().filter(|key| key.validity.contains(today)) {
+                    result = Self::verify_with_key(&key.vk, qr, photo_digest);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    fn verify_with_key(
+        vk: &PreparedVerifyingKey<Bn256>,
+        qr: &ProofQrCode,
+        photo_digest: &PhotosDigest,
+    ) -> Result<(), ZkError> {
+        let public = &qr.public;
+        let mask = hiding_mask(qr.version, public, &qr.challenge, photo_digest);
+        let unhide = |hidden: &[u8], index: usize| {
+            unhide_bellman_proof(hidden, qr.version, &mask, index).map_err(|e| match e {
+                QrError::ProofTag => ZkError::TagMismatch,
+                _ => ZkError::PhotoMismatch,
+            })
+        };
+        let inputs = build_public_inputs(public, qr.challenge.as_bytes())?;
+        verify_relation(vk, &inputs[..PUBLIC_INPUTS], &unhide(&qr.proof, 0)?)?;
+        match (&public.second, &qr.second_proof) {
+            (None, None) => Ok(()),
+            (Some(_), Some(second_proof)) => {
+                verify_relation(vk, &inputs[PUBLIC_INPUTS..], &unhide(second_proof, 1)?)
+            }
+            _ => Err(ZkError::VerificationFailed),
+        }
     }
 
-    arguments.push(Bn128Field::from(birthday));
-    arguments.push(Bn128Field::from(delta));
-    arguments.push(Bn128Field::from(today));
-    arguments.push(Bn128Field::from(younger));
-    arguments.push(Bn128Field::from_byte_vector(
-        rq.private.photos_digest.clone(),
-    ));
-    arguments.push(Bn128Field::from_byte_vector(rq.private.private_key));
+    /// Same as `verify`, the QR code must have been generated for the
+    /// `nonce` shown by this verifier. The nonce is compared as a field
+    /// element since the binary layout pads it. It is not a public input
+    /// of the circuit and does not stop replay by the holder, see
+    /// `compute_challenge_with_nonce`. A photo digest of zero is
+    /// accepted only if `allow_unbound` is set, see `verify_binding`.
+    pub fn verify_with_nonce(
+        &self,
+        qr: &ProofQrCode,
+        photo_digest: &PhotosDigest,
+        nonce: &Nonce,
+        allow_unbound: bool,
+    ) -> Result<(), ZkError> {
+        check_nonce(qr, nonce)?;
+        self.verify_binding(qr, photo_digest, allow_unbound)
+    }
+
+    /// Same as `verify` but tells a proof of a relation which does not
+    /// hold from a corrupted one.
+    pub fn verify_outcome(
+        &self,
+        qr: &ProofQrCode,
+        photo_digest: &PhotosDigest,
+    ) -> VerificationOutcome {
+        // `verify` fails only because of the QR code, an unknown or
+        // expired key is reported as a malformed code too.
+        VerificationOutcome::from_result(self.verify(qr, photo_digest))
+            .unwrap_or(VerificationOutcome::Malformed)
+    }
+
+    /// Verifies many QR codes, e.g. a queue at the door, in parallel
+    /// with the parsed key. Each QR code is verified with its own
+    /// photo digest and the results are independent: the result at
+    /// index `i` is the result of `verify` of the item `i`.
+    pub fn verify_batch(&self, items: &[(ProofQrCode, PhotosDigest)]) -> Vec<Result<(), ZkError>> {
+        // There are no threads in the browser.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use rayon::prelude::*;
+            items
+                .par_iter()
+                .map(|(qr, photo_digest)| self.verify(qr, photo_digest))
+                .collect()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            items
+                .iter()
+                .map(|(qr, photo_digest)| self.verify(qr, photo_digest))
+                .collect()
+        }
+    }
+}
+
+#[cfg(feature = "verifier")]
+fn check_nonce(qr: &ProofQrCode, nonce: &Nonce) -> Result<(), ZkError> {
+    let expected = nonce.to_field();
+    match &qr.public.nonce {
+        Some(found) if found.to_field() == expected => Ok(()),
+        _ => Err(ZkError::NonceMismatch),
+    }
+}
+
+/// Verification key embedded in the library, the JSON written by
+/// `zokrates setup`.
+#[cfg(feature = "verifier")]
+pub fn embedded_verification_key() -> &'static [u8] {
+    VERIFICATION_KEY
+}
+
+/// Public arguments of the program in the order of the circuit:
+/// delta, today and younger, 1 for "younger than". A decoy proof is
+/// generated with 0 for both delta and younger.
+fn public_arguments(delta: i32, today: i32, younger: i32) -> [Bn128Field; 3] {
+    [
+        Bn128Field::from(delta),
+        Bn128Field::from(today),
+        Bn128Field::from(younger),
+    ]
+}
+
+/// Public inputs of the Groth16 verification, the public arguments of
+/// the program followed by its output, the challenge. Each bound of a
+/// band is verified with its own inputs, so there are `PUBLIC_INPUTS`
+/// of them for the first bound followed by as many for the second one.
+/// The public values and the challenge are checked first.
+pub fn build_public_inputs(public: &Public, challenge: &[u8]) -> Result<Vec<Bn128Field>, ZkError> {
+    public.validate()?;
+    let challenge = Challenge::new(challenge.to_vec())?.to_field();
+    let mut bounds = vec![(public.relation, public.delta)];
+    if let Some(bound) = &public.second {
+        bounds.push((bound.relation, bound.delta));
+    }
+    let mut inputs = Vec::with_capacity(bounds.len() * PUBLIC_INPUTS);
+    for (relation, delta) in bounds {
+        let younger = (relation == Relation::Younger) as i32;
+        inputs.extend_from_slice(&public_arguments(delta, public.today, younger));
+        inputs.push(challenge.clone());
+    }
+    Ok(inputs)
+}
+
+/// Verifies one proof of a relation against its public inputs.
+#[cfg(feature = "verifier")]
+fn verify_relation(
+    vk: &PreparedVerifyingKey<Bn256>,
+    inputs: &[Bn128Field],
+    proof: &BellmanProof<Bn256>,
+) -> Result<(), ZkError> {
+    let inputs: Vec<_> = inputs
+        .iter()
+        .map(|input| input.clone().into_bellman())
+        .collect();
+    match verify_groth16(vk, proof, &inputs) {
+        Ok(true) => Ok(()),
+        _ => Err(ZkError::VerificationFailed),
+    }
+}
+
+/// Result of a verification which tells a refused claim from a
+/// broken QR code.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum VerificationOutcome {
+    /// The proof verifies.
+    Valid,
+    /// The proof verifies with a photo digest of zero, which unbound
+    /// proofs were allowed for. Any holder can show such a code.
+    ValidUnbound,
+    /// The proof decodes but does not verify. This is a routine
+    /// refusal: the prover generates such a decoy proof when the
+    /// relation does not hold.
+    NotSatisfied,
+    /// The QR code or the hidden proof cannot be decoded, the QR code
+    /// is corrupted or tampered with. A wrong photo digest gives this
+    /// outcome for sealed proofs and usually for masked ones, but a
+    /// masked proof may also unhide to curve points which do not
+    /// verify.
+    Malformed,
+    /// The photo digest is zero and proofs unbound to the photo are not
+    /// allowed. The QR code may be fine, the verifier has no photo
+    /// digest to check it with.
+    Unbound,
+}
+
+impl VerificationOutcome {
+    /// Classifies the result of a verification. Errors which are not
+    /// caused by the QR code are returned unchanged.
+    pub fn from_result(result: Result<(), ZkError>) -> Result<Self, ZkError> {
+        match result {
+            Ok(()) => Ok(VerificationOutcome::Valid),
+            Err(ZkError::VerificationFailed) => Ok(VerificationOutcome::NotSatisfied),
+            Err(ZkError::PhotoMismatch)
+            | Err(ZkError::TagMismatch)
+            | Err(ZkError::ProofDeserialization)
+            | Err(ZkError::InvalidInput(_))
+            | Err(ZkError::QrCode(_)) => Ok(VerificationOutcome::Malformed),
+            Err(ZkError::UnboundProof) => Ok(VerificationOutcome::Unbound),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Same as `from_result` for a verification with `photo_digest`, a
+    /// valid proof of the zero digest is `ValidUnbound`.
+    pub fn from_binding(
+        result: Result<(), ZkError>,
+        photo_digest: &PhotosDigest,
+    ) -> Result<Self, ZkError> {
+        match Self::from_result(result)? {
+            VerificationOutcome::Valid if photo_digest.is_unbound() => {
+                Ok(VerificationOutcome::ValidUnbound)
+            }
+            outcome => Ok(outcome),
+        }
+    }
+}
+
+/// Why a QR code was refused, so that the verifier can tell a card
+/// of somebody else from a forged code. A proof sealed with a key of
+/// the photo digest does not open with a wrong digest, `TagMismatch`.
+/// A proof of the older versions is hidden by a mask of the photo
+/// digest: a wrong digest usually unhides to bytes which are not curve
+/// points, `ProofUnparseable`, but it may also give points which fail
+/// the pairing check, `PairingCheckFailed`.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+pub enum VerificationFailure {
+    /// The unhidden proof is not a set of curve points, almost
+    /// certainly the photo digest does not belong to the card.
+    ProofUnparseable,
+    /// The proof decodes but does not verify: a decoy proof of a
+    /// relation which does not hold, a forged or expired code, or
+    /// rarely a wrong photo digest.
+    PairingCheckFailed,
+    /// The public values of the QR code are out of range or cannot be
+    /// decoded.
+    BadPublicEncoding,
+    /// The tag of the sealed proof does not match, the photo digest
+    /// does not belong to the card or the proof was tampered with.
+    TagMismatch,
+    /// The photo digest is zero and proofs unbound to the photo are not
+    /// allowed.
+    UnboundDigest,
+}
+
+impl VerificationFailure {
+    /// Classifies an error of `verify_proof`. Errors of the verifier
+    /// itself, e.g. an unknown key, are not failures of the QR code and
+    /// give `None`.
+    pub fn from_error(e: &ZkError) -> Option<Self> {
+        match e {
+            ZkError::PhotoMismatch | ZkError::ProofDeserialization => {
+                Some(VerificationFailure::ProofUnparseable)
+            }
+            ZkError::VerificationFailed => Some(VerificationFailure::PairingCheckFailed),
+            ZkError::InvalidInput(_) | ZkError::QrCode(_) => {
+                Some(VerificationFailure::BadPublicEncoding)
+            }
+            ZkError::TagMismatch => Some(VerificationFailure::TagMismatch),
+            ZkError::UnboundProof => Some(VerificationFailure::UnboundDigest),
+            _ => None,
+        }
+    }
+}
+
+/// Verifier context shared by the whole process, used by
+/// `verify_proof` for the embedded circuit. The verification key is
+/// parsed and prepared for pairing on the first call; the context is
+/// read-only afterwards, so any number of threads verify with it at
+/// once.
+#[cfg(feature = "verifier")]
+pub fn shared_verifier() -> Result<&'static Verifier, ZkError> {
+    SHARED_VERIFIER.as_ref().map_err(|e| e.clone())
+}
+
+/// Verifies the proof with the verifier of the circuit of the QR
+/// code, the shared verifier context for the embedded circuit.
+#[cfg(feature = "verifier")]
+pub fn verify_proof(qr: &ProofQrCode, photo_digest: &PhotosDigest) -> Result<(), ZkError> {
+    verify_proof_binding(qr, photo_digest, false)
+}
+
+/// Same as `verify_proof`, accepts a photo digest of zero if
+/// `allow_unbound` is set, see `Verifier::verify_binding`.
+#[cfg(feature = "verifier")]
+pub fn verify_proof_binding(
+    qr: &ProofQrCode,
+    photo_digest: &PhotosDigest,
+    allow_unbound: bool,
+) -> Result<(), ZkError> {
+    if qr.circuit == EMBEDDED_CIRCUIT {
+        return shared_verifier()?.verify_binding(qr, photo_digest, allow_unbound);
+    }
+    let verifier = registry().verifier(qr.circuit)?;
+    verifier.verify_binding(qr, photo_digest, allow_unbound)
+}
+
+/// Same as `verify_proof` with the photo digest as little-endian bytes,
+/// e.g. borrowed from a JNI or C buffer. The bytes are copied into a
+/// `PhotosDigest`, which wipes the copy when it is dropped.
+#[cfg(feature = "verifier")]
+pub fn verify_proof_with_digest_bytes(
+    qr: &ProofQrCode,
+    photo_digest: &[u8],
+) -> Result<(), ZkError> {
+    verify_proof(qr, &PhotosDigest::new(photo_digest.to_vec())?)
+}
+
+/// Same as `verify_proof`, the QR code must have been generated for
+/// the `nonce` shown by the verifier. The holder can replay the code
+/// for another nonce, see `compute_challenge_with_nonce`. A photo
+/// digest of zero is accepted only if `allow_unbound` is set, see
+/// `verify_proof_binding`.
+#[cfg(feature = "verifier")]
+pub fn verify_proof_with_nonce(
+    qr: &ProofQrCode,
+    photo_digest: &PhotosDigest,
+    nonce: &Nonce,
+    allow_unbound: bool,
+) -> Result<(), ZkError> {
+    check_nonce(qr, nonce)?;
+    verify_proof_binding(qr, photo_digest, allow_unbound)
+}
+
+/// Freshness window of the verifier.
+#[cfg(feature = "verifier")]
+#[derive(PartialEq, Debug, Clone)]
+pub struct VerifyOptions {
+    /// Maximal difference between the verifier's time and the time
+    /// the QR code was generated.
+    pub max_age_minutes: u32,
+    /// Verifier's UTC time in minutes since midnight.
+    pub verifier_now: i32,
+    /// Verifier's julian date.
+    pub verifier_today: i32,
+    /// Accepts a photo digest of zero, see `Verifier::verify_binding`.
+    pub allow_unbound_proof: bool,
+}
+
+#[cfg(feature = "verifier")]
+impl VerifyOptions {
+    /// Returns true if the QR code was generated at most
+    /// `max_age_minutes` before or after the verifier's time. The
+    /// window may span midnight.
+    pub fn is_fresh(&self, public: &Public) -> bool {
+        let days = self.verifier_today as i64 - public.today as i64;
+        let minutes = self.verifier_now as i64 - public.now as i64;
+        let age = days * MINUTES_PER_DAY as i64 + minutes;
+        age.abs() <= self.max_age_minutes as i64
+    }
+}
+
+/// Verifies the proof if the QR code is fresh, fails with
+/// `ZkError::Stale` otherwise.
+#[cfg(feature = "verifier")]
+pub fn verify_with_options(
+    qr: &ProofQrCode,
+    photo_digest: &PhotosDigest,
+    options: &VerifyOptions,
+) -> Result<(), ZkError> {
+    if !options.is_fresh(&qr.public) {
+        return Err(ZkError::Stale);
+    }
+    verify_proof_binding(qr, photo_digest, options.allow_unbound_proof)
+}
+
+/// Prover and verifier of one circuit, e.g. of artifacts downloaded by
+/// the app instead of the embedded ones. `generate_proof` and
+/// `verify_proof` use the shared contexts of the embedded circuit.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub struct ZkContext {
+    prover: Prover,
+    verifier: Verifier,
+}
+
+#[cfg(all(feature = "prover", feature = "verifier"))]
+impl ZkContext {
+    /// Context of the program and keys embedded in the library.
+    pub fn embedded() -> Result<Self, ZkError> {
+        Ok(ZkContext {
+            prover: Prover::embedded()?,
+            verifier: Verifier::embedded()?,
+        })
+    }
+
+    /// Reads the files written by
+    /// `zokrates/compile_circuit_and_make_setup.sh`, see
+    /// `Prover::from_artifacts` and `Verifier::from_verification_key`.
+    /// Whether the keys come from the same setup is only found out by
+    /// proving, see `self_test_with`.
+    pub fn from_paths(
+        program: &Path,
+        abi: &Path,
+        proving_key: &Path,
+        verification_key: &Path,
+    ) -> Result<Self, ZkError> {
+        let read = |path: &Path| {
+            std::fs::read(path).map_err(|e| {
+                ZkError::InvalidArtifact(format!("cannot read {}: {}", path.display(), e))
+            })
+        };
+        Ok(ZkContext {
+            prover: Prover::from_artifacts(&read(program)?, &read(abi)?, &read(proving_key)?)?,
+            verifier: Verifier::from_verification_key(&read(verification_key)?)?,
+        })
+    }
+
+    pub fn generate_proof(&self, rq: QrRequest) -> Result<ProofQrCode, ZkError> {
+        self.prover.prove(rq)
+    }
+
+    pub fn verify_proof(
+        &self,
+        qr: &ProofQrCode,
+        photo_digest: &PhotosDigest,
+    ) -> Result<(), ZkError> {
+        self.verifier.verify(qr, photo_digest)
+    }
+
+    /// Makes `generate_proof` and `verify_proof` use the context for
+    /// the circuit, see `register_prover`.
+    pub fn register(self, circuit: u8) -> Result<(), ZkError> {
+        let mut registry = registry_mut();
+        registry.register_prover(circuit, self.prover)?;
+        registry.register_verifier(circuit, self.verifier)
+    }
+}
+
+/// Timings of `self_test` in milliseconds and fingerprints of the
+/// artifacts.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    /// Preparation of the shared prover and verifier, zero if they
+    /// have already been prepared.
+    pub prepare_ms: u64,
+    pub prove_ms: u64,
+    pub verify_ms: u64,
+    /// Hex SHA-256 of the embedded artifacts. Not known for the
+    /// contexts passed to `self_test_with`.
+    pub program_sha256: Option<String>,
+    pub proving_key_sha256: Option<String>,
+    pub verification_key_sha256: Option<String>,
+}
+
+/// Fixed request of the self test: old birthday, delta 18 days.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+fn self_test_request() -> Result<QrRequest, ZkError> {
+    QrRequest::build(
+        Public {
+            today: 2459000,
+            now: 0,
+            relation: Relation::Older,
+            delta: 18,
+            second: None,
+            nonce: None,
+        },
+        Private::with(
+            2415021,
+            PrivateKey::default(),
+            PhotosDigest::new(vec![1, 2, 3])?,
+        )?,
+    )
+    .map_err(ZkError::from)
+}
+
+#[cfg(all(feature = "prover", feature = "verifier"))]
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Generates and verifies a fixed proof with the shared contexts so
+/// that the apps can detect a corrupt installation on startup. The
+/// contexts stay prepared for later proofs.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub fn self_test() -> Result<SelfTestReport, ZkError> {
+    let start = Instant::now();
+    let prover = shared_prover()?;
+    let verifier = shared_verifier()?;
+    let prepare_ms = start.elapsed().as_millis() as u64;
+    let mut report = self_test_with(prover, verifier)?;
+    report.prepare_ms = prepare_ms;
+    report.program_sha256 = Some(sha256_hex(PROGRAM));
+    report.proving_key_sha256 = Some(sha256_hex(PROVING_KEY));
+    report.verification_key_sha256 = Some(sha256_hex(VERIFICATION_KEY));
+    Ok(report)
+}
+
+/// Description of the embedded circuit for tooling, e.g. a health
+/// endpoint of the web service or a circuit upgrade script.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct CircuitInfo {
+    /// Name, type and whether the input is private, in the order of the
+    /// ABI.
+    pub abi_inputs: Vec<(String, String, bool)>,
+    /// Hex SHA-256 of the embedded artifacts, the same as reported by
+    /// `self_test`.
+    pub program_sha256: String,
+    pub proving_key_sha256: String,
+    pub verification_key_sha256: String,
+    /// Curve of the program and the keys in the ZoKrates naming.
+    pub curve: String,
+}
+
+/// Reads the embedded ABI and hashes the embedded artifacts. Neither
+/// the program nor the keys are parsed, so this is cheap also before
+/// the shared contexts are prepared.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub fn circuit_info() -> Result<CircuitInfo, ZkError> {
+    let abi: Abi = serde_json::from_slice(ABI).map_err(|_| ZkError::KeyDeserialization)?;
+    Ok(CircuitInfo {
+        abi_inputs: abi
+            .inputs
+            .iter()
+            .map(|input| (input.name.clone(), input.ty.to_string(), !input.public))
+            .collect(),
+        program_sha256: sha256_hex(PROGRAM),
+        proving_key_sha256: sha256_hex(PROVING_KEY),
+        verification_key_sha256: sha256_hex(VERIFICATION_KEY),
+        // `Prover` accepts only programs compiled for bn128.
+        curve: "bn128".to_string(),
+    })
+}
+
+/// Runs the self test with the given contexts. Fails with
+/// `CorruptInstallation` if the challenge of the program differs from
+/// the library or the proof does not verify.
+#[cfg(all(feature = "prover", feature = "verifier"))]
+pub fn self_test_with(prover: &Prover, verifier: &Verifier) -> Result<SelfTestReport, ZkError> {
+    let rq = self_test_request()?;
+    let expected = compute_challenge(&card_key_unchecked(&rq.private), rq.public.today);
+    let photos_digest = rq.private.photos_digest.clone();
+
+    let start = Instant::now();
+    let qr = prover.prove(rq)?;
+    let prove_ms = start.elapsed().as_millis() as u64;
+    if qr.challenge != expected {
+        return Err(ZkError::CorruptInstallation(
+            "challenge of the program does not match".to_string(),
+        ));
+    }
+
+    let start = Instant::now();
+    match verifier.verify(&qr, &photos_digest) {
+        Ok(()) => {}
+        Err(ZkError::VerificationFailed)
+        | Err(ZkError::PhotoMismatch)
+        | Err(ZkError::TagMismatch)
+        | Err(ZkError::ProofDeserialization) => {
+            return Err(ZkError::CorruptInstallation(
+                "proof does not verify".to_string(),
+            ))
+        }
+        Err(e) => return Err(e),
+    }
+    let verify_ms = start.elapsed().as_millis() as u64;
+
+    Ok(SelfTestReport {
+        prepare_ms: 0,
+        prove_ms: prove_ms,
+        verify_ms: verify_ms,
+        program_sha256: None,
+        proving_key_sha256: None,
+        verification_key_sha256: None,
+    })
+}
+
+#[inline]
+fn hide_buffer(buf: &mut [u8], hidding: &[u8]) {
+    if !hidding.is_empty() {
+        for (b, h) in buf.iter_mut().zip(hidding.iter().cycle()) {
+            *b ^= h;
+        }
+    }
+}
+
+/// Part of the `hiding_mask` of the format `version` which hides the
+/// proof `index`, 0 for the proof and 1 for the second proof of a band.
+/// The repeated mask of older versions is shared by both proofs.
+#[inline]
+fn proof_mask(hidding: &[u8], version: u8, index: usize) -> &[u8] {
+    if version < STREAM_MASK_VERSION {
+        return hidding;
+    }
+    let length = if version >= SEALED_VERSION {
+        SEAL_KEY_LENGTH
+    } else {
+        PROOF_LENGTH
+    };
+    let start = (index * length).min(hidding.len());
+    &hidding[start..(start + length).min(hidding.len())]
+}
+
+/// Cipher of a sealed proof, the key is the `proof_mask` of the proof.
+fn seal_cipher(key: &[u8]) -> Result<ChaCha20Poly1305, QrError> {
+    if key.len() != SEAL_KEY_LENGTH {
+        return Err(QrError::ProofTag);
+    }
+    Ok(ChaCha20Poly1305::new(Key::from_slice(key)))
+}
+
+/// Writes the proof `index` of a QR code of the format `version`,
+/// `hidden_proof_length(version)` bytes, masked with its part of
+/// `hidding` or sealed with it.
+///
+/// The nonce of a sealed proof is derived from the key and the proof,
+/// so that two proofs of codes with the same key never share a nonce
+/// and a proof always seals to the same bytes.
+pub fn hide_bellman_proof(
+    proof: &BellmanProof<Bn256>,
+    version: u8,
+    hidding: &[u8],
+    index: usize,
+) -> Vec<u8> {
+    let mut proof_bytes = [0u8; PROOF_LENGTH];
+    proof
+        .write(&mut proof_bytes[..])
+        .expect("proof has PROOF_LENGTH bytes");
+    let mask = proof_mask(hidding, version, index);
+    if version < SEALED_VERSION {
+        hide_buffer(&mut proof_bytes, mask);
+        return proof_bytes.to_vec();
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"legalage proof seal nonce");
+    hasher.update(mask);
+    hasher.update(&proof_bytes[..]);
+    let mut sealed = hasher.finalize()[..SEAL_NONCE_LENGTH].to_vec();
+    let encrypted = seal_cipher(mask)
+        .expect("hiding mask of the version")
+        .encrypt(
+            chacha20poly1305::Nonce::from_slice(&sealed),
+            &proof_bytes[..],
+        )
+        .expect("proof fits one message");
+    sealed.extend_from_slice(&encrypted);
+    sealed
+}
+
+/// Reads a proof written by `hide_bellman_proof` with the same format
+/// version and index.
+pub fn unhide_bellman_proof(
+    hidden: &[u8],
+    version: u8,
+    hidding: &[u8],
+    index: usize,
+) -> Result<BellmanProof<Bn256>, QrError> {
+    if hidden.len() != hidden_proof_length(version) {
+        return Err(QrError::ProofPoints);
+    }
+    let mask = proof_mask(hidding, version, index);
+    let mut b = [0u8; PROOF_LENGTH];
+    if version < SEALED_VERSION {
+        b.copy_from_slice(hidden);
+        hide_buffer(&mut b, mask);
+    } else {
+        let (nonce, encrypted) = hidden.split_at(SEAL_NONCE_LENGTH);
+        let opened = seal_cipher(mask)?
+            .decrypt(chacha20poly1305::Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| QrError::ProofTag)?;
+        b.copy_from_slice(&opened);
+    }
+    // The bytes come from the QR code, a malformed point must not
+    // bring the verifier down even if the curve library panics.
+    std::panic::catch_unwind(move || BellmanProof::<Bn256>::read(&b[..]))
+        .map_err(|_| QrError::ProofPoints)?
+        .map_err(|_| QrError::ProofPoints)
+}
+
+#[cfg(all(test, feature = "prover", feature = "verifier"))]
+mod tests {
+    use super::*;
+    use crate::api::{
+        Bound, CardKey, PhotosDigest, Private, PrivateKey, ProofQrCode, Public, QrRequest,
+        QrSection, Relation, BAND_PAYLOAD_LENGTH, MAX_JULIAN_DAY, PAYLOAD_LENGTH,
+        SEALED_PROOF_LENGTH,
+    };
+    use crate::date::{delta_for_years, julian_from_ymd};
+    use crate::test_alloc::count_allocations;
+    use proptest::prelude::*;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use zokrates_core::proof_system::{bellman::groth16::ProofPoints, Proof};
+    use zokrates_field::Bn128Field;
+
+    static DESERIALIZATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    /// The QR code with its proofs masked the way the format `version`
+    /// masks them, e.g. as an older app would have generated it.
+    fn with_version(qr: &ProofQrCode, digest: &PhotosDigest, version: u8) -> ProofQrCode {
+        let old = hiding_mask(qr.version, &qr.public, &qr.challenge, digest);
+        let new = hiding_mask(version, &qr.public, &qr.challenge, digest);
+        let remask = |hidden: &[u8], index: usize| {
+            let proof = unhide_bellman_proof(hidden, qr.version, &old, index).unwrap();
+            hide_bellman_proof(&proof, version, &new, index)
+        };
+        let mut remasked = qr.clone();
+        remasked.version = version;
+        if version < 3 {
+            // Key ids came with the version 3.
+            remasked.key_id = None;
+        }
+        remasked.proof = remask(&qr.proof, 0);
+        remasked.second_proof = qr.second_proof.as_ref().map(|proof| remask(proof, 1));
+        remasked
+    }
+
+    /// Verification by the ZoKrates verifier from the parsed key, the
+    /// way `Verifier` used to verify before preparing its keys.
+    fn verify_with_zokrates(vk: &VerificationKey, qr: &ProofQrCode, digest: &PhotosDigest) -> bool {
+        let public = &qr.public;
+        let mask = hiding_mask(qr.version, public, &qr.challenge, digest);
+        let proof = unhide_bellman_proof(&qr.proof, qr.version, &mask, 0).unwrap();
+        let mut raw: Vec<u8> = Vec::new();
+        proof.write(&mut raw).unwrap();
+        let younger = (public.relation == Relation::Younger) as i32;
+        let inputs = vec![
+            Bn128Field::from(public.delta),
+            Bn128Field::from(public.today),
+            Bn128Field::from(younger),
+            Bn128Field::from_byte_vector(qr.challenge.as_bytes().to_vec()),
+        ];
+        let proof = Proof::<ProofPoints> {
+            proof: ProofPoints::from_bellman::<Bn128Field>(&proof),
+            inputs: inputs
+                .iter()
+                .map(|bn128| bn128.to_biguint().to_str_radix(16))
+                .collect(),
+            raw: hex::encode(&raw),
+        };
+        <G16 as ProofSystem<Bn128Field>>::verify(vk.clone(), proof)
+    }
+
+    #[test]
+    fn prepared_key_allocates_less() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        let vk: VerificationKey = serde_json::from_slice(VERIFICATION_KEY).unwrap();
+        let verifier = Verifier::embedded().unwrap();
+
+        let (verified, before) =
+            count_allocations(|| verify_with_zokrates(&vk, &p, &photos_digest));
+        assert!(verified);
+        let (result, after) = count_allocations(|| verifier.verify(&p, &photos_digest));
+        assert_eq!(result, Ok(()));
+        assert!(
+            after * 10 <= before,
+            "{} allocations before, {} after",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn verify_digest_bytes() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        let vec = vec![2u8, 7];
+        assert_eq!(verify_proof_with_digest_bytes(&p, &vec), Ok(()));
+        assert_eq!(verify_proof_with_digest_bytes(&p, &[2, 7]), Ok(()));
+        assert!(verify_proof_with_digest_bytes(&p, &[2, 8]).is_err());
+        assert_eq!(
+            verify_proof_with_digest_bytes(&p, &[0xff; FIELD_LENGTH]),
+            Err(ZkError::InvalidInput(ValidationError::PhotosDigest))
+        );
+
+        // Only the copy of the digest is added.
+        verify_proof(&p, &photos_digest).unwrap();
+        let (_, typed) = count_allocations(|| verify_proof(&p, &photos_digest));
+        let (_, bytes) = count_allocations(|| verify_proof_with_digest_bytes(&p, &vec));
+        assert!(bytes <= typed + 1, "{} allocations, {} typed", bytes, typed);
+    }
+
+    #[test]
+    fn prepared_key_matches_proving_key() {
+        let params = &Prover::embedded().unwrap().params;
+        let vk: VerificationKey = serde_json::from_slice(VERIFICATION_KEY).unwrap();
+        assert_eq!(g1_point(&vk.alpha.0, &vk.alpha.1), Ok(params.vk.alpha_g1));
+        assert_eq!(g2_point(&vk.beta.0, &vk.beta.1), Ok(params.vk.beta_g2));
+        assert_eq!(g2_point(&vk.gamma.0, &vk.gamma.1), Ok(params.vk.gamma_g2));
+        assert_eq!(g2_point(&vk.delta.0, &vk.delta.1), Ok(params.vk.delta_g2));
+        for (p, ic) in vk.gamma_abc.iter().zip(params.vk.ic.iter()) {
+            assert_eq!(g1_point(&p.0, &p.1), Ok(*ic));
+        }
+        assert_eq!(fq("0xzz"), Err(ZkError::KeyDeserialization));
+    }
+
+    /// Logger keeping all messages of all tests.
+    struct CapturingLogger {
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.lines
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", record.target(), record.args()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: Lazy<CapturingLogger> = Lazy::new(|| CapturingLogger {
+        lines: Mutex::new(Vec::new()),
+    });
+
+    fn captured_log() -> Vec<String> {
+        let _ = log::set_logger(&*LOGGER);
+        log::set_max_level(log::LevelFilter::Trace);
+        LOGGER.lines.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn private_values_are_not_logged() {
+        let _ = captured_log();
+        let birthday = 2001002;
+        let private_key = vec![0xdeu8, 0xad, 0xbe, 0xef];
+        let photos_digest = digest(&[0x5a, 0x17, 0xc3, 0x99, 0x42, 0x6e]);
+        for delta in &[18, 30] {
+            let rq = QrRequest {
+                circuit: EMBEDDED_CIRCUIT,
+                public: Public {
+                    today: 2001022,
+                    now: 1200,
+                    relation: Relation::Older,
+                    delta: *delta,
+                    second: None,
+                    nonce: None,
+                },
+                private: Private {
+                    birthday: birthday,
+                    private_key: PrivateKey::new(private_key.clone()).unwrap(),
+                    photos_digest: photos_digest.clone(),
+                },
+                allow_unbound_proof: false,
+            };
+            let _ = super::generate_card_key(&Private {
+                birthday: birthday,
+                private_key: PrivateKey::new(private_key.clone()).unwrap(),
+                photos_digest: photos_digest.clone(),
+            });
+            let qr = super::generate_proof(rq).unwrap();
+            // The proof of 30 days is a decoy.
+            let verified = super::verify_proof(&qr, &photos_digest);
+            assert_eq!(verified.is_ok(), *delta == 18);
+        }
+        let lines = captured_log();
+        assert!(lines.iter().any(|l| l.contains("2001022")));
+        // The shared contexts may have been prepared before the logger
+        // was installed, the phases of each proof are always logged.
+        for phase in &["witness computation", "proof generation", "verification"] {
+            let event = format!("{} took", phase);
+            assert!(lines.iter().any(|l| l.contains(&event)), "{}", phase);
+        }
+        for line in lines {
+            assert!(!line.contains(&birthday.to_string()));
+            assert!(!line.contains(&hex::encode(&private_key)));
+            assert!(!line.contains(&format!("{:?}", private_key)));
+            assert!(!line.contains(&photos_digest.to_string()));
+            assert!(!line.contains(&hex::encode(photos_digest.as_bytes())));
+        }
+    }
+
+    pub fn count_deserialization() {
+        DESERIALIZATIONS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn artifacts_are_deserialized_once() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        assert!(super::verify_proof(&p, &photos_digest).is_ok());
+        assert_eq!(DESERIALIZATIONS.load(Ordering::SeqCst), 2);
+        assert!(super::verify_proof(&p, &photos_digest).is_ok());
+        let _ = older_proof(&photos_digest);
+        assert_eq!(DESERIALIZATIONS.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn artifacts_are_shared_between_threads() {
+        fn assert_sync<T: Sync + Send>(_: &T) {}
+        assert_sync(&*SHARED_PROVER);
+        assert_sync(&*SHARED_VERIFIER);
+    }
+
+    #[test]
+    fn verify_concurrently_with_shared_verifier() {
+        let prover = Arc::new(Prover::prepare().unwrap());
+        let verifier = Arc::new(Verifier::new().unwrap());
+        let photos_digest = digest(&[2u8, 7]);
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let prover = prover.clone();
+                let verifier = verifier.clone();
+                let photos_digest = photos_digest.clone();
+                thread::spawn(move || {
+                    let rq = QrRequest {
+                        circuit: EMBEDDED_CIRCUIT,
+                        public: Public {
+                            today: 2020,
+                            now: 1200,
+                            relation: Relation::Older,
+                            delta: 10 + i,
+                            second: None,
+                            nonce: None,
+                        },
+                        private: Private {
+                            birthday: 2001,
+                            private_key: PrivateKey::default(),
+                            photos_digest: photos_digest.clone(),
+                        },
+                        allow_unbound_proof: false,
+                    };
+                    let p = prover.prove(rq).unwrap();
+                    verifier.verify(&p, &photos_digest)
+                })
+            })
+            .collect();
+        for t in threads {
+            assert_eq!(t.join().unwrap(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn verify_proof_from_many_threads() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = Arc::new(older_proof(&photos_digest));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let p = p.clone();
+                let photos_digest = photos_digest.clone();
+                thread::spawn(move || {
+                    (0..20)
+                        .map(|_| super::verify_proof(&p, &photos_digest))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for t in threads {
+            assert_eq!(t.join().unwrap(), vec![Ok(()); 20]);
+        }
+    }
+
+    fn prepared_request(delta: i32, photos_digest: &PhotosDigest) -> QrRequest {
+        QrRequest::build(
+            Public::older_than(delta, 2020, 1200).unwrap(),
+            Private::with(2001, PrivateKey::default(), photos_digest.clone()).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn progress_stages_in_order() {
+        let photos_digest = digest(&[2u8, 7]);
+        let expected = vec![
+            ProofStage::ParsingProgram,
+            ProofStage::ComputingWitness,
+            ProofStage::GeneratingProof,
+            ProofStage::Encoding,
+        ];
+
+        let stages = Mutex::new(vec![]);
+        let p = Prover::embedded()
+            .unwrap()
+            .prove_with_progress(prepared_request(18, &photos_digest), |stage| {
+                stages.lock().unwrap().push(stage)
+            })
+            .unwrap();
+        assert_eq!(*stages.lock().unwrap(), expected);
+        assert_eq!(super::verify_proof(&p, &photos_digest), Ok(()));
+
+        // A band proves two relations but reports each stage once.
+        let stages = Mutex::new(vec![]);
+        let p = generate_proof_with_progress(band_request(2001, 18, 30), |stage| {
+            stages.lock().unwrap().push(stage)
+        })
+        .unwrap();
+        assert_eq!(*stages.lock().unwrap(), expected);
+        assert!(p.second_proof.is_some());
+        assert_eq!(super::verify_proof(&p, &photos_digest), Ok(()));
+    }
+
+    #[test]
+    fn cancelled_before_proving() {
+        let photos_digest = digest(&[2u8, 7]);
+        let cancellation = CancellationToken::new();
+        let stages = Mutex::new(vec![]);
+        let result = generate_proof_cancellable(
+            prepared_request(18, &photos_digest),
+            |stage| {
+                stages.lock().unwrap().push(stage);
+                if stage == ProofStage::GeneratingProof {
+                    cancellation.cancel();
+                }
+            },
+            &cancellation,
+        );
+        assert_eq!(result, Err(ZkError::Cancelled));
+        assert_eq!(
+            *stages.lock().unwrap(),
+            vec![
+                ProofStage::ParsingProgram,
+                ProofStage::ComputingWitness,
+                ProofStage::GeneratingProof,
+            ]
+        );
+
+        // Cancelled before the start, an invalid request is still
+        // reported as such.
+        let result =
+            generate_proof_cancellable(prepared_request(18, &photos_digest), |_| (), &cancellation);
+        assert_eq!(result, Err(ZkError::Cancelled));
+        let mut invalid = prepared_request(18, &photos_digest);
+        invalid.public.delta = -1;
+        assert_eq!(
+            generate_proof_cancellable(invalid, |_| (), &cancellation),
+            Err(ZkError::InvalidInput(ValidationError::Delta))
+        );
+        let p = generate_proof_cancellable(
+            prepared_request(18, &photos_digest),
+            |_| (),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(super::verify_proof(&p, &photos_digest), Ok(()));
+    }
+
+    #[test]
+    fn seeded_proofs_are_identical() {
+        let photos_digest = digest(&[2u8, 7]);
+        let config = ZkConfig {
+            rng_seed: Some([7u8; 32]),
+        };
+        let p1 = Prover::with_config(config.clone())
+            .unwrap()
+            .prove(prepared_request(18, &photos_digest))
+            .unwrap();
+        let p2 = Prover::with_config(config)
+            .unwrap()
+            .prove(prepared_request(18, &photos_digest))
+            .unwrap();
+        assert_eq!(p1.to_bytes().unwrap(), p2.to_bytes().unwrap());
+        assert_eq!(super::verify_proof(&p1, &photos_digest), Ok(()));
+
+        let p3 = Prover::prepare()
+            .unwrap()
+            .prove(prepared_request(18, &photos_digest))
+            .unwrap();
+        assert_ne!(p1.proof, p3.proof);
+    }
+
+    #[test]
+    fn stats_of_last_proof() {
+        let photos_digest = digest(&[2u8, 7]);
+        thread::spawn(move || {
+            assert_eq!(last_proof_stats(), None);
+            super::generate_proof(prepared_request(18, &photos_digest)).unwrap();
+            let stats = last_proof_stats().unwrap();
+            assert!(stats.proving_ms > 0);
+            assert!(stats.total_ms >= stats.proving_ms);
+            assert!(stats.total_ms >= stats.witness_ms);
+            assert!(stats.constraint_count > 0);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn generate_seeded_proof() {
+        let photos_digest = digest(&[2u8, 7]);
+        let seeded = |seed| {
+            super::generate_proof_seeded(prepared_request(18, &photos_digest), seed)
+                .unwrap()
+                .to_string()
+        };
+        let p1 = seeded([3u8; 32]);
+        assert_eq!(p1, seeded([3u8; 32]));
+        let p2 = seeded([4u8; 32]);
+        assert_ne!(p1, p2);
+        for p in &[p1, p2] {
+            let qr = ProofQrCode::from_str(p).unwrap();
+            assert_eq!(super::verify_proof(&qr, &photos_digest), Ok(()));
+        }
+    }
+
+    #[test]
+    fn runtime_artifacts() {
+        let photos_digest = digest(&[2u8, 7]);
+        let prover = Prover::from_artifacts(PROGRAM, ABI, PROVING_KEY).unwrap();
+        let p = prover.prove(prepared_request(18, &photos_digest)).unwrap();
+
+        let vk_json = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/zokrates/verification.key"
+        ))
+        .unwrap();
+        let verifier = Verifier::from_verification_key(&vk_json).unwrap();
+        assert_eq!(verifier.verify(&p, &photos_digest), Ok(()));
+        let p = Prover::embedded()
+            .unwrap()
+            .prove(prepared_request(18, &photos_digest))
+            .unwrap();
+        assert_eq!(verifier.verify(&p, &photos_digest), Ok(()));
+    }
+
+    /// Writes the embedded artifacts to files of a new directory.
+    fn artifact_files(name: &str) -> [std::path::PathBuf; 4] {
+        let dir = std::env::temp_dir().join(format!("legalage-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [
+            dir.join("out"),
+            dir.join("abi.json"),
+            dir.join("proving.key"),
+            dir.join("verification.key"),
+        ];
+        for (file, bytes) in files
+            .iter()
+            .zip(&[PROGRAM, ABI, PROVING_KEY, VERIFICATION_KEY])
+        {
+            std::fs::write(file, bytes).unwrap();
+        }
+        files
+    }
+
+    #[test]
+    fn context_from_paths() {
+        let photos_digest = digest(&[2u8, 7]);
+        let [program, abi, proving_key, verification_key] = artifact_files("context");
+        let context =
+            ZkContext::from_paths(&program, &abi, &proving_key, &verification_key).unwrap();
+        let p = context
+            .generate_proof(prepared_request(18, &photos_digest))
+            .unwrap();
+        assert_eq!(context.verify_proof(&p, &photos_digest), Ok(()));
+        assert_eq!(super::verify_proof(&p, &photos_digest), Ok(()));
+        let embedded = ZkContext::embedded().unwrap();
+        assert_eq!(embedded.verify_proof(&p, &photos_digest), Ok(()));
+
+        context.register(8).unwrap();
+        let mut rq = prepared_request(18, &photos_digest);
+        rq.circuit = 8;
+        let p = super::generate_proof(rq).unwrap();
+        assert_eq!(super::verify_proof(&p, &photos_digest), Ok(()));
+        assert!(ZkContext::embedded()
+            .unwrap()
+            .register(EMBEDDED_CIRCUIT)
+            .is_err());
+        std::fs::remove_dir_all(program.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn context_from_broken_paths() {
+        let [program, abi, proving_key, verification_key] = artifact_files("broken");
+        let load = || ZkContext::from_paths(&program, &abi, &proving_key, &verification_key);
+        std::fs::write(&proving_key, &PROVING_KEY[..PROVING_KEY.len() / 2]).unwrap();
+        assert_eq!(load().err(), Some(ZkError::KeyDeserialization));
+        std::fs::write(&abi, b"{").unwrap();
+        assert_eq!(load().err(), Some(ZkError::KeyDeserialization));
+        std::fs::write(&program, &PROGRAM[..16]).unwrap();
+        assert_eq!(load().err(), Some(ZkError::KeyDeserialization));
+        std::fs::remove_file(&program).unwrap();
+        match load() {
+            Err(ZkError::InvalidArtifact(message)) => assert!(message.contains("out")),
+            _ => panic!("missing program is refused"),
+        }
+        std::fs::remove_dir_all(abi.parent().unwrap()).unwrap();
+    }
+
+    /// Proving and verification key of a second trusted setup of the
+    /// embedded program.
+    static SECOND_SETUP: Lazy<(Vec<u8>, Vec<u8>)> = Lazy::new(|| {
+        let program = Prover::embedded().unwrap().program.clone();
+        let keypair = <G16 as ProofSystem<Bn128Field>>::setup(program);
+        (keypair.pk, serde_json::to_vec(&keypair.vk).unwrap())
+    });
+
+    #[test]
+    fn self_test_of_embedded_artifacts() {
+        let report = super::self_test().unwrap();
+        assert_eq!(report.program_sha256, Some(sha256_hex(PROGRAM)));
+        assert_eq!(report.proving_key_sha256, Some(sha256_hex(PROVING_KEY)));
+        assert_eq!(report.verification_key_sha256.map(|h| h.len()), Some(64));
+        let json = serde_json::to_string(&super::self_test().unwrap()).unwrap();
+        assert!(json.contains("\"prove_ms\""));
+    }
+
+    #[test]
+    fn circuit_info_of_embedded_artifacts() {
+        let info = circuit_info().unwrap();
+        let inputs: Vec<(&str, &str, bool)> = info
+            .abi_inputs
+            .iter()
+            .map(|(name, ty, private)| (name.as_str(), ty.as_str(), *private))
+            .collect();
+        // The photo digest is called photo_hash in the circuit.
+        assert_eq!(
+            inputs,
+            vec![
+                ("birthday", "field", true),
+                ("delta", "field", false),
+                ("today", "field", false),
+                ("younger", "field", false),
+                ("photo_hash", "field", true),
+                ("private_key", "field", true),
+            ]
+        );
+        assert_eq!(
+            inputs.iter().filter(|input| !input.2).count() + 1,
+            PUBLIC_INPUTS
+        );
+        for digest in &[
+            &info.program_sha256,
+            &info.proving_key_sha256,
+            &info.verification_key_sha256,
+        ] {
+            assert_eq!(digest.len(), 64);
+            assert!(digest.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+        assert_eq!(circuit_info(), Ok(info.clone()));
+        let report = super::self_test().unwrap();
+        assert_eq!(report.program_sha256, Some(info.program_sha256.clone()));
+        assert_eq!(info.curve, "bn128");
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(
+            json["abi_inputs"][0],
+            serde_json::json!(["birthday", "field", true])
+        );
+    }
+
+    #[test]
+    fn self_test_with_mismatched_key() {
+        let prover = Prover::embedded().unwrap();
+        let mismatched = Verifier::from_verification_key(&SECOND_SETUP.1).unwrap();
+        match self_test_with(&prover, &mismatched) {
+            Err(ZkError::CorruptInstallation(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let report = self_test_with(&prover, &Verifier::embedded().unwrap()).unwrap();
+        assert_eq!(report.program_sha256, None);
+    }
+
+    fn rotated_verifier(old: KeyValidity, new: KeyValidity) -> Verifier {
+        let mut verifier = Verifier::without_keys();
+        verifier.add_key(1, VERIFICATION_KEY, old).unwrap();
+        verifier.add_key(2, &SECOND_SETUP.1, new).unwrap();
+        verifier
+    }
+
+    #[test]
+    fn key_rotation() {
+        let photos_digest = digest(&[2u8, 7]);
+        let old = Prover::embedded()
+            .unwrap()
+            .prove(prepared_request(18, &photos_digest))
+            .unwrap();
+        let new = Prover::from_artifacts(PROGRAM, ABI, &SECOND_SETUP.0)
+            .unwrap()
+            .with_key_id(2)
+            .prove(prepared_request(18, &photos_digest))
+            .unwrap();
+        assert_eq!(old.key_id, Some(1));
+        assert_eq!(new.key_id, Some(2));
+        let new = ProofQrCode::from_str(&new.to_string()).unwrap();
+        assert_eq!(new.key_id, Some(2));
+
+        let both = rotated_verifier(KeyValidity::always(), KeyValidity::always());
+        assert_eq!(both.verify(&old, &photos_digest), Ok(()));
+        assert_eq!(both.verify(&new, &photos_digest), Ok(()));
+
+        // Each proof verifies only against its own key.
+        let mut swapped = old.clone();
+        swapped.key_id = Some(2);
+        assert_eq!(
+            both.verify(&swapped, &photos_digest),
+            Err(ZkError::VerificationFailed)
+        );
+        let mut swapped = new.clone();
+        swapped.key_id = Some(1);
+        assert_eq!(
+            both.verify(&swapped, &photos_digest),
+            Err(ZkError::VerificationFailed)
+        );
+
+        let embedded = Verifier::embedded().unwrap();
+        assert_eq!(
+            embedded.verify(&new, &photos_digest),
+            Err(ZkError::UnknownKey(2))
+        );
+        assert_eq!(
+            Verifier::without_keys().verify(&old, &photos_digest),
+            Err(ZkError::UnknownKey(1))
+        );
+        assert_eq!(
+            super::verify_proof(&new, &photos_digest),
+            Err(ZkError::UnknownKey(2))
+        );
+    }
+
+    #[test]
+    fn expired_keys() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = prepared_request(18, &photos_digest);
+        let today = p.public.today;
+        let p = Prover::embedded().unwrap().prove(p).unwrap();
+        for (validity, expected) in &[
+            (KeyValidity::always(), Ok(())),
+            (
+                KeyValidity {
+                    valid_from: Some(today),
+                    valid_to: Some(today),
+                },
+                Ok(()),
+            ),
+            (
+                KeyValidity {
+                    valid_from: None,
+                    valid_to: Some(today - 1),
+                },
+                Err(ZkError::KeyExpired),
+            ),
+            (
+                KeyValidity {
+                    valid_from: Some(today + 1),
+                    valid_to: None,
+                },
+                Err(ZkError::KeyExpired),
+            ),
+        ] {
+            let verifier = rotated_verifier(*validity, KeyValidity::always());
+            assert_eq!(verifier.verify(&p, &photos_digest), *expected);
+        }
+    }
+
+    #[test]
+    fn legacy_codes_try_active_keys() {
+        let photos_digest = digest(&[2u8, 7]);
+        let new = Prover::from_artifacts(PROGRAM, ABI, &SECOND_SETUP.0)
+            .unwrap()
+            .with_key_id(2)
+            .prove(prepared_request(18, &photos_digest))
+            .unwrap();
+        let legacy =
+            ProofQrCode::from_str(&with_version(&new, &photos_digest, 0).to_legacy_string())
+                .unwrap();
+        assert_eq!(legacy.key_id, None);
+        let today = legacy.public.today;
+        let expired = KeyValidity {
+            valid_from: None,
+            valid_to: Some(today - 1),
+        };
+
+        let both = rotated_verifier(KeyValidity::always(), KeyValidity::always());
+        assert_eq!(both.verify(&legacy, &photos_digest), Ok(()));
+        let verifier = rotated_verifier(KeyValidity::always(), expired);
+        assert_eq!(
+            verifier.verify(&legacy, &photos_digest),
+            Err(ZkError::VerificationFailed)
+        );
+        let verifier = rotated_verifier(expired, expired);
+        assert_eq!(
+            verifier.verify(&legacy, &photos_digest),
+            Err(ZkError::KeyExpired)
+        );
+        assert_eq!(
+            Verifier::embedded()
+                .unwrap()
+                .verify(&legacy, &photos_digest),
+            Err(ZkError::VerificationFailed)
+        );
+    }
+
+    #[test]
+    fn duplicate_key_id_is_rejected() {
+        let mut verifier = Verifier::embedded().unwrap();
+        match verifier.add_key(EMBEDDED_KEY, VERIFICATION_KEY, KeyValidity::always()) {
+            Err(ZkError::InvalidArtifact(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn registered_circuit() {
+        let photos_digest = digest(&[2u8, 7]);
+        register_prover(
+            2,
+            Prover::from_artifacts(PROGRAM, ABI, PROVING_KEY).unwrap(),
+        )
+        .unwrap();
+        register_verifier(
+            2,
+            Verifier::from_verification_key(VERIFICATION_KEY).unwrap(),
+        )
+        .unwrap();
+
+        let mut rq = prepared_request(18, &photos_digest);
+        rq.circuit = 2;
+        let p = generate_proof(rq).unwrap();
+        assert_eq!(p.circuit, 2);
+        let p = ProofQrCode::from_str(&p.to_string()).unwrap();
+        assert_eq!(p.circuit, 2);
+        // Both circuits share the keys, only the dispatch differs.
+        assert_eq!(verify_proof(&p, &photos_digest), Ok(()));
+
+        let mut unknown = p.clone();
+        unknown.circuit = 9;
+        assert_eq!(
+            verify_proof(&unknown, &photos_digest),
+            Err(ZkError::UnsupportedCircuit(9))
+        );
+        let mut rq = prepared_request(18, &photos_digest);
+        rq.circuit = 9;
+        assert_eq!(
+            generate_proof(rq).err(),
+            Some(ZkError::UnsupportedCircuit(9))
+        );
+    }
+
+    #[test]
+    fn embedded_circuit_cannot_be_replaced() {
+        let verifier = Verifier::from_verification_key(VERIFICATION_KEY).unwrap();
+        match register_verifier(EMBEDDED_CIRCUIT, verifier) {
+            Err(ZkError::InvalidArtifact(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut registry = CircuitRegistry::new();
+        assert_eq!(
+            registry.verifier(2).err(),
+            Some(ZkError::UnsupportedCircuit(2))
+        );
+        let verifier = Verifier::from_verification_key(VERIFICATION_KEY).unwrap();
+        registry.register_verifier(2, verifier).unwrap();
+        assert!(registry.verifier(2).is_ok());
+        assert!(registry.prover(2).is_err());
+    }
+
+    #[test]
+    fn truncated_keys_are_rejected() {
+        let half = VERIFICATION_KEY.len() / 2;
+        assert_eq!(
+            Verifier::from_verification_key(&VERIFICATION_KEY[..half]).err(),
+            Some(ZkError::KeyDeserialization)
+        );
+        let half = PROVING_KEY.len() / 2;
+        assert_eq!(
+            Prover::from_artifacts(PROGRAM, ABI, &PROVING_KEY[..half]).err(),
+            Some(ZkError::KeyDeserialization)
+        );
+        assert_eq!(
+            Prover::from_artifacts(&PROGRAM[..10], ABI, PROVING_KEY).err(),
+            Some(ZkError::KeyDeserialization)
+        );
+    }
+
+    #[test]
+    fn key_of_other_circuit_is_rejected() {
+        let mut vk: serde_json::Value = serde_json::from_slice(VERIFICATION_KEY).unwrap();
+        vk["gamma_abc"].as_array_mut().unwrap().pop();
+        let vk_json = serde_json::to_vec(&vk).unwrap();
+        match Verifier::from_verification_key(&vk_json) {
+            Err(ZkError::InvalidArtifact(_)) => {}
+            _ => panic!("key with a missing input accepted"),
+        }
+
+        let mut abi: serde_json::Value = serde_json::from_slice(ABI).unwrap();
+        abi["inputs"][0]["public"] = serde_json::Value::Bool(true);
+        let abi_json = serde_json::to_vec(&abi).unwrap();
+        match Prover::from_artifacts(PROGRAM, &abi_json, PROVING_KEY) {
+            Err(ZkError::InvalidArtifact(_)) => {}
+            _ => panic!("key of a different ABI accepted"),
+        }
+    }
+
+    #[test]
+    fn seeded_private_key() {
+        let seed = [1u32, 2, 3, 4];
+        assert_eq!(
+            super::generate_random_private_key_with_seed(seed),
+            super::generate_random_private_key_with_seed(seed)
+        );
+        assert_ne!(
+            super::generate_random_private_key_with_seed(seed),
+            super::generate_random_private_key_with_seed([4u32, 3, 2, 1])
+        );
+    }
+
+    #[test]
+    fn prepared_prover_proofs_verify() {
+        let photos_digest = digest(&[2u8, 7]);
+        let prover = Prover::prepare().unwrap();
+        for delta in &[10, 18] {
+            let p = prover
+                .prove(prepared_request(*delta, &photos_digest))
+                .unwrap();
+            assert_eq!(super::verify_proof(&p, &photos_digest), Ok(()));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_prepared_prover() {
+        // Run with `cargo test --release -- --ignored --nocapture`.
+        let photos_digest = digest(&[2u8, 7]);
+
+        let start = Instant::now();
+        let prover = Prover::prepare().unwrap();
+        prover.prove(prepared_request(18, &photos_digest)).unwrap();
+        let cold = start.elapsed();
+
+        let start = Instant::now();
+        prover.prove(prepared_request(18, &photos_digest)).unwrap();
+        let prepared = start.elapsed();
+
+        println!("cold: {:?}, prepared: {:?}", cold, prepared);
+        assert!(prepared < cold);
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_decoy_timing() {
+        // Run with `cargo test --release -- --ignored --nocapture`.
+        // A decoy proof of a relation which does not hold is generated
+        // by the same steps as a valid one, the mean times must differ
+        // by noise only.
+        let photos_digest = digest(&[2u8, 7]);
+        let prover = Prover::prepare().unwrap();
+        prover.prove(prepared_request(18, &photos_digest)).unwrap();
+
+        let rounds = 10;
+        let mut valid = Duration::default();
+        let mut decoy = Duration::default();
+        // Interleaved so that a slow phase hits both.
+        for _ in 0..rounds {
+            let start = Instant::now();
+            prover.prove(prepared_request(18, &photos_digest)).unwrap();
+            valid += start.elapsed();
+            let start = Instant::now();
+            prover.prove(prepared_request(30, &photos_digest)).unwrap();
+            decoy += start.elapsed();
+        }
+        let valid = valid / rounds;
+        let decoy = decoy / rounds;
+        println!("valid: {:?}, decoy: {:?}", valid, decoy);
+        let difference = if valid > decoy {
+            valid - decoy
+        } else {
+            decoy - valid
+        };
+        assert!(difference < valid / 10);
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_shared_verifier() {
+        // Run with `cargo test --release -- --ignored --nocapture`.
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        let n = 20;
+
+        let start = Instant::now();
+        for _ in 0..n {
+            Verifier::new().unwrap().verify(&p, &photos_digest).unwrap();
+        }
+        let per_call = start.elapsed() / n;
+
+        let verifier = Verifier::new().unwrap();
+        let start = Instant::now();
+        for _ in 0..n {
+            verifier.verify(&p, &photos_digest).unwrap();
+        }
+        let shared = start.elapsed() / n;
+
+        println!(
+            "per-call setup: {:?}, shared verifier: {:?}",
+            per_call, shared
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_batch_verification() {
+        // Run with `cargo test --release -- --ignored --nocapture`.
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        let items: Vec<_> = (0..40)
+            .map(|_| (p.clone(), photos_digest.clone()))
+            .collect();
+        let verifier = Verifier::new().unwrap();
+
+        let start = Instant::now();
+        for (qr, photo_digest) in &items {
+            verifier.verify(qr, photo_digest).unwrap();
+        }
+        let sequential = start.elapsed();
+
+        let start = Instant::now();
+        let results = verifier.verify_batch(&items);
+        let batch = start.elapsed();
+        assert!(results.iter().all(|r| r.is_ok()));
+
+        println!("sequential: {:?}, batch: {:?}", sequential, batch);
+    }
+
+    #[test]
+    fn verify_batch_results_are_independent() {
+        let photos_digest = digest(&[2u8, 7]);
+        let valid = older_proof(&photos_digest);
+        let mut tampered = valid.clone();
+        tampered.public.delta = 17;
+        let mut garbage = valid.clone();
+        garbage.proof = vec![1; garbage.proof.len()];
+
+        let items = vec![
+            (valid.clone(), photos_digest.clone()),
+            (tampered, photos_digest.clone()),
+            (garbage, photos_digest.clone()),
+            (valid.clone(), digest(&[3u8, 1, 4])),
+            (valid, photos_digest),
+        ];
+        let results = Verifier::new().unwrap().verify_batch(&items);
+        assert_eq!(results.len(), items.len());
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(results[1], Err(ZkError::VerificationFailed));
+        // Garbage may or may not decode to curve points.
+        assert!(results[2].is_err());
+        assert!(results[3].is_err());
+        assert_eq!(results[4], Ok(()));
+        assert!(Verifier::new().unwrap().verify_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn generate_random_private_key() {
+        let key = super::generate_random_private_key();
+        println!("{:?}", key);
+        assert_eq!(32, key.as_bytes().len());
+    }
+
+    fn digest(bytes: &[u8]) -> PhotosDigest {
+        PhotosDigest::new(bytes.to_vec()).unwrap()
+    }
+
+    fn bn128(s: &str) -> Bn128Field {
+        Bn128Field::try_from_dec_str(s).unwrap()
+    }
+
+    #[test]
+    fn generate_card_key() {
+        let m1 =
+            bn128("2398929016733331352351677352383442125702690766615627729516078292095018104789");
+        assert_eq!(compute_mimc7r10_hash(&bn128("3"), &bn128("20010")), m1);
+
+        let private = Private {
+            birthday: 2001,
+            private_key: PrivateKey::new(bn128("10").into_byte_vector()).unwrap(),
+            photos_digest: PhotosDigest::new(bn128("3").into_byte_vector()).unwrap(),
+        };
+        let key = super::generate_card_key(&private).unwrap();
+        assert_eq!(32, key.as_bytes().len());
+
+        assert_eq!(Bn128Field::from_byte_vector(key.into()), bn128("3") * m1);
+    }
+
+    #[test]
+    fn generate_card_key_refuses_zero_values() {
+        let photos_digest = digest(&[2u8, 7]);
+        // The empty key of many tests is the zero element.
+        let private = Private::with(2001, PrivateKey::default(), photos_digest.clone()).unwrap();
+        assert_eq!(
+            super::generate_card_key(&private),
+            Err(ValidationError::PrivateKey)
+        );
+        let zero = PrivateKey::new(vec![0; FIELD_LENGTH]).unwrap();
+        let private = Private::with(2001, zero, photos_digest).unwrap();
+        assert_eq!(
+            super::generate_card_key(&private),
+            Err(ValidationError::PrivateKey)
+        );
+        let private = Private::with(
+            2001,
+            PrivateKey::new(vec![10]).unwrap(),
+            PhotosDigest::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            super::generate_card_key(&private),
+            Err(ValidationError::PhotosDigest)
+        );
+    }
+
+    #[test]
+    fn compute_challenge() {
+        let m1 =
+            bn128("20806133116655125295815844821187893628062572117889123030572462808546913026234");
+        assert_eq!(compute_mimc7r10_hash(&bn128("2020"), &bn128("27")), m1);
+
+        let card_key = CardKey::new(bn128("27").into_byte_vector()).unwrap();
+        let today = 2020;
+        let challenge = super::compute_challenge(&card_key, today);
+        assert_eq!(32, challenge.as_bytes().len());
+
+        assert_eq!(Bn128Field::from_byte_vector(challenge.into()), m1);
+    }
+
+    #[test]
+    fn challenge_bound_to_nonce() {
+        let card_key = CardKey::new(bn128("27").into_byte_vector()).unwrap();
+        let challenge = super::compute_challenge(&card_key, 2020);
+        let nonce = Nonce::new(vec![3; NONCE_LENGTH]).unwrap();
+        let bound = bind_nonce(&challenge, &nonce);
+        assert_eq!(
+            bound.to_field(),
+            compute_mimc7r10_hash(&challenge.to_field(), &nonce.to_field())
+        );
+        assert_ne!(bound.to_field(), challenge.to_field());
+        let other = Nonce::new(vec![4; NONCE_LENGTH]).unwrap();
+        assert_ne!(bind_nonce(&challenge, &other).to_field(), bound.to_field());
+        let tomorrow = super::compute_challenge(&card_key, 2021);
+        assert_ne!(bind_nonce(&tomorrow, &nonce).to_field(), bound.to_field());
+        assert_eq!(
+            compute_challenge_with_nonce(&card_key, 2020, Some(&nonce)),
+            bound
+        );
+        assert_eq!(
+            compute_challenge_with_nonce(&card_key, 2020, None),
+            challenge
+        );
+    }
+
+    #[test]
+    fn verify_older() {
+        let photos_digest = digest(&[2u8, 7]);
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: 2001,
+                private_key: PrivateKey::default(),
+                photos_digest: photos_digest.clone(),
+            },
+            allow_unbound_proof: false,
+        };
+        let p = super::generate_proof(rq).unwrap();
+        assert!(super::verify_proof(&p, &photos_digest).is_ok());
+        let ps = p.to_string();
+        assert!(super::verify_proof(&ProofQrCode::from_str(&ps).unwrap(), &photos_digest).is_ok());
+    }
+
+    #[test]
+    fn verify_after_json_round_trip() {
+        let photos_digest = digest(&[3u8, 1, 4]);
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: 2001,
+                private_key: PrivateKey::default(),
+                photos_digest: photos_digest.clone(),
+            },
+            allow_unbound_proof: false,
+        };
+        let p = super::generate_proof(rq).unwrap();
+        let json = serde_json::to_string(&p).unwrap();
+        let parsed: ProofQrCode = serde_json::from_str(&json).unwrap();
+        assert!(super::verify_proof(&parsed, &photos_digest).is_ok());
+    }
+
+    #[test]
+    fn hidden_proof_string_round_trip() {
+        // The QR code carries the hidden bytes, not the proof points.
+        let photos_digest = digest(&[2u8, 7, 1, 8, 2, 8]);
+        let p = older_proof(&photos_digest);
+        let mask = hiding_mask(p.version, &p.public, &p.challenge, &photos_digest);
+        let mut plain = vec![];
+        unhide_bellman_proof(&p.proof, p.version, &mask, 0)
+            .unwrap()
+            .write(&mut plain)
+            .unwrap();
+        assert_ne!(plain, p.proof);
+        assert_eq!(
+            ProofQrCode::proof_from_str(&p.proof_to_string()),
+            Ok(p.proof.clone())
+        );
+        let parsed = ProofQrCode::from_str(&p.to_string()).unwrap();
+        assert_eq!(parsed, p);
+        assert!(super::verify_proof(&parsed, &photos_digest).is_ok());
+    }
+
+    #[test]
+    fn decoded_request_gives_same_challenge() {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: 2001,
+                private_key: PrivateKey::new(bn128("10").into_byte_vector()).unwrap(),
+                photos_digest: digest(&[2u8, 7]),
+            },
+            allow_unbound_proof: false,
+        };
+        let decoded = QrRequest::from_qr_code_string(&rq.to_qr_code_string()).unwrap();
+        let p1 = super::generate_proof(rq).unwrap();
+        let p2 = super::generate_proof(decoded).unwrap();
+        assert_eq!(p1.challenge, p2.challenge);
+    }
+
+    fn older_proof(photos_digest: &PhotosDigest) -> ProofQrCode {
+        super::generate_proof(prepared_request(18, photos_digest)).unwrap()
+    }
+
+    #[test]
+    fn proof_bound_to_nonce() {
+        let photos_digest = digest(&[2u8, 7]);
+        let nonce = Nonce::new(vec![3; NONCE_LENGTH]).unwrap();
+        let mut rq = prepared_request(18, &photos_digest);
+        rq.public.nonce = Some(nonce.clone());
+        let p = super::generate_proof(rq).unwrap();
+        let p = ProofQrCode::from_str(&p.to_string()).unwrap();
+        assert_eq!(
+            verify_proof_with_nonce(&p, &photos_digest, &nonce, false),
+            Ok(())
+        );
+
+        let other = Nonce::new(vec![4; NONCE_LENGTH]).unwrap();
+        assert_eq!(
+            verify_proof_with_nonce(&p, &photos_digest, &other, false),
+            Err(ZkError::NonceMismatch)
+        );
+        // Replacing the nonce in the QR code does not help.
+        let mut replaced = p.clone();
+        replaced.public.nonce = Some(other.clone());
+        assert!(verify_proof_with_nonce(&replaced, &photos_digest, &other, false).is_err());
+    }
+
+    #[test]
+    fn resealed_proof_verifies_for_other_nonce() {
+        // The nonce is not a public input of the circuit, whoever knows
+        // the photo digest can seal the proof again for another nonce.
+        let photos_digest = digest(&[2u8, 7]);
+        let nonce = Nonce::new(vec![3; NONCE_LENGTH]).unwrap();
+        let mut rq = prepared_request(18, &photos_digest);
+        rq.public.nonce = Some(nonce);
+        let p = super::generate_proof(rq).unwrap();
+        let mask = hiding_mask(p.version, &p.public, &p.challenge, &photos_digest);
+        let proof = unhide_bellman_proof(&p.proof, p.version, &mask, 0).unwrap();
+
+        let other = Nonce::new(vec![4; NONCE_LENGTH]).unwrap();
+        let mut resealed = p.clone();
+        resealed.public.nonce = Some(other.clone());
+        let mask = hiding_mask(p.version, &resealed.public, &p.challenge, &photos_digest);
+        resealed.proof = hide_bellman_proof(&proof, p.version, &mask, 0);
+        assert_eq!(
+            verify_proof_with_nonce(&resealed, &photos_digest, &other, false),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn absent_nonce_keeps_photo_digest_mask() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        assert_eq!(p.public.nonce, None);
+        assert_eq!(
+            hiding_mask(3, &p.public, &p.challenge, &photos_digest),
+            vec![2u8, 7]
+        );
+        assert_eq!(super::verify_proof(&p, &photos_digest), Ok(()));
+        assert_eq!(
+            verify_proof_with_nonce(&p, &photos_digest, &generate_nonce(), false),
+            Err(ZkError::NonceMismatch)
+        );
+    }
+
+    #[test]
+    fn verify_wrong_photo_digest() {
+        let photos_digest = digest(&[2u8, 7, 1, 8, 2, 8]);
+        let p = older_proof(&photos_digest);
+        for wrong in &[&[3u8, 1, 4, 1, 5, 9][..], &[2u8, 7, 1, 8, 2, 9], &[]] {
+            assert!(super::verify_proof(&p, &digest(wrong)).is_err());
+        }
+        // The bytes unhidden with a wrong digest are rarely curve
+        // points. With every mask the verifier fails without a panic,
+        // which would bring the app down through JNI.
+        for version in &[0u8, 2, 3, STREAM_MASK_VERSION, QR_FORMAT_VERSION] {
+            let qr = with_version(&p, &photos_digest, *version);
+            let qr = ProofQrCode::from_str(&qr.to_string()).unwrap();
+            for i in 0..16u8 {
+                let wrong = digest(&[i, 7, 1, 8, 2]);
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    super::verify_proof(&qr, &wrong)
+                }));
+                assert!(result.expect("verification panicked").is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn verify_tampered_string() {
+        let photos_digest = digest(&[2u8, 7, 1, 8, 2, 8]);
+        let p = older_proof(&photos_digest);
+        let legacy = with_version(&p, &photos_digest, 0);
+        let parts: Vec<String> = legacy
+            .to_legacy_string()
+            .split(";")
+            .map(String::from)
+            .collect();
+        let mut proof = ProofQrCode::proof_from_str(&parts[1]).unwrap();
+        for i in 0..proof.len() {
+            proof[i] ^= 0x5a;
+        }
+        let tampered = format!(
+            "{};{};{}",
+            parts[0],
+            bs58::encode(&proof).into_string(),
+            parts[2]
+        );
+        let qr = ProofQrCode::from_str(&tampered).unwrap();
+        assert!(super::verify_proof(&qr, &photos_digest).is_err());
+
+        let mut tampered = p.clone();
+        for b in tampered.proof.iter_mut() {
+            *b ^= 0x5a;
+        }
+        let qr = ProofQrCode::from_str(&tampered.to_string()).unwrap();
+        assert!(super::verify_proof(&qr, &photos_digest).is_err());
+    }
+
+    #[test]
+    fn flipped_byte_of_stream_masked_proof_is_refused() {
+        // Without a tag a flipped bit of the keystream masked proof
+        // flips the same bit of the points.
+        let photos_digest = digest(&[2u8, 7]);
+        let p = with_version(
+            &older_proof(&photos_digest),
+            &photos_digest,
+            STREAM_MASK_VERSION,
+        );
+        let verifier = Verifier::new().unwrap();
+        for i in 0..PROOF_LENGTH {
+            let mut tampered = p.clone();
+            tampered.proof[i] ^= 1 << (i % 8);
+            assert!(verifier.verify(&tampered, &photos_digest).is_err(), "{}", i);
+        }
+        assert_eq!(verifier.verify(&p, &photos_digest), Ok(()));
+    }
+
+    #[test]
+    fn flipped_byte_of_sealed_proof_is_refused_by_tag() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = band_proof(2001, 18, 30);
+        assert_eq!(p.version, QR_FORMAT_VERSION);
+        let verifier = Verifier::new().unwrap();
+        for i in 0..SEALED_PROOF_LENGTH {
+            let mut tampered = p.clone();
+            tampered.proof[i] ^= 1 << (i % 8);
+            assert_eq!(
+                verifier.verify(&tampered, &photos_digest),
+                Err(ZkError::TagMismatch),
+                "{}",
+                i
+            );
+            let mut tampered = p.clone();
+            tampered.second_proof.as_mut().unwrap()[i] ^= 1 << (i % 8);
+            assert_eq!(
+                verifier.verify(&tampered, &photos_digest),
+                Err(ZkError::TagMismatch),
+                "{}",
+                i
+            );
+        }
+        // Each proof of a band has its own key, swapped proofs do not
+        // open.
+        let mut swapped = p.clone();
+        swapped.proof = p.second_proof.clone().unwrap();
+        swapped.second_proof = Some(p.proof.clone());
+        assert_eq!(
+            verifier.verify(&swapped, &photos_digest),
+            Err(ZkError::TagMismatch)
+        );
+        assert_eq!(verifier.verify(&p, &photos_digest), Ok(()));
+    }
+
+    #[test]
+    fn sealed_proof_round_trip() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        assert_eq!(p.version, QR_FORMAT_VERSION);
+        assert_eq!(p.proof.len(), SEALED_PROOF_LENGTH);
+        let keys = hiding_mask(p.version, &p.public, &p.challenge, &photos_digest);
+        assert_eq!(keys.len(), 2 * SEAL_KEY_LENGTH);
+        let proof = unhide_bellman_proof(&p.proof, p.version, &keys, 0).unwrap();
+        // Sealing is deterministic, the nonce depends on the proof.
+        assert_eq!(hide_bellman_proof(&proof, p.version, &keys, 0), p.proof);
+        assert_ne!(hide_bellman_proof(&proof, p.version, &keys, 1), p.proof);
+        assert_eq!(
+            unhide_bellman_proof(&p.proof, p.version, &keys, 1).err(),
+            Some(QrError::ProofTag)
+        );
+        let decoded = ProofQrCode::from_str(&p.to_string()).unwrap();
+        assert_eq!(super::verify_proof(&decoded, &photos_digest), Ok(()));
+        assert_eq!(
+            super::verify_proof(&decoded, &digest(&[2u8, 8])),
+            Err(ZkError::TagMismatch)
+        );
+    }
+
+    #[test]
+    fn stream_mask_round_trip() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = with_version(
+            &older_proof(&photos_digest),
+            &photos_digest,
+            STREAM_MASK_VERSION,
+        );
+        assert_eq!(p.version, STREAM_MASK_VERSION);
+        let mask = hiding_mask(p.version, &p.public, &p.challenge, &photos_digest);
+        assert_eq!(mask.len(), 2 * PROOF_LENGTH);
+        let proof = unhide_bellman_proof(&p.proof, p.version, &mask, 0).unwrap();
+        assert_eq!(hide_bellman_proof(&proof, p.version, &mask, 0), p.proof);
+        assert_ne!(hide_bellman_proof(&proof, p.version, &mask, 1), p.proof);
+        let decoded = ProofQrCode::from_str(&p.to_string()).unwrap();
+        assert_eq!(super::verify_proof(&decoded, &photos_digest), Ok(()));
+    }
+
+    #[test]
+    fn proof_points_are_compressed() {
+        // All format versions write the compressed points, half of the
+        // uncompressed a, b and c.
+        let photos_digest = digest(&[2u8, 7]);
+        let p = with_version(
+            &older_proof(&photos_digest),
+            &photos_digest,
+            STREAM_MASK_VERSION,
+        );
+        let mask = hiding_mask(p.version, &p.public, &p.challenge, &photos_digest);
+        let proof = unhide_bellman_proof(&p.proof, p.version, &mask, 0).unwrap();
+        let uncompressed = proof.a.into_uncompressed().as_ref().len()
+            + proof.b.into_uncompressed().as_ref().len()
+            + proof.c.into_uncompressed().as_ref().len();
+        assert_eq!(uncompressed, 256);
+        assert_eq!(p.proof.len(), PROOF_LENGTH);
+        assert_eq!(uncompressed - PROOF_LENGTH, 128);
+        let mut written = vec![];
+        proof.write(&mut written).unwrap();
+        assert_eq!(written.len(), PROOF_LENGTH);
+        assert!(BellmanProof::<Bn256>::read(&written[..]).unwrap() == proof);
+    }
+
+    #[test]
+    fn old_mask_versions_verify() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        for version in &[0u8, 2, 3, STREAM_MASK_VERSION] {
+            let old = with_version(&p, &photos_digest, *version);
+            assert_ne!(old.proof, p.proof);
+            let decoded = ProofQrCode::from_str(&old.to_string()).unwrap();
+            assert_eq!(decoded.version, *version);
+            assert_eq!(super::verify_proof(&decoded, &photos_digest), Ok(()));
+        }
+        let band = band_proof(2001, 18, 30);
+        assert_eq!(super::verify_proof(&band, &photos_digest), Ok(()));
+        for version in &[3, STREAM_MASK_VERSION] {
+            let old = with_version(&band, &photos_digest, *version);
+            let decoded = ProofQrCode::from_str(&old.to_string()).unwrap();
+            assert_eq!(super::verify_proof(&decoded, &photos_digest), Ok(()));
+        }
+    }
+
+    /// Length of the longest run of equal bytes at the same positions.
+    fn longest_common_run(a: &[u8], b: &[u8]) -> usize {
+        let (mut longest, mut run) = (0, 0);
+        for (x, y) in a.iter().zip(b) {
+            run = if x == y { run + 1 } else { 0 };
+            longest = longest.max(run);
+        }
+        longest
+    }
+
+    #[test]
+    fn stream_masks_share_no_pattern() {
+        let photos_digest = digest(&[2u8, 7, 1, 8]);
+        let masks: Vec<Vec<u8>> = [2459000, 2459001]
+            .iter()
+            .map(|today| {
+                let public = Public::older_than(18, *today, 1200).unwrap();
+                let private =
+                    Private::with(2415021, PrivateKey::default(), photos_digest.clone()).unwrap();
+                let qr = generate_proof(QrRequest::build(public, private).unwrap()).unwrap();
+                let qr = with_version(&qr, &photos_digest, STREAM_MASK_VERSION);
+                let proof = unhide_bellman_proof(
+                    &qr.proof,
+                    qr.version,
+                    &hiding_mask(qr.version, &qr.public, &qr.challenge, &photos_digest),
+                    0,
+                )
+                .unwrap();
+                let mut raw = vec![];
+                proof.write(&mut raw).unwrap();
+                // The mask the proof was actually hidden with.
+                raw.iter().zip(&qr.proof).map(|(r, h)| r ^ h).collect()
+            })
+            .collect();
+        assert!(longest_common_run(&masks[0], &masks[1]) < 4);
+
+        // The repeated mask of the older versions shares everything.
+        let old = hiding_mask(
+            3,
+            &Public::older_than(18, 2459000, 1200).unwrap(),
+            &Challenge::default(),
+            &photos_digest,
+        );
+        assert_eq!(old, photos_digest.as_bytes().to_vec());
+    }
+
+    #[test]
+    fn corrupted_scan_is_refused_by_checksum() {
+        let photos_digest = digest(&[2u8, 7]);
+        for (qr, length) in &[
+            (older_proof(&photos_digest), PAYLOAD_LENGTH),
+            (band_proof(2001, 18, 30), BAND_PAYLOAD_LENGTH),
+        ] {
+            let s = qr.to_string();
+            let bytes = bs58::decode(&s).into_vec().unwrap();
+            assert_eq!(bytes.len(), *length);
+            // All bytes after the version, which is read first.
+            for i in 1..bytes.len() {
+                let mut corrupted = bytes.clone();
+                corrupted[i] ^= 1 << (i % 8);
+                assert_eq!(
+                    ProofQrCode::from_str(&bs58::encode(&corrupted).into_string()),
+                    Err(QrError::ChecksumMismatch),
+                    "byte {}",
+                    i
+                );
+            }
+            // A misread character of the string.
+            for i in &[5, s.len() / 2, s.len() - 1] {
+                let mut misread: Vec<char> = s.chars().collect();
+                misread[*i] = if misread[*i] == '2' { '3' } else { '2' };
+                let misread: String = misread.into_iter().collect();
+                assert_eq!(
+                    ProofQrCode::from_str(&misread),
+                    Err(QrError::ChecksumMismatch)
+                );
+            }
+            assert_eq!(
+                super::verify_proof(&s.parse().unwrap(), &photos_digest),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn chunked_qr_code_verifies() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        let mut frames = p.to_chunked_strings(40).unwrap();
+        assert!(frames.len() > 1);
+        frames.reverse();
+        let decoded = ProofQrCode::from_chunks(frames.iter().map(String::as_str)).unwrap();
+        assert_eq!(decoded, p);
+        assert_eq!(super::verify_proof(&decoded, &photos_digest), Ok(()));
+
+        frames.pop();
+        assert!(ProofQrCode::from_chunks(frames.iter().map(String::as_str)).is_err());
+    }
+
+    #[test]
+    fn verification_outcomes() {
+        let photos_digest = digest(&[2u8, 7]);
+        let verifier = Verifier::new().unwrap();
+        let valid = older_proof(&photos_digest);
+        assert_eq!(
+            verifier.verify_outcome(&valid, &photos_digest),
+            VerificationOutcome::Valid
+        );
 
-    let witness = interpreter
-        .execute(&prg, &arguments)
-        .map_err(|e| format!("Execution failed: {}", e))?;
+        // The birthday is only 19 days before today, the prover
+        // generates a decoy proof.
+        let decoy = super::generate_proof(prepared_request(50, &photos_digest)).unwrap();
+        assert_eq!(
+            verifier.verify_outcome(&decoy, &photos_digest),
+            VerificationOutcome::NotSatisfied
+        );
 
-    let outs = witness.return_values();
-    assert_eq!(1, outs.len());
-    let out = &outs[0];
+        let mut truncated = valid.clone();
+        truncated.proof.truncate(10);
+        assert_eq!(
+            verifier.verify_outcome(&truncated, &photos_digest),
+            VerificationOutcome::Malformed
+        );
+    }
 
-    let proof = G16::generate_proof(prg, witness, PROVING_KEY.to_vec());
+    #[test]
+    fn wrong_digest_is_never_accepted() {
+        let photos_digest = digest(&[2u8, 7]);
+        let valid = older_proof(&photos_digest);
+        let masked = with_version(&valid, &photos_digest, STREAM_MASK_VERSION);
+        let mut unparseable = 0;
+        for other in 0..16u8 {
+            assert_eq!(
+                super::verify_proof(&valid, &digest(&[other, 8]))
+                    .map_err(|e| VerificationFailure::from_error(&e)),
+                Err(Some(VerificationFailure::TagMismatch))
+            );
+            let result = super::verify_proof(&masked, &digest(&[other, 8]));
+            match result.as_ref().map_err(VerificationFailure::from_error) {
+                Err(Some(VerificationFailure::ProofUnparseable)) => unparseable += 1,
+                Err(Some(VerificationFailure::PairingCheckFailed)) => (),
+                _ => panic!("wrong digest gives {:?}", result),
+            }
+        }
+        // Usually the masked points do not decode.
+        assert!(unparseable > 0);
 
-    let hidden_proof = hide_bellman_proof(
-        &proof.proof.into_bellman::<Bn128Field>(),
-        &rq.private.photos_digest,
-    );
+        let decoy = super::generate_proof(prepared_request(50, &photos_digest)).unwrap();
+        assert_eq!(
+            super::verify_proof(&decoy, &photos_digest)
+                .map_err(|e| VerificationFailure::from_error(&e)),
+            Err(Some(VerificationFailure::PairingCheckFailed))
+        );
+        let mut future = valid.clone();
+        future.public.today = MAX_JULIAN_DAY + 1;
+        assert_eq!(
+            super::verify_proof(&future, &photos_digest)
+                .map_err(|e| VerificationFailure::from_error(&e)),
+            Err(Some(VerificationFailure::BadPublicEncoding))
+        );
+        assert_eq!(
+            VerificationFailure::from_error(&ZkError::UnknownKey(9)),
+            None
+        );
+    }
 
-    let qr = ProofQrCode {
-        public: rq.public,
-        proof: hidden_proof,
-        challenge: out.into_byte_vector(),
-    };
-    Ok(qr)
-}
+    #[test]
+    fn outcome_of_errors() {
+        assert_eq!(
+            VerificationOutcome::from_result(Err(ZkError::QrCode(QrError::Truncated(
+                QrSection::Payload
+            )))),
+            Ok(VerificationOutcome::Malformed)
+        );
+        assert_eq!(
+            VerificationOutcome::from_result(Err(ZkError::KeyDeserialization)),
+            Err(ZkError::KeyDeserialization)
+        );
+    }
 
-pub fn verify_proof(qr: &ProofQrCode, photo_digest: &Vec<u8>) -> Result<(), String> {
-    let vk = serde_json::from_reader(VERIFICATION_KEY)
-        .map_err(|why| format!("Couldn't deserialize verification key: {}", why))?;
+    #[test]
+    fn generate_proof_rejects_invalid_request() {
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: -5,
+                private_key: PrivateKey::default(),
+                photos_digest: PhotosDigest::default(),
+            },
+            allow_unbound_proof: false,
+        };
+        assert_eq!(
+            super::generate_proof(rq).unwrap_err(),
+            ZkError::InvalidInput(ValidationError::Birthday)
+        );
+    }
 
-    let mut inputs: Vec<Bn128Field> = Vec::new();
+    #[test]
+    fn verify_max_julian_day() {
+        let photos_digest = digest(&[2u8, 7]);
+        let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: MAX_JULIAN_DAY,
+                now: 1200,
+                relation: Relation::Older,
+                delta: 18,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: MAX_JULIAN_DAY - 19,
+                private_key: PrivateKey::default(),
+                photos_digest: photos_digest.clone(),
+            },
+            allow_unbound_proof: false,
+        };
+        let p = super::generate_proof(rq).unwrap();
+        assert!(super::verify_proof(&p, &photos_digest).is_ok());
+    }
 
-    // Inverting the relation.
-    let delta = qr.public.delta;
-    let today = qr.public.today;
-    let younger =  qr.public.relation == Relation::Younger;
+    fn band_request(birthday: i32, older: i32, younger: i32) -> QrRequest {
+        QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: 2020,
+                now: 1200,
+                relation: Relation::Older,
+                delta: older,
+                second: Some(Bound {
+                    relation: Relation::Younger,
+                    delta: younger,
+                }),
+                nonce: None,
+            },
+            private: Private {
+                birthday: birthday,
+                private_key: PrivateKey::new(vec![10]).unwrap(),
+                photos_digest: digest(&[2u8, 7]),
+            },
+            allow_unbound_proof: false,
+        }
+    }
 
+    fn band_proof(birthday: i32, older: i32, younger: i32) -> ProofQrCode {
+        super::generate_proof(band_request(birthday, older, younger)).unwrap()
+    }
 
-    inputs.push(Bn128Field::from(delta));
-    inputs.push(Bn128Field::from(today));
-    inputs.push(Bn128Field::from(if younger {1} else {0}));
-    inputs.push(Bn128Field::from_byte_vector(qr.challenge.clone()));
+    #[test]
+    fn verify_band() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = band_proof(2000, 18, 26);
+        assert!(p.second_proof.is_some());
+        assert!(super::verify_proof(&p, &photos_digest).is_ok());
+        let parsed = ProofQrCode::from_str(&p.to_string()).unwrap();
+        assert_eq!(parsed, p);
+        assert!(super::verify_proof(&parsed, &photos_digest).is_ok());
+        let compact = ProofQrCode::from_str(&p.to_string_compact().unwrap()).unwrap();
+        assert!(super::verify_proof(&compact, &photos_digest).is_ok());
+    }
 
-    let proof = unhide_bellman_proof(&qr.proof, photo_digest).unwrap(); // TODO error
+    #[test]
+    fn verify_band_only_lower_bound_holds() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = band_proof(1990, 18, 26);
+        assert!(super::verify_proof(&p, &photos_digest).is_err());
 
-    let mut raw: Vec<u8> = Vec::new();
-    proof.write(&mut raw).unwrap();
+        // The lower bound alone does not verify either, the verifier
+        // must not learn which bound failed.
+        let mut lower = p.clone();
+        lower.public.second = None;
+        lower.second_proof = None;
+        assert!(super::verify_proof(&lower, &photos_digest).is_err());
+    }
 
-    let proof_points = ProofPoints::from_bellman::<Bn128Field>(&proof);
+    #[test]
+    fn verify_band_decoy() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = band_proof(2010, 18, 26);
+        assert!(super::verify_proof(&p, &photos_digest).is_err());
 
-    let proof = Proof::<ProofPoints> {
-        proof: proof_points,
-        inputs: inputs
-            .iter()
-            .map(|bn128| bn128.to_biguint().to_str_radix(16))
-            .collect(),
-        raw: hex::encode(&raw),
-    };
+        let mut upper = p.clone();
+        upper.public.relation = Relation::Younger;
+        upper.public.delta = 26;
+        upper.public.second = None;
+        upper.proof = p.second_proof.clone().unwrap();
+        upper.second_proof = None;
+        assert!(super::verify_proof(&upper, &photos_digest).is_err());
+    }
 
-    let ans = <G16 as ProofSystem<Bn128Field>>::verify(vk, proof);
-    if ans {
-        Ok(())
-    } else {
-        Err(String::from("no"))
+    #[test]
+    fn verify_band_without_second_proof() {
+        let photos_digest = digest(&[2u8, 7]);
+        let mut p = band_proof(2000, 18, 26);
+        p.second_proof = None;
+        assert_eq!(
+            super::verify_proof(&p, &photos_digest),
+            Err(ZkError::VerificationFailed)
+        );
     }
-}
 
-fn hide_buffer(buf: &mut Vec<u8>, hidding: &Vec<u8>) {
-    if hidding.len() > 0 {
-        for i in 0..buf.len() {
-            buf[i] ^= hidding[i % hidding.len()];
+    #[test]
+    fn youth_discount_window() {
+        // 18 to 26 years is one code with two proofs; a false bound
+        // fails the whole code.
+        let photos_digest = digest(&[2u8, 7]);
+        let today = julian_from_ymd(2020, 5, 17).unwrap();
+        let policy = crate::policy::AgePolicy::between_years(18, 26);
+        for (birthday, valid) in &[
+            (julian_from_ymd(2000, 1, 1).unwrap(), true),
+            (julian_from_ymd(2002, 5, 18).unwrap(), false),
+            (julian_from_ymd(1994, 5, 17).unwrap(), false),
+        ] {
+            let mut rq = band_request(*birthday, 0, 0);
+            rq.public = policy.public(today, 600).unwrap();
+            let p = super::generate_proof(rq).unwrap();
+            let parsed = ProofQrCode::from_str(&p.to_string()).unwrap();
+            assert_eq!(super::verify_proof(&parsed, &photos_digest).is_ok(), *valid);
+            assert!(policy.matches(&parsed.public, today));
         }
     }
-}
-
-pub fn hide_bellman_proof(proof: &BellmanProof<Bn256>, hidding: &Vec<u8>) -> Vec<u8> {
-    let mut proof_bytes: Vec<u8> = Vec::new();
-    proof.write(&mut proof_bytes).unwrap();
-    hide_buffer(&mut proof_bytes, hidding);
-    proof_bytes
-}
-
-pub fn unhide_bellman_proof(
-    hidden: &Vec<u8>,
-    hidding: &Vec<u8>,
-) -> Result<BellmanProof<Bn256>, QrError> {
-    let mut b = hidden.clone();
-    hide_buffer(&mut b, hidding);
-    let mut rdr = Cursor::new(b);
-    BellmanProof::<Bn256>::read(&mut rdr).map_err(|_| QrError {})
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::api::{Private, ProofQrCode, Public, QrRequest, Relation};
-    use std::str::FromStr;
-    use zokrates_field::Bn128Field;
+    fn options(max_age_minutes: u32, verifier_today: i32, verifier_now: i32) -> VerifyOptions {
+        VerifyOptions {
+            max_age_minutes: max_age_minutes,
+            verifier_now: verifier_now,
+            verifier_today: verifier_today,
+            allow_unbound_proof: false,
+        }
+    }
 
     #[test]
-    fn generate_random_private_key() {
-        let key = super::generate_random_private_key();
-        println!("{:?}", key);
-        assert_eq!(32, key.len());
+    fn freshness_window() {
+        let public = Public::older_than(0, 2020, 600).unwrap();
+        assert!(options(10, 2020, 600).is_fresh(&public));
+        assert!(options(10, 2020, 610).is_fresh(&public));
+        assert!(!options(10, 2020, 611).is_fresh(&public));
+        assert!(options(10, 2020, 590).is_fresh(&public));
+        assert!(!options(10, 2020, 589).is_fresh(&public));
+        assert!(!options(10, 2021, 600).is_fresh(&public));
     }
 
-    fn bn128(s: &str) -> Bn128Field {
-        Bn128Field::try_from_dec_str(s).unwrap()
+    #[test]
+    fn freshness_window_spans_midnight() {
+        let mut public = Public::older_than(0, 2020, 23 * 60 + 58).unwrap();
+        assert!(options(3, 2021, 1).is_fresh(&public));
+        assert!(!options(3, 2021, 2).is_fresh(&public));
+        assert!(!options(3, 2020, 1).is_fresh(&public));
+
+        public.today = 2021;
+        public.now = 1;
+        assert!(options(3, 2020, 23 * 60 + 58).is_fresh(&public));
     }
 
     #[test]
-    fn mimc7r10() {
-        // values from ZoKrartes test
+    fn verify_stale_proof() {
+        let photos_digest = digest(&[2u8, 7]);
+        let p = older_proof(&photos_digest);
+        assert_eq!(
+            super::verify_with_options(&p, &photos_digest, &options(5, 2020, 1205)),
+            Ok(())
+        );
+        assert_eq!(
+            super::verify_with_options(&p, &photos_digest, &options(5, 2020, 1206)),
+            Err(ZkError::Stale)
+        );
+    }
 
+    #[test]
+    fn unbound_proof_needs_opt_in() {
+        let unbound = PhotosDigest::default();
+        let mut rq = prepared_request(18, &unbound);
         assert_eq!(
-            compute_mimc7r10_hash(&bn128("0"), &bn128("0")),
-            bn128("6004544488495356385698286530147974336054653445122716140990101827963729149289")
+            super::generate_proof(rq.clone()),
+            Err(ZkError::InvalidInput(ValidationError::PhotosDigest))
         );
         assert_eq!(
-            compute_mimc7r10_hash(&bn128("100"), &bn128("0")),
-            bn128("2977550761518141183167168643824354554080911485709001361112529600968315693145")
+            super::generate_proof_seeded(rq.clone(), [1; 32]),
+            Err(ZkError::InvalidInput(ValidationError::PhotosDigest))
         );
+        rq.allow_unbound_proof = true;
+        let p = super::generate_proof(rq).unwrap();
         assert_eq!(
-            compute_mimc7r10_hash(
-                &bn128("100"),
-                &bn128(
-                    "21888242871839275222246405745257275088548364400416034343698204186575808495617"
-                )
-            ),
-            bn128("2977550761518141183167168643824354554080911485709001361112529600968315693145")
+            super::verify_proof(&p, &unbound),
+            Err(ZkError::UnboundProof)
         );
+        let empty = PhotosDigest::new(Vec::new()).unwrap();
+        assert_eq!(super::verify_proof(&p, &empty), Err(ZkError::UnboundProof));
         assert_eq!(
-            compute_mimc7r10_hash(
-                &bn128(
-                    "21888242871839275222246405745257275088548364400416034343698204186575808495618"
-                ),
-                &bn128("1")
-            ),
-            bn128("11476724043755138071320043459606423473319855817296339514744600646762741571430")
+            VerificationOutcome::from_result(super::verify_proof(&p, &unbound)),
+            Ok(VerificationOutcome::Unbound)
         );
         assert_eq!(
-            compute_mimc7r10_hash(
-                &bn128(
-                    "21888242871839275222246405745257275088548364400416034343698204186575808495617"
-                ),
-                &bn128(
-                    "21888242871839275222246405745257275088548364400416034343698204186575808495617"
-                )
+            VerificationFailure::from_error(&ZkError::UnboundProof),
+            Some(VerificationFailure::UnboundDigest)
+        );
+        assert_eq!(super::verify_proof_binding(&p, &unbound, true), Ok(()));
+        assert_eq!(
+            VerificationOutcome::from_binding(
+                super::verify_proof_binding(&p, &unbound, true),
+                &unbound
             ),
-            bn128("6004544488495356385698286530147974336054653445122716140990101827963729149289")
+            Ok(VerificationOutcome::ValidUnbound)
         );
-    }
+        let mut options = options(5, 2020, 1200);
+        options.allow_unbound_proof = true;
+        assert_eq!(super::verify_with_options(&p, &unbound, &options), Ok(()));
 
-    #[test]
-    fn generate_card_key() {
-        let m1 =
-            bn128("2398929016733331352351677352383442125702690766615627729516078292095018104789");
-        assert_eq!(compute_mimc7r10_hash(&bn128("3"), &bn128("20010")), m1);
-
-        let private = Private {
-            birthday: 2001,
-            private_key: bn128("10").into_byte_vector(),
-            photos_digest: bn128("3").into_byte_vector(),
-        };
-        let key = super::generate_card_key(private);
-        assert_eq!(32, key.len());
-
-        assert_eq!(Bn128Field::from_byte_vector(key), bn128("3") * m1);
+        // A bound proof is refused with the zero digest as well.
+        let bound = older_proof(&digest(&[2u8, 7]));
+        assert_ne!(
+            super::verify_with_options(&bound, &unbound, &options),
+            Ok(())
+        );
     }
 
     #[test]
-    fn compute_challenge() {
-        let m1 =
-            bn128("20806133116655125295815844821187893628062572117889123030572462808546913026234");
-        assert_eq!(compute_mimc7r10_hash(&bn128("2020"), &bn128("27")), m1);
-
-        let card_key = bn128("27").into_byte_vector();
-        let today = 2020;
-        let challenge = super::compute_challenge(card_key, today);
-        assert_eq!(32, challenge.len());
-
-        assert_eq!(Bn128Field::from_byte_vector(challenge), m1);
+    fn unbound_proof_with_nonce_needs_opt_in() {
+        let unbound = PhotosDigest::default();
+        let nonce = Nonce::new(vec![3; NONCE_LENGTH]).unwrap();
+        let mut rq = prepared_request(18, &unbound);
+        rq.allow_unbound_proof = true;
+        rq.public.nonce = Some(nonce.clone());
+        let p = super::generate_proof(rq).unwrap();
+        assert_eq!(
+            verify_proof_with_nonce(&p, &unbound, &nonce, false),
+            Err(ZkError::UnboundProof)
+        );
+        assert_eq!(verify_proof_with_nonce(&p, &unbound, &nonce, true), Ok(()));
+        let verifier = Verifier::new().unwrap();
+        assert_eq!(
+            verifier.verify_with_nonce(&p, &unbound, &nonce, false),
+            Err(ZkError::UnboundProof)
+        );
+        assert_eq!(
+            verifier.verify_with_nonce(&p, &unbound, &nonce, true),
+            Ok(())
+        );
+        // The flag does not relax the nonce check.
+        assert_eq!(
+            verifier.verify_with_nonce(&p, &unbound, &generate_nonce(), true),
+            Err(ZkError::NonceMismatch)
+        );
     }
 
     #[test]
-    fn verify_older() {
-        let photos_digest = vec![2u8, 7];
-        let rq = QrRequest {
-            public: Public {
-                today: 2020,
-                now: 1200,
-                relation: Relation::Older,
-                delta: 18,
-            },
-            private: Private {
-                birthday: 2001,
-                private_key: Vec::new(),
-                photos_digest: photos_digest.clone(),
-            },
+    fn verify_challenge() {
+        let photos_digest = digest(&[2u8, 7]);
+        let private = Private {
+            birthday: 2001,
+            private_key: PrivateKey::new(vec![10]).unwrap(),
+            photos_digest: photos_digest.clone(),
         };
-        let p = super::generate_proof(rq).unwrap();
-        assert!(super::verify_proof(&p, &photos_digest).is_ok());
-        let ps = p.to_string();
-        assert!(super::verify_proof(&ProofQrCode::from_str(&ps).unwrap(), &photos_digest).is_ok());
+        let card_key = super::generate_card_key(&private).unwrap();
+        let p = older_proof(&photos_digest);
+        // older_proof uses an empty private key, which
+        // `generate_card_key` refuses.
+        assert!(!super::verify_challenge(&card_key, &p.public, &p.challenge));
+
+        let card_key = card_key_unchecked(&Private {
+            birthday: 2001,
+            private_key: PrivateKey::default(),
+            photos_digest: photos_digest.clone(),
+        });
+        assert!(super::verify_challenge(&card_key, &p.public, &p.challenge));
+        let shortest = Challenge::from_field(&p.challenge.to_field());
+        assert!(super::verify_challenge(&card_key, &p.public, &shortest));
+        let mut tampered = p.challenge.as_bytes().to_vec();
+        tampered[0] ^= 1;
+        let tampered = Challenge::new(tampered).unwrap();
+        assert!(!super::verify_challenge(&card_key, &p.public, &tampered));
+        let mut tomorrow = p.public.clone();
+        tomorrow.today += 1;
+        assert!(!super::verify_challenge(&card_key, &tomorrow, &p.challenge));
     }
 
     #[test]
     fn verify_younger() {
-        let photos_digest = Vec::new();
+        let photos_digest = digest(&[2u8, 7]);
         let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
             public: Public {
                 today: 2020,
                 now: 1200,
                 relation: Relation::Younger,
                 delta: 21,
+                second: None,
+                nonce: None,
             },
             private: Private {
                 birthday: 2001,
-                private_key: Vec::new(),
+                private_key: PrivateKey::default(),
                 photos_digest: photos_digest.clone(),
             },
+            allow_unbound_proof: false,
         };
         let p = super::generate_proof(rq).unwrap();
         assert!(super::verify_proof(&p, &photos_digest).is_ok());
@@ -350,62 +3796,444 @@ mod tests {
 
     #[test]
     fn verify_invalid() {
-        let photos_digest = Vec::new();
+        let photos_digest = digest(&[2u8, 7]);
         let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
             public: Public {
                 today: 2020,
                 now: 1200,
                 relation: Relation::Older,
                 delta: 18,
+                second: None,
+                nonce: None,
             },
             private: Private {
                 birthday: 2010,
-                private_key: Vec::new(),
+                private_key: PrivateKey::default(),
                 photos_digest: photos_digest.clone(),
             },
+            allow_unbound_proof: false,
         };
         let p = super::generate_proof(rq).unwrap();
-        assert!(!super::verify_proof(&p, &photos_digest).is_ok());
+        assert_eq!(
+            super::verify_proof(&p, &photos_digest),
+            Err(ZkError::VerificationFailed)
+        );
+    }
+
+    fn relation_request(relation: Relation, birthday: i32, delta: i32, today: i32) -> QrRequest {
+        QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
+            public: Public {
+                today: today,
+                now: 1200,
+                relation: relation,
+                delta: delta,
+                second: None,
+                nonce: None,
+            },
+            private: Private {
+                birthday: birthday,
+                private_key: PrivateKey::default(),
+                photos_digest: digest(&[2u8, 7]),
+            },
+            allow_unbound_proof: false,
+        }
+    }
+
+    /// Request with a valid birthday, today and delta.
+    fn arbitrary_request() -> impl Strategy<Value = QrRequest> {
+        (1..=MAX_JULIAN_DAY)
+            .prop_flat_map(|today| (1..=today, 0..=MAX_JULIAN_DAY, Just(today)))
+            .prop_flat_map(|(birthday, delta, today)| {
+                prop_oneof![Just(Relation::Younger), Just(Relation::Older)]
+                    .prop_map(move |relation| relation_request(relation, birthday, delta, today))
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+
+        #[test]
+        fn circuit_agrees_with_relation(rq in arbitrary_request()) {
+            let younger = (rq.public.relation == Relation::Younger) as i32;
+            let witness = shared_prover()
+                .unwrap()
+                .compute_witness(&rq, rq.public.delta, younger);
+            prop_assert_eq!(witness.is_ok(), rq.is_relation_valid().unwrap());
+        }
+
+        #[test]
+        fn public_inputs_of_prover_and_verifier_agree(rq in arbitrary_request()) {
+            // The prover proves a decoy, delta 0 "older than", if the
+            // relation does not hold.
+            let prover = shared_prover().unwrap();
+            let mut public = rq.public.clone();
+            if !rq.is_relation_valid().unwrap() {
+                public.relation = Relation::Older;
+                public.delta = 0;
+            }
+            let younger = (public.relation == Relation::Younger) as i32;
+            if let Ok((witness, challenge)) = prover.compute_witness(&rq, public.delta, younger) {
+                let proved = Computation::with_witness(prover.program.clone(), witness)
+                    .public_inputs_values();
+                let inputs = build_public_inputs(&public, challenge.as_bytes()).unwrap();
+                prop_assert_eq!(inputs.len(), PUBLIC_INPUTS);
+                let inputs: Vec<_> = inputs.into_iter().map(|i| i.into_bellman()).collect();
+                prop_assert_eq!(proved, inputs);
+            }
+        }
+
+        #[test]
+        fn challenge_does_not_depend_on_relation(rq in arbitrary_request()) {
+            // The decoy proof must give the same challenge.
+            let prover = shared_prover().unwrap();
+            if let Ok((_, challenge)) = prover.compute_witness(&rq, 0, 0) {
+                prop_assert_eq!(
+                    challenge,
+                    compute_challenge(&card_key_unchecked(&rq.private), rq.public.today)
+                );
+            }
+        }
+    }
+
+    /// Valid request whose relation holds or not.
+    fn request_with(holds: bool) -> impl Strategy<Value = QrRequest> {
+        (2..=MAX_JULIAN_DAY)
+            .prop_flat_map(|today| (1..today, Just(today)))
+            .prop_flat_map(move |(birthday, today)| {
+                let age = today - birthday;
+                let older = if holds {
+                    0..age
+                } else {
+                    age..MAX_JULIAN_DAY + 1
+                };
+                let younger = if holds {
+                    age + 1..MAX_JULIAN_DAY + 1
+                } else {
+                    0..age + 1
+                };
+                prop_oneof![
+                    older.prop_map(move |delta| (Relation::Older, delta)),
+                    younger.prop_map(move |delta| (Relation::Younger, delta)),
+                ]
+                .prop_map(move |(relation, delta)| {
+                    let mut rq = relation_request(relation, birthday, delta, today);
+                    rq.private.photos_digest = digest(&[2u8, 7]);
+                    rq
+                })
+            })
+    }
+
+    proptest! {
+        // Each case generates a proof.
+        #![proptest_config(ProptestConfig::with_cases(6))]
+
+        #[test]
+        fn proof_of_holding_relation_verifies(rq in request_with(true)) {
+            prop_assert_eq!(rq.is_relation_valid(), Ok(true));
+            let photos_digest = rq.private.photos_digest.clone();
+            let qr = super::generate_proof(rq).unwrap();
+            prop_assert_eq!(super::verify_proof(&qr, &photos_digest), Ok(()));
+        }
+
+        #[test]
+        fn proof_of_false_relation_fails(rq in request_with(false)) {
+            prop_assert_eq!(rq.is_relation_valid(), Ok(false));
+            let photos_digest = rq.private.photos_digest.clone();
+            let qr = super::generate_proof(rq).unwrap();
+            prop_assert_eq!(
+                super::verify_proof(&qr, &photos_digest),
+                Err(ZkError::VerificationFailed)
+            );
+        }
     }
 
     #[test]
-    fn verify_marginal_case_older() {
-        let photos_digest = Vec::new();
-        // Equality is refused. Wait till midnight.
+    fn values_out_of_range_are_rejected() {
+        // (birthday, delta, today, error)
+        let table = [
+            (2001, 18, i32::MAX, ValidationError::Today),
+            (2001, 18, MAX_JULIAN_DAY + 1, ValidationError::Today),
+            (2001, i32::MAX, 2020, ValidationError::Delta),
+            (2001, MAX_JULIAN_DAY + 1, 2020, ValidationError::Delta),
+            (2001, -1, 2020, ValidationError::Delta),
+            (-1, 18, 2020, ValidationError::Birthday),
+            (i32::MIN, 18, 2020, ValidationError::Birthday),
+            (0, 18, 2020, ValidationError::Birthday),
+        ];
+        for relation in &[Relation::Older, Relation::Younger] {
+            for (birthday, delta, today, error) in &table {
+                let rq = relation_request(*relation, *birthday, *delta, *today);
+                assert_eq!(
+                    evaluate_relation(&rq),
+                    RelationOutcome::InvalidInput(error.clone())
+                );
+                assert_eq!(
+                    super::generate_proof(rq),
+                    Err(ZkError::InvalidInput(error.clone()))
+                );
+            }
+        }
+
+        let photos_digest = digest(&[2u8, 7]);
+        for relation in &[Relation::Older, Relation::Younger] {
+            let valid = super::generate_proof(relation_request(*relation, 2001, 18, 2020)).unwrap();
+            for (_, delta, today, error) in &table[..5] {
+                let mut qr = valid.clone();
+                qr.public.today = *today;
+                qr.public.delta = *delta;
+                // The decoded code reaches the verifier unchanged.
+                let qr = ProofQrCode::from_str(&qr.to_string()).unwrap();
+                assert_eq!(
+                    super::verify_proof(&qr, &photos_digest),
+                    Err(ZkError::InvalidInput(error.clone()))
+                );
+                assert_eq!(
+                    VerificationOutcome::from_result(super::verify_proof(&qr, &photos_digest)),
+                    Ok(VerificationOutcome::Malformed)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn public_inputs_are_checked() {
+        let challenge = [1u8; FIELD_LENGTH];
+        let public = Public::older_than(18, 2020, 0).unwrap();
+        assert_eq!(
+            build_public_inputs(&public, &challenge).unwrap().len(),
+            PUBLIC_INPUTS
+        );
+        let band = band_request(2001, 18, 30).public;
+        let inputs = build_public_inputs(&band, &challenge).unwrap();
+        assert_eq!(inputs.len(), 2 * PUBLIC_INPUTS);
+        assert_eq!(inputs[PUBLIC_INPUTS + 2], Bn128Field::from(1));
+        assert_eq!(
+            build_public_inputs(&public, &[0xff; FIELD_LENGTH]),
+            Err(ZkError::InvalidInput(ValidationError::Challenge))
+        );
+        let mut same = band.clone();
+        same.second.as_mut().unwrap().relation = same.relation;
+        assert_eq!(
+            build_public_inputs(&same, &challenge),
+            Err(ZkError::InvalidInput(ValidationError::SecondBound))
+        );
+        for (delta, today, error) in &[
+            (18, MAX_JULIAN_DAY + 1, ValidationError::Today),
+            (18, 0, ValidationError::Today),
+            (-1, 2020, ValidationError::Delta),
+            (MAX_JULIAN_DAY + 1, 2020, ValidationError::Delta),
+        ] {
+            let mut p = public.clone();
+            p.delta = *delta;
+            p.today = *today;
+            assert_eq!(
+                build_public_inputs(&p, &challenge),
+                Err(ZkError::InvalidInput(error.clone()))
+            );
+        }
+    }
+
+    #[test]
+    fn largest_values_are_accepted() {
+        // Birthday 1 and the largest delta and day, Younger holds and
+        // Older gives a decoy.
+        for (relation, holds) in &[(Relation::Younger, true), (Relation::Older, false)] {
+            let rq = relation_request(*relation, 1, MAX_JULIAN_DAY, MAX_JULIAN_DAY);
+            assert_eq!(rq.validate(), Ok(()));
+            let p = super::generate_proof(rq).unwrap();
+            assert_eq!(p.public.delta, MAX_JULIAN_DAY);
+            assert_eq!(super::verify_proof(&p, &digest(&[2u8, 7])).is_ok(), *holds);
+        }
+    }
+
+    #[test]
+    fn relation_boundaries() {
+        // (relation, birthday + delta - today, holds)
+        let table = [
+            (Relation::Older, -1, true),
+            (Relation::Older, 0, false),
+            (Relation::Older, 1, false),
+            (Relation::Younger, -1, false),
+            (Relation::Younger, 0, false),
+            (Relation::Younger, 1, true),
+        ];
+        let delta = 6575;
+        for today in &[2459000, MAX_JULIAN_DAY] {
+            for (relation, offset, holds) in &table {
+                let birthday = today - delta + offset;
+                let rq = relation_request(*relation, birthday, delta, *today);
+                assert_eq!(rq.is_relation_valid(), Ok(*holds));
+                let expected = if *holds {
+                    RelationOutcome::Satisfied
+                } else {
+                    RelationOutcome::NotSatisfied
+                };
+                assert_eq!(evaluate_relation(&rq), expected);
+                let p = super::generate_proof(rq).unwrap();
+                assert_eq!(
+                    super::verify_proof(&p, &digest(&[2u8, 7])).is_ok(),
+                    *holds,
+                    "{:?} birthday {} today {}",
+                    relation,
+                    birthday,
+                    today
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn evaluate_band() {
+        let photos_digest = digest(&[2u8, 7]);
+        let today = 2459000;
+        for (birthday, outcome) in &[
+            (today - 7000, RelationOutcome::Satisfied),
+            (today - 6000, RelationOutcome::NotSatisfied),
+            (today - 10000, RelationOutcome::NotSatisfied),
+        ] {
+            let mut rq = relation_request(Relation::Older, *birthday, 6575, today);
+            rq.public.second = Some(Bound {
+                relation: Relation::Younger,
+                delta: 9497,
+            });
+            assert_eq!(&evaluate_relation(&rq), outcome);
+            let p = super::generate_proof(rq).unwrap();
+            assert_eq!(
+                super::verify_proof(&p, &photos_digest).is_ok(),
+                *outcome == RelationOutcome::Satisfied
+            );
+        }
+    }
+
+    #[test]
+    fn evaluate_invalid_request() {
+        let rq = relation_request(Relation::Older, 0, 6575, 2459000);
+        assert_eq!(
+            evaluate_relation(&rq),
+            RelationOutcome::InvalidInput(ValidationError::Birthday)
+        );
+        let rq = relation_request(Relation::Older, 2451000, -1, 2459000);
+        assert_eq!(
+            evaluate_relation(&rq),
+            RelationOutcome::InvalidInput(ValidationError::Delta)
+        );
+    }
+
+    fn birthday_proof(relation: Relation, birthday: i32, today: i32) -> bool {
+        let photos_digest = digest(&[2u8, 7]);
         let rq = QrRequest {
+            circuit: EMBEDDED_CIRCUIT,
             public: Public {
-                today: 2020,
+                today: today,
                 now: 1200,
-                relation: Relation::Older,
-                delta: 20,
+                relation: relation,
+                delta: delta_for_years(18, today).unwrap(),
+                second: None,
+                nonce: None,
             },
             private: Private {
-                birthday: 2000,
-                private_key: Vec::new(),
+                birthday: birthday,
+                private_key: PrivateKey::default(),
                 photos_digest: photos_digest.clone(),
             },
+            allow_unbound_proof: false,
         };
         let p = super::generate_proof(rq).unwrap();
-        assert!(!super::verify_proof(&p, &photos_digest).is_ok());
+        super::verify_proof(&p, &photos_digest).is_ok()
+    }
+
+    #[test]
+    fn relation_boundaries_agree() {
+        let photos_digest = digest(&[2u8, 7]);
+        let (birthday, delta) = (2451000, 6575);
+        for relation in &[Relation::Older, Relation::Younger] {
+            for today in birthday + delta - 1..=birthday + delta + 1 {
+                let holds = crate::api::relation_holds(birthday, today, delta, *relation);
+                let expected = match relation {
+                    Relation::Older => today > birthday + delta,
+                    Relation::Younger => today < birthday + delta,
+                };
+                assert_eq!(holds, expected, "{:?} {}", relation, today);
+                let rq = relation_request(*relation, birthday, delta, today);
+                assert_eq!(rq.is_relation_valid(), Ok(holds));
+                let p = super::generate_proof(rq).unwrap();
+                assert_eq!(super::verify_proof(&p, &photos_digest).is_ok(), holds);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_marginal_case_older() {
+        let birthday = julian_from_ymd(2002, 5, 17).unwrap();
+        let birthday18 = julian_from_ymd(2020, 5, 17).unwrap();
+        // Equality is refused. Wait till midnight.
+        assert!(!birthday_proof(Relation::Older, birthday, birthday18));
+        assert!(birthday_proof(Relation::Older, birthday, birthday18 + 1));
     }
 
     #[test]
     fn verify_marginal_case_younger() {
-        let photos_digest = Vec::new();
-        let rq = QrRequest {
+        let birthday = julian_from_ymd(2002, 5, 17).unwrap();
+        let birthday18 = julian_from_ymd(2020, 5, 17).unwrap();
+        // Equality is refused on the birthday too.
+        assert!(!birthday_proof(Relation::Younger, birthday, birthday18));
+        assert!(birthday_proof(Relation::Younger, birthday, birthday18 - 1));
+    }
+}
+
+#[cfg(all(test, feature = "prover", not(feature = "verifier")))]
+mod prover_tests {
+    use super::*;
+
+    #[test]
+    fn generate_proof_without_verifier() {
+        let photos_digest = PhotosDigest::new(vec![2u8, 7]).unwrap();
+        let private = || {
+            Private::with(
+                2001,
+                PrivateKey::new(vec![10]).unwrap(),
+                photos_digest.clone(),
+            )
+        };
+        let public = Public::older_than(18, 2020, 1200).unwrap();
+        let rq = QrRequest::build(public, private().unwrap()).unwrap();
+        let qr = generate_proof(rq).unwrap();
+        let parsed: ProofQrCode = qr.to_string().parse().unwrap();
+        assert_eq!(parsed, qr);
+        let card_key = generate_card_key(&private().unwrap()).unwrap();
+        assert!(verify_challenge(&card_key, &qr.public, &qr.challenge));
+    }
+}
+
+#[cfg(all(test, feature = "verifier", not(feature = "prover")))]
+mod verifier_tests {
+    use super::*;
+    use crate::api::{FIELD_LENGTH, QR_FORMAT_VERSION, SEALED_PROOF_LENGTH};
+
+    #[test]
+    fn verify_proof_without_prover() {
+        assert!(Verifier::new().is_ok());
+        let qr = ProofQrCode {
+            version: QR_FORMAT_VERSION,
+            circuit: EMBEDDED_CIRCUIT,
+            key_id: Some(EMBEDDED_KEY),
             public: Public {
                 today: 2020,
                 now: 1200,
                 relation: Relation::Older,
-                delta: 20,
-            },
-            private: Private {
-                birthday: 2000,
-                private_key: Vec::new(),
-                photos_digest: photos_digest.clone(),
+                delta: 18,
+                second: None,
+                nonce: None,
             },
+            proof: vec![1; SEALED_PROOF_LENGTH],
+            challenge: Challenge::new(vec![9; FIELD_LENGTH]).unwrap(),
+            second_proof: None,
         };
-        let p = super::generate_proof(rq).unwrap();
-        assert!(!super::verify_proof(&p, &photos_digest).is_ok());
+        assert_eq!(
+            verify_proof(&qr, &PhotosDigest::default()),
+            Err(ZkError::TagMismatch)
+        );
     }
 }